@@ -0,0 +1,170 @@
+// Names of memory regions and IO registers for debug output.
+// https://gbdev.io/pandocs/Memory_Map.html
+
+use crate::{cpu::CPU, memory_bus::*};
+
+/// https://gbdev.io/pandocs/Hardware_Reg_List.html
+const IO_REGISTER_NAMES: [(u16, &str); 44] = [
+    (0xFF00, "P1"),
+    (0xFF01, "SB"),
+    (0xFF02, "SC"),
+    (0xFF04, "DIV"),
+    (0xFF05, "TIMA"),
+    (0xFF06, "TMA"),
+    (0xFF07, "TAC"),
+    (0xFF0F, "IF"),
+    (0xFF10, "NR10"),
+    (0xFF11, "NR11"),
+    (0xFF12, "NR12"),
+    (0xFF13, "NR13"),
+    (0xFF14, "NR14"),
+    (0xFF16, "NR21"),
+    (0xFF17, "NR22"),
+    (0xFF18, "NR23"),
+    (0xFF19, "NR24"),
+    (0xFF1A, "NR30"),
+    (0xFF1B, "NR31"),
+    (0xFF1C, "NR32"),
+    (0xFF1D, "NR33"),
+    (0xFF1E, "NR34"),
+    (0xFF20, "NR41"),
+    (0xFF21, "NR42"),
+    (0xFF22, "NR43"),
+    (0xFF23, "NR44"),
+    (0xFF24, "NR50"),
+    (0xFF25, "NR51"),
+    (0xFF26, "NR52"),
+    (0xFF30, "WAVE"),
+    (0xFF40, "LCDC"),
+    (0xFF41, "STAT"),
+    (0xFF42, "SCY"),
+    (0xFF43, "SCX"),
+    (0xFF44, "LY"),
+    (0xFF45, "LYC"),
+    (0xFF46, "DMA"),
+    (0xFF47, "BGP"),
+    (0xFF48, "OBP0"),
+    (0xFF49, "OBP1"),
+    (0xFF4A, "WY"),
+    (0xFF4B, "WX"),
+    (0xFF50, "BANK"),
+    (0xFFFF, "IE"),
+];
+
+/// Name of the IO register at `addr` (including IE), e.g. `NR52`. Wave RAM is `WAVE`.
+pub fn io_register_name(addr: u16) -> Option<&'static str> {
+    let addr = match addr {
+        0xFF30..=0xFF3F => 0xFF30,
+        IO_REGISTERS_START..=IO_REGISTERS_END | INTERRUPT_ENABLED_REGISTER => addr,
+        _ => return None,
+    };
+    IO_REGISTER_NAMES
+        .iter()
+        .find(|(reg, _)| *reg == addr)
+        .map(|(_, name)| *name)
+}
+
+/// Region of `addr`, e.g. `ROMX:01` or `IO:NR52`. `rom_bank` is the bank mapped at the address.
+pub fn region_label(addr: u16, rom_bank: usize) -> String {
+    match addr {
+        ROM_BANK_0_START..=ROM_BANK_0_END if rom_bank == 0 => "ROM0".to_string(),
+        ROM_BANK_0_START..=ROM_BANK_N_END => format!("ROMX:{rom_bank:02X}"),
+        VIDEO_RAM_START..=VIDEO_RAM_END => "VRAM".to_string(),
+        EXTERNAL_RAM_START..=EXTERNAL_RAM_END => "SRAM".to_string(),
+        WORKING_RAM_START..=WORKING_RAM_END => "WRAM".to_string(),
+        ECHO_RAM_START..=ECHO_RAM_END => "ECHO".to_string(),
+        OAM_START..=OAM_END => "OAM".to_string(),
+        UNUSED_START..=UNUSED_END => "UNUSED".to_string(),
+        IO_REGISTERS_START..=IO_REGISTERS_END => match io_register_name(addr) {
+            Some(name) => format!("IO:{name}"),
+            None => "IO".to_string(),
+        },
+        HIGH_RAM_AREA_START..=HIGH_RAM_AREA_END => "HRAM".to_string(),
+        INTERRUPT_ENABLED_REGISTER => "IE".to_string(),
+    }
+}
+
+/// Writes memory in `start..=end` as lines of 16 bytes: region label, address, bytes and printable
+/// ASCII characters.
+pub fn hexdump(
+    cpu: &CPU,
+    start: u16,
+    end: u16,
+    out: &mut impl std::io::Write,
+) -> std::io::Result<()> {
+    let bytes: Vec<u8> = (start..=end).map(|addr| cpu.debug_read(addr)).collect();
+    write_hexdump(start, &bytes, |addr| cpu.rom_bank(addr), out)
+}
+
+fn write_hexdump(
+    start: u16,
+    bytes: &[u8],
+    rom_bank: impl Fn(u16) -> usize,
+    out: &mut impl std::io::Write,
+) -> std::io::Result<()> {
+    for (line_idx, line) in bytes.chunks(16).enumerate() {
+        let addr = start.wrapping_add(line_idx as u16 * 16);
+
+        let hex: Vec<String> = line.iter().map(|byte| format!("{byte:02X}")).collect();
+        let ascii: String = line
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        writeln!(
+            out,
+            "{:<8} {addr:04X}  {:<47}  |{ascii}|",
+            region_label(addr, rom_bank(addr)),
+            hex.join(" ")
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn labels() {
+        assert_eq!(region_label(0x0150, 0), "ROM0");
+        assert_eq!(region_label(0x0150, 0x20), "ROMX:20");
+        assert_eq!(region_label(0x4000, 1), "ROMX:01");
+        assert_eq!(region_label(0xC123, 0), "WRAM");
+        assert_eq!(region_label(0xFF26, 0), "IO:NR52");
+        assert_eq!(region_label(0xFF3A, 0), "IO:WAVE");
+        assert_eq!(region_label(0xFF03, 0), "IO");
+        assert_eq!(region_label(0xFFFF, 0), "IE");
+        assert_eq!(io_register_name(0xFFFF), Some("IE"));
+        assert_eq!(io_register_name(0xC000), None);
+    }
+
+    #[test]
+    fn hexdump_lines() {
+        let mut out = vec![];
+        let bytes: Vec<u8> = (0..20).map(|idx| b'A' + idx).collect();
+        write_hexdump(
+            0x3FF8,
+            &bytes,
+            |addr| (addr >= 0x4000) as usize * 3,
+            &mut out,
+        )
+        .unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "ROM0     3FF8  41 42 43 44 45 46 47 48 49 4A 4B 4C 4D 4E 4F 50  |ABCDEFGHIJKLMNOP|",
+                "ROMX:03  4008  51 52 53 54                                      |QRST|",
+            ]
+        );
+    }
+}
@@ -1,11 +1,124 @@
 pub struct Args {
+    /// `demo.gb` with `--demo`, for the files kept next to the ROM.
     pub rom_path: std::path::PathBuf,
+    /// Run the built-in demo instead of a ROM file.
+    pub demo: bool,
+    pub model: crate::model::Model,
+    /// DMG boot ROM to run before the game.
+    pub boot_rom_path: Option<std::path::PathBuf>,
+    /// Where to write the Code/Data Log of executed ROM bytes on exit.
+    pub coverage_path: Option<std::path::PathBuf>,
+    /// Count memory accesses to show them in the heatmap view.
+    pub heatmap: bool,
+    /// Number of last bus transactions to keep for dumps.
+    pub flight_recorder: Option<usize>,
+    /// Log invalid ROM banks and disabled cartridge RAM accesses.
+    pub bank_diagnostics: bool,
+    pub interrupt_latency: bool,
+    /// Scripted DIV/LY values, see [`crate::rng_script::RngScript::parse`]. Recorded movies keep
+    /// it, played ones bring their own.
+    pub rng_script_path: Option<std::path::PathBuf>,
+    /// IPS/BPS patches applied to the ROM in the given order.
+    pub patch_paths: Vec<std::path::PathBuf>,
+    /// Game Genie and GameShark codes, see [`crate::cheat`].
+    pub cheats: Vec<String>,
+    /// Record the key presses from power-on into a movie, see [`crate::movie`].
+    pub record_movie_path: Option<std::path::PathBuf>,
+    /// Replay a movie from power-on.
+    pub play_movie_path: Option<std::path::PathBuf>,
+    /// Debug views shown next to the game screen in the same window.
+    pub views: Vec<crate::compositor::ViewKind>,
+    pub layout: crate::compositor::Layout,
+    /// Debug views shown in their own windows.
+    pub windows: Vec<crate::compositor::ViewKind>,
+    /// Pause before executing instructions at these addresses.
+    pub break_pcs: Vec<u16>,
+    /// Pause when the next frame reaches this scanline.
+    pub break_line: Option<u8>,
+    /// Remove the DC offset from audio of every output.
+    pub dc_blocker: bool,
+    /// Emulate CPU bus conflicts during OAM DMA.
+    pub dma_conflicts: bool,
+    /// Objects drawn per scanline, 10 on hardware.
+    pub sprite_limit: usize,
+    pub fast_ppu: bool,
+    /// Draw the SGB border around the screen, with `--model sgb`.
+    pub sgb_border: bool,
+    /// Colors of the 4 shades, see [`crate::palette::parse`].
+    pub palette: [u32; 4],
+    /// Where exported frames and ripped tiles are written.
+    pub rip_dir: std::path::PathBuf,
+    /// Save every unique tile seen in VRAM during the session.
+    pub rip_tiles: bool,
+    /// Address to wait on for another emulator to connect the link cable.
+    pub link_listen: Option<String>,
+    /// Address of another emulator waiting with `--link-listen`.
+    pub link_connect: Option<String>,
+    /// Plug a Game Boy Printer into the serial port, saving prints to `rip_dir`.
+    pub printer: bool,
+    /// Run two instances of the game side by side in the game window.
+    pub split_screen: bool,
+    /// Run the emulator on the GUI thread between window updates instead of its own thread.
+    pub single_thread: bool,
+    /// Refresh rate of the display to present frames at, instead of the fixed 16 ms limiter.
+    pub vsync_hz: Option<f64>,
+    /// Speed of the limiter.
+    pub speed: crate::clock::Speed,
+    /// Pace emulation by the audio device consuming samples instead of the limiter.
+    pub audio_sync: bool,
+    /// Settings file reloaded while running, see [`crate::settings`].
+    pub config_path: Option<std::path::PathBuf>,
+    /// WAV file to record the audio to.
+    pub wav_path: Option<std::path::PathBuf>,
+    /// Initial size of the game window in multiples of the screen.
+    pub scale: usize,
+    /// How often changed battery-backed RAM is written to the `.sav` file, None to write it only
+    /// on exit.
+    pub autosave: Option<std::time::Duration>,
 }
 
 pub fn parse_args() -> Result<Args, lexopt::Error> {
     use lexopt::prelude::*;
 
     let mut rom_path = None;
+    let mut demo = false;
+    let mut model = crate::model::Model::Dmg;
+    let mut boot_rom_path = None;
+    let mut coverage_path = None;
+    let mut heatmap = false;
+    let mut flight_recorder = None;
+    let mut bank_diagnostics = false;
+    let mut interrupt_latency = false;
+    let mut rng_script_path = None;
+    let mut patch_paths = vec![];
+    let mut cheats = vec![];
+    let mut record_movie_path = None;
+    let mut play_movie_path = None;
+    let mut views = vec![];
+    let mut layout = crate::compositor::Layout::Row;
+    let mut windows = vec![];
+    let mut break_pcs = vec![];
+    let mut break_line = None;
+    let mut dc_blocker = true;
+    let mut dma_conflicts = false;
+    let mut sprite_limit = crate::gpu::MAX_OBJS_PER_SCANLINE;
+    let mut fast_ppu = false;
+    let mut sgb_border = false;
+    let mut palette = crate::palette::GRAY;
+    let mut rip_dir = std::path::PathBuf::from(".");
+    let mut rip_tiles = false;
+    let mut link_listen = None;
+    let mut link_connect = None;
+    let mut single_thread = false;
+    let mut printer = false;
+    let mut split_screen = false;
+    let mut vsync_hz = None;
+    let mut speed = crate::clock::Speed::Times(1.0);
+    let mut audio_sync = false;
+    let mut config_path = None;
+    let mut wav_path = None;
+    let mut scale = 4;
+    let mut autosave = Some(std::time::Duration::from_secs(10));
     let mut parser = lexopt::Parser::from_env();
 
     while let Some(arg) = parser.next()? {
@@ -14,15 +127,161 @@ pub fn parse_args() -> Result<Args, lexopt::Error> {
                 assert!(rom_path.is_none());
                 rom_path = Some(path.parse()?);
             }
+            Long("demo") => demo = true,
+            Long("model") => model = parser.value()?.parse()?,
+            Long("bootrom") => boot_rom_path = Some(parser.value()?.parse()?),
+            Long("coverage") => {
+                coverage_path = Some(parser.value()?.parse()?);
+            }
+            Long("heatmap") => heatmap = true,
+            Long("flight-recorder") => flight_recorder = Some(parser.value()?.parse()?),
+            Long("bank-diagnostics") => bank_diagnostics = true,
+            Long("interrupt-latency") => interrupt_latency = true,
+            Long("rng-script") => {
+                rng_script_path = Some(parser.value()?.parse()?);
+            }
+            Long("cheat") => cheats.push(parser.value()?.string()?),
+            Long("record-movie") => record_movie_path = Some(parser.value()?.parse()?),
+            Long("play-movie") => play_movie_path = Some(parser.value()?.parse()?),
+            Long("patch") => {
+                patch_paths.push(parser.value()?.parse()?);
+            }
+            Long("views") => {
+                views = parser
+                    .value()?
+                    .parse_with(|s| s.split(',').map(str::parse).collect::<Result<Vec<_>, _>>())?;
+            }
+            Long("layout") => layout = parser.value()?.parse()?,
+            Long("windows") => {
+                windows = parser
+                    .value()?
+                    .parse_with(|s| s.split(',').map(str::parse).collect::<Result<Vec<_>, _>>())?;
+            }
+            Long("break-pc") => {
+                break_pcs.push(
+                    parser
+                        .value()?
+                        .parse_with(|s| u16::from_str_radix(s.trim_start_matches("0x"), 16))?,
+                );
+            }
+            Long("break-line") => break_line = Some(parser.value()?.parse()?),
+            Long("no-dc-blocker") => dc_blocker = false,
+            Long("dma-conflicts") => dma_conflicts = true,
+            Long("sprite-limit") => sprite_limit = parser.value()?.parse()?,
+            Long("fast-ppu") => fast_ppu = true,
+            Long("sgb-border") => sgb_border = true,
+            Long("palette") => palette = parser.value()?.parse_with(crate::palette::parse)?,
+            Long("rip-dir") => rip_dir = parser.value()?.parse()?,
+            Long("rip-tiles") => rip_tiles = true,
+            Long("link-listen") => link_listen = Some(parser.value()?.parse()?),
+            Long("link-connect") => link_connect = Some(parser.value()?.parse()?),
+            Long("printer") => printer = true,
+            Long("single-thread") => single_thread = true,
+            Long("split-screen") => split_screen = true,
+            Long("vsync") => vsync_hz = Some(parser.value()?.parse()?),
+            Long("speed") => speed = parser.value()?.parse()?,
+            Long("audio-sync") => audio_sync = true,
+            Long("config") => config_path = Some(parser.value()?.parse()?),
+            Long("record-wav") => wav_path = Some(parser.value()?.parse()?),
+            Long("scale") => scale = parser.value()?.parse()?,
+            Long("autosave") => {
+                let secs: u64 = parser.value()?.parse()?;
+                autosave = (secs > 0).then(|| std::time::Duration::from_secs(secs));
+            }
+            Long("selftest") => {
+                let passed = crate::selftest::run_and_print();
+                std::process::exit(if passed { 0 } else { 1 });
+            }
             Long("help") => {
-                println!("Usage: gbemu ROM_PATH");
+                println!(
+                    "Usage: gbemu [--model dmg|mgb|sgb] [--sgb-border] [--bootrom FILE] [--coverage FILE] \
+                    [--heatmap] [--flight-recorder N] [--bank-diagnostics] [--interrupt-latency] \
+                    [--rng-script FILE] [--patch FILE]... [--cheat CODE]... \
+                    [--record-movie FILE | --play-movie FILE] [--views tiles,bg-map,oam,apu-scope] \
+                    [--layout row|column|grid=N] [--windows tiles,bg-map,oam,apu-scope] \
+                    [--break-pc HEX_ADDR]... [--break-line LY] \
+                    [--no-dc-blocker] [--dma-conflicts] [--sprite-limit N] [--fast-ppu] \
+                    [--palette gray|green|contrast|\"RGB RGB RGB RGB\"] \
+                    [--rip-dir DIR] [--rip-tiles] [--link-listen ADDR | --link-connect ADDR | --printer] \
+                    [--split-screen | --single-thread] [--vsync HZ | --speed N|max | --audio-sync] [--config FILE] \
+                    [--record-wav FILE] [--scale N] [--autosave SECS] ROM_PATH | --demo\n       \
+                    gbemu --selftest"
+                );
                 std::process::exit(0);
             }
             _ => return Err(arg.unexpected()),
         }
     }
 
+    if vsync_hz.is_some() && speed != crate::clock::Speed::Times(1.0) {
+        return Err("--speed needs the default limiter, not --vsync".into());
+    }
+    if audio_sync && (vsync_hz.is_some() || speed != crate::clock::Speed::Times(1.0)) {
+        return Err("--audio-sync can't be combined with --vsync or --speed".into());
+    }
+    if record_movie_path.is_some() && play_movie_path.is_some() {
+        return Err("--record-movie can't be combined with --play-movie".into());
+    }
+    if printer && (link_listen.is_some() || link_connect.is_some()) {
+        return Err("--printer can't be combined with the link cable".into());
+    }
+    if single_thread && split_screen {
+        return Err("--single-thread can't be combined with --split-screen".into());
+    }
+    if sgb_border && model != crate::model::Model::Sgb {
+        return Err("--sgb-border needs --model sgb".into());
+    }
+    if sgb_border && (!views.is_empty() || split_screen) {
+        return Err("--sgb-border can't be combined with --views or --split-screen".into());
+    }
+    if scale == 0 {
+        return Err("--scale must be at least 1".into());
+    }
+
     Ok(Args {
-        rom_path: rom_path.ok_or("missing argument ROM_PATH")?,
+        rom_path: match (rom_path, demo) {
+            (Some(_), true) => return Err("--demo doesn't take a ROM_PATH".into()),
+            (Some(path), false) => path,
+            (None, true) => "demo.gb".into(),
+            (None, false) => return Err("missing argument ROM_PATH".into()),
+        },
+        demo,
+        model,
+        boot_rom_path,
+        coverage_path,
+        heatmap,
+        flight_recorder,
+        bank_diagnostics,
+        interrupt_latency,
+        rng_script_path,
+        patch_paths,
+        cheats,
+        record_movie_path,
+        play_movie_path,
+        views,
+        layout,
+        windows,
+        break_pcs,
+        break_line,
+        dc_blocker,
+        dma_conflicts,
+        sprite_limit,
+        fast_ppu,
+        sgb_border,
+        palette,
+        rip_dir,
+        rip_tiles,
+        link_listen,
+        link_connect,
+        printer,
+        split_screen,
+        single_thread,
+        vsync_hz,
+        speed,
+        audio_sync,
+        config_path,
+        wav_path,
+        scale,
+        autosave,
     })
 }
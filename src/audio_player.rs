@@ -1,5 +1,30 @@
 pub trait AudioPlayer: Send {
     fn play(&mut self, buff: crate::AudioBuff);
+
+    /// Rate the sink outputs samples at. Filters in front of the sink are tuned for it.
+    fn sample_rate(&self) -> u32 {
+        crate::SAMPLE_RATE as u32
+    }
+
+    /// Whether the sink keeps up with the emulation. While it doesn't, e.g. during fast-forward,
+    /// samples aren't mixed and their buffers are dropped.
+    fn can_consume(&self) -> bool {
+        true
+    }
+}
+
+impl AudioPlayer for Box<dyn AudioPlayer> {
+    fn play(&mut self, buff: crate::AudioBuff) {
+        self.as_mut().play(buff);
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.as_ref().sample_rate()
+    }
+
+    fn can_consume(&self) -> bool {
+        self.as_ref().can_consume()
+    }
 }
 
 pub struct VoidAudioPlayer {}
@@ -14,18 +39,786 @@ impl AudioPlayer for VoidAudioPlayer {
     fn play(&mut self, _buff: crate::AudioBuff) {}
 }
 
+/// Bounded queue of stereo samples from the emulation thread to the audio callback.
+#[derive(Clone)]
+pub struct AudioRing {
+    samples: std::sync::Arc<std::sync::Mutex<std::collections::VecDeque<(f32, f32)>>>,
+    /// Notified when the callback takes samples.
+    taken: std::sync::Arc<std::sync::Condvar>,
+    capacity: usize,
+}
+
+impl AudioRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: std::sync::Arc::new(std::sync::Mutex::new(
+                std::collections::VecDeque::with_capacity(capacity),
+            )),
+            taken: std::sync::Arc::new(std::sync::Condvar::new()),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queues the samples of `buff`. The oldest ones are dropped when it's full.
+    pub fn push(&self, buff: &crate::AudioBuff) {
+        let mut samples = self.samples.lock().unwrap();
+        samples.extend(buff.0.iter().copied().zip(buff.1.iter().copied()));
+        let overflow = samples.len().saturating_sub(self.capacity);
+        samples.drain(..overflow);
+    }
+
+    /// Fills `out` with the oldest samples.
+    ///
+    /// # Returns
+    ///
+    /// How many samples were queued, the rest of `out` is left untouched.
+    pub fn pop_into(&self, out: &mut [(f32, f32)]) -> usize {
+        let mut samples = self.samples.lock().unwrap();
+        let len = out.len().min(samples.len());
+        for (out, sample) in out.iter_mut().zip(samples.drain(..len)) {
+            *out = sample;
+        }
+        self.taken.notify_all();
+        len
+    }
+
+    /// Blocks until `len` more samples fit, or `timeout` passes, e.g. when the device stopped.
+    pub fn wait_for_room(&self, len: usize, timeout: std::time::Duration) {
+        let samples = self.samples.lock().unwrap();
+        let _ = self
+            .taken
+            .wait_timeout_while(samples, timeout, |samples| {
+                samples.len() + len > self.capacity
+            })
+            .unwrap();
+    }
+}
+
 pub struct CpalAudioPlayer {
-    sender: std::sync::mpsc::Sender<crate::AudioBuff>,
+    ring: AudioRing,
+    /// Rate of the output device.
+    sample_rate: u32,
 }
 
 impl CpalAudioPlayer {
-    pub fn new(sender: std::sync::mpsc::Sender<crate::AudioBuff>) -> Self {
-        Self { sender }
+    /// More queued buffers only add latency.
+    pub const MAX_QUEUED: usize = 4;
+
+    /// Plays through `ring`, which the audio callback empties.
+    pub fn new(ring: AudioRing, sample_rate: u32) -> Self {
+        Self { ring, sample_rate }
     }
 }
 
 impl AudioPlayer for CpalAudioPlayer {
     fn play(&mut self, buff: crate::AudioBuff) {
-        let _ = self.sender.send((buff.0, buff.1));
+        self.ring.push(&buff);
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn can_consume(&self) -> bool {
+        self.ring.len() + crate::AUDIO_BUF_LEN <= self.ring.capacity()
+    }
+}
+
+/// First-order high-pass filter that removes the DC offset of the mixed channels (the DACs output
+/// only positive values) without cutting audible frequencies.
+/// https://www.dsprelated.com/freebooks/filters/DC_Blocker.html
+pub struct DcBlocker {
+    /// Pole of the filter: the closer to 1, the lower the cutoff frequency.
+    pole: f32,
+    last_input: (f32, f32),
+    last_output: (f32, f32),
+}
+
+impl DcBlocker {
+    const CUTOFF_HZ: f32 = 20.0;
+
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            pole: (-2.0 * std::f32::consts::PI * Self::CUTOFF_HZ / sample_rate as f32).exp(),
+            last_input: (0.0, 0.0),
+            last_output: (0.0, 0.0),
+        }
+    }
+
+    pub fn process(&mut self, buff: &mut crate::AudioBuff) {
+        for (left, right) in buff.0.iter_mut().zip(buff.1.iter_mut()) {
+            let input = (*left, *right);
+            self.last_output = (
+                input.0 - self.last_input.0 + self.pole * self.last_output.0,
+                input.1 - self.last_input.1 + self.pole * self.last_output.1,
+            );
+            self.last_input = input;
+            (*left, *right) = self.last_output;
+        }
+    }
+}
+
+/// Sink with its own DC blocker tuned for the sink's sample rate, so sinks with different rates
+/// can be fed from the same buffers.
+pub struct DcBlockedAudioPlayer<P> {
+    player: P,
+    dc_blocker: DcBlocker,
+}
+
+impl<P: AudioPlayer> DcBlockedAudioPlayer<P> {
+    pub fn new(player: P) -> Self {
+        Self {
+            dc_blocker: DcBlocker::new(player.sample_rate()),
+            player,
+        }
+    }
+}
+
+impl<P: AudioPlayer> AudioPlayer for DcBlockedAudioPlayer<P> {
+    fn play(&mut self, mut buff: crate::AudioBuff) {
+        self.dc_blocker.process(&mut buff);
+        self.player.play(buff);
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.player.sample_rate()
+    }
+
+    fn can_consume(&self) -> bool {
+        self.player.can_consume()
+    }
+}
+
+/// Output settings that the frontend may change while playing, e.g. from a reloaded config.
+#[derive(Clone)]
+pub struct OutputControl {
+    volume: std::sync::Arc<std::sync::atomic::AtomicU32>,
+    dc_blocker: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl OutputControl {
+    pub fn new(volume: f32, dc_blocker: bool) -> Self {
+        Self {
+            volume: std::sync::Arc::new(volume.to_bits().into()),
+            dc_blocker: std::sync::Arc::new(dc_blocker.into()),
+        }
+    }
+
+    pub fn set(&self, volume: f32, dc_blocker: bool) {
+        use std::sync::atomic::Ordering::Relaxed;
+        self.volume.store(volume.to_bits(), Relaxed);
+        self.dc_blocker.store(dc_blocker, Relaxed);
+    }
+
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(std::sync::atomic::Ordering::Relaxed))
+    }
+
+    pub fn dc_blocker(&self) -> bool {
+        self.dc_blocker.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+/// Applies the [`OutputControl`] settings of the moment to every buffer.
+pub struct ControlledAudioPlayer<P> {
+    player: P,
+    control: OutputControl,
+    dc_blocker: DcBlocker,
+}
+
+impl<P: AudioPlayer> ControlledAudioPlayer<P> {
+    pub fn new(player: P, control: OutputControl) -> Self {
+        Self {
+            dc_blocker: DcBlocker::new(player.sample_rate()),
+            player,
+            control,
+        }
+    }
+}
+
+impl<P: AudioPlayer> AudioPlayer for ControlledAudioPlayer<P> {
+    fn play(&mut self, mut buff: crate::AudioBuff) {
+        if self.control.dc_blocker() {
+            self.dc_blocker.process(&mut buff);
+        }
+        let volume = self.control.volume();
+        if volume != 1.0 {
+            for sample in buff.0.iter_mut().chain(&mut buff.1) {
+                *sample *= volume;
+            }
+        }
+        self.player.play(buff);
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.player.sample_rate()
+    }
+
+    fn can_consume(&self) -> bool {
+        self.player.can_consume()
+    }
+}
+
+/// Sends every buffer to all sinks, e.g. to play and dump audio at the same time.
+pub struct SplitAudioPlayer {
+    players: Vec<Box<dyn AudioPlayer>>,
+}
+
+impl SplitAudioPlayer {
+    pub fn new(players: Vec<Box<dyn AudioPlayer>>) -> Self {
+        Self { players }
+    }
+}
+
+impl AudioPlayer for SplitAudioPlayer {
+    fn play(&mut self, buff: crate::AudioBuff) {
+        for player in &mut self.players {
+            player.play(buff);
+        }
+    }
+
+    fn can_consume(&self) -> bool {
+        self.players.iter().any(|player| player.can_consume())
+    }
+}
+
+const WAV_HEADER_SIZE: usize = 44;
+
+/// Plays through `player` and records the samples to a 16-bit stereo WAV file.
+/// http://soundfile.sapp.org/doc/WaveFormat/
+pub struct WavAudioPlayer<P, W: std::io::Write + std::io::Seek> {
+    player: P,
+    /// `None` after a write error, the recording stops there.
+    out: Option<W>,
+    sample_rate: u32,
+    data_bytes: u32,
+}
+
+impl<P: AudioPlayer> WavAudioPlayer<P, std::io::BufWriter<std::fs::File>> {
+    pub fn create(player: P, path: &std::path::Path) -> std::io::Result<Self> {
+        let out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        Self::new(player, out)
+    }
+}
+
+impl<P: AudioPlayer, W: std::io::Write + std::io::Seek> WavAudioPlayer<P, W> {
+    /// Writes the header, its sizes are filled in when the player is dropped.
+    pub fn new(player: P, mut out: W) -> std::io::Result<Self> {
+        let sample_rate = player.sample_rate();
+        out.write_all(&Self::header(sample_rate, 0))?;
+        Ok(Self {
+            player,
+            out: Some(out),
+            sample_rate,
+            data_bytes: 0,
+        })
+    }
+}
+
+impl<P, W: std::io::Write + std::io::Seek> WavAudioPlayer<P, W> {
+    fn header(sample_rate: u32, data_bytes: u32) -> [u8; WAV_HEADER_SIZE] {
+        const CHANNELS: u16 = 2;
+        const BITS_PER_SAMPLE: u16 = 16;
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+
+        let mut header = [0; WAV_HEADER_SIZE];
+        let fields: [&[u8]; 13] = [
+            b"RIFF",
+            &(WAV_HEADER_SIZE as u32 - 8 + data_bytes).to_le_bytes(),
+            b"WAVE",
+            b"fmt ",
+            &16u32.to_le_bytes(),
+            // PCM
+            &1u16.to_le_bytes(),
+            &CHANNELS.to_le_bytes(),
+            &sample_rate.to_le_bytes(),
+            &(sample_rate * block_align as u32).to_le_bytes(),
+            &block_align.to_le_bytes(),
+            &BITS_PER_SAMPLE.to_le_bytes(),
+            b"data",
+            &data_bytes.to_le_bytes(),
+        ];
+        let mut pos = 0;
+        for field in fields {
+            header[pos..pos + field.len()].copy_from_slice(field);
+            pos += field.len();
+        }
+        header
+    }
+
+    fn record(&mut self, buff: &crate::AudioBuff) -> std::io::Result<()> {
+        let Some(out) = &mut self.out else {
+            return Ok(());
+        };
+        let mut bytes = Vec::with_capacity(buff.0.len() * 4);
+        for (&left, &right) in buff.0.iter().zip(&buff.1) {
+            for sample in [left, right] {
+                let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                bytes.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+        out.write_all(&bytes)?;
+        self.data_bytes = self.data_bytes.saturating_add(bytes.len() as u32);
+        Ok(())
+    }
+
+    /// Fills in the sizes of the header.
+    fn finish(&mut self) -> std::io::Result<()> {
+        let Some(out) = &mut self.out else {
+            return Ok(());
+        };
+        out.seek(std::io::SeekFrom::Start(0))?;
+        out.write_all(&Self::header(self.sample_rate, self.data_bytes))?;
+        out.flush()
+    }
+}
+
+impl<P: AudioPlayer, W: std::io::Write + std::io::Seek + Send> AudioPlayer
+    for WavAudioPlayer<P, W>
+{
+    fn play(&mut self, buff: crate::AudioBuff) {
+        if let Err(err) = self.record(&buff) {
+            eprintln!("Can't record audio: {err}");
+            self.out = None;
+        }
+        self.player.play(buff);
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.player.sample_rate()
+    }
+
+    fn can_consume(&self) -> bool {
+        self.player.can_consume()
+    }
+}
+
+impl<P, W: std::io::Write + std::io::Seek> Drop for WavAudioPlayer<P, W> {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            eprintln!("Can't finish the audio recording: {err}");
+        }
+    }
+}
+
+/// Stretch factor of a [`StretchedAudioPlayer`] (output duration / input duration) shared with
+/// the code that measures the drift.
+#[derive(Clone)]
+pub struct StretchRatio(std::sync::Arc<std::sync::atomic::AtomicU32>);
+
+impl StretchRatio {
+    /// Larger changes of pitch are audible.
+    pub const MAX_DEVIATION: f32 = 0.005;
+
+    pub fn new() -> Self {
+        Self(std::sync::Arc::new(1.0f32.to_bits().into()))
+    }
+
+    /// Sets the ratio clamped to [`Self::MAX_DEVIATION`].
+    ///
+    /// # Returns
+    ///
+    /// Whether the ratio was in range.
+    pub fn set(&self, ratio: f32) -> bool {
+        let clamped = ratio.clamp(1.0 - Self::MAX_DEVIATION, 1.0 + Self::MAX_DEVIATION);
+        self.0
+            .store(clamped.to_bits(), std::sync::atomic::Ordering::Relaxed);
+        clamped == ratio
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(std::sync::atomic::Ordering::Relaxed))
+    }
+}
+
+/// Linear interpolation of the input buffers at another rate, cut into output buffers.
+struct LinearResampler {
+    /// Position of the next output sample relative to the next input buffer, -1 is `last`.
+    pos: f64,
+    /// The last sample of the previous input buffer.
+    last: (f32, f32),
+    /// Output samples not sent yet.
+    pending: (Vec<f32>, Vec<f32>),
+}
+
+impl LinearResampler {
+    fn new() -> Self {
+        Self {
+            pos: 0.0,
+            last: (0.0, 0.0),
+            pending: (vec![], vec![]),
+        }
+    }
+
+    /// Interpolates `buff` every `step` input samples and plays the output buffers filled.
+    fn process(&mut self, buff: crate::AudioBuff, step: f64, player: &mut impl AudioPlayer) {
+        let sample = |idx: isize| {
+            if idx < 0 {
+                self.last
+            } else {
+                (buff.0[idx as usize], buff.1[idx as usize])
+            }
+        };
+
+        let end = (crate::AUDIO_BUF_LEN - 1) as f64;
+        while self.pos < end {
+            let idx = self.pos.floor();
+            let frac = (self.pos - idx) as f32;
+            let (a, b) = (sample(idx as isize), sample(idx as isize + 1));
+            self.pending.0.push(a.0 + (b.0 - a.0) * frac);
+            self.pending.1.push(a.1 + (b.1 - a.1) * frac);
+            self.pos += step;
+        }
+        self.pos -= crate::AUDIO_BUF_LEN as f64;
+        self.last = sample(crate::AUDIO_BUF_LEN as isize - 1);
+
+        while self.pending.0.len() >= crate::AUDIO_BUF_LEN {
+            let mut out = ([0.0; crate::AUDIO_BUF_LEN], [0.0; crate::AUDIO_BUF_LEN]);
+            for (pending, out) in [
+                (&mut self.pending.0, &mut out.0),
+                (&mut self.pending.1, &mut out.1),
+            ] {
+                out.copy_from_slice(&pending[..crate::AUDIO_BUF_LEN]);
+                pending.drain(..crate::AUDIO_BUF_LEN);
+            }
+            player.play(out);
+        }
+    }
+}
+
+/// Resampling stage which time-stretches audio by a slowly changing ratio, e.g. to keep audio
+/// aligned with emulation paced by the display instead of real time. Uses linear interpolation,
+/// which is inaudible for such small ratios.
+pub struct StretchedAudioPlayer<P> {
+    player: P,
+    ratio: StretchRatio,
+    resampler: LinearResampler,
+}
+
+impl<P: AudioPlayer> StretchedAudioPlayer<P> {
+    pub fn new(player: P, ratio: StretchRatio) -> Self {
+        Self {
+            player,
+            ratio,
+            resampler: LinearResampler::new(),
+        }
+    }
+}
+
+impl<P: AudioPlayer> AudioPlayer for StretchedAudioPlayer<P> {
+    fn play(&mut self, buff: crate::AudioBuff) {
+        let step = 1.0 / self.ratio.get() as f64;
+        self.resampler.process(buff, step, &mut self.player);
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.player.sample_rate()
+    }
+
+    fn can_consume(&self) -> bool {
+        self.player.can_consume()
+    }
+}
+
+/// Converts the emulated [`crate::SAMPLE_RATE`] to the rate of `player`, e.g. 48 kHz of the
+/// output device.
+pub struct ResampledAudioPlayer<P> {
+    player: P,
+    /// Input samples per output sample.
+    step: f64,
+    resampler: LinearResampler,
+}
+
+impl<P: AudioPlayer> ResampledAudioPlayer<P> {
+    pub fn new(player: P) -> Self {
+        Self {
+            step: crate::SAMPLE_RATE as f64 / player.sample_rate() as f64,
+            player,
+            resampler: LinearResampler::new(),
+        }
+    }
+}
+
+impl<P: AudioPlayer> AudioPlayer for ResampledAudioPlayer<P> {
+    fn play(&mut self, buff: crate::AudioBuff) {
+        self.resampler.process(buff, self.step, &mut self.player);
+    }
+
+    fn sample_rate(&self) -> u32 {
+        crate::SAMPLE_RATE as u32
+    }
+
+    fn can_consume(&self) -> bool {
+        self.player.can_consume()
+    }
+}
+
+/// Keeps the pitch and the timing of audio emulated at another speed than real time. In slow
+/// motion every buffer is played `1 / speed` times, in fast-forward only one of every `speed`
+/// buffers is played. Buffers are faded in and out to avoid clicks between them.
+pub struct SpeedAudioPlayer<P> {
+    player: P,
+    clock: crate::clock::ScaledClock,
+    /// Buffers received in fast-forward.
+    skipped: usize,
+}
+
+impl<P: AudioPlayer> SpeedAudioPlayer<P> {
+    /// Samples faded at both ends of a repeated buffer.
+    const FADE_LEN: usize = 64;
+
+    /// Reads the speed from `clock`, which paces the emulation.
+    pub fn new(player: P, clock: crate::clock::ScaledClock) -> Self {
+        Self {
+            player,
+            clock,
+            skipped: 0,
+        }
+    }
+}
+
+impl<P: AudioPlayer> AudioPlayer for SpeedAudioPlayer<P> {
+    fn play(&mut self, mut buff: crate::AudioBuff) {
+        let speed = self.clock.speed();
+        let repeats = (1.0 / speed).round().max(1.0) as usize;
+        let every = speed.round().max(1.0) as usize;
+        if every > 1 {
+            self.skipped = (self.skipped + 1) % every;
+            if self.skipped != 0 {
+                return;
+            }
+        }
+        if repeats > 1 || every > 1 {
+            for idx in 0..Self::FADE_LEN {
+                let gain = idx as f32 / Self::FADE_LEN as f32;
+                for channel in [&mut buff.0, &mut buff.1] {
+                    channel[idx] *= gain;
+                    channel[crate::AUDIO_BUF_LEN - 1 - idx] *= gain;
+                }
+            }
+        }
+        for _ in 0..repeats {
+            self.player.play(buff);
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.player.sample_rate()
+    }
+
+    fn can_consume(&self) -> bool {
+        self.player.can_consume()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Capture(u32, std::sync::mpsc::Sender<crate::AudioBuff>);
+
+    impl AudioPlayer for Capture {
+        fn play(&mut self, buff: crate::AudioBuff) {
+            self.1.send(buff).unwrap();
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn dc_blocker_per_sink_rate() {
+        let (fast_snd, fast_rcv) = std::sync::mpsc::channel();
+        let (slow_snd, slow_rcv) = std::sync::mpsc::channel();
+        let mut player = SplitAudioPlayer::new(vec![
+            Box::new(DcBlockedAudioPlayer::new(Capture(48_000, fast_snd))),
+            Box::new(DcBlockedAudioPlayer::new(Capture(8_000, slow_snd))),
+        ]);
+
+        // Constant offset.
+        let buff = ([0.5; crate::AUDIO_BUF_LEN], [-0.5; crate::AUDIO_BUF_LEN]);
+        for _ in 0..20 {
+            player.play(buff);
+        }
+        drop(player);
+
+        let (fast, slow) = (
+            fast_rcv.iter().last().unwrap(),
+            slow_rcv.iter().last().unwrap(),
+        );
+        for (left, right) in [(fast.0, fast.1), (slow.0, slow.1)] {
+            assert!(left.iter().chain(&right).all(|sample| sample.abs() < 0.01));
+        }
+
+        // A step decays slower at a higher rate: the filters are tuned independently.
+        let mut fast = DcBlocker::new(48_000);
+        let mut slow = DcBlocker::new(8_000);
+        let (mut fast_buff, mut slow_buff) = (buff, buff);
+        fast.process(&mut fast_buff);
+        slow.process(&mut slow_buff);
+        assert_eq!(fast_buff.0[0], slow_buff.0[0]);
+        assert!(fast_buff.0[100] > slow_buff.0[100]);
+    }
+
+    #[test]
+    fn ring_is_bounded() {
+        let ring = AudioRing::new(crate::AUDIO_BUF_LEN + 10);
+        let mut buff = ([0.0; crate::AUDIO_BUF_LEN], [0.0; crate::AUDIO_BUF_LEN]);
+        buff.0[crate::AUDIO_BUF_LEN - 5] = 1.0;
+        ring.push(&buff);
+        ring.push(&buff);
+        // The oldest samples are dropped.
+        assert_eq!(ring.len(), ring.capacity());
+        let mut out = [(-1.0, -1.0); 8];
+        assert_eq!(ring.pop_into(&mut out), 8);
+        assert_eq!(out[5], (1.0, 0.0));
+
+        // Times out while full, returns as soon as there's room.
+        let full = std::time::Instant::now();
+        ring.wait_for_room(crate::AUDIO_BUF_LEN, std::time::Duration::from_millis(20));
+        assert!(full.elapsed() >= std::time::Duration::from_millis(20));
+        let consumer = ring.clone();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            consumer.pop_into(&mut [(0.0, 0.0); crate::AUDIO_BUF_LEN]);
+        });
+        ring.wait_for_room(crate::AUDIO_BUF_LEN, std::time::Duration::from_secs(10));
+        assert!(ring.len() <= 10);
+        thread.join().unwrap();
+
+        let mut out = [(-1.0, -1.0); 20];
+        assert_eq!(ring.pop_into(&mut out), 2);
+        assert_eq!(out[2], (-1.0, -1.0));
+    }
+
+    #[test]
+    fn wav_recording() {
+        let (snd, rcv) = std::sync::mpsc::channel();
+        let mut wav = std::io::Cursor::new(vec![]);
+        let mut player = WavAudioPlayer::new(Capture(8_000, snd), &mut wav).unwrap();
+        let mut buff = ([0.0; crate::AUDIO_BUF_LEN], [0.0; crate::AUDIO_BUF_LEN]);
+        (buff.0[0], buff.1[0]) = (1.0, -2.0);
+        player.play(buff);
+        drop(player);
+
+        // Still played.
+        assert_eq!(rcv.iter().count(), 1);
+        let wav = wav.into_inner();
+        let data_bytes = crate::AUDIO_BUF_LEN as u32 * 4;
+        assert_eq!(wav.len(), 44 + data_bytes as usize);
+        assert_eq!(wav[..4], *b"RIFF");
+        assert_eq!(wav[4..8], (36 + data_bytes).to_le_bytes());
+        assert_eq!(wav[24..28], 8_000u32.to_le_bytes());
+        assert_eq!(wav[40..44], data_bytes.to_le_bytes());
+        // Samples are clamped.
+        assert_eq!(wav[44..48], [0xFF, 0x7F, 0x01, 0x80]);
+    }
+
+    #[test]
+    fn stretch_changes_sample_count() {
+        let ramp: crate::AudioBuff = (
+            std::array::from_fn(|idx| idx as f32),
+            std::array::from_fn(|idx| -(idx as f32)),
+        );
+
+        // Interpolation delays the output by one sample.
+        for (ratio, buffers) in [(1.0, 999), (0.995, 995), (1.005, 1004)] {
+            let (snd, rcv) = std::sync::mpsc::channel();
+            let stretch = StretchRatio::new();
+            assert!(stretch.set(ratio));
+            let mut player = StretchedAudioPlayer::new(Capture(44_100, snd), stretch);
+            for _ in 0..1000 {
+                player.play(ramp);
+            }
+            drop(player);
+
+            let output: Vec<_> = rcv.iter().collect();
+            assert_eq!(output.len(), buffers, "ratio {ratio}");
+            if ratio == 1.0 {
+                assert!(output.iter().all(|buff| *buff == ramp));
+            } else {
+                // Interpolated between neighbouring input samples.
+                let first = output[0];
+                assert_eq!(first.0[0], 0.0);
+                assert!((first.0[100] - 100.0 / ratio).abs() < 0.01);
+                assert_eq!(first.1[100], -first.0[100]);
+            }
+        }
+
+        // 44.1 to 48 kHz.
+        let (snd, rcv) = std::sync::mpsc::channel();
+        let mut player = ResampledAudioPlayer::new(Capture(48_000, snd));
+        assert_eq!(player.sample_rate(), 44_100);
+        for _ in 0..441 {
+            player.play(ramp);
+        }
+        drop(player);
+        let output: Vec<_> = rcv.iter().collect();
+        assert_eq!(output.len(), 479);
+        assert!((output[0].0[480] - 441.0).abs() < 0.01);
+
+        let stretch = StretchRatio::new();
+        assert!(!stretch.set(1.1));
+        assert_eq!(stretch.get(), 1.0 + StretchRatio::MAX_DEVIATION);
+    }
+
+    #[test]
+    fn slow_motion_and_fast_forward() {
+        let clock =
+            crate::clock::ScaledClock::new(std::sync::Arc::new(crate::clock::MockClock::new()));
+        let (snd, rcv) = std::sync::mpsc::channel();
+        let mut player = SpeedAudioPlayer::new(Capture(44_100, snd), clock.clone());
+        let buff = ([1.0; crate::AUDIO_BUF_LEN], [1.0; crate::AUDIO_BUF_LEN]);
+
+        player.play(buff);
+        assert_eq!(rcv.try_iter().collect::<Vec<_>>(), [buff]);
+
+        clock.set_speed(0.25);
+        player.play(buff);
+        let output: Vec<_> = rcv.try_iter().collect();
+        assert_eq!(output.len(), 4);
+        assert_eq!(output[0].0[0], 0.0);
+        assert_eq!(output[0].0[crate::AUDIO_BUF_LEN / 2], 1.0);
+        assert_eq!(output[3].1[crate::AUDIO_BUF_LEN - 1], 0.0);
+
+        clock.set_speed(4.0);
+        for _ in 0..8 {
+            player.play(buff);
+        }
+        let output: Vec<_> = rcv.try_iter().collect();
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0].0[0], 0.0);
+    }
+
+    #[test]
+    fn output_control_applies_while_playing() {
+        let (snd, rcv) = std::sync::mpsc::channel();
+        let control = OutputControl::new(0.5, false);
+        let mut player = ControlledAudioPlayer::new(Capture(44_100, snd), control.clone());
+        let buff = ([0.5; crate::AUDIO_BUF_LEN], [-0.5; crate::AUDIO_BUF_LEN]);
+
+        player.play(buff);
+        let out = rcv.try_recv().unwrap();
+        assert_eq!((out.0[100], out.1[100]), (0.25, -0.25));
+
+        control.set(1.0, true);
+        for _ in 0..20 {
+            player.play(buff);
+        }
+        let out = rcv.try_iter().last().unwrap();
+        assert!(out.0.iter().chain(&out.1).all(|sample| sample.abs() < 0.01));
     }
 }
@@ -0,0 +1,205 @@
+// Runs every ROM in a directory headlessly and writes a CSV report:
+// rom,status,frames,frame_crc32,elapsed_ms,speed
+//
+// The emulator is deterministic and no input is pressed, so the final frame checksum of a ROM only
+// changes when emulation changes. Compare reports of two builds to find regressions.
+//
+// With `--watchdog FRAMES`, a ROM spinning in a tight loop without IO writes or interrupts for that
+// many frames is stopped and reported with the `hang` status.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use gbemu::{
+    audio_player::VoidAudioPlayer, cpu::CPU, error::GbError, watchdog::Watchdog, SCREEN_HEIGHT,
+    SCREEN_WIDTH,
+};
+
+const DEFAULT_FRAMES: u64 = 600;
+
+struct Args {
+    rom_dir: PathBuf,
+    frames: u64,
+    output: Option<PathBuf>,
+    watchdog_frames: Option<u64>,
+}
+
+struct RomReport {
+    status: String,
+    /// Emulated frames, less than requested if the watchdog stopped the ROM.
+    frames: u64,
+    frame_crc32: Option<u32>,
+    elapsed: Duration,
+}
+
+fn parse_args() -> Result<Args, lexopt::Error> {
+    use lexopt::prelude::*;
+
+    let mut rom_dir = None;
+    let mut frames = DEFAULT_FRAMES;
+    let mut output = None;
+    let mut watchdog_frames = None;
+    let mut parser = lexopt::Parser::from_env();
+
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Value(path) => {
+                assert!(rom_dir.is_none());
+                rom_dir = Some(path.parse()?);
+            }
+            Long("frames") => frames = parser.value()?.parse()?,
+            Long("output") => output = Some(parser.value()?.parse()?),
+            Long("watchdog") => watchdog_frames = Some(parser.value()?.parse()?),
+            Long("help") => {
+                println!(
+                    "Usage: gbemu-batch [--frames N (default {DEFAULT_FRAMES})] \
+                    [--output FILE.csv] [--watchdog FRAMES] ROM_DIR"
+                );
+                std::process::exit(0);
+            }
+            _ => return Err(arg.unexpected()),
+        }
+    }
+
+    Ok(Args {
+        rom_dir: rom_dir.ok_or("missing argument ROM_DIR")?,
+        frames,
+        output,
+        watchdog_frames,
+    })
+}
+
+fn main() {
+    let args = parse_args().unwrap();
+
+    // Stable order, so reports of different runs can be diffed.
+    let roms = gbemu::list_roms(&args.rom_dir).unwrap();
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(std::fs::File::create(path).unwrap()),
+        None => Box::new(std::io::stdout()),
+    };
+
+    // Panics are reported in the CSV.
+    std::panic::set_hook(Box::new(|_| {}));
+
+    writeln!(out, "rom,status,frames,frame_crc32,elapsed_ms,speed").unwrap();
+    for rom in &roms {
+        let report = run_rom(rom, args.frames, args.watchdog_frames);
+
+        let emulated_secs =
+            (report.frames * gbemu::TICKS_PER_FRAME) as f64 / gbemu::CPU_FREQ as f64;
+        let speed = emulated_secs / report.elapsed.as_secs_f64().max(f64::EPSILON);
+
+        writeln!(
+            out,
+            "{},{},{},{},{},{:.2}",
+            csv_field(&rom.file_name().unwrap().to_string_lossy()),
+            csv_field(&report.status),
+            report.frames,
+            report
+                .frame_crc32
+                .map(|crc| format!("{crc:08X}"))
+                .unwrap_or_default(),
+            report.elapsed.as_millis(),
+            speed
+        )
+        .unwrap();
+
+        eprintln!("{}: {}", rom.display(), report.status);
+    }
+}
+
+fn run_rom(path: &Path, frames: u64, watchdog_frames: Option<u64>) -> RomReport {
+    let start = Instant::now();
+
+    let rom = match gbemu::read_rom(path) {
+        Ok(rom) => rom,
+        Err(err) => {
+            return RomReport {
+                status: format!("error: {err}"),
+                frames,
+                frame_crc32: None,
+                elapsed: start.elapsed(),
+            }
+        }
+    };
+
+    // Same stack size as the GUI emulation thread. A separate thread also contains panics of
+    // unimplemented instructions.
+    let result = std::thread::Builder::new()
+        .stack_size(1024 * 1024 * 10)
+        .spawn(move || -> Result<_, GbError> {
+            let mut cpu = CPU::try_new(rom, Box::new(VoidAudioPlayer::new()))?;
+
+            // Loop address range and the frame where the watchdog tripped.
+            let hang = Arc::new(Mutex::new(None));
+            if let Some(watchdog_frames) = watchdog_frames {
+                let hang = Arc::clone(&hang);
+                let mut watchdog = Watchdog::new(watchdog_frames);
+                let mut cycles = 0;
+                cpu.on_instruction(Box::new(move |step| {
+                    cycles += step.cycles;
+                    let flow = watchdog.observe(step);
+                    if flow.is_break() {
+                        *hang.lock().unwrap() =
+                            Some((watchdog.loop_range(), cycles / gbemu::TICKS_PER_FRAME));
+                    }
+                    flow
+                }));
+            }
+
+            let _ = cpu.run(frames * gbemu::TICKS_PER_FRAME);
+
+            let mut buff = [0u8; SCREEN_HEIGHT * SCREEN_WIDTH * 3];
+            cpu.gpu().to_rgb8(&mut buff);
+            let hang = *hang.lock().unwrap();
+            Ok((gbemu::patch::crc32(&buff), hang))
+        })
+        .unwrap()
+        .join();
+
+    let elapsed = start.elapsed();
+    match result {
+        Ok(Ok((crc, hang))) => RomReport {
+            status: match hang {
+                Some(((start, end), _)) => format!("hang: PC loops in {start:04X}-{end:04X}"),
+                None => "ok".to_string(),
+            },
+            frames: hang.map_or(frames, |(_, frames)| frames),
+            frame_crc32: Some(crc),
+            elapsed,
+        },
+        Ok(Err(err)) => RomReport {
+            status: format!("error: {err}"),
+            frames,
+            frame_crc32: None,
+            elapsed,
+        },
+        Err(payload) => {
+            let msg = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            RomReport {
+                status: format!("panic: {msg}"),
+                frames,
+                frame_crc32: None,
+                elapsed,
+            }
+        }
+    }
+}
+
+fn csv_field(val: &str) -> String {
+    if val.contains([',', '"', '\n']) {
+        format!("\"{}\"", val.replace('"', "\"\""))
+    } else {
+        val.to_string()
+    }
+}
@@ -0,0 +1,199 @@
+// Steps a ROM frame by frame in two accuracy configurations and reports the first frame where the
+// screen or the CPU registers differ:
+//
+//     gbemu-framediff --a default --b dma-conflicts ROM
+//
+// A configuration is a comma-separated list of `model=dmg|mgb|sgb`, `dma-conflicts`, `fast-ppu`
+// and `sprite-limit=N`, or `default`. Exits with code 1 if the configurations diverge, 2 if the ROM
+// can't be loaded.
+
+use std::{ops::ControlFlow, path::PathBuf};
+
+use gbemu::{
+    audio_player::VoidAudioPlayer, cpu::CPU, error::GbError, frame::Frame, model::Model,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+const DEFAULT_FRAMES: u64 = 600;
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Config {
+    model: Model,
+    dma_conflicts: bool,
+    fast_ppu: bool,
+    sprite_limit: Option<usize>,
+}
+
+struct Args {
+    rom_path: PathBuf,
+    frames: u64,
+    a: Config,
+    b: Config,
+}
+
+/// Everything compared after a frame.
+struct Snapshot {
+    frame: Frame,
+    registers: [(&'static str, u16); 5],
+}
+
+impl std::str::FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Config::default();
+        for option in s.split(',') {
+            match option.split_once('=') {
+                _ if option == "default" => (),
+                _ if option == "dma-conflicts" => config.dma_conflicts = true,
+                _ if option == "fast-ppu" => config.fast_ppu = true,
+                Some(("model", model)) => config.model = model.parse()?,
+                Some(("sprite-limit", limit)) => {
+                    config.sprite_limit = Some(limit.parse().map_err(|e| format!("{limit}: {e}"))?)
+                }
+                _ => return Err(format!("unknown option {option}")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn parse_args() -> Result<Args, lexopt::Error> {
+    use lexopt::prelude::*;
+
+    let mut rom_path = None;
+    let mut frames = DEFAULT_FRAMES;
+    let mut a = Config::default();
+    let mut b = Config::default();
+    let mut parser = lexopt::Parser::from_env();
+
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Value(path) => {
+                assert!(rom_path.is_none());
+                rom_path = Some(path.parse()?);
+            }
+            Long("frames") => frames = parser.value()?.parse()?,
+            Long("a") => a = parser.value()?.parse()?,
+            Long("b") => b = parser.value()?.parse()?,
+            Long("help") => {
+                println!(
+                    "Usage: gbemu-framediff [--frames N (default {DEFAULT_FRAMES})] \
+                    [--a CONFIG] [--b CONFIG] ROM_PATH\n\
+                    CONFIG: default or a comma-separated list of model=dmg|mgb|sgb, dma-conflicts, \
+                    fast-ppu, sprite-limit=N"
+                );
+                std::process::exit(0);
+            }
+            _ => return Err(arg.unexpected()),
+        }
+    }
+
+    Ok(Args {
+        rom_path: rom_path.ok_or("missing argument ROM_PATH")?,
+        frames,
+        a,
+        b,
+    })
+}
+
+fn main() {
+    let args = parse_args().unwrap();
+    let rom = gbemu::read_rom(&args.rom_path).unwrap_or_else(|err| exit_unloadable(&err));
+
+    // Same stack size as the GUI emulation thread.
+    let diverged = std::thread::Builder::new()
+        .stack_size(1024 * 1024 * 10)
+        .spawn(move || compare(rom, &args))
+        .unwrap()
+        .join()
+        .unwrap();
+
+    if diverged {
+        std::process::exit(1);
+    }
+}
+
+fn compare(rom: Vec<u8>, args: &Args) -> bool {
+    let mut a = create_cpu(rom.clone(), args.a);
+    let mut b = create_cpu(rom, args.b);
+
+    for _ in 0..args.frames {
+        let (a_snapshot, b_snapshot) = (step_frame(&mut a), step_frame(&mut b));
+        let (a_pixels, b_pixels) = (&a_snapshot.frame.pixels, &b_snapshot.frame.pixels);
+
+        let diff_pixels: Vec<_> = (0..SCREEN_HEIGHT * SCREEN_WIDTH)
+            .filter(|&idx| a_pixels[idx] != b_pixels[idx])
+            .collect();
+        let diff_registers: Vec<_> = a_snapshot
+            .registers
+            .iter()
+            .zip(&b_snapshot.registers)
+            .filter(|(a, b)| a != b)
+            .collect();
+
+        if diff_pixels.is_empty() && diff_registers.is_empty() {
+            continue;
+        }
+
+        println!(
+            "Frame {} ({:.3} s): {:?} and {:?} diverged",
+            a_snapshot.frame.number,
+            a_snapshot.frame.timestamp().as_secs_f64(),
+            args.a,
+            args.b
+        );
+        if let Some(&idx) = diff_pixels.first() {
+            println!(
+                "  {} pixels differ, first at ({}, {}): {:06X} vs {:06X}",
+                diff_pixels.len(),
+                idx % SCREEN_WIDTH,
+                idx / SCREEN_WIDTH,
+                a_pixels[idx],
+                b_pixels[idx]
+            );
+        }
+        for ((name, a), (_, b)) in diff_registers {
+            println!("  {name}: {a:04X} vs {b:04X}");
+        }
+        return true;
+    }
+
+    println!("No differences in {} frames", args.frames);
+    false
+}
+
+fn create_cpu(rom: Vec<u8>, config: Config) -> CPU {
+    let mut cpu = CPU::try_with_model(rom, Box::new(VoidAudioPlayer::new()), config.model)
+        .unwrap_or_else(|err| exit_unloadable(&err));
+    cpu.set_dma_conflicts(config.dma_conflicts);
+    cpu.set_fast_renderer(config.fast_ppu);
+    if let Some(limit) = config.sprite_limit {
+        cpu.set_sprite_limit(limit);
+    }
+    // Stop after every frame.
+    cpu.on_frame(Box::new(|_| ControlFlow::Break(())));
+    cpu
+}
+
+fn exit_unloadable(err: &GbError) -> ! {
+    eprintln!("Can't load the ROM: {err}");
+    std::process::exit(2);
+}
+
+fn step_frame(cpu: &mut CPU) -> Snapshot {
+    let _ = cpu.run(u64::MAX);
+
+    let frame = cpu.screen().clone();
+    let regs = cpu.registers();
+    Snapshot {
+        frame,
+        registers: [
+            ("AF", regs.af()),
+            ("BC", regs.bc()),
+            ("DE", regs.de()),
+            ("HL", regs.hl()),
+            ("PC", cpu.pc()),
+        ],
+    }
+}
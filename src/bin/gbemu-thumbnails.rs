@@ -0,0 +1,102 @@
+// Saves a screenshot of every ROM in a directory after N frames without input, named after the ROM
+// (`Tetris.gb` -> `Tetris.png`), e.g. title screens for the game gallery of a launcher.
+
+use std::path::{Path, PathBuf};
+
+use gbemu::{audio_player::VoidAudioPlayer, cpu::CPU};
+
+const DEFAULT_FRAMES: u64 = 300;
+
+struct Args {
+    rom_dir: PathBuf,
+    frames: u64,
+    output_dir: PathBuf,
+}
+
+fn parse_args() -> Result<Args, lexopt::Error> {
+    use lexopt::prelude::*;
+
+    let mut rom_dir = None;
+    let mut frames = DEFAULT_FRAMES;
+    let mut output_dir = PathBuf::from(".");
+    let mut parser = lexopt::Parser::from_env();
+
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Value(path) => {
+                assert!(rom_dir.is_none());
+                rom_dir = Some(path.parse()?);
+            }
+            Long("frames") => frames = parser.value()?.parse()?,
+            Long("output") => output_dir = parser.value()?.parse()?,
+            Long("help") => {
+                println!(
+                    "Usage: gbemu-thumbnails [--frames N (default {DEFAULT_FRAMES})] \
+                    [--output DIR (default .)] ROM_DIR"
+                );
+                std::process::exit(0);
+            }
+            _ => return Err(arg.unexpected()),
+        }
+    }
+
+    Ok(Args {
+        rom_dir: rom_dir.ok_or("missing argument ROM_DIR")?,
+        frames,
+        output_dir,
+    })
+}
+
+fn main() {
+    let args = parse_args().unwrap();
+    assert!(args.frames > 0, "--frames must be at least 1");
+
+    std::fs::create_dir_all(&args.output_dir).unwrap();
+    // Panics are reported per ROM.
+    std::panic::set_hook(Box::new(|_| {}));
+
+    let mut failed = 0;
+    for rom in gbemu::list_roms(&args.rom_dir).unwrap() {
+        let path = args
+            .output_dir
+            .join(rom.file_stem().unwrap())
+            .with_extension("png");
+        match save_thumbnail(&rom, args.frames, &path) {
+            Ok(()) => eprintln!("{}: {}", rom.display(), path.display()),
+            Err(err) => {
+                eprintln!("{}: {err}", rom.display());
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn save_thumbnail(rom_path: &Path, frames: u64, path: &Path) -> Result<(), String> {
+    let rom = gbemu::read_rom(rom_path).map_err(|err| format!("error: {err}"))?;
+
+    // Same stack size as the GUI emulation thread. A separate thread also contains panics of
+    // unimplemented instructions.
+    let frame = std::thread::Builder::new()
+        .stack_size(1024 * 1024 * 10)
+        .spawn(move || -> Result<_, String> {
+            let mut cpu = CPU::try_new(rom, Box::new(VoidAudioPlayer::new()))
+                .map_err(|err| format!("error: {err}"))?;
+            Ok(cpu.frames().nth(frames as usize - 1).unwrap())
+        })
+        .unwrap()
+        .join()
+        .map_err(|payload| {
+            let msg = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            format!("panic: {msg}")
+        })??;
+
+    gbemu::rip::export_screen(&frame.pixels, &gbemu::palette::GRAY, path)
+        .map_err(|err| format!("error: {err}"))
+}
@@ -0,0 +1,129 @@
+// Cheat codes. Game Genie codes patch bytes read from ROM, GameShark codes write to RAM once per
+// frame, at VBlank.
+//
+// Game Genie: `ABC-DEF` or `ABC-DEF-GHI`. AB is the new value and the address is $(F^F)CDE.
+// GHI is the byte the ROM must have there for the patch to apply, so it only patches the right
+// ROM bank: GI rotated right by 2, XOR $BA. H isn't used.
+// GameShark: `TTVVLLHH` writes $VV to $HHLL. Only type $01 (and $00) is supported, the other
+// types select banks of CGB WRAM.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cheat {
+    /// ROM reads of `addr` return `value`, if the ROM has `compare` there.
+    GameGenie {
+        addr: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// `value` is written to `addr` at every VBlank.
+    GameShark { addr: u16, value: u8 },
+}
+
+impl Cheat {
+    /// Parses a Game Genie or GameShark code, case insensitive.
+    pub fn parse(code: &str) -> Result<Self, String> {
+        let digits = code
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| format!("{code}: not a hex code"))?;
+        let byte = |idx: usize| (digits[idx] << 4) | digits[idx + 1];
+
+        match digits.len() {
+            6 | 9 => {
+                let addr = ((digits[5] as u16 ^ 0xF) << 12)
+                    | ((digits[2] as u16) << 8)
+                    | ((digits[3] as u16) << 4)
+                    | digits[4] as u16;
+                if addr >= 0x8000 {
+                    return Err(format!(
+                        "{code}: Game Genie codes patch ROM, not ${addr:04X}"
+                    ));
+                }
+                let compare = (digits.len() == 9)
+                    .then(|| ((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA);
+                Ok(Self::GameGenie {
+                    addr,
+                    value: byte(0),
+                    compare,
+                })
+            }
+            8 => match byte(0) {
+                0x00 | 0x01 => Ok(Self::GameShark {
+                    addr: u16::from_le_bytes([byte(4), byte(6)]),
+                    value: byte(2),
+                }),
+                kind => Err(format!(
+                    "{code}: GameShark code type {kind:02X} isn't supported"
+                )),
+            },
+            _ => Err(format!(
+                "{code}: expected a Game Genie (ABC-DEF-GHI) or GameShark (01VVLLHH) code"
+            )),
+        }
+    }
+
+    /// `original` read from ROM at `addr`, patched if the code applies to it.
+    pub fn patch_rom(&self, addr: u16, original: u8) -> u8 {
+        match *self {
+            Self::GameGenie {
+                addr: patched,
+                value,
+                compare,
+            } if patched == addr && compare.is_none_or(|compare| compare == original) => value,
+            _ => original,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_codes() {
+        assert_eq!(
+            Cheat::parse("00A-17B-C49"),
+            Ok(Cheat::GameGenie {
+                addr: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8),
+            })
+        );
+        assert_eq!(
+            Cheat::parse("3eA-17f"),
+            Ok(Cheat::GameGenie {
+                addr: 0x0A17,
+                value: 0x3E,
+                compare: None,
+            })
+        );
+        assert_eq!(
+            Cheat::parse("0163C1DA"),
+            Ok(Cheat::GameShark {
+                addr: 0xDAC1,
+                value: 0x63,
+            })
+        );
+
+        for (code, err) in [
+            ("00A-17G", "00A-17G: not a hex code"),
+            ("00A-170", "00A-170: Game Genie codes patch ROM, not $FA17"),
+            (
+                "9163C1DA",
+                "9163C1DA: GameShark code type 91 isn't supported",
+            ),
+        ] {
+            assert_eq!(Cheat::parse(code), Err(err.to_string()));
+        }
+    }
+
+    #[test]
+    fn compare_selects_the_bank() {
+        let cheat = Cheat::parse("00A-17B-C49").unwrap();
+        assert_eq!(cheat.patch_rom(0x4A17, 0xC8), 0x00);
+        assert_eq!(cheat.patch_rom(0x4A17, 0xC9), 0xC9);
+        assert_eq!(cheat.patch_rom(0x4A18, 0xC8), 0xC8);
+    }
+}
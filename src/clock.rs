@@ -0,0 +1,244 @@
+// Host time for features that depend on wall-clock time (frame limiter, RTC, timers), so they stay
+// consistent when emulation speed changes and can be tested without waiting.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub trait Clock: Send + Sync {
+    /// Time since an arbitrary epoch, never decreases.
+    fn now(&self) -> Duration;
+
+    /// Blocks until `duration` of this clock's time passes.
+    fn sleep(&self, duration: Duration);
+}
+
+/// Real time of the host. Browsers have no blocking sleep.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct SystemClock {
+    epoch: Instant,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Time that only passes when it's advanced or slept on, for tests.
+#[derive(Clone)]
+pub struct MockClock(Arc<Mutex<Duration>>);
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Duration::ZERO)))
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.0.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// Time of another clock running `speed` times faster, e.g. 2.0 for turbo and 0.5 for slow
+/// motion. Clones share the speed.
+#[derive(Clone)]
+pub struct ScaledClock {
+    inner: Arc<dyn Clock>,
+    state: Arc<Mutex<Scale>>,
+}
+
+struct Scale {
+    speed: f64,
+    /// Times of both clocks at the last speed change.
+    inner_origin: Duration,
+    origin: Duration,
+}
+
+impl ScaledClock {
+    pub fn new(inner: Arc<dyn Clock>) -> Self {
+        let inner_origin = inner.now();
+        Self {
+            inner,
+            state: Arc::new(Mutex::new(Scale {
+                speed: 1.0,
+                inner_origin,
+                origin: inner_origin,
+            })),
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.state.lock().unwrap().speed
+    }
+
+    /// Changes the speed from now on, time that already passed stays the same.
+    pub fn set_speed(&self, speed: f64) {
+        assert!(speed > 0.0);
+
+        let inner_now = self.inner.now();
+        let mut state = self.state.lock().unwrap();
+        state.origin = state.at(inner_now);
+        state.inner_origin = inner_now;
+        state.speed = speed;
+    }
+}
+
+impl Scale {
+    fn at(&self, inner_now: Duration) -> Duration {
+        self.origin + (inner_now - self.inner_origin).mul_f64(self.speed)
+    }
+}
+
+impl Clock for ScaledClock {
+    fn now(&self) -> Duration {
+        let inner_now = self.inner.now();
+        self.state.lock().unwrap().at(inner_now)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let speed = self.speed();
+        self.inner.sleep(duration.div_f64(speed));
+    }
+}
+
+/// Emulation speed relative to the Game Boy, see `--speed`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Speed {
+    Times(f64),
+    /// As fast as the host can, e.g. for benchmarks.
+    Uncapped,
+}
+
+impl std::str::FromStr for Speed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "max" {
+            return Ok(Speed::Uncapped);
+        }
+        match s.parse::<f64>() {
+            Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(Speed::Times(speed)),
+            _ => Err(format!(
+                "invalid speed {s} (expected a positive number or max)"
+            )),
+        }
+    }
+}
+
+/// Sleeps until the end of every frame of `frame` length. A frame that ends late is made up for by
+/// the next ones, so the average rate stays exact, unless it's so late (e.g. the host was
+/// suspended) that catching up would run a burst of frames.
+pub struct FramePacer<C> {
+    clock: C,
+    frame: Duration,
+    deadline: Duration,
+}
+
+impl<C: Clock> FramePacer<C> {
+    /// Lateness that isn't made up for.
+    const MAX_LAG_FRAMES: u32 = 4;
+
+    pub fn new(clock: C, frame: Duration) -> Self {
+        let deadline = clock.now() + frame;
+        Self {
+            clock,
+            frame,
+            deadline,
+        }
+    }
+
+    pub fn wait(&mut self) {
+        let now = self.clock.now();
+        if now < self.deadline {
+            self.clock.sleep(self.deadline - now);
+        } else if now - self.deadline > self.frame * Self::MAX_LAG_FRAMES {
+            self.deadline = now;
+        }
+        self.deadline += self.frame;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frame_pacer_keeps_the_rate() {
+        let clock = MockClock::new();
+        let frame = Duration::from_millis(16);
+        let mut pacer = FramePacer::new(clock.clone(), frame);
+        let ms = |ms| Duration::from_millis(ms);
+
+        clock.advance(ms(10));
+        pacer.wait();
+        assert_eq!(clock.now(), ms(16));
+
+        // Late by 14 ms, the next frame is shorter.
+        clock.advance(ms(30));
+        pacer.wait();
+        assert_eq!(clock.now(), ms(46));
+        pacer.wait();
+        assert_eq!(clock.now(), ms(48));
+
+        // Too late to catch up.
+        clock.advance(ms(1000));
+        pacer.wait();
+        assert_eq!(clock.now(), ms(1048));
+        pacer.wait();
+        assert_eq!(clock.now(), ms(1064));
+    }
+
+    #[test]
+    fn parse_speed() {
+        assert_eq!("2.5".parse(), Ok(Speed::Times(2.5)));
+        assert_eq!("max".parse(), Ok(Speed::Uncapped));
+        assert!("0".parse::<Speed>().is_err());
+        assert!("fast".parse::<Speed>().is_err());
+    }
+
+    #[test]
+    fn scaled_clock_keeps_passed_time() {
+        let mock = MockClock::new();
+        let scaled = ScaledClock::new(Arc::new(mock.clone()));
+
+        mock.advance(Duration::from_millis(100));
+        assert_eq!(scaled.now(), Duration::from_millis(100));
+
+        scaled.set_speed(0.5);
+        assert_eq!(scaled.now(), Duration::from_millis(100));
+        mock.advance(Duration::from_millis(100));
+        assert_eq!(scaled.now(), Duration::from_millis(150));
+
+        scaled.set_speed(4.0);
+        scaled.sleep(Duration::from_millis(40));
+        assert_eq!(mock.now(), Duration::from_millis(210));
+        assert_eq!(scaled.now(), Duration::from_millis(190));
+    }
+}
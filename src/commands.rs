@@ -0,0 +1,658 @@
+// Emulator commands of the frontend and the key chords that run them, and the keys of the joypad.
+// Both are remapped in the settings file:
+//
+// bind.quick_save = ctrl+s
+// bind.dump_memory = none      # Unbound.
+// joypad.a = z, k              # Any of the keys presses the button.
+
+use minifb::Key;
+
+use crate::{cpu::JoypadKey, input_macro::KEY_NAMES};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Pause,
+    FrameAdvance,
+    SoftReset,
+    DumpBusLog,
+    DumpRegisters,
+    DumpMemory,
+    ExportFrame,
+    Screenshot,
+    ToggleFullscreen,
+    ToggleHeatmap,
+    TogglePpuStats,
+    ToggleFps,
+    ToggleBackground,
+    ToggleWindow,
+    ToggleSprites,
+    ToggleChannel1,
+    ToggleChannel2,
+    ToggleChannel3,
+    ToggleChannel4,
+    RecordMacro,
+    PlayMacro,
+    QuickSave,
+    QuickLoad,
+    RearmBreakpoints,
+    BreakAtLine,
+    SwitchFocus,
+    SlowMotion,
+    FastForward,
+    Rewind,
+    Help,
+}
+
+/// Windows where a chord runs its command.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// The game window and debug windows.
+    AnyWindow,
+    Game,
+}
+
+pub struct CommandInfo {
+    pub command: Command,
+    /// Used in the settings file.
+    pub name: &'static str,
+    pub description: &'static str,
+    pub scope: Scope,
+    pub default: Chord,
+}
+
+pub const COMMAND_COUNT: usize = 30;
+
+/// Every command, in the order of [`Command`].
+#[rustfmt::skip]
+pub const COMMANDS: [CommandInfo; COMMAND_COUNT] = [
+    info(Command::Pause, "pause", "Pause or resume emulation", Scope::AnyWindow, Key::P),
+    info(Command::FrameAdvance, "frame_advance", "Run one frame while paused", Scope::AnyWindow, Key::N),
+    info(Command::SoftReset, "soft_reset", "Reset the game", Scope::AnyWindow, Key::R),
+    info(Command::DumpBusLog, "dump_bus_log", "Print the last bus accesses", Scope::AnyWindow, Key::F),
+    info(Command::DumpRegisters, "dump_registers", "Print CPU and IO registers", Scope::AnyWindow, Key::I),
+    info(Command::DumpMemory, "dump_memory", "Hexdump code, stack and IO", Scope::AnyWindow, Key::M),
+    info(Command::ExportFrame, "export_frame", "Save the screen as a PNG", Scope::AnyWindow, Key::E),
+    info(Command::Screenshot, "screenshot", "Save a screenshot in the current directory", Scope::AnyWindow, Key::F12),
+    info(Command::ToggleFullscreen, "toggle_fullscreen", "Switch the game window to fullscreen and back", Scope::Game, Key::F11),
+    info(Command::ToggleHeatmap, "toggle_heatmap", "Show or hide the memory heatmap", Scope::Game, Key::H),
+    info(Command::TogglePpuStats, "toggle_ppu_stats", "Show or hide PPU statistics", Scope::Game, Key::T),
+    info(Command::ToggleFps, "toggle_fps", "Show or hide the frame rate and speed", Scope::Game, Key::F10),
+    info(Command::ToggleBackground, "toggle_background", "Hide or show the background", Scope::Game, Key::Key1),
+    info(Command::ToggleWindow, "toggle_window", "Hide or show the window", Scope::Game, Key::Key2),
+    info(Command::ToggleSprites, "toggle_sprites", "Hide or show the sprites", Scope::Game, Key::Key3),
+    shifted(info(Command::ToggleChannel1, "toggle_channel_1", "Mute or unmute audio channel 1", Scope::Game, Key::Key1)),
+    shifted(info(Command::ToggleChannel2, "toggle_channel_2", "Mute or unmute audio channel 2", Scope::Game, Key::Key2)),
+    shifted(info(Command::ToggleChannel3, "toggle_channel_3", "Mute or unmute audio channel 3", Scope::Game, Key::Key3)),
+    shifted(info(Command::ToggleChannel4, "toggle_channel_4", "Mute or unmute audio channel 4", Scope::Game, Key::Key4)),
+    info(Command::RecordMacro, "record_macro", "Start or stop recording a macro", Scope::Game, Key::F2),
+    info(Command::PlayMacro, "play_macro", "Replay the macro", Scope::Game, Key::F3),
+    info(Command::QuickSave, "quick_save", "Save the state in memory", Scope::Game, Key::F5),
+    info(Command::QuickLoad, "quick_load", "Load the state from memory", Scope::Game, Key::F9),
+    info(Command::RearmBreakpoints, "rearm_breakpoints", "Re-arm --break-pc breakpoints", Scope::Game, Key::B),
+    info(Command::BreakAtLine, "break_at_line", "Run until the --break-line scanline", Scope::Game, Key::L),
+    info(Command::SwitchFocus, "switch_focus", "Send keys to the other split-screen game", Scope::Game, Key::Backslash),
+    info(Command::SlowMotion, "slow_motion", "Cycle through slow motion speeds", Scope::Game, Key::S),
+    info(Command::FastForward, "fast_forward", "Run at 4x speed while held", Scope::Game, Key::Tab),
+    info(Command::Rewind, "rewind", "Rewind gameplay while held", Scope::Game, Key::Backspace),
+    info(Command::Help, "help", "List the commands and run them", Scope::Game, Key::F1),
+];
+
+const fn info(
+    command: Command,
+    name: &'static str,
+    description: &'static str,
+    scope: Scope,
+    key: Key,
+) -> CommandInfo {
+    CommandInfo {
+        command,
+        name,
+        description,
+        scope,
+        default: Chord::new(key),
+    }
+}
+
+/// `info` with Shift held in the default chord.
+const fn shifted(mut info: CommandInfo) -> CommandInfo {
+    info.default.shift = true;
+    info
+}
+
+/// Most keys (or controller buttons) a joypad button can have.
+pub const KEYS_PER_BUTTON: usize = 4;
+
+/// Inputs of every joypad button, indexed by [`JoypadKey`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ButtonMap<T>([[Option<T>; KEYS_PER_BUTTON]; 8]);
+
+/// Keys of the joypad buttons. Chords without modifiers can't take them.
+pub type Keymap = ButtonMap<Key>;
+/// Controller buttons of the joypad buttons.
+pub type PadMap = ButtonMap<PadButton>;
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new([
+            (JoypadKey::Right, Key::Right),
+            (JoypadKey::Left, Key::Left),
+            (JoypadKey::Up, Key::Up),
+            (JoypadKey::Down, Key::Down),
+            (JoypadKey::A, Key::Z),
+            (JoypadKey::B, Key::X),
+            (JoypadKey::Select, Key::Space),
+            (JoypadKey::Start, Key::Enter),
+        ])
+    }
+}
+
+impl Default for PadMap {
+    /// A and B where they are on Nintendo controllers: the right and the bottom face buttons.
+    fn default() -> Self {
+        Self::new([
+            (JoypadKey::Right, PadButton::DPadRight),
+            (JoypadKey::Left, PadButton::DPadLeft),
+            (JoypadKey::Up, PadButton::DPadUp),
+            (JoypadKey::Down, PadButton::DPadDown),
+            (JoypadKey::A, PadButton::East),
+            (JoypadKey::B, PadButton::South),
+            (JoypadKey::Select, PadButton::Select),
+            (JoypadKey::Start, PadButton::Start),
+        ])
+    }
+}
+
+impl<T: Copy + PartialEq> ButtonMap<T> {
+    /// No inputs for any button.
+    pub const fn unbound() -> Self {
+        Self([[None; KEYS_PER_BUTTON]; 8])
+    }
+
+    fn new(inputs: [(JoypadKey, T); 8]) -> Self {
+        let mut map = Self::unbound();
+        for (button, input) in inputs {
+            map.0[button as usize][0] = Some(input);
+        }
+        map
+    }
+
+    /// Replaces the inputs of `button`, no inputs leave it unbound.
+    pub fn set(&mut self, button: JoypadKey, inputs: &[T]) -> Result<(), String> {
+        if inputs.len() > KEYS_PER_BUTTON {
+            return Err(format!("a button takes at most {KEYS_PER_BUTTON} keys"));
+        }
+        let slots = &mut self.0[button as usize];
+        *slots = [None; KEYS_PER_BUTTON];
+        for (slot, &input) in slots.iter_mut().zip(inputs) {
+            *slot = Some(input);
+        }
+        Ok(())
+    }
+
+    pub fn keys(&self, button: JoypadKey) -> impl Iterator<Item = T> + '_ {
+        self.0[button as usize].iter().flatten().copied()
+    }
+
+    /// Buttons pressed by `input`, an input can press several at once.
+    pub fn buttons(&self, input: T) -> impl Iterator<Item = JoypadKey> + '_ {
+        KEY_NAMES
+            .iter()
+            .zip(&self.0)
+            .filter(move |(_, inputs)| inputs.contains(&Some(input)))
+            .map(|((_, button), _)| *button)
+    }
+
+    pub fn contains(&self, input: T) -> bool {
+        self.0.iter().flatten().any(|&i| i == Some(input))
+    }
+}
+
+/// Controller buttons by their position, like the SDL and gilrs names: `south` is A on Xbox
+/// controllers and B on Nintendo ones.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    RightTrigger,
+    LeftTrigger2,
+    RightTrigger2,
+    Select,
+    Start,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+pub const PAD_BUTTON_NAMES: [(&str, PadButton); 14] = [
+    ("south", PadButton::South),
+    ("east", PadButton::East),
+    ("north", PadButton::North),
+    ("west", PadButton::West),
+    ("l1", PadButton::LeftTrigger),
+    ("r1", PadButton::RightTrigger),
+    ("l2", PadButton::LeftTrigger2),
+    ("r2", PadButton::RightTrigger2),
+    ("select", PadButton::Select),
+    ("start", PadButton::Start),
+    ("dpad_up", PadButton::DPadUp),
+    ("dpad_down", PadButton::DPadDown),
+    ("dpad_left", PadButton::DPadLeft),
+    ("dpad_right", PadButton::DPadRight),
+];
+
+pub fn parse_pad_button(name: &str) -> Result<PadButton, String> {
+    let name = name.to_lowercase();
+    PAD_BUTTON_NAMES
+        .iter()
+        .find(|(button, _)| *button == name)
+        .map(|(_, button)| *button)
+        .ok_or_else(|| format!("unknown controller button {name}"))
+}
+
+/// Keys that chords can use, named by their lowercase `Debug` name without the `Key` prefix of
+/// digits, e.g. `a`, `1`, `f5`, `pagedown`.
+#[rustfmt::skip]
+const KEYS: [Key; 69] = [
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L,
+    Key::M, Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X,
+    Key::Y, Key::Z,
+    Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6, Key::Key7,
+    Key::Key8, Key::Key9,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10,
+    Key::F11, Key::F12,
+    Key::Up, Key::Down, Key::Left, Key::Right, Key::Enter, Key::Space, Key::Tab, Key::Escape,
+    Key::Backspace, Key::Insert, Key::Delete, Key::Home, Key::End, Key::PageUp, Key::PageDown,
+    Key::Minus, Key::Equal, Key::Comma, Key::Period, Key::Slash, Key::Backslash,
+];
+
+/// Parses a key name, see [`KEYS`], case insensitive.
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    let name = name.to_lowercase();
+    KEYS.iter()
+        .copied()
+        .find(|&key| key_name(key) == name)
+        .ok_or_else(|| format!("unknown key {name}"))
+}
+
+fn key_name(key: Key) -> String {
+    let name = format!("{key:?}").to_lowercase();
+    match name.strip_prefix("key") {
+        Some(digit) if !digit.is_empty() => digit.to_string(),
+        _ => name,
+    }
+}
+
+/// A key pressed while holding exactly these modifiers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Chord {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub key: Key,
+}
+
+impl Chord {
+    pub const fn new(key: Key) -> Self {
+        Self {
+            ctrl: false,
+            shift: false,
+            alt: false,
+            key,
+        }
+    }
+
+    /// Parses `ctrl+shift+f5`-like text, case insensitive.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.to_lowercase();
+        let mut parts: Vec<_> = text.split('+').map(str::trim).collect();
+        let key = parse_key(parts.pop().unwrap())?;
+
+        let mut chord = Self::new(key);
+        for modifier in parts {
+            let held = match modifier {
+                "ctrl" => &mut chord.ctrl,
+                "shift" => &mut chord.shift,
+                "alt" => &mut chord.alt,
+                _ => return Err(format!("unknown modifier {modifier}")),
+            };
+            *held = true;
+        }
+        Ok(chord)
+    }
+
+    fn has_modifiers(&self) -> bool {
+        self.ctrl || self.shift || self.alt
+    }
+}
+
+impl std::fmt::Display for Chord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "ctrl+"),
+            (self.shift, "shift+"),
+            (self.alt, "alt+"),
+        ] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+        f.write_str(&key_name(self.key))
+    }
+}
+
+/// Chords of every command, indexed by [`Command`]. `None` leaves the command unbound.
+pub type Bindings = [Option<Chord>; COMMAND_COUNT];
+
+pub fn default_bindings() -> Bindings {
+    COMMANDS.map(|info| Some(info.default))
+}
+
+pub fn find_command(name: &str) -> Option<Command> {
+    COMMANDS
+        .iter()
+        .find(|info| info.name == name)
+        .map(|info| info.command)
+}
+
+/// Reports chords bound to two commands that can run in the same window, and chords that take the
+/// keys of the joypad or Escape.
+pub fn check_conflicts(bindings: &Bindings, keymap: &Keymap) -> Result<(), String> {
+    for (idx, chord) in bindings.iter().enumerate() {
+        let Some(chord) = chord else {
+            continue;
+        };
+        let name = COMMANDS[idx].name;
+        if !chord.has_modifiers() && (keymap.contains(chord.key) || chord.key == Key::Escape) {
+            return Err(format!("{chord} of {name} is reserved for the joypad"));
+        }
+        // Game commands and commands of every window both run in the game window.
+        if let Some(other) = bindings[idx + 1..].iter().position(|c| c == &Some(*chord)) {
+            let other = COMMANDS[idx + 1 + other].name;
+            return Err(format!("{chord} is bound to both {name} and {other}"));
+        }
+    }
+    Ok(())
+}
+
+/// # Returns
+///
+/// The command of `chord` in a window of `scope`.
+pub fn lookup(bindings: &Bindings, chord: Chord, scope: Scope) -> Option<Command> {
+    COMMANDS
+        .iter()
+        .zip(bindings)
+        .find(|(info, bound)| {
+            **bound == Some(chord) && (info.scope == Scope::AnyWindow || scope == Scope::Game)
+        })
+        .map(|(info, _)| info.command)
+}
+
+/// # Returns
+///
+/// How well `query` matches `text` as a subsequence, lower is better: the fewest characters
+/// skipped between the first and the last match.
+fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let text: Vec<_> = text.to_lowercase().chars().collect();
+    let query: Vec<_> = query.chars().collect();
+    let Some(&first) = query.first() else {
+        return Some(0);
+    };
+
+    (0..text.len())
+        .filter(|&start| text[start] == first)
+        .filter_map(|start| {
+            let mut end = start;
+            for &c in &query[1..] {
+                end += 1 + text[end + 1..].iter().position(|&text_c| text_c == c)?;
+            }
+            Some(end - start + 1 - query.len())
+        })
+        .min()
+}
+
+pub enum PaletteAction {
+    /// The query or the selection changed.
+    Edit,
+    Close,
+    Run(Command),
+}
+
+/// The help overlay: every command with its chord, filtered by a typed query, to read the bindings
+/// and run commands without them.
+#[derive(Clone, Debug, Default)]
+pub struct CommandPalette {
+    /// Lowercase letters and digits.
+    query: String,
+    /// Index in [`CommandPalette::matches`].
+    selected: usize,
+}
+
+impl CommandPalette {
+    /// Updates the query or the selection, Enter runs the selected command and Escape closes.
+    pub fn type_key(&mut self, key: Key) -> PaletteAction {
+        let count = self.matches().len();
+        match key {
+            Key::Escape => return PaletteAction::Close,
+            Key::Enter => {
+                return self
+                    .selected()
+                    .map_or(PaletteAction::Close, PaletteAction::Run)
+            }
+            Key::Backspace => {
+                self.query.pop();
+                self.selected = 0;
+            }
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => self.selected = (self.selected + 1).min(count.saturating_sub(1)),
+            _ => {
+                let name = key_name(key);
+                if name.len() == 1 {
+                    self.query += &name;
+                    self.selected = 0;
+                }
+            }
+        }
+        PaletteAction::Edit
+    }
+
+    /// Commands whose name or description matches the query, best first.
+    pub fn matches(&self) -> Vec<&'static CommandInfo> {
+        let mut matches: Vec<_> = COMMANDS
+            .iter()
+            .filter_map(|info| {
+                let score = [info.name, info.description]
+                    .iter()
+                    .filter_map(|text| fuzzy_score(&self.query, text))
+                    .min()?;
+                Some((score, info))
+            })
+            .collect();
+        matches.sort_by_key(|(score, _)| *score);
+        matches.into_iter().map(|(_, info)| info).collect()
+    }
+
+    pub fn selected(&self) -> Option<Command> {
+        self.matches().get(self.selected).map(|info| info.command)
+    }
+
+    /// Draws the query, the matching commands with their chords and the description of the
+    /// selected one over a `width` pixels wide frame.
+    pub fn draw(&self, bindings: &Bindings, buff: &mut [u32], width: usize) {
+        use crate::osd::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+        const TEXT: u32 = 0xE0E0E0;
+        const SELECTED: u32 = 0xFFC000;
+        const CHORD_COLUMNS: usize = 9;
+
+        let height = buff.len() / width;
+        osd::dim(buff, width, 0, 0, width, height);
+        let lines = height / GLYPH_HEIGHT;
+        let mut text = |line: usize, column: usize, text: &str, color| {
+            let (x, y) = (1 + column * GLYPH_WIDTH, 1 + line * GLYPH_HEIGHT);
+            osd::draw_text(buff, width, x, y, text, color);
+        };
+
+        text(0, 0, &format!("> {}_", self.query), TEXT);
+        let matches = self.matches();
+        // The title, the description and a blank line around the list.
+        let rows = lines.saturating_sub(4);
+        let first = self.selected.saturating_sub(rows.saturating_sub(1));
+        for (row, info) in matches.iter().enumerate().skip(first).take(rows) {
+            let color = if row == self.selected { SELECTED } else { TEXT };
+            let chord = bindings[info.command as usize].map_or("-".to_string(), |c| c.to_string());
+            text(row - first + 2, 0, &chord, color);
+            text(row - first + 2, CHORD_COLUMNS, info.name, color);
+        }
+        if let Some(info) = matches.get(self.selected) {
+            text(lines.saturating_sub(1), 0, info.description, SELECTED);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_chords() {
+        for (idx, info) in COMMANDS.iter().enumerate() {
+            assert_eq!(info.command as usize, idx, "{}", info.name);
+        }
+
+        let chord = Chord::parse("Ctrl+Shift+F5").unwrap();
+        assert_eq!(
+            chord,
+            Chord {
+                ctrl: true,
+                shift: true,
+                alt: false,
+                key: Key::F5
+            }
+        );
+        assert_eq!(chord.to_string(), "ctrl+shift+f5");
+        assert_eq!(Chord::parse("1"), Ok(Chord::new(Key::Key1)));
+        assert_eq!(Chord::parse("pagedown"), Ok(Chord::new(Key::PageDown)));
+        assert_eq!(Chord::parse("ctrl+"), Err("unknown key ".to_string()));
+        assert_eq!(
+            Chord::parse("meta+a"),
+            Err("unknown modifier meta".to_string())
+        );
+    }
+
+    #[test]
+    fn conflicts_and_lookup() {
+        let mut bindings = default_bindings();
+        let mut keymap = Keymap::default();
+        assert_eq!(check_conflicts(&bindings, &keymap), Ok(()));
+        let p = Chord::new(Key::P);
+        assert_eq!(lookup(&bindings, p, Scope::AnyWindow), Some(Command::Pause));
+        let f5 = Chord::new(Key::F5);
+        assert_eq!(lookup(&bindings, f5, Scope::AnyWindow), None);
+        assert_eq!(lookup(&bindings, f5, Scope::Game), Some(Command::QuickSave));
+
+        bindings[Command::QuickLoad as usize] = Some(f5);
+        assert_eq!(
+            check_conflicts(&bindings, &keymap),
+            Err("f5 is bound to both quick_save and quick_load".to_string())
+        );
+        bindings[Command::QuickSave as usize] = None;
+        assert_eq!(check_conflicts(&bindings, &keymap), Ok(()));
+
+        bindings[Command::Pause as usize] = Some(Chord::new(Key::Z));
+        assert_eq!(
+            check_conflicts(&bindings, &keymap),
+            Err("z of pause is reserved for the joypad".to_string())
+        );
+        bindings[Command::Pause as usize] = Chord::parse("ctrl+z").ok();
+        assert_eq!(check_conflicts(&bindings, &keymap), Ok(()));
+
+        // Chords take the keys the joypad doesn't use anymore.
+        keymap.set(JoypadKey::A, &[Key::K, Key::Z]).unwrap();
+        bindings[Command::Pause as usize] = Some(Chord::new(Key::K));
+        assert!(check_conflicts(&bindings, &keymap).is_err());
+        keymap.set(JoypadKey::A, &[Key::A]).unwrap();
+        keymap.set(JoypadKey::Select, &[]).unwrap();
+        bindings[Command::Pause as usize] = Some(Chord::new(Key::Space));
+        assert_eq!(check_conflicts(&bindings, &keymap), Ok(()));
+    }
+
+    #[test]
+    fn keymap() {
+        let mut keymap = Keymap::default();
+        keymap.set(JoypadKey::A, &[Key::Z, Key::K]).unwrap();
+        keymap.set(JoypadKey::B, &[Key::X, Key::K]).unwrap();
+        assert_eq!(
+            keymap.buttons(Key::K).collect::<Vec<_>>(),
+            [JoypadKey::A, JoypadKey::B]
+        );
+        assert_eq!(
+            keymap.keys(JoypadKey::A).collect::<Vec<_>>(),
+            [Key::Z, Key::K]
+        );
+        assert_eq!(keymap.buttons(Key::Enter).next(), Some(JoypadKey::Start));
+        assert!(keymap.set(JoypadKey::Up, &[Key::W; 5]).is_err());
+
+        let pad_map = PadMap::default();
+        assert_eq!(
+            pad_map.buttons(PadButton::East).collect::<Vec<_>>(),
+            [JoypadKey::A]
+        );
+        assert_eq!(parse_pad_button("DPad_Up"), Ok(PadButton::DPadUp));
+        assert!(parse_pad_button("mode").is_err());
+    }
+
+    #[test]
+    fn command_palette() {
+        let mut palette = CommandPalette::default();
+        assert_eq!(palette.matches().len(), COMMAND_COUNT);
+        assert_eq!(palette.selected(), Some(Command::Pause));
+
+        let names = |palette: &CommandPalette| -> Vec<_> {
+            palette.matches().iter().map(|info| info.name).collect()
+        };
+        for key in [Key::Q, Key::S] {
+            palette.type_key(key);
+        }
+        assert_eq!(names(&palette), ["quick_save"]);
+
+        // The closest match first, descriptions match too.
+        palette.type_key(Key::Backspace);
+        palette.type_key(Key::Backspace);
+        for key in [Key::M, Key::E, Key::M] {
+            palette.type_key(key);
+        }
+        let found = names(&palette);
+        assert_eq!(found[0], "dump_memory");
+        assert!(found.contains(&"quick_load"));
+        assert!(!found.contains(&"soft_reset"));
+        palette.type_key(Key::Down);
+        palette.type_key(Key::Down);
+        assert_eq!(
+            palette.selected(),
+            palette.matches().get(2).map(|i| i.command)
+        );
+
+        palette.type_key(Key::Backspace);
+        palette.type_key(Key::Key9);
+        assert_eq!(palette.selected(), None);
+        palette.type_key(Key::Up);
+        assert_eq!(palette.selected(), None);
+        assert!(matches!(palette.type_key(Key::Enter), PaletteAction::Close));
+        palette.type_key(Key::Backspace);
+        let selected = palette.selected();
+        assert!(selected.is_some());
+        assert!(matches!(
+            palette.type_key(Key::Enter),
+            PaletteAction::Run(command) if Some(command) == selected
+        ));
+
+        // Draws at any size.
+        for (width, height) in [(160, 144), (3, 1)] {
+            let mut buff = vec![0xFFFFFF; width * height];
+            palette.draw(&default_bindings(), &mut buff, width);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// Known compatibility problems, reported when a ROM is loaded instead of failing mysteriously
+// mid-game.
+
+use crate::mbc::HEADER_CHECKSUM_ADDR;
+
+/// https://gbdev.io/pandocs/The_Cartridge_Header.html#0143--cgb-flag
+const CGB_FLAG_ADDR: usize = 0x143;
+const GLOBAL_CHECKSUM_ADDR: usize = 0x14E;
+
+/// Checksum of the whole ROM from its header, `None` if the ROM is too short.
+pub fn global_checksum(rom: &[u8]) -> Option<u16> {
+    let bytes = rom.get(GLOBAL_CHECKSUM_ADDR..GLOBAL_CHECKSUM_ADDR + 2)?;
+    // Big endian, unlike everything else.
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// A game with a feature that doesn't work, identified by the checksums of its header.
+pub struct KnownIssue {
+    pub title: &'static str,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    pub issue: &'static str,
+}
+
+/// Keyed by both checksums, so revisions and translations of a game don't share entries.
+pub const KNOWN_ISSUES: [KnownIssue; 1] = [KnownIssue {
+    title: "Tetris",
+    header_checksum: 0x0A,
+    global_checksum: 0x16BF,
+    issue: "the 2-player mode needs a second emulator linked with --link-listen and \
+        --link-connect",
+}];
+
+/// # Returns
+///
+/// Messages about problems to expect with the ROM, from the database and from its header.
+pub fn check(rom: &[u8]) -> Vec<String> {
+    let mut notes = vec![];
+
+    if rom.get(CGB_FLAG_ADDR) == Some(&0xC0) {
+        notes.push(
+            "This game requires a Game Boy Color, which isn't emulated: expect an error screen or \
+            glitches."
+                .to_string(),
+        );
+    }
+
+    let (Some(&header_checksum), Some(global_checksum)) =
+        (rom.get(HEADER_CHECKSUM_ADDR), global_checksum(rom))
+    else {
+        return notes;
+    };
+    for known in &KNOWN_ISSUES {
+        if known.header_checksum == header_checksum && known.global_checksum == global_checksum {
+            notes.push(format!("{}: {}.", known.title, known.issue));
+        }
+    }
+
+    notes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::HEADER_SIZE;
+
+    #[test]
+    fn known_and_header_issues() {
+        let mut rom = vec![0; HEADER_SIZE];
+        assert!(check(&rom).is_empty());
+
+        rom[HEADER_CHECKSUM_ADDR] = 0x0A;
+        rom[GLOBAL_CHECKSUM_ADDR..][..2].copy_from_slice(&[0x16, 0xBF]);
+        rom[CGB_FLAG_ADDR] = 0xC0;
+        let notes = check(&rom);
+        assert_eq!(notes.len(), 2);
+        assert!(notes[0].contains("Game Boy Color"));
+        assert!(notes[1].starts_with("Tetris: the 2-player mode"));
+
+        // CGB enhanced games run on DMG.
+        rom[CGB_FLAG_ADDR] = 0x80;
+        assert_eq!(check(&rom).len(), 1);
+    }
+}
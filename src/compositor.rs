@@ -0,0 +1,480 @@
+// Places the game screen and debug views side by side in one window buffer, or renders debug
+// views on their own.
+
+use std::str::FromStr;
+
+use crate::{
+    cpu::CPU,
+    gpu::{tile_data_offset, tile_map_offset, BackgroundColors, Coordinate, GPU},
+    memory_bus::OAM_SIZE,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+const BACKGROUND: u32 = 0x202020;
+/// Space between views.
+const MARGIN: usize = 4;
+
+pub struct ViewContext<'a> {
+    pub cpu: &'a CPU,
+    /// The game screen as it is shown to the user (possibly with overlays).
+    pub screen: &'a [u32; SCREEN_HEIGHT * SCREEN_WIDTH],
+}
+
+pub trait View: Send {
+    /// Width and height in pixels.
+    fn size(&self) -> (usize, usize);
+    fn draw(&self, ctx: &ViewContext, canvas: &mut Canvas);
+}
+
+/// A rectangle of the compositor buffer a view draws to.
+pub struct Canvas<'a> {
+    buffer: &'a mut [u32],
+    stride: usize,
+    left: usize,
+    top: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Canvas<'_> {
+    /// Pixels outside of the view are ignored.
+    pub fn set(&mut self, x: usize, y: usize, color: u32) {
+        if x < self.width && y < self.height {
+            self.buffer[(self.top + y) * self.stride + self.left + x] = color;
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// All views in one row.
+    Row,
+    /// All views in one column.
+    Column,
+    /// Views fill rows of `columns` views from left to right.
+    Grid { columns: usize },
+}
+
+pub struct Compositor {
+    layout: Layout,
+    views: Vec<Box<dyn View>>,
+    buffer: Vec<u32>,
+}
+
+impl Compositor {
+    pub fn new(layout: Layout) -> Self {
+        Self {
+            layout,
+            views: vec![],
+            buffer: vec![],
+        }
+    }
+
+    pub fn add(&mut self, view: Box<dyn View>) {
+        self.views.push(view);
+    }
+
+    /// Width and height of the composed buffer.
+    pub fn size(&self) -> (usize, usize) {
+        self.placements().1
+    }
+
+    /// Draws all views and returns the buffer of [`Compositor::size`] pixels, row by row.
+    pub fn compose(&mut self, ctx: &ViewContext) -> &[u32] {
+        let (placements, (width, height)) = self.placements();
+
+        self.buffer.clear();
+        self.buffer.resize(width * height, BACKGROUND);
+
+        for (view, (left, top)) in self.views.iter().zip(placements) {
+            let (view_width, view_height) = view.size();
+            let mut canvas = Canvas {
+                buffer: &mut self.buffer,
+                stride: width,
+                left,
+                top,
+                width: view_width,
+                height: view_height,
+            };
+            view.draw(ctx, &mut canvas);
+        }
+
+        &self.buffer
+    }
+
+    fn columns(&self) -> usize {
+        match self.layout {
+            Layout::Row => self.views.len(),
+            Layout::Column => 1,
+            Layout::Grid { columns } => columns,
+        }
+        .max(1)
+    }
+
+    /// Top-left corner of every view and the total size. Every column is as wide as its widest
+    /// view and every row is as tall as its tallest view.
+    fn placements(&self) -> (Vec<(usize, usize)>, (usize, usize)) {
+        let columns = self.columns();
+        let rows = self.views.len().div_ceil(columns);
+
+        let mut col_widths = vec![0; columns];
+        let mut row_heights = vec![0; rows];
+        for (idx, view) in self.views.iter().enumerate() {
+            let (width, height) = view.size();
+            col_widths[idx % columns] = col_widths[idx % columns].max(width);
+            row_heights[idx / columns] = row_heights[idx / columns].max(height);
+        }
+
+        let offsets = |sizes: &[usize]| -> Vec<usize> {
+            sizes
+                .iter()
+                .scan(MARGIN, |offset, size| {
+                    let res = *offset;
+                    *offset += size + MARGIN;
+                    Some(res)
+                })
+                .collect()
+        };
+        let (col_offsets, row_offsets) = (offsets(&col_widths), offsets(&row_heights));
+
+        let placements = (0..self.views.len())
+            .map(|idx| (col_offsets[idx % columns], row_offsets[idx / columns]))
+            .collect();
+        let width = col_widths.iter().sum::<usize>() + MARGIN * (columns + 1);
+        let height = row_heights.iter().sum::<usize>() + MARGIN * (rows + 1);
+
+        (placements, (width, height))
+    }
+}
+
+/// Draws a single view into a buffer of the view's size, e.g. for a view in its own window.
+pub fn render(view: &dyn View, ctx: &ViewContext, buffer: &mut Vec<u32>) {
+    let (width, height) = view.size();
+    buffer.clear();
+    buffer.resize(width * height, BACKGROUND);
+
+    let mut canvas = Canvas {
+        buffer,
+        stride: width,
+        left: 0,
+        top: 0,
+        width,
+        height,
+    };
+    view.draw(ctx, &mut canvas);
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    /// `row`, `column` or `grid=N`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "row" => Ok(Layout::Row),
+            "column" => Ok(Layout::Column),
+            _ => match s.strip_prefix("grid=").map(str::parse) {
+                Some(Ok(columns)) if columns > 0 => Ok(Layout::Grid { columns }),
+                _ => Err(format!(
+                    "unknown layout {s} (expected row, column or grid=N)"
+                )),
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ViewKind {
+    Screen,
+    Tiles,
+    BgMap,
+    Oam,
+    ApuScope,
+}
+
+impl ViewKind {
+    pub fn create(self) -> Box<dyn View> {
+        match self {
+            ViewKind::Screen => Box::new(ScreenView),
+            ViewKind::Tiles => Box::new(TileView),
+            ViewKind::BgMap => Box::new(BgMapView),
+            ViewKind::Oam => Box::new(OamView),
+            ViewKind::ApuScope => Box::new(ApuScopeView),
+        }
+    }
+}
+
+impl FromStr for ViewKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "screen" => Ok(ViewKind::Screen),
+            "tiles" => Ok(ViewKind::Tiles),
+            "bg-map" => Ok(ViewKind::BgMap),
+            "oam" => Ok(ViewKind::Oam),
+            "apu-scope" => Ok(ViewKind::ApuScope),
+            _ => Err(format!(
+                "unknown view {s} (expected screen, tiles, bg-map, oam or apu-scope)"
+            )),
+        }
+    }
+}
+
+pub struct ScreenView;
+
+impl View for ScreenView {
+    fn size(&self) -> (usize, usize) {
+        (SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+
+    fn draw(&self, ctx: &ViewContext, canvas: &mut Canvas) {
+        for (idx, &color) in ctx.screen.iter().enumerate() {
+            canvas.set(idx % SCREEN_WIDTH, idx / SCREEN_WIDTH, color);
+        }
+    }
+}
+
+/// All 384 tiles of VRAM, 16 per row, with the BG palette.
+pub struct TileView;
+
+impl TileView {
+    const TILES: usize = 384;
+    const TILES_PER_ROW: usize = 16;
+}
+
+impl View for TileView {
+    fn size(&self) -> (usize, usize) {
+        (
+            Self::TILES_PER_ROW * 8,
+            Self::TILES / Self::TILES_PER_ROW * 8,
+        )
+    }
+
+    fn draw(&self, ctx: &ViewContext, canvas: &mut Canvas) {
+        let gpu = ctx.cpu.gpu();
+        for tile in 0..Self::TILES {
+            let (left, top) = (
+                tile % Self::TILES_PER_ROW * 8,
+                tile / Self::TILES_PER_ROW * 8,
+            );
+            draw_tile(gpu, tile * 16, gpu.bg_colors, false, canvas, left, top, 8);
+        }
+    }
+}
+
+/// The whole 256×256 BG map selected by LCDC with the visible area outlined.
+pub struct BgMapView;
+
+impl View for BgMapView {
+    fn size(&self) -> (usize, usize) {
+        (256, 256)
+    }
+
+    fn draw(&self, ctx: &ViewContext, canvas: &mut Canvas) {
+        const VIEWPORT_COLOR: u32 = 0xFF0000;
+
+        let gpu = ctx.cpu.gpu();
+        let map = if gpu.lcd_control.bg_tile_map_area {
+            0x9C00
+        } else {
+            0x9800
+        };
+
+        for idx in 0..32 * 32 {
+            let tile = Coordinate::new((idx % 32 * 8) as u8, (idx / 32 * 8) as u8);
+            let tile_idx = gpu.vram[tile_map_offset(map, tile)];
+            let tile_offset =
+                tile_data_offset(gpu.lcd_control.bg_and_window_tile_data_area, tile_idx);
+            draw_tile(
+                gpu,
+                tile_offset,
+                gpu.bg_colors,
+                false,
+                canvas,
+                idx % 32 * 8,
+                idx / 32 * 8,
+                8,
+            );
+        }
+
+        let (left, top) = (gpu.viewport.x as usize, gpu.viewport.y as usize);
+        for dx in 0..SCREEN_WIDTH {
+            canvas.set((left + dx) % 256, top, VIEWPORT_COLOR);
+            canvas.set(
+                (left + dx) % 256,
+                (top + SCREEN_HEIGHT - 1) % 256,
+                VIEWPORT_COLOR,
+            );
+        }
+        for dy in 0..SCREEN_HEIGHT {
+            canvas.set(left, (top + dy) % 256, VIEWPORT_COLOR);
+            canvas.set(
+                (left + SCREEN_WIDTH - 1) % 256,
+                (top + dy) % 256,
+                VIEWPORT_COLOR,
+            );
+        }
+    }
+}
+
+/// All 40 objects in OAM order, 8 per row, drawn with their palettes.
+pub struct OamView;
+
+impl OamView {
+    const OBJS: usize = OAM_SIZE / 4;
+    const OBJS_PER_ROW: usize = 8;
+    const CELL_WIDTH: usize = 8 + 2;
+    const CELL_HEIGHT: usize = 16 + 2;
+}
+
+impl View for OamView {
+    fn size(&self) -> (usize, usize) {
+        (
+            Self::OBJS_PER_ROW * Self::CELL_WIDTH,
+            Self::OBJS / Self::OBJS_PER_ROW * Self::CELL_HEIGHT,
+        )
+    }
+
+    fn draw(&self, ctx: &ViewContext, canvas: &mut Canvas) {
+        let gpu = ctx.cpu.gpu();
+        let obj_height = if gpu.lcd_control.obj_size { 16 } else { 8 };
+
+        for obj in 0..Self::OBJS {
+            let attrs = &gpu.oam[obj * 4..obj * 4 + 4];
+            let tile_idx = if obj_height == 16 {
+                attrs[2] & !1
+            } else {
+                attrs[2]
+            };
+            let palette = if attrs[3] & (1 << 4) != 0 {
+                gpu.obj1_colors
+            } else {
+                gpu.obj0_colors
+            };
+
+            draw_tile(
+                gpu,
+                tile_idx as usize * 16,
+                palette,
+                true,
+                canvas,
+                obj % Self::OBJS_PER_ROW * Self::CELL_WIDTH + 1,
+                obj / Self::OBJS_PER_ROW * Self::CELL_HEIGHT + 1,
+                obj_height,
+            );
+        }
+    }
+}
+
+/// Waveforms of the last audio buffer: left channel in green, right channel in red.
+pub struct ApuScopeView;
+
+impl ApuScopeView {
+    const HEIGHT: usize = 64;
+}
+
+impl View for ApuScopeView {
+    fn size(&self) -> (usize, usize) {
+        (SCREEN_WIDTH, Self::HEIGHT)
+    }
+
+    fn draw(&self, ctx: &ViewContext, canvas: &mut Canvas) {
+        let (left, right) = ctx.cpu.last_audio_buffer();
+        let step = left.len() / SCREEN_WIDTH;
+
+        for x in 0..SCREEN_WIDTH {
+            canvas.set(x, Self::HEIGHT / 2, 0x404040);
+            for (samples, color) in [(left, 0x00FF00), (right, 0xFF0000)] {
+                // Samples are mixed into [-1; 1].
+                let sample = samples[x * step].clamp(-1.0, 1.0);
+                let y = ((1.0 - sample) * (Self::HEIGHT - 1) as f32 / 2.0) as usize;
+                canvas.set(x, y, color);
+            }
+        }
+    }
+}
+
+/// Draws `height` lines of the tile at `tile_offset` in VRAM.
+#[allow(clippy::too_many_arguments)]
+fn draw_tile(
+    gpu: &GPU,
+    tile_offset: usize,
+    palette: BackgroundColors,
+    transparent: bool,
+    canvas: &mut Canvas,
+    left: usize,
+    top: usize,
+    height: usize,
+) {
+    for y in 0..height {
+        let Some(&[lo, hi]) = gpu.vram.get(tile_offset + y * 2..tile_offset + y * 2 + 2) else {
+            return;
+        };
+        for x in 0..8 {
+            let bit = 7 - x;
+            let color_raw = (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1);
+            if transparent && color_raw == 0 {
+                continue;
+            }
+            let shade = palette.get()[color_raw as usize].rgb() as u32;
+            canvas.set(left + x, top + y, (shade << 16) | (shade << 8) | shade);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Fill(usize, usize, u32);
+
+    impl View for Fill {
+        fn size(&self) -> (usize, usize) {
+            (self.0, self.1)
+        }
+
+        fn draw(&self, _ctx: &ViewContext, canvas: &mut Canvas) {
+            for y in 0..self.1 + 1 {
+                for x in 0..self.0 + 1 {
+                    canvas.set(x, y, self.2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grid_layout() {
+        let cpu = CPU::new_without_sound(vec![0; 0x8000]);
+        let screen = [0; SCREEN_HEIGHT * SCREEN_WIDTH];
+        let ctx = ViewContext {
+            cpu: &cpu,
+            screen: &screen,
+        };
+
+        let mut compositor = Compositor::new(Layout::Grid { columns: 2 });
+        compositor.add(Box::new(Fill(2, 1, 1)));
+        compositor.add(Box::new(Fill(1, 3, 2)));
+        compositor.add(Box::new(Fill(1, 1, 3)));
+
+        // Columns: 2 and 1 pixels wide, rows: 3 and 1 pixels tall.
+        let (width, height) = (2 + 1 + 3 * MARGIN, 3 + 1 + 3 * MARGIN);
+        assert_eq!(compositor.size(), (width, height));
+
+        let buffer = compositor.compose(&ctx);
+        let at = |x: usize, y: usize| buffer[y * width + x];
+        assert_eq!(at(MARGIN, MARGIN), 1);
+        assert_eq!(at(MARGIN + 1, MARGIN), 1);
+        // Views can't draw outside of their rectangle.
+        assert_eq!(at(MARGIN + 2, MARGIN), BACKGROUND);
+        assert_eq!(at(MARGIN, MARGIN + 1), BACKGROUND);
+        assert_eq!(at(2 * MARGIN + 2, MARGIN + 2), 2);
+        assert_eq!(at(MARGIN, 2 * MARGIN + 3), 3);
+    }
+
+    #[test]
+    fn parse_layout() {
+        assert_eq!("row".parse(), Ok(Layout::Row));
+        assert_eq!("grid=3".parse(), Ok(Layout::Grid { columns: 3 }));
+        assert!("grid=0".parse::<Layout>().is_err());
+    }
+}
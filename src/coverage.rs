@@ -0,0 +1,99 @@
+// Code coverage of the cartridge ROM.
+//
+// Every executed instruction marks the ROM bytes of its opcode and operands. Addresses are translated to the
+// absolute offset inside the ROM file using the currently mapped bank, so the same address in
+// different banks is tracked separately.
+
+/// Code/Data Log flag: the byte was executed as a part of an instruction.
+// https://fceux.com/web/help/CodeDataLogger.html
+pub const CDL_CODE: u8 = 1 << 0;
+
+pub struct Coverage {
+    /// One bit per ROM byte.
+    bitmap: Vec<u8>,
+    rom_size: usize,
+}
+
+impl Coverage {
+    pub fn new(rom_size: usize) -> Self {
+        Self {
+            bitmap: vec![0; rom_size.div_ceil(8)],
+            rom_size,
+        }
+    }
+
+    pub fn mark(&mut self, rom_offset: usize) {
+        if rom_offset < self.rom_size {
+            self.bitmap[rom_offset / 8] |= 1 << (rom_offset % 8);
+        }
+    }
+
+    pub fn is_executed(&self, rom_offset: usize) -> bool {
+        rom_offset < self.rom_size && self.bitmap[rom_offset / 8] & (1 << (rom_offset % 8)) != 0
+    }
+
+    /// Bit `i % 8` of byte `i / 8` is set if ROM offset `i` was executed.
+    pub fn bitmap(&self) -> &[u8] {
+        &self.bitmap
+    }
+
+    pub fn executed_count(&self) -> usize {
+        self.bitmap.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    /// Code/Data Log: one byte per ROM byte with [`CDL_CODE`] set for executed bytes. This is the
+    /// format understood by disassemblers that accept CDL files (e.g. mgbdis, Mesen).
+    pub fn to_cdl(&self) -> Vec<u8> {
+        (0..self.rom_size)
+            .map(|offset| {
+                if self.is_executed(offset) {
+                    CDL_CODE
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
+    pub fn export_cdl(&self, path: &std::path::Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_cdl())
+    }
+}
+
+/// Absolute offset in the ROM file of `addr` (0x0000-0x7FFF) when `bank` is mapped there.
+pub fn rom_offset(bank: usize, addr: u16) -> usize {
+    bank * 0x4000 + (addr as usize & 0x3FFF)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bank_aware_offsets() {
+        let mut cov = Coverage::new(0x10000);
+
+        cov.mark(rom_offset(1, 0x4000));
+        cov.mark(rom_offset(3, 0x4001));
+
+        assert!(cov.is_executed(0x4000));
+        assert!(!cov.is_executed(0x4001));
+        assert!(cov.is_executed(0xC001));
+        assert_eq!(cov.executed_count(), 2);
+    }
+
+    #[test]
+    fn cdl_export() {
+        let mut cov = Coverage::new(16);
+        cov.mark(0);
+        cov.mark(9);
+        // Out of ROM, ignored.
+        cov.mark(100);
+
+        let cdl = cov.to_cdl();
+        assert_eq!(cdl.len(), 16);
+        assert_eq!(cdl[0], CDL_CODE);
+        assert_eq!(cdl[9], CDL_CODE);
+        assert_eq!(cdl.iter().filter(|&&b| b != 0).count(), 2);
+    }
+}
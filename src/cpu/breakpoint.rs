@@ -0,0 +1,45 @@
+// Temporary breakpoints used to pause emulation at exact screen positions.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Before the instruction at the address is executed.
+    Pc(u16),
+    /// When the PPU enters the scanline (LY) of the next frame.
+    Scanline(u8),
+}
+
+/// A breakpoint which is removed once it is hit.
+pub(super) struct TempBreakpoint {
+    breakpoint: Breakpoint,
+    /// For [`Breakpoint::Scanline`]: LY wrapped to 0 after the breakpoint was added.
+    frame_started: bool,
+}
+
+impl TempBreakpoint {
+    pub fn new(breakpoint: Breakpoint) -> Self {
+        Self {
+            breakpoint,
+            frame_started: false,
+        }
+    }
+
+    pub fn breakpoint(&self) -> Breakpoint {
+        self.breakpoint
+    }
+
+    /// Called after every CPU step. `old_ly` is LY before the step.
+    pub fn is_hit(&mut self, pc: u16, old_ly: u8, ly: u8) -> bool {
+        match self.breakpoint {
+            Breakpoint::Pc(addr) => pc == addr,
+            Breakpoint::Scanline(line) => {
+                if old_ly == ly {
+                    return false;
+                }
+                if ly == 0 {
+                    self.frame_started = true;
+                }
+                self.frame_started && ly == line
+            }
+        }
+    }
+}
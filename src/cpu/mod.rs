@@ -1,16 +1,59 @@
+mod breakpoint;
 pub mod instruction;
 mod registers;
 
-pub use crate::joypad::JoypadKey;
+use std::ops::ControlFlow;
+
 use crate::{
     audio_player::{AudioPlayer, VoidAudioPlayer},
+    cheat::Cheat,
+    coverage::Coverage,
+    error::GbError,
+    flight_recorder::FlightRecorder,
+    frame::Frame,
+    heatmap::MemoryHeatmap,
+    input_macro::{InputMacro, MacroEvent},
+    interrupt_latency::InterruptLatency,
+    mbc::HEADER_CHECKSUM_ADDR,
     memory_bus::MemoryBus,
+    model::Model,
+    movie::Movie,
+    opcodes,
+    rng_script::RngScript,
+    savestate::{SaveState, StateVisitor},
+    sgb::Sgb,
+};
+pub use crate::{
+    gpu::{Layer, Layers},
+    joypad::JoypadKey,
+    mbc::{BankDiagnostics, BankingIssue, MbcState},
+    memory_bus::{Device, IoWrite, IoWriteCallback},
+    serial::SerialDevice,
 };
 
+pub use breakpoint::Breakpoint;
+use breakpoint::TempBreakpoint;
 use instruction::Instruction;
 use registers::{CpuRegisters, HALF_CARRY_MASK};
 
+pub type FrameCallback = Box<dyn FnMut(&Frame) -> ControlFlow<()> + Send>;
+pub type InstructionCallback = Box<dyn FnMut(&StepInfo) -> ControlFlow<()> + Send>;
+
+/// What one [`CPU::cycle`] did, passed to instruction callbacks.
+#[derive(Copy, Clone, Debug)]
+pub struct StepInfo {
+    /// Address of the next instruction.
+    pub pc: u16,
+    /// T-cycles the step took.
+    pub cycles: u64,
+    /// An interrupt handler was called instead of executing an instruction.
+    pub interrupt: bool,
+    /// Writes to IO registers since power-on.
+    pub io_writes: u64,
+}
+
 pub struct CPU {
+    model: Model,
     registers: CpuRegisters,
     memory: MemoryBus,
     /// Program counter.
@@ -18,51 +61,464 @@ pub struct CPU {
     /// Stack pointer.
     sp: u16,
     is_halted: bool,
+    /// HALT was executed with IME off and an interrupt pending: the PC isn't incremented after
+    /// reading the next opcode.
+    halt_bug: bool,
     interrupts_enabled: bool,
     // Counters to schedule enable/disable IME.
     di_timer: u8,
     ei_timer: u8,
+
+    /// Executed ROM addresses, tracked only when enabled.
+    coverage: Option<Coverage>,
+    /// Delays before interrupt handlers, tracked only when enabled.
+    interrupt_latency: Option<InterruptLatency>,
+
+    frame_callbacks: Vec<FrameCallback>,
+    frame: Frame,
+    last_frame_count: u64,
+    /// One of the frame callbacks or breakpoints asked to stop emulation.
+    stop_requested: bool,
+
+    breakpoints: Vec<TempBreakpoint>,
+    breakpoint_hit: Option<Breakpoint>,
+
+    instruction_callbacks: Vec<InstructionCallback>,
+    /// The last step called an interrupt handler.
+    interrupted: bool,
+
+    /// Key presses of the macro being recorded, with the frame it started at.
+    macro_recording: Option<(u64, Vec<MacroEvent>)>,
+    /// Key presses to apply at the start of a frame, with absolute frame numbers, sorted.
+    scheduled_inputs: std::collections::VecDeque<MacroEvent>,
+    /// Key presses of the movie being recorded, as they were applied.
+    movie_recording: Option<Vec<MacroEvent>>,
+    /// Key presses of the player are ignored until the movie ends.
+    playing_movie: bool,
+    /// GameShark codes written at every VBlank.
+    ram_cheats: Vec<Cheat>,
+}
+
+/// Iterator over emulated frames, see [`CPU::frames`].
+pub struct Frames<'a> {
+    cpu: &'a mut CPU,
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        self.cpu.next_frame().cloned()
+    }
 }
 
 impl CPU {
     const INSTRUCTION_PREFIX: u8 = 0xCB;
 
+    /// # Panics
+    ///
+    /// If the ROM can't be emulated, see [`CPU::try_new`].
     pub fn new(game_rom: Vec<u8>, player: Box<dyn AudioPlayer>) -> Self {
-        Self {
-            registers: CpuRegisters::new(),
-            memory: MemoryBus::new(game_rom, player),
+        Self::with_model(game_rom, player, Model::Dmg)
+    }
+
+    /// # Panics
+    ///
+    /// If the ROM can't be emulated, see [`CPU::try_with_model`].
+    pub fn with_model(game_rom: Vec<u8>, player: Box<dyn AudioPlayer>, model: Model) -> Self {
+        Self::try_with_model(game_rom, player, model).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Fails with [`GbError::InvalidRom`] if the cartridge header is truncated or describes a
+    /// cartridge that isn't emulated.
+    pub fn try_new(game_rom: Vec<u8>, player: Box<dyn AudioPlayer>) -> Result<Self, GbError> {
+        Self::try_with_model(game_rom, player, Model::Dmg)
+    }
+
+    /// See [`CPU::try_new`].
+    pub fn try_with_model(
+        game_rom: Vec<u8>,
+        player: Box<dyn AudioPlayer>,
+        model: Model,
+    ) -> Result<Self, GbError> {
+        let header_checksum = game_rom.get(HEADER_CHECKSUM_ADDR).copied().unwrap_or(0);
+        Ok(Self {
+            model,
+            registers: CpuRegisters::after_boot(model, header_checksum),
+            memory: MemoryBus::try_with_model(game_rom, player, model)?,
             pc: 0x100,
             sp: 0xFFFE,
             is_halted: false,
+            halt_bug: false,
             interrupts_enabled: true,
             di_timer: 0,
             ei_timer: 0,
-        }
+            coverage: None,
+            interrupt_latency: None,
+            frame_callbacks: vec![],
+            frame: Frame::new(),
+            last_frame_count: 0,
+            stop_requested: false,
+            breakpoints: vec![],
+            breakpoint_hit: None,
+            instruction_callbacks: vec![],
+            interrupted: false,
+            macro_recording: None,
+            scheduled_inputs: Default::default(),
+            movie_recording: None,
+            playing_movie: false,
+            ram_cheats: vec![],
+        })
     }
 
+    /// # Panics
+    ///
+    /// If the ROM can't be emulated, see [`CPU::try_new`].
     pub fn new_without_sound(game_rom: Vec<u8>) -> Self {
         Self::new(game_rom, Box::new(VoidAudioPlayer::new()))
     }
 
+    /// Restarts the game as if the console was power cycled, but keeps cartridge RAM, so progress
+    /// saved by the game survives.
+    pub fn soft_reset(&mut self) {
+        self.reset(false);
+    }
+
+    /// Restarts the game as if a fresh cartridge was inserted: cartridge RAM is cleared too.
+    pub fn hard_reset(&mut self) {
+        self.reset(true);
+    }
+
+    /// Serializes the whole emulator state (CPU, memory, PPU, timers, cartridge banking and RAM,
+    /// APU), e.g. for quick save. Debug settings and callbacks aren't part of it.
+    pub fn save_state(&mut self) -> Vec<u8> {
+        let mut state = StateVisitor::saving();
+        self.visit_state(&mut state);
+        state.finish_save()
+    }
+
+    /// Restores a state from [`CPU::save_state`] of the same game and model. Nothing changes when
+    /// it fails.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), GbError> {
+        let backup = self.save_state();
+
+        let mut state = StateVisitor::loading(data)?;
+        self.visit_state(&mut state);
+        let result = state.finish_load();
+        if let Some(latency) = &mut self.interrupt_latency {
+            latency.clear_requests();
+        }
+        if let (Ok(()), Some(events)) = (&result, &mut self.movie_recording) {
+            // Recording goes on from the loaded frame, the presses after it are redone.
+            let frame = self.memory.gpu.frame_count();
+            events.retain(|event| event.frame <= frame);
+            self.last_frame_count = frame;
+            self.scheduled_inputs.clear();
+        }
+        if result.is_err() {
+            let mut state = StateVisitor::loading(&backup).unwrap();
+            self.visit_state(&mut state);
+            state.finish_load().unwrap();
+        }
+        result
+    }
+
+    /// Runs `boot_rom` from 0x0000 (Nintendo logo scroll) before the cartridge, from now and
+    /// after every reset. The game restarts.
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<(), GbError> {
+        self.memory.set_boot_rom(boot_rom)?;
+        self.reset(false);
+        Ok(())
+    }
+
+    fn reset(&mut self, clear_cartridge_ram: bool) {
+        self.memory.reset(clear_cartridge_ram);
+
+        if self.memory.is_boot_rom_mapped() {
+            self.registers = CpuRegisters::power_on();
+            self.pc = 0;
+            self.sp = 0;
+            self.interrupts_enabled = false;
+        } else {
+            let header_checksum = self.memory.read_byte(HEADER_CHECKSUM_ADDR as u16);
+            self.registers = CpuRegisters::after_boot(self.model, header_checksum);
+            self.pc = 0x100;
+            self.sp = 0xFFFE;
+            self.interrupts_enabled = true;
+        }
+        self.is_halted = false;
+        self.di_timer = 0;
+        self.ei_timer = 0;
+        self.last_frame_count = 0;
+        self.scheduled_inputs.clear();
+        // Timed by the cycles since power-on.
+        if let Some(latency) = &mut self.interrupt_latency {
+            latency.clear_requests();
+        }
+    }
+
     pub fn cycle(&mut self) -> u64 {
+        if self.breakpoints.is_empty() && self.instruction_callbacks.is_empty() {
+            return self.step();
+        }
+
+        let old_ly = self.memory.gpu.lcd_status.line();
+        let cycles = self.step();
+        let ly = self.memory.gpu.lcd_status.line();
+
+        let pc = self.pc;
+        let info = StepInfo {
+            pc,
+            cycles,
+            interrupt: self.interrupted,
+            io_writes: self.memory.io_writes(),
+        };
+        for callback in &mut self.instruction_callbacks {
+            if callback(&info).is_break() {
+                self.stop_requested = true;
+            }
+        }
+
+        // Every breakpoint must see every step, so scanline breakpoints don't miss the frame start.
+        self.breakpoints.retain_mut(|bp| {
+            if !bp.is_hit(pc, old_ly, ly) {
+                return true;
+            }
+            self.breakpoint_hit.get_or_insert(bp.breakpoint());
+            self.stop_requested = true;
+            false
+        });
+
+        cycles
+    }
+
+    fn step(&mut self) -> u64 {
         self.update_ime();
 
+        if let Some(latency) = &mut self.interrupt_latency {
+            latency.observe(self.memory.interrupt_flags(), self.memory.cycles());
+        }
+
         let cycles = self.process_interrupts();
+        self.interrupted = cycles != 0;
         if cycles != 0 {
             return self.memory.step(cycles);
         }
 
-        let instruction = self.get_current_instruction();
-
-        let (new_pc, cycles) = if self.is_halted {
-            (self.pc, 4)
-        } else {
-            self.execute(instruction)
-        };
+        self.memory.instruction_pc = self.pc;
+        let (new_pc, cycles) = self.dispatch();
 
         self.pc = new_pc;
 
-        self.memory.step(cycles)
+        let cycles = self.memory.step(cycles);
+
+        if self.memory.take_hook_stop_request() {
+            self.stop_requested = true;
+        }
+
+        if self.memory.gpu.frame_count() != self.last_frame_count {
+            self.last_frame_count = self.memory.gpu.frame_count();
+            self.apply_scheduled_inputs();
+            self.apply_ram_cheats();
+            self.notify_frame();
+        }
+
+        cycles
+    }
+
+    /// Decodes and executes the instruction at PC.
+    ///
+    /// # Returns
+    ///
+    /// The new PC and the instruction cycles.
+    fn dispatch(&mut self) -> (u16, u64) {
+        crate::profile_scope!(crate::profiler::Scope::CpuDispatch);
+
+        let (instruction, opcode) = self.decode_current();
+        if self.is_halted {
+            return (self.pc, self.halted_cycles());
+        }
+
+        if let Some(coverage) = &mut self.coverage {
+            for addr in (0..opcode.length as u16).map(|idx| self.pc.wrapping_add(idx)) {
+                if let Some(offset) = self.memory.rom_offset(addr) {
+                    coverage.mark(offset);
+                }
+            }
+        }
+        if std::mem::take(&mut self.halt_bug) {
+            // The opcode was read without incrementing PC: operands start at the opcode, and
+            // relative jumps and pushed return addresses are one byte lower.
+            self.pc = self.pc.wrapping_sub(1);
+        }
+        let next_pc = self.pc.wrapping_add(opcode.length as u16);
+        match self.execute(instruction, next_pc) {
+            Some(target) => (target, opcode.cycles_taken.unwrap_or(opcode.cycles) as u64),
+            None => (next_pc, opcode.cycles as u64),
+        }
+    }
+
+    /// Nothing happens while halted until the next event of the hardware, so it's skipped to at
+    /// once instead of 4 cycles at a time. The event falls in the last M-cycle, like when stepping.
+    fn halted_cycles(&self) -> u64 {
+        // IME changes are counted in steps.
+        if self.di_timer != 0 || self.ei_timer != 0 {
+            return 4;
+        }
+        // Keeps the skip short enough for `run` budgets and for the blank frames of a disabled LCD.
+        let cycles = self
+            .memory
+            .cycles_until_event()
+            .unwrap_or(crate::TICKS_PER_SCANLINE)
+            .min(crate::TICKS_PER_SCANLINE);
+        cycles.div_ceil(4).max(1) * 4
+    }
+
+    /// Adds a breakpoint which stops [`CPU::run`] once and is removed after that. See
+    /// [`CPU::take_breakpoint_hit`].
+    pub fn add_temp_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(TempBreakpoint::new(breakpoint));
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Returns the breakpoint hit since the last call, if any.
+    pub fn take_breakpoint_hit(&mut self) -> Option<Breakpoint> {
+        self.breakpoint_hit.take()
+    }
+
+    /// Registers a callback called after every completed frame. Returning `ControlFlow::Break`
+    /// stops [`CPU::run`].
+    pub fn on_frame(&mut self, callback: FrameCallback) {
+        self.frame_callbacks.push(callback);
+    }
+
+    /// Registers a callback called after every instruction or interrupt dispatch. Returning
+    /// `ControlFlow::Break` stops [`CPU::run`].
+    pub fn on_instruction(&mut self, callback: InstructionCallback) {
+        self.instruction_callbacks.push(callback);
+    }
+
+    /// Registers a callback called after every write to the IO register at `addr` (0xFF00-0xFF7F),
+    /// e.g. LCDC to detect screen transitions or NR52 to detect music changes. Returning
+    /// `ControlFlow::Break` stops [`CPU::run`] after the instruction.
+    pub fn on_io_write(&mut self, addr: u16, callback: IoWriteCallback) {
+        self.memory.on_io_write(addr, callback);
+    }
+
+    /// Emulates until a callback breaks, a breakpoint is hit or at least `max_cycles` pass.
+    ///
+    /// # Returns
+    ///
+    /// `ControlFlow::Break` if emulation was stopped by a callback or a breakpoint.
+    pub fn run(&mut self, max_cycles: u64) -> ControlFlow<()> {
+        let mut cycles = 0;
+        while cycles < max_cycles {
+            cycles += self.cycle();
+
+            if self.stop_requested {
+                self.stop_requested = false;
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Emulates until the next frame is completed.
+    ///
+    /// # Returns
+    ///
+    /// The frame, or `None` if a callback or a breakpoint stopped emulation first. While the LCD
+    /// is off, its blank screen is returned every frame time.
+    pub fn next_frame(&mut self) -> Option<&Frame> {
+        let frames = self.memory.gpu.frame_count();
+        let mut cycles = 0;
+        while self.memory.gpu.frame_count() == frames
+            && (self.memory.gpu.lcd_control.lcd_enable || cycles < crate::TICKS_PER_LCD_FRAME)
+        {
+            cycles += self.cycle();
+
+            if self.stop_requested {
+                self.stop_requested = false;
+                return None;
+            }
+        }
+
+        self.frame.update(&self.memory.gpu, self.memory.cycles());
+        Some(&self.frame)
+    }
+
+    /// Emulates until the PPU finishes the current scanline, e.g. to test raster effects without
+    /// counting cycles.
+    ///
+    /// # Returns
+    ///
+    /// The new LY, or `None` if a callback or a breakpoint stopped emulation first. While the LCD
+    /// is off, LY stays 0 and this returns every scanline time.
+    pub fn step_scanline(&mut self) -> Option<u8> {
+        let line = self.memory.gpu.lcd_status.line();
+        let mut cycles = 0;
+        while self.memory.gpu.lcd_status.line() == line
+            && (self.memory.gpu.lcd_control.lcd_enable || cycles < crate::TICKS_PER_SCANLINE)
+        {
+            cycles += self.cycle();
+
+            if self.stop_requested {
+                self.stop_requested = false;
+                return None;
+            }
+        }
+
+        Some(self.memory.gpu.lcd_status.line())
+    }
+
+    /// Emulates one frame, for frontends without callbacks or breakpoints that stop emulation.
+    ///
+    /// # Returns
+    ///
+    /// The new frame, or the screen as it was when emulation stopped.
+    pub fn run_frame(&mut self) -> &Frame {
+        if self.next_frame().is_none() {
+            self.frame.update(&self.memory.gpu, self.memory.cycles());
+        }
+        &self.frame
+    }
+
+    /// The screen as it is now, e.g. while paused or between [`CPU::run`] calls. Use
+    /// [`CPU::next_frame`] to get whole frames.
+    pub fn screen(&mut self) -> &Frame {
+        self.frame.update(&self.memory.gpu, self.memory.cycles());
+        &self.frame
+    }
+
+    /// T-cycles emulated since power-on.
+    pub fn cycles(&self) -> u64 {
+        self.memory.cycles()
+    }
+
+    /// Emulates lazily, one frame per iteration, e.g. `for frame in cpu.frames().take(600)`. Use
+    /// [`CPU::next_frame`] to avoid copying frames.
+    pub fn frames(&mut self) -> Frames<'_> {
+        Frames { cpu: self }
+    }
+
+    fn notify_frame(&mut self) {
+        if self.frame_callbacks.is_empty() {
+            return;
+        }
+
+        self.frame.update(&self.memory.gpu, self.memory.cycles());
+        for callback in &mut self.frame_callbacks {
+            if callback(&self.frame).is_break() {
+                self.stop_requested = true;
+            }
+        }
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
     }
 
     pub fn pc(&self) -> u16 {
@@ -73,18 +529,386 @@ impl CPU {
         &self.registers
     }
 
+    pub fn sp(&self) -> u16 {
+        self.sp
+    }
+
+    /// Reads memory for debug tools, the access isn't counted in the heatmap.
+    pub fn debug_read(&self, addr: u16) -> u8 {
+        self.memory.debug_read(addr)
+    }
+
+    /// Reads memory without side effects, like [`CPU::debug_read`].
+    #[cfg(feature = "debug")]
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.memory.debug_read(addr)
+    }
+
+    /// Writes memory like the CPU does: writes to ROM switch banks and writes to IO registers
+    /// have their usual effects.
+    #[cfg(feature = "debug")]
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        self.memory.write_byte(addr, val);
+    }
+
+    #[cfg(feature = "debug")]
+    pub fn registers_mut(&mut self) -> &mut CpuRegisters {
+        &mut self.registers
+    }
+
+    #[cfg(feature = "debug")]
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    #[cfg(feature = "debug")]
+    pub fn set_sp(&mut self, sp: u16) {
+        self.sp = sp;
+    }
+
+    /// IME, the master switch of interrupts. DI and EI change it one instruction late.
+    #[cfg(feature = "debug")]
+    pub fn ime(&self) -> bool {
+        self.interrupts_enabled
+    }
+
+    /// Sets IME, cancelling a pending DI or EI.
+    #[cfg(feature = "debug")]
+    pub fn set_ime(&mut self, enabled: bool) {
+        self.interrupts_enabled = enabled;
+        self.di_timer = 0;
+        self.ei_timer = 0;
+    }
+
+    pub fn mbc_state(&self) -> MbcState {
+        self.memory.mbc_state()
+    }
+
+    /// ROM bank mapped at `addr` in $0000-$7FFF.
+    pub fn rom_bank(&self, addr: u16) -> usize {
+        self.memory.rom_bank(addr)
+    }
+
+    /// Changes the cartridge banking registers, e.g. to reproduce bank switching bugs.
+    pub fn set_mbc_state(&mut self, state: MbcState) {
+        self.memory.set_mbc_state(state);
+    }
+
+    /// The cartridge keeps its RAM with a battery, so it should persist between sessions.
+    pub fn has_battery(&self) -> bool {
+        self.memory.has_battery()
+    }
+
+    /// Contents of a `.sav` file.
+    pub fn cartridge_ram(&self) -> Vec<u8> {
+        self.memory.cartridge_ram()
+    }
+
+    pub fn load_cartridge_ram(&mut self, data: &[u8]) {
+        self.memory.load_cartridge_ram(data);
+    }
+
+    /// Messages for the user since the last call, e.g. writes the hardware ignores or saved
+    /// prints. The library doesn't print them, it's up to the frontend.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        self.memory.take_warnings()
+    }
+
+    /// Whether the game changed the cartridge RAM since the last call, so the `.sav` file is
+    /// outdated.
+    pub fn take_cartridge_ram_dirty(&mut self) -> bool {
+        self.memory.take_cartridge_ram_dirty()
+    }
+
+    pub fn enable_coverage(&mut self) {
+        if self.coverage.is_none() {
+            self.coverage = Some(Coverage::new(self.memory.rom_size()));
+        }
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Starts counting memory accesses per page (and per byte for WRAM if `wram_detail`).
+    pub fn enable_heatmap(&mut self, wram_detail: bool) {
+        self.memory.heatmap = Some(MemoryHeatmap::new(wram_detail));
+    }
+
+    /// Emulates the bus conflicts during OAM DMA: the CPU can only access HRAM and IO registers.
+    pub fn set_dma_conflicts(&mut self, enabled: bool) {
+        self.memory.dma_conflicts = enabled;
+    }
+
+    /// Keeps the last `capacity` bus transactions. They are printed if the emulator panics.
+    pub fn enable_flight_recorder(&mut self, capacity: usize) {
+        self.memory.flight_recorder = Some(FlightRecorder::new(capacity));
+    }
+
+    /// Measures the latency of interrupts per source, see [`InterruptLatency`].
+    pub fn enable_interrupt_latency(&mut self) {
+        self.interrupt_latency.get_or_insert_with(Default::default);
+    }
+
+    pub fn interrupt_latency(&self) -> Option<&InterruptLatency> {
+        self.interrupt_latency.as_ref()
+    }
+
+    /// Logs games selecting ROM banks beyond the ROM and accessing disabled cartridge RAM, with the
+    /// PC of the instruction, see [`BankDiagnostics`].
+    pub fn enable_bank_diagnostics(&mut self) {
+        self.memory.enable_bank_diagnostics();
+    }
+
+    pub fn bank_diagnostics(&self) -> Option<&BankDiagnostics> {
+        self.memory.bank_diagnostics.as_ref()
+    }
+
+    pub fn flight_recorder(&self) -> Option<&FlightRecorder> {
+        self.memory.flight_recorder.as_ref()
+    }
+
+    pub fn heatmap(&self) -> Option<&MemoryHeatmap> {
+        self.memory.heatmap.as_ref()
+    }
+
+    /// Colors and border sent by the game on the SGB model.
+    pub fn sgb(&self) -> Option<&Sgb> {
+        self.memory.sgb()
+    }
+
+    /// Makes DIV and LY reads deterministic, see [`RngScript`].
+    pub fn set_rng_script(&mut self, script: Option<RngScript>) {
+        self.memory.rng_script = script;
+    }
+
     pub fn key_up(&mut self, key: JoypadKey) {
-        self.memory.key_up(key);
+        self.press_key(key, false);
     }
 
     pub fn key_down(&mut self, key: JoypadKey) {
-        self.memory.key_down(key);
+        self.press_key(key, true);
+    }
+
+    fn press_key(&mut self, key: JoypadKey, pressed: bool) {
+        if self.is_playing_movie() {
+            return;
+        }
+        self.record_key(key, pressed);
+        if self.movie_recording.is_some() {
+            // Applied at the start of the next frame, where the movie replays it.
+            let frame = self.memory.gpu.frame_count() + 1;
+            let idx = self
+                .scheduled_inputs
+                .partition_point(|event| event.frame <= frame);
+            self.scheduled_inputs.insert(
+                idx,
+                MacroEvent {
+                    frame,
+                    key,
+                    pressed,
+                },
+            );
+        } else if pressed {
+            self.memory.key_down(key);
+        } else {
+            self.memory.key_up(key);
+        }
+    }
+
+    /// Records the following key presses into a macro, see [`CPU::stop_macro_recording`].
+    pub fn start_macro_recording(&mut self) {
+        self.macro_recording = Some((self.memory.gpu.frame_count(), vec![]));
+    }
+
+    /// # Returns
+    ///
+    /// The key presses since [`CPU::start_macro_recording`], or `None` if it wasn't called.
+    pub fn stop_macro_recording(&mut self) -> Option<InputMacro> {
+        self.macro_recording
+            .take()
+            .map(|(_, events)| InputMacro { events })
+    }
+
+    pub fn is_recording_macro(&self) -> bool {
+        self.macro_recording.is_some()
+    }
+
+    /// Presses the keys of the macro at the starts of frames, the first frame of the macro is the
+    /// next one. Keys of a macro still playing are pressed too.
+    pub fn play_macro(&mut self, input_macro: &InputMacro) {
+        let start = self.memory.gpu.frame_count() + 1;
+        self.scheduled_inputs
+            .extend(input_macro.events.iter().map(|event| MacroEvent {
+                frame: start + event.frame,
+                ..*event
+            }));
+        self.scheduled_inputs
+            .make_contiguous()
+            .sort_by_key(|event| event.frame);
+    }
+
+    fn record_key(&mut self, key: JoypadKey, pressed: bool) {
+        if let Some((start, events)) = &mut self.macro_recording {
+            events.push(MacroEvent {
+                frame: self.memory.gpu.frame_count() - *start,
+                key,
+                pressed,
+            });
+        }
+    }
+
+    fn apply_scheduled_inputs(&mut self) {
+        let frame = self.memory.gpu.frame_count();
+        while let Some(event) = self.scheduled_inputs.front() {
+            if event.frame > frame {
+                break;
+            }
+            let event = self.scheduled_inputs.pop_front().unwrap();
+            if let Some(events) = &mut self.movie_recording {
+                events.push(MacroEvent { frame, ..event });
+            }
+            if event.pressed {
+                self.memory.key_down(event.key);
+            } else {
+                self.memory.key_up(event.key);
+            }
+        }
+    }
+
+    /// Restarts the game from power-on with cleared cartridge RAM, and records the key presses
+    /// from now on into a movie, see [`CPU::stop_movie_recording`]. Presses are delayed to the
+    /// start of the next frame, where the movie replays them.
+    pub fn start_movie_recording(&mut self) {
+        self.hard_reset();
+        self.playing_movie = false;
+        self.movie_recording = Some(vec![]);
+    }
+
+    /// # Returns
+    ///
+    /// The movie since [`CPU::start_movie_recording`], or `None` if it wasn't called.
+    pub fn stop_movie_recording(&mut self) -> Option<Movie> {
+        let events = self.movie_recording.take()?;
+        Some(Movie {
+            rom_checksum: self.rom_checksum(),
+            rng_script: self.memory.rng_script.clone(),
+            inputs: InputMacro { events },
+        })
+    }
+
+    /// Restarts the game from power-on with cleared cartridge RAM, and presses the keys of the
+    /// movie on the same frames as when it was recorded. Keys pressed with [`CPU::key_down`] are
+    /// ignored until the movie ends. The RNG script of the movie replaces the one set with
+    /// [`CPU::set_rng_script`].
+    pub fn play_movie(&mut self, movie: &Movie) -> Result<(), String> {
+        if movie.rom_checksum != self.rom_checksum() {
+            return Err(format!(
+                "the movie was recorded with a ROM with checksum {:04X}, not {:04X}",
+                movie.rom_checksum,
+                self.rom_checksum()
+            ));
+        }
+        self.set_rng_script(movie.rng_script.clone());
+        self.hard_reset();
+        self.movie_recording = None;
+        self.playing_movie = true;
+        self.scheduled_inputs.extend(&movie.inputs.events);
+        Ok(())
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        self.playing_movie && !self.scheduled_inputs.is_empty()
+    }
+
+    fn rom_checksum(&self) -> u16 {
+        crate::compat::global_checksum(self.memory.rom()).unwrap_or(0)
+    }
+
+    /// Adds a Game Genie or GameShark code, see [`crate::cheat`]. Codes stay active across
+    /// resets.
+    pub fn add_cheat(&mut self, code: &str) -> Result<(), String> {
+        match Cheat::parse(code)? {
+            cheat @ Cheat::GameGenie { .. } => self.memory.rom_cheats.push(cheat),
+            cheat @ Cheat::GameShark { .. } => self.ram_cheats.push(cheat),
+        }
+        Ok(())
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.memory.rom_cheats.clear();
+        self.ram_cheats.clear();
+    }
+
+    fn apply_ram_cheats(&mut self) {
+        for cheat in &self.ram_cheats {
+            if let Cheat::GameShark { addr, value } = *cheat {
+                self.memory.write_byte(addr, value);
+            }
+        }
     }
 
     pub fn gpu(&self) -> &crate::gpu::GPU {
         &self.memory.gpu
     }
 
+    pub fn visible_layers(&self) -> Layers {
+        self.memory.gpu.visible_layers
+    }
+
+    /// Hides layers in the drawn frames without changing LCDC, see [`Layers`].
+    pub fn set_visible_layers(&mut self, layers: Layers) {
+        self.memory.gpu.visible_layers = layers;
+    }
+
+    /// Plugs the link cable into `device`, or unplugs it.
+    pub fn set_serial_device(&mut self, device: Option<Box<dyn SerialDevice>>) {
+        self.memory.set_serial_device(device);
+    }
+
+    /// Connects custom hardware to the memory bus. Devices attached earlier answer first where
+    /// they map the same addresses. They aren't part of save states and stay after resets.
+    pub fn attach_device(&mut self, device: Box<dyn Device>) {
+        self.memory.attach_device(device);
+    }
+
+    /// Disconnects the devices from [`CPU::attach_device`], in the order they were attached.
+    pub fn detach_devices(&mut self) -> Vec<Box<dyn Device>> {
+        self.memory.detach_devices()
+    }
+
+    /// Draws up to `limit` objects per scanline instead of the 10 of hardware. Not accurate, but
+    /// removes the flicker of crowded games.
+    pub fn set_sprite_limit(&mut self, limit: usize) {
+        self.memory.gpu.sprites_per_line = limit;
+    }
+
+    /// Draws whole scanlines at once instead of running the pixel FIFO. Faster, but mid-scanline
+    /// raster effects are lost.
+    pub fn set_fast_renderer(&mut self, enabled: bool) {
+        self.memory.gpu.fast_renderer = enabled;
+    }
+
+    /// Whether audio channel `idx` (0-3 for CH1-CH4) is heard.
+    pub fn audio_channel_enabled(&self, idx: usize) -> bool {
+        self.memory.sound.channel_enabled(idx)
+    }
+
+    /// Mutes or unmutes audio channel `idx` (0-3 for CH1-CH4), e.g. to isolate one.
+    pub fn set_audio_channel_enabled(&mut self, idx: usize, enabled: bool) {
+        self.memory.sound.set_channel_enabled(idx, enabled);
+    }
+
+    /// Colors of the 4 DMG shades in frames, see [`crate::palette`].
+    pub fn set_palette(&mut self, palette: [u32; 4]) {
+        self.memory.gpu.palette = palette;
+    }
+
+    /// The last audio buffer sent to the audio player.
+    pub fn last_audio_buffer(&self) -> &crate::AudioBuff {
+        self.memory.sound.last_played()
+    }
+
     // https://gbdev.io/pandocs/Interrupts.html#ime-interrupt-master-enable-flag-write-only
     // The effect of ei is delayed by one instruction. This means that ei followed immediately
     // by di does not allow any interrupts between them. This interacts with the halt bug in an
@@ -101,52 +925,84 @@ impl CPU {
         self.ei_timer = self.ei_timer.saturating_sub(1);
     }
 
+    /// Any pending interrupt wakes the CPU from HALT, even with IME off, but it's only serviced
+    /// with IME on. Returns the cycles of the dispatch.
+    /// https://gbdev.io/pandocs/Interrupts.html#interrupt-handling
     fn process_interrupts(&mut self) -> u64 {
-        if self.memory.pending_interrupt() {
-            self.is_halted = false;
+        if !self.memory.pending_interrupt() {
+            return 0;
         }
-
+        let was_halted = std::mem::replace(&mut self.is_halted, false);
         if !self.interrupts_enabled {
             return 0;
         }
 
-        if self.memory.vbank_interrupt() {
+        // Two wait states, then PC is pushed high byte first. That push can overwrite IE at
+        // $FFFF, and the interrupt is only chosen after it: if none is left, the CPU jumps to $0000.
+        self.interrupts_enabled = false;
+        // After the HALT bug the return address is HALT itself, which runs again.
+        let return_pc = self
+            .pc
+            .wrapping_sub(std::mem::take(&mut self.halt_bug) as u16);
+        self.sp = self.sp.wrapping_sub(1);
+        self.memory
+            .write_byte(self.sp, (return_pc >> u8::BITS) as u8);
+        let source = self.take_interrupt();
+        self.sp = self.sp.wrapping_sub(1);
+        self.memory.write_byte(self.sp, return_pc as u8);
+        self.pc = source.map_or(0, |source| 0x40 + source as u16 * 8);
+
+        const DISPATCH_CYCLES: u64 = 5 * 4;
+        // Leaving HALT takes one more M-cycle.
+        let cycles = DISPATCH_CYCLES + if was_halted { 4 } else { 0 };
+        if let (Some(latency), Some(source)) = (&mut self.interrupt_latency, source) {
+            latency.handler_started(source, self.memory.cycles() + cycles);
+        }
+        cycles
+    }
+
+    /// Acknowledges the highest priority interrupt that is requested and enabled.
+    fn take_interrupt(&mut self) -> Option<usize> {
+        let source = if self.memory.vbank_interrupt() {
             self.memory.reset_vbank_interrupt();
-            self.interrupt(0x40);
+            0
         } else if self.memory.lcd_interrupt() {
             self.memory.reset_lcd_interrupt();
-            self.interrupt(0x48);
+            1
         } else if self.memory.timer_interrupt() {
             self.memory.reset_timer_interrupt();
-            self.interrupt(0x50);
+            2
         } else if self.memory.serial_interrupt() {
             self.memory.reset_serial_interrupt();
-            self.interrupt(0x58);
+            3
         } else if self.memory.joypad_interrupt() {
             self.memory.reset_joypad_interrupt();
-            self.interrupt(0x60);
+            4
         } else {
-            return 0;
-        }
-
-        5 * 4
+            return None;
+        };
+        Some(source)
     }
 
-    fn interrupt(&mut self, addr: u16) {
-        self.interrupts_enabled = false;
-        self.push_stack(self.pc);
-        self.pc = addr;
+    pub fn get_current_instruction(&self) -> Instruction {
+        self.decode_current().0
     }
 
-    pub fn get_current_instruction(&self) -> Instruction {
+    /// Decodes the instruction at PC along with its lengths and cycles.
+    fn decode_current(&self) -> (Instruction, &'static opcodes::Opcode) {
         let byte = self.read_current_byte();
         if byte == Self::INSTRUCTION_PREFIX {
-            let byte = self.read_next_byte();
-            Instruction::from_byte(byte, true)
-                .unwrap_or_else(|| panic!("Prefixed instruction 0x{byte:X} doesn't exist exist."))
+            // After the HALT bug the prefix is read again as the opcode.
+            let byte = self
+                .memory
+                .read_byte(self.pc.wrapping_add(!self.halt_bug as u16));
+            let instruction = Instruction::from_byte(byte, true)
+                .unwrap_or_else(|| panic!("Prefixed instruction 0x{byte:X} doesn't exist exist."));
+            (instruction, opcodes::get(byte, true))
         } else {
-            Instruction::from_byte(byte, false)
-                .unwrap_or_else(|| panic!("Not prefixed instruction 0x{byte:X} doesn't exist."))
+            let instruction = Instruction::from_byte(byte, false)
+                .unwrap_or_else(|| panic!("Not prefixed instruction 0x{byte:X} doesn't exist."));
+            (instruction, opcodes::get(byte, false))
         }
     }
 
@@ -171,7 +1027,12 @@ impl CPU {
         self.memory.read_byte(self.registers.hl())
     }
 
-    fn execute(&mut self, instruction: Instruction) -> (u16, u64) {
+    /// Executes the instruction, `next_pc` is the address of the following instruction.
+    ///
+    /// # Returns
+    ///
+    /// The jump target if the instruction changes the control flow, `None` otherwise.
+    fn execute(&mut self, instruction: Instruction, next_pc: u16) -> Option<u16> {
         macro_rules! arithmetic_instruction {
             ($target:ident; $func:ident) => {{
                 let _fake;
@@ -179,46 +1040,43 @@ impl CPU {
             }};
             ($target:ident; $func:ident => $var:expr) => {
                 match $target {
-                    // Bytes: 1; Cycles: 1;
                     instruction::ArithmeticTarget::A => {
                         $var = self.$func(self.registers.a);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::ArithmeticTarget::B => {
                         $var = self.$func(self.registers.b);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::ArithmeticTarget::C => {
                         $var = self.$func(self.registers.c);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::ArithmeticTarget::D => {
                         $var = self.$func(self.registers.d);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::ArithmeticTarget::E => {
                         $var = self.$func(self.registers.e);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::ArithmeticTarget::H => {
                         $var = self.$func(self.registers.h);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::ArithmeticTarget::L => {
                         $var = self.$func(self.registers.l);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
 
-                    // Bytes: 1; Cycles: 2;
                     instruction::ArithmeticTarget::HLP => {
                         $var = self.$func(self.read_hl_byte());
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
 
-                    // Bytes: 2; Cycles: 2;
                     instruction::ArithmeticTarget::U8 => {
                         $var = self.$func(self.read_next_byte());
-                        (self.pc.wrapping_add(2), 2)
+                        None
                     }
                 }
             };
@@ -227,61 +1085,57 @@ impl CPU {
         macro_rules! incdec_instruction {
             ($target:ident; u8: $func_u8:ident, u16: $func_u16:ident) => {
                 match $target {
-                    // Bytes: 1; Cycles: 1;
                     instruction::IncDecTarget::A => {
                         self.registers.a = self.$func_u8(self.registers.a);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::IncDecTarget::B => {
                         self.registers.b = self.$func_u8(self.registers.b);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::IncDecTarget::C => {
                         self.registers.c = self.$func_u8(self.registers.c);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::IncDecTarget::D => {
                         self.registers.d = self.$func_u8(self.registers.d);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::IncDecTarget::E => {
                         self.registers.e = self.$func_u8(self.registers.e);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::IncDecTarget::H => {
                         self.registers.h = self.$func_u8(self.registers.h);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::IncDecTarget::L => {
                         self.registers.l = self.$func_u8(self.registers.l);
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
 
-                    // Bytes: 1; Cycles: 2;
                     instruction::IncDecTarget::BC => {
                         self.registers.set_bc(self.$func_u16(self.registers.bc()));
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                     instruction::IncDecTarget::DE => {
                         self.registers.set_de(self.$func_u16(self.registers.de()));
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                     instruction::IncDecTarget::HL => {
                         self.registers.set_hl(self.$func_u16(self.registers.hl()));
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
 
-                    // Bytes: 1; Cycles: 3;
                     instruction::IncDecTarget::HLP => {
                         let new_val = self.$func_u8(self.read_hl_byte());
                         self.memory.write_byte(self.registers.hl(), new_val);
-                        (self.pc.wrapping_add(1), 3)
+                        None
                     }
 
-                    // Bytes: 1; Cycles: 2;
                     instruction::IncDecTarget::SP => {
                         self.sp = self.$func_u16(self.sp);
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                 }
             };
@@ -292,35 +1146,35 @@ impl CPU {
                 match $target {
                     instruction::LoadByteTarget::A => {
                         self.registers.a = $source;
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::LoadByteTarget::B => {
                         self.registers.b = $source;
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::LoadByteTarget::C => {
                         self.registers.c = $source;
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::LoadByteTarget::D => {
                         self.registers.d = $source;
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::LoadByteTarget::E => {
                         self.registers.e = $source;
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::LoadByteTarget::H => {
                         self.registers.h = $source;
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::LoadByteTarget::L => {
                         self.registers.l = $source;
-                        (self.pc.wrapping_add(1), 1)
+                        None
                     }
                     instruction::LoadByteTarget::HLP => {
                         self.memory.write_byte(self.registers.hl(), $source);
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                 }
             };
@@ -331,42 +1185,42 @@ impl CPU {
                 match $target {
                     instruction::PrefixTarget::A => {
                         self.registers.a = self.$func(self.registers.a, $($opt),*);
-                        (self.pc.wrapping_add( 2), 2)
+                        None
                     }
                     instruction::PrefixTarget::B => {
                         self.registers.b = self.$func(self.registers.b, $($opt),*);
-                        (self.pc.wrapping_add( 2), 2)
+                        None
                     }
                     instruction::PrefixTarget::C => {
                         self.registers.c = self.$func(self.registers.c, $($opt),*);
-                        (self.pc.wrapping_add( 2), 2)
+                        None
                     }
                     instruction::PrefixTarget::D => {
                         self.registers.d = self.$func(self.registers.d, $($opt),*);
-                        (self.pc.wrapping_add( 2), 2)
+                        None
                     }
                     instruction::PrefixTarget::E => {
                         self.registers.e = self.$func(self.registers.e, $($opt),*);
-                        (self.pc.wrapping_add( 2), 2)
+                        None
                     }
                     instruction::PrefixTarget::H => {
                         self.registers.h = self.$func(self.registers.h, $($opt),*);
-                        (self.pc.wrapping_add( 2), 2)
+                        None
                     }
                     instruction::PrefixTarget::L => {
                         self.registers.l = self.$func(self.registers.l, $($opt),*);
-                        (self.pc.wrapping_add( 2), 2)
+                        None
                     }
                     instruction::PrefixTarget::HLP => {
                         let new_val = self.$func(self.read_hl_byte(), $($opt),*);
                         self.memory.write_byte(self.registers.hl(), new_val);
-                        (self.pc.wrapping_add( 2), 4)
+                        None
                     }
                 }
             };
         }
 
-        let res = match instruction {
+        match instruction {
             Instruction::ADD(target) => {
                 arithmetic_instruction!(target; add_without_carry => self.registers.a)
             }
@@ -387,22 +1241,22 @@ impl CPU {
                 instruction::ADDHLTarget::BC => {
                     let new_val = self.add_hl(self.registers.bc());
                     self.registers.set_hl(new_val);
-                    (self.pc.wrapping_add(1), 2)
+                    None
                 }
                 instruction::ADDHLTarget::DE => {
                     let new_val = self.add_hl(self.registers.de());
                     self.registers.set_hl(new_val);
-                    (self.pc.wrapping_add(1), 2)
+                    None
                 }
                 instruction::ADDHLTarget::HL => {
                     let new_val = self.add_hl(self.registers.hl());
                     self.registers.set_hl(new_val);
-                    (self.pc.wrapping_add(1), 2)
+                    None
                 }
                 instruction::ADDHLTarget::SP => {
                     let new_val = self.add_hl(self.sp);
                     self.registers.set_hl(new_val);
-                    (self.pc.wrapping_add(1), 2)
+                    None
                 }
             },
 
@@ -416,115 +1270,115 @@ impl CPU {
             Instruction::BIT(pos, target) => match target {
                 instruction::PrefixTarget::A => {
                     self.check_bit(self.registers.a, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::B => {
                     self.check_bit(self.registers.b, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::C => {
                     self.check_bit(self.registers.c, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::D => {
                     self.check_bit(self.registers.d, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::E => {
                     self.check_bit(self.registers.e, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::H => {
                     self.check_bit(self.registers.h, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::L => {
                     self.check_bit(self.registers.l, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::HLP => {
                     self.check_bit(self.read_hl_byte(), pos as u32);
-                    (self.pc.wrapping_add(2), 3)
+                    None
                 }
             },
             Instruction::RES(pos, target) => match target {
                 instruction::PrefixTarget::A => {
                     self.registers.a = self.reset_bit(self.registers.a, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::B => {
                     self.registers.b = self.reset_bit(self.registers.b, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::C => {
                     self.registers.c = self.reset_bit(self.registers.c, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::D => {
                     self.registers.d = self.reset_bit(self.registers.d, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::E => {
                     self.registers.e = self.reset_bit(self.registers.e, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::H => {
                     self.registers.h = self.reset_bit(self.registers.h, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::L => {
                     self.registers.l = self.reset_bit(self.registers.l, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::HLP => {
                     self.memory.write_byte(
                         self.registers.hl(),
                         self.reset_bit(self.read_hl_byte(), pos as u32),
                     );
-                    (self.pc.wrapping_add(2), 4)
+                    None
                 }
             },
             Instruction::SET(pos, target) => match target {
                 instruction::PrefixTarget::A => {
                     self.registers.a = self.set_bit(self.registers.a, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::B => {
                     self.registers.b = self.set_bit(self.registers.b, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::C => {
                     self.registers.c = self.set_bit(self.registers.c, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::D => {
                     self.registers.d = self.set_bit(self.registers.d, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::E => {
                     self.registers.e = self.set_bit(self.registers.e, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::H => {
                     self.registers.h = self.set_bit(self.registers.h, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::L => {
                     self.registers.l = self.set_bit(self.registers.l, pos as u32);
-                    (self.pc.wrapping_add(2), 2)
+                    None
                 }
                 instruction::PrefixTarget::HLP => {
                     self.memory.write_byte(
                         self.registers.hl(),
                         self.set_bit(self.read_hl_byte(), pos as u32),
                     );
-                    (self.pc.wrapping_add(2), 4)
+                    None
                 }
             },
 
             Instruction::CPL => {
                 self.registers.a = self.complement_accum();
-                (self.pc.wrapping_add(1), 1)
+                None
             }
             Instruction::AND(target) => {
                 arithmetic_instruction!(target; bitwise_and => self.registers.a)
@@ -538,11 +1392,11 @@ impl CPU {
 
             Instruction::SCF => {
                 self.set_carry_flag(true);
-                (self.pc.wrapping_add(1), 1)
+                None
             }
             Instruction::CCF => {
                 self.set_carry_flag(!self.registers.f.carry);
-                (self.pc.wrapping_add(1), 1)
+                None
             }
 
             #[allow(clippy::self_assignment)]
@@ -556,14 +1410,8 @@ impl CPU {
                     instruction::LoadByteSource::H => load_byte!(target, self.registers.h),
                     instruction::LoadByteSource::L => load_byte!(target, self.registers.l),
 
-                    instruction::LoadByteSource::HLP => {
-                        let res = load_byte!(target, self.read_hl_byte());
-                        (res.0, res.1 + 1)
-                    }
-                    instruction::LoadByteSource::U8 => {
-                        let res = load_byte!(target, self.read_next_byte());
-                        (res.0 + 1, res.1 + 1)
-                    }
+                    instruction::LoadByteSource::HLP => load_byte!(target, self.read_hl_byte()),
+                    instruction::LoadByteSource::U8 => load_byte!(target, self.read_next_byte()),
                 },
 
                 instruction::LoadType::Word(target) => {
@@ -574,39 +1422,39 @@ impl CPU {
                         instruction::LoadWordTarget::HL => self.registers.set_hl(value),
                         instruction::LoadWordTarget::SP => self.sp = value,
                     }
-                    (self.pc.wrapping_add(3), 3)
+                    None
                 }
 
                 instruction::LoadType::AFromIndirect(target) => match target {
                     instruction::IndirectTarget::C => {
                         self.registers.a = self.memory.read_high_byte(self.registers.c);
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                     instruction::IndirectTarget::U8 => {
                         self.registers.a = self.memory.read_high_byte(self.read_next_byte());
-                        (self.pc.wrapping_add(2), 3)
+                        None
                     }
                     instruction::IndirectTarget::U16 => {
                         self.registers.a = self.memory.read_byte(self.read_next_word());
-                        (self.pc.wrapping_add(3), 4)
+                        None
                     }
                     instruction::IndirectTarget::BCP => {
                         self.registers.a = self.memory.read_byte(self.registers.bc());
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                     instruction::IndirectTarget::DEP => {
                         self.registers.a = self.memory.read_byte(self.registers.de());
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                     instruction::IndirectTarget::HLI => {
                         self.registers.a = self.memory.read_byte(self.registers.hl());
                         self.registers.set_hl(self.registers.hl() + 1);
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                     instruction::IndirectTarget::HLD => {
                         self.registers.a = self.memory.read_byte(self.registers.hl());
                         self.registers.set_hl(self.registers.hl() - 1);
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                 },
 
@@ -614,39 +1462,39 @@ impl CPU {
                     instruction::IndirectTarget::C => {
                         self.memory
                             .write_high_byte(self.registers.c, self.registers.a);
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                     instruction::IndirectTarget::U8 => {
                         self.memory
                             .write_high_byte(self.read_next_byte(), self.registers.a);
-                        (self.pc.wrapping_add(2), 3)
+                        None
                     }
                     instruction::IndirectTarget::U16 => {
                         self.memory
                             .write_byte(self.read_next_word(), self.registers.a);
-                        (self.pc.wrapping_add(3), 4)
+                        None
                     }
                     instruction::IndirectTarget::BCP => {
                         self.memory
                             .write_byte(self.registers.bc(), self.registers.a);
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                     instruction::IndirectTarget::DEP => {
                         self.memory
                             .write_byte(self.registers.de(), self.registers.a);
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                     instruction::IndirectTarget::HLI => {
                         self.memory
                             .write_byte(self.registers.hl(), self.registers.a);
                         self.registers.set_hl(self.registers.hl() + 1);
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                     instruction::IndirectTarget::HLD => {
                         self.memory
                             .write_byte(self.registers.hl(), self.registers.a);
                         self.registers.set_hl(self.registers.hl() - 1);
-                        (self.pc.wrapping_add(1), 2)
+                        None
                     }
                 },
 
@@ -656,12 +1504,12 @@ impl CPU {
                     self.memory.write_byte(addr, self.sp as u8);
                     self.memory
                         .write_byte(addr + 1, (self.sp >> u8::BITS) as u8);
-                    (self.pc.wrapping_add(3), 5)
+                    None
                 }
 
                 instruction::LoadType::SPFromHL => {
                     self.sp = self.registers.hl();
-                    (self.pc.wrapping_add(1), 2)
+                    None
                 }
 
                 instruction::LoadType::HLFromSPN => {
@@ -675,31 +1523,31 @@ impl CPU {
                     // Set if overflow from bit 7.
                     self.registers.f.carry = (self.sp & 0xFF) + (val & 0xFF) > 0xFF;
 
-                    (self.pc.wrapping_add(2), 3)
+                    None
                 }
             },
 
             Instruction::RL(target) => bit_shift_instruction!(target; rotate_left: true, true),
             Instruction::RLA => {
                 self.registers.a = self.rotate_left(self.registers.a, true, false);
-                (self.pc.wrapping_add(1), 1)
+                None
             }
             Instruction::RLC(target) => bit_shift_instruction!(target; rotate_left: false, true),
             Instruction::RLCA => {
                 self.registers.a = self.rotate_left(self.registers.a, false, false);
-                (self.pc.wrapping_add(1), 1)
+                None
             }
             Instruction::SLA(target) => bit_shift_instruction!(target; shift_left_arith:),
 
             Instruction::RR(target) => bit_shift_instruction!(target; rotate_right: true, true),
             Instruction::RRA => {
                 self.registers.a = self.rotate_right(self.registers.a, true, false);
-                (self.pc.wrapping_add(1), 1)
+                None
             }
             Instruction::RRC(target) => bit_shift_instruction!(target; rotate_right: false, true),
             Instruction::RRCA => {
                 self.registers.a = self.rotate_right(self.registers.a, false, false);
-                (self.pc.wrapping_add(1), 1)
+                None
             }
             Instruction::SRA(target) => bit_shift_instruction!(target; shift_right: true),
             Instruction::SRL(target) => bit_shift_instruction!(target; shift_right: false),
@@ -709,42 +1557,34 @@ impl CPU {
             Instruction::JR(test) => {
                 let addr = self.read_next_byte() as i8 as i16 as u16;
                 let jump = self.jump_test_res(test);
-                self.jump_relative(addr, jump)
+                jump.then(|| next_pc.wrapping_add(addr))
             }
             Instruction::JP(test) => {
                 let addr = self.read_next_word();
                 let jump = self.jump_test_res(test);
-                self.jump_absolute(addr, jump)
+                jump.then_some(addr)
             }
-            Instruction::JPHLP => (self.registers.hl(), 1),
+            Instruction::JPHLP => Some(self.registers.hl()),
 
             Instruction::CALL(test) => {
                 let jump_addr = self.read_next_word();
                 let jump_test = self.jump_test_res(test);
-                self.call(jump_addr, jump_test)
+                self.call(jump_addr, jump_test, next_pc)
             }
 
             Instruction::RET(test) => {
                 let jump_test = self.jump_test_res(test);
-                let next_pc = self.ret(jump_test);
-                let cycles = if let instruction::JumpTest::Always = test {
-                    4
-                } else if jump_test {
-                    5
-                } else {
-                    2
-                };
-                (next_pc, cycles)
+                self.ret(jump_test)
             }
 
             Instruction::RETI => {
                 self.interrupts_enabled = true;
-                (self.ret(true), 4)
+                self.ret(true)
             }
 
             Instruction::RST(vec_) => {
-                self.push_stack(self.pc.wrapping_add(1));
-                (vec_.to_addr(), 4)
+                self.push_stack(next_pc);
+                Some(vec_.to_addr())
             }
 
             Instruction::ADDSP => {
@@ -753,76 +1593,80 @@ impl CPU {
                 // it will subtract if i8 is negative.
                 let val = self.read_next_byte() as i8 as i16 as u16;
                 self.move_sp_relative(val);
-                (self.pc.wrapping_add(2), 4)
+                None
             }
 
             Instruction::PUSH(target) => match target {
                 instruction::StackTarget::AF => {
                     self.push_stack(self.registers.af());
-                    (self.pc.wrapping_add(1), 4)
+                    None
                 }
                 instruction::StackTarget::BC => {
                     self.push_stack(self.registers.bc());
-                    (self.pc.wrapping_add(1), 4)
+                    None
                 }
                 instruction::StackTarget::DE => {
                     self.push_stack(self.registers.de());
-                    (self.pc.wrapping_add(1), 4)
+                    None
                 }
                 instruction::StackTarget::HL => {
                     self.push_stack(self.registers.hl());
-                    (self.pc.wrapping_add(1), 4)
+                    None
                 }
             },
             Instruction::POP(target) => match target {
                 instruction::StackTarget::AF => {
                     let val = self.pop_stack();
                     self.registers.set_af(val);
-                    (self.pc.wrapping_add(1), 3)
+                    None
                 }
                 instruction::StackTarget::BC => {
                     let val = self.pop_stack();
                     self.registers.set_bc(val);
-                    (self.pc.wrapping_add(1), 3)
+                    None
                 }
                 instruction::StackTarget::DE => {
                     let val = self.pop_stack();
                     self.registers.set_de(val);
-                    (self.pc.wrapping_add(1), 3)
+                    None
                 }
                 instruction::StackTarget::HL => {
                     let val = self.pop_stack();
                     self.registers.set_hl(val);
-                    (self.pc.wrapping_add(1), 3)
+                    None
                 }
             },
 
             Instruction::DI => {
                 self.di_timer = 2;
-                (self.pc.wrapping_add(1), 1)
+                None
             }
             Instruction::EI => {
                 self.ei_timer = 2;
-                (self.pc.wrapping_add(1), 1)
+                None
             }
 
             Instruction::HALT => {
-                self.is_halted = true;
-                (self.pc.wrapping_add(1), 1)
+                // With IME off and an interrupt already pending, HALT doesn't halt and the HALT
+                // bug reads the next byte twice.
+                if !self.interrupts_enabled && self.memory.pending_interrupt() {
+                    self.halt_bug = true;
+                } else {
+                    self.is_halted = true;
+                }
+                None
             }
 
             Instruction::DAA => {
                 self.registers.a = self.decimal_adjust_accum(self.registers.a);
-                (self.pc.wrapping_add(1), 1)
+                None
             }
 
-            Instruction::NOP => (self.pc.wrapping_add(1), 1),
+            Instruction::NOP => None,
 
             // https://gbdev.io/pandocs/Reducing_Power_Consumption.html?highlight=stop#using-the-stop-instruction
             Instruction::STOP => unimplemented!("STOP instruction is not supported currently."),
-        };
-        // Convert MCycles to TCycles.
-        (res.0, res.1 * 4)
+        }
     }
 
     // https://rgbds.gbdev.io/docs/v0.9.0/gbz80.7
@@ -1088,40 +1932,16 @@ impl CPU {
     }
 
     #[must_use]
-    fn jump_relative(&mut self, addr: u16, jump: bool) -> (u16, u64) {
+    fn call(&mut self, addr: u16, jump: bool, next_pc: u16) -> Option<u16> {
         if jump {
-            (self.pc.wrapping_add(2).wrapping_add(addr), 3)
-        } else {
-            (self.pc.wrapping_add(2), 2)
+            self.push_stack(next_pc);
         }
+        jump.then_some(addr)
     }
 
     #[must_use]
-    fn jump_absolute(&mut self, addr: u16, jump: bool) -> (u16, u64) {
-        if jump {
-            (addr, 4)
-        } else {
-            (self.pc.wrapping_add(3), 3)
-        }
-    }
-
-    #[must_use]
-    fn call(&mut self, addr: u16, jump: bool) -> (u16, u64) {
-        if jump {
-            self.push_stack(self.pc.wrapping_add(3));
-            (addr, 6)
-        } else {
-            (self.pc.wrapping_add(3), 3)
-        }
-    }
-
-    #[must_use]
-    fn ret(&mut self, jump: bool) -> u16 {
-        if jump {
-            self.pop_stack()
-        } else {
-            self.pc.wrapping_add(1)
-        }
+    fn ret(&mut self, jump: bool) -> Option<u16> {
+        jump.then(|| self.pop_stack())
     }
 
     fn move_sp_relative(&mut self, addr: u16) {
@@ -1154,10 +1974,134 @@ impl CPU {
     }
 }
 
+impl SaveState for CPU {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        let mut model = self.model as u8;
+        state.u8(&mut model);
+        state.check(model == self.model as u8, || {
+            format!("it was made for another model than {:?}", self.model)
+        });
+
+        let regs = &mut self.registers;
+        for reg in [
+            &mut regs.a,
+            &mut regs.b,
+            &mut regs.c,
+            &mut regs.d,
+            &mut regs.e,
+            &mut regs.h,
+            &mut regs.l,
+        ] {
+            state.u8(reg);
+        }
+        state.as_u8(&mut regs.f, u8::from, |val| Some(val.into()));
+        state.u16(&mut self.pc);
+        state.u16(&mut self.sp);
+        state.bool(&mut self.is_halted);
+        state.bool(&mut self.halt_bug);
+        state.bool(&mut self.interrupts_enabled);
+        state.u8(&mut self.di_timer);
+        state.u8(&mut self.ei_timer);
+        state.u64(&mut self.last_frame_count);
+
+        state.visit(&mut self.memory);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn save_and_load_state() {
+        let mut rom = vec![0; 0x8000];
+        // JP $0150
+        rom[0x100..][..3].copy_from_slice(&[0xC3, 0x50, 0x01]);
+        // LD HL,$C000; loop: INC (HL); INC L; LDH A,(DIV); ADD A,(HL); LD (HL),A; JR loop
+        rom[0x150..][..11].copy_from_slice(&[
+            0x21, 0x00, 0xC0, 0x34, 0x2C, 0xF0, 0x04, 0x86, 0x77, 0x18, 0xF8,
+        ]);
+        let mut cpu = CPU::new_without_sound(rom.clone());
+        let _ = cpu.run(1_000_000);
+
+        let state = cpu.save_state();
+        let _ = cpu.run(500_000);
+        let expected = cpu.save_state();
+
+        cpu.load_state(&state).unwrap();
+        let _ = cpu.run(500_000);
+        assert_eq!(cpu.save_state(), expected);
+
+        // A fresh emulator continues the same way.
+        let mut other = CPU::new_without_sound(rom);
+        other.load_state(&state).unwrap();
+        let _ = other.run(500_000);
+        assert_eq!(other.save_state(), expected);
+
+        // Failed loads keep the state.
+        let mut other_game = CPU::new_without_sound(vec![0; 0x8000]);
+        let before = other_game.save_state();
+        assert_eq!(
+            other_game.load_state(&state).unwrap_err().to_string(),
+            "invalid save state: it was made with another game"
+        );
+        assert_eq!(other_game.save_state(), before);
+        assert!(cpu.load_state(&state[..state.len() / 2]).is_err());
+        assert_eq!(cpu.save_state(), expected);
+    }
+
+    #[test]
+    fn cheats() {
+        let mut rom = vec![0; 0x8000];
+        // JR -2
+        rom[0x100..][..2].copy_from_slice(&[0x18, 0xFE]);
+        rom[0x200] = 0x12;
+        let mut cpu = CPU::new_without_sound(rom);
+        assert!(cpu.add_cheat("342-007").is_err());
+
+        cpu.add_cheat("342-00F").unwrap();
+        cpu.add_cheat("019900C0").unwrap();
+        assert_eq!(cpu.debug_read(0x200), 0x34);
+        assert_eq!(cpu.debug_read(0xC000), 0x00);
+        cpu.run_frame();
+        assert_eq!(cpu.debug_read(0xC000), 0x99);
+
+        cpu.clear_cheats();
+        cpu.memory.write_byte(0xC000, 0);
+        cpu.run_frame();
+        assert_eq!(cpu.debug_read(0x200), 0x12);
+        assert_eq!(cpu.debug_read(0xC000), 0x00);
+    }
+
+    #[test]
+    fn boot_rom() {
+        let mut rom = vec![0; 0x8000];
+        // JR -2
+        rom[0x100..][..2].copy_from_slice(&[0x18, 0xFE]);
+        let mut cpu = CPU::new_without_sound(rom);
+        assert!(cpu.set_boot_rom(vec![0; 0x200]).is_err());
+        assert_eq!(cpu.pc, 0x100);
+
+        // NOPs, then LD A,1; LDH ($50),A right before the cartridge starts at 0x100.
+        let mut boot_rom = vec![0; crate::memory_bus::BOOT_ROM_SIZE];
+        boot_rom[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        boot_rom[0] = 0x42;
+        cpu.set_boot_rom(boot_rom).unwrap();
+        assert_eq!(cpu.pc, 0);
+        assert_eq!(cpu.memory.read_byte(0), 0x42);
+        assert_eq!(u8::from(cpu.memory.gpu.lcd_control), 0);
+
+        while cpu.pc != 0x100 {
+            cpu.cycle();
+        }
+        assert_eq!(cpu.memory.read_byte(0), 0);
+        assert!(!cpu.memory.is_boot_rom_mapped());
+
+        // Resets run it again.
+        cpu.soft_reset();
+        assert_eq!((cpu.pc, cpu.memory.read_byte(0)), (0, 0x42));
+    }
+
     #[test]
     fn instruction_swap_bits() {
         let mut cpu = CPU::new_without_sound(vec![0; 0x200]);
@@ -1176,6 +2120,412 @@ mod test {
         assert_eq!(cpu.registers.f, flag);
     }
 
+    #[test]
+    fn soft_reset_keeps_cartridge_ram() {
+        let mut rom = vec![0; 0x8000];
+        // MBC1+RAM, 8 KiB RAM.
+        rom[crate::mbc::CARTRIDGE_TYPE_ADDR] = 0x02;
+        rom[crate::mbc::RAM_SIZE_ADDR] = 0x02;
+        let mut cpu = CPU::new_without_sound(rom);
+
+        cpu.memory.write_byte(0x0000, 0x0A);
+        cpu.memory.write_byte(0xA000, 0x42);
+        cpu.memory.write_byte(0xC000, 0x42);
+        cpu.pc = 0x1234;
+
+        cpu.soft_reset();
+        assert_eq!(cpu.pc, 0x100);
+        assert_eq!(cpu.memory.read_byte(0xC000), 0);
+        // RAM is disabled after reset.
+        assert_eq!(cpu.memory.read_byte(0xA000), 0xFF);
+        cpu.memory.write_byte(0x0000, 0x0A);
+        assert_eq!(cpu.memory.read_byte(0xA000), 0x42);
+
+        cpu.hard_reset();
+        cpu.memory.write_byte(0x0000, 0x0A);
+        assert_eq!(cpu.memory.read_byte(0xA000), 0);
+    }
+
+    #[test]
+    fn interrupt_dispatch() {
+        let mut cpu = CPU::new_without_sound(vec![0; 0x8000]);
+        let request = |cpu: &mut CPU, enabled: u8, requested: u8| {
+            cpu.memory.write_byte(0xFFFF, enabled);
+            cpu.memory.write_byte(0xFF0F, requested);
+        };
+        cpu.pc = 0x1234;
+        cpu.sp = 0xD000;
+
+        // A pending interrupt wakes the CPU even with IME off, without servicing it.
+        cpu.interrupts_enabled = false;
+        cpu.is_halted = true;
+        request(&mut cpu, 0x04, 0x04);
+        assert_eq!(cpu.process_interrupts(), 0);
+        assert!(!cpu.is_halted);
+        assert_eq!(cpu.pc, 0x1234);
+
+        // 5 M-cycles, one more when halted.
+        cpu.interrupts_enabled = true;
+        assert_eq!(cpu.process_interrupts(), 20);
+        assert_eq!((cpu.pc, cpu.sp), (0x50, 0xCFFE));
+        let pushed = [cpu.memory.read_byte(0xCFFE), cpu.memory.read_byte(0xCFFF)];
+        assert_eq!(pushed, [0x34, 0x12]);
+        assert_eq!(cpu.memory.interrupt_flags(), 0);
+        cpu.interrupts_enabled = true;
+        cpu.is_halted = true;
+        request(&mut cpu, 0x01, 0x01);
+        assert_eq!(cpu.process_interrupts(), 24);
+        assert_eq!(cpu.pc, 0x40);
+
+        // Pushing the high byte of PC to IE disables the requested interrupt: dispatch is
+        // cancelled and jumps to $0000.
+        cpu.interrupts_enabled = true;
+        (cpu.pc, cpu.sp) = (0x0234, 0x0000);
+        request(&mut cpu, 0x04, 0x04);
+        assert_eq!(cpu.process_interrupts(), 20);
+        assert_eq!(cpu.pc, 0x0000);
+        assert_eq!(cpu.memory.interrupt_flags(), 0x04);
+    }
+
+    /// CPU at HALT followed by `program`, with IME off and a pending interrupt for the HALT bug.
+    fn halt_bug_cpu(program: &[u8]) -> CPU {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100] = 0x76;
+        rom[0x101..][..program.len()].copy_from_slice(program);
+        let mut cpu = CPU::new_without_sound(rom);
+        cpu.interrupts_enabled = false;
+        cpu.memory.write_byte(0xFFFF, 0x04);
+        cpu.memory.write_byte(0xFF0F, 0x04);
+        cpu.step();
+        assert!(!cpu.is_halted);
+        cpu
+    }
+
+    #[test]
+    fn halt_bug() {
+        // INC A; HALT; LD B,$04
+        let mut cpu = halt_bug_cpu(&[0x3C, 0x76, 0x06, 0x04]);
+        cpu.registers.a = 0;
+
+        // INC A runs twice, then LD B reads its own opcode as the operand.
+        cpu.step();
+        cpu.step();
+        assert_eq!((cpu.registers.a, cpu.pc), (2, 0x102));
+        cpu.step();
+        cpu.step();
+        assert_eq!((cpu.registers.b, cpu.pc), (0x06, 0x104));
+    }
+
+    #[test]
+    fn halt_bug_jumps() {
+        // JR +2 jumps by its own opcode from the byte after it.
+        let mut cpu = halt_bug_cpu(&[0x18, 0x02]);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x102 + 0x18);
+
+        // CALL $1234 calls $34CD and returns after its second byte.
+        let mut cpu = halt_bug_cpu(&[0xCD, 0x34, 0x12]);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x34CD);
+        assert_eq!(cpu.pop_stack(), 0x0103);
+
+        // RST $08 returns to itself.
+        let mut cpu = halt_bug_cpu(&[0xCF]);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x0008);
+        assert_eq!(cpu.pop_stack(), 0x0101);
+    }
+
+    #[test]
+    fn frames_iterator() {
+        let mut cpu = CPU::new_without_sound(vec![0; 0x8000]);
+        let frames: Vec<_> = cpu.frames().take(3).collect();
+        let numbers: Vec<_> = frames.iter().map(|frame| frame.number).collect();
+        assert_eq!(numbers, [0, 1, 2]);
+        assert!(frames.iter().all(|frame| frame.lcd_on));
+        // One LCD frame apart, give or take the last instruction.
+        let frame_time = frames[2].cycle - frames[1].cycle;
+        assert!(frame_time.abs_diff(crate::TICKS_PER_LCD_FRAME) < 24);
+        let cycles = cpu.cycles();
+        assert_eq!(cpu.screen().cycle, cycles);
+        assert!(cpu.screen().timestamp() > std::time::Duration::from_millis(33));
+        assert_eq!(cpu.run_frame().number, 3);
+
+        let mut rom = vec![0; 0x8000];
+        // XOR A; LDH (LCDC),A; JR -2
+        rom[0x100..][..5].copy_from_slice(&[0xAF, 0xE0, 0x40, 0x18, 0xFE]);
+        let mut cpu = CPU::new_without_sound(rom);
+        for frame in cpu.frames().take(2) {
+            assert!(frame.pixels.iter().all(|&pixel| pixel == 0xFFFFFF));
+            assert!(!frame.lcd_on);
+        }
+        assert_eq!(cpu.gpu().frame_count(), 0);
+
+        cpu.add_temp_breakpoint(Breakpoint::Pc(0x103));
+        assert!(cpu.next_frame().is_none());
+    }
+
+    #[test]
+    fn step_scanline() {
+        let mut rom = vec![0; 0x8000];
+        // JR -2
+        rom[0x100..][..2].copy_from_slice(&[0x18, 0xFE]);
+        let mut cpu = CPU::new_without_sound(rom);
+        let lines: Vec<_> = (0..154).map(|_| cpu.step_scanline().unwrap()).collect();
+        assert_eq!(lines, (1..=153).chain([0]).collect::<Vec<_>>());
+        assert_eq!(cpu.gpu().frame_count(), 1);
+
+        cpu.add_temp_breakpoint(Breakpoint::Pc(0x100));
+        assert_eq!(cpu.step_scanline(), None);
+        assert_eq!(cpu.gpu().lcd_status.line(), 0);
+        assert_eq!(cpu.step_scanline(), Some(1));
+    }
+
+    #[test]
+    fn input_macros() {
+        let mut rom = vec![0; 0x8000];
+        // JR -2
+        rom[0x100..][..2].copy_from_slice(&[0x18, 0xFE]);
+        let mut cpu = CPU::new_without_sound(rom);
+        let _ = cpu.next_frame();
+
+        cpu.start_macro_recording();
+        cpu.key_down(JoypadKey::A);
+        let _ = cpu.next_frame();
+        let _ = cpu.next_frame();
+        cpu.key_up(JoypadKey::A);
+        let input_macro = cpu.stop_macro_recording().unwrap();
+        assert_eq!(
+            input_macro.to_text(),
+            "# frame key action\n0 a down\n2 a up\n"
+        );
+
+        // Select buttons.
+        cpu.memory.write_byte(0xFF00, 0x10);
+        let a_pressed = |cpu: &CPU| cpu.memory.read_byte(0xFF00) & 1 == 0;
+        cpu.play_macro(&input_macro);
+        let mut pressed = vec![a_pressed(&cpu)];
+        for _ in 0..4 {
+            let _ = cpu.next_frame();
+            pressed.push(a_pressed(&cpu));
+        }
+        assert_eq!(pressed, [false, true, true, false, false]);
+    }
+
+    #[test]
+    fn invalid_roms() {
+        let mut unsupported = vec![0; crate::mbc::HEADER_SIZE];
+        unsupported[crate::mbc::CARTRIDGE_TYPE_ADDR] = 0xFC;
+        for rom in [
+            vec![],
+            vec![0],
+            vec![0; crate::mbc::HEADER_SIZE - 1],
+            unsupported,
+        ] {
+            let len = rom.len();
+            let result = CPU::try_with_model(rom, Box::new(VoidAudioPlayer::new()), Model::Sgb);
+            assert!(matches!(result, Err(GbError::InvalidRom(_))), "{len} bytes");
+        }
+
+        let header_only = vec![0; crate::mbc::HEADER_SIZE];
+        assert!(CPU::try_new(header_only, Box::new(VoidAudioPlayer::new())).is_ok());
+    }
+
+    #[test]
+    fn movies_replay_exactly() {
+        let mut rom = vec![0; 0x8000];
+        // Counts in C while A is held: LD A,0x10; LDH (0x00),A; LDH A,(0x00); RRA; JR C,-5;
+        // INC C; JR -8. The count depends on the exact cycle of the presses.
+        rom[0x100..][..12].copy_from_slice(&[
+            0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, 0x1F, 0x38, 0xFB, 0x0C, 0x18, 0xF8,
+        ]);
+        let mut cpu = CPU::new_without_sound(rom);
+        let script = RngScript::new(vec![0x12], vec![]);
+        cpu.set_rng_script(Some(script.clone()));
+        cpu.start_movie_recording();
+        let _ = cpu.next_frame();
+        let _ = cpu.run(crate::TICKS_PER_FRAME / 3);
+        cpu.key_down(JoypadKey::A);
+        let _ = cpu.next_frame();
+        let _ = cpu.run(crate::TICKS_PER_FRAME / 2);
+        cpu.key_up(JoypadKey::A);
+        let _ = cpu.next_frame();
+        cpu.key_down(JoypadKey::B);
+        let _ = cpu.next_frame();
+        let _ = cpu.next_frame();
+        assert_ne!(cpu.registers().c, 0);
+        let recorded = cpu.save_state();
+        let movie = cpu.stop_movie_recording().unwrap();
+        assert_eq!(
+            movie.inputs.to_text(),
+            "# frame key action\n2 a down\n3 a up\n4 b down\n"
+        );
+        assert_eq!(movie.rng_script, Some(script));
+
+        cpu.key_down(JoypadKey::Start);
+        cpu.set_rng_script(None);
+        cpu.play_movie(&movie).unwrap();
+        assert!(cpu.is_playing_movie());
+        // Ignored while the movie plays.
+        cpu.key_down(JoypadKey::Select);
+        for _ in 0..5 {
+            let _ = cpu.next_frame();
+        }
+        assert!(!cpu.is_playing_movie());
+        assert_eq!(cpu.save_state(), recorded);
+        assert_eq!(cpu.memory.read_byte(0xFF04), 0x12);
+
+        let other = Movie {
+            rom_checksum: 0x1234,
+            ..movie
+        };
+        assert!(cpu.play_movie(&other).is_err());
+    }
+
+    #[test]
+    fn attached_devices() {
+        use std::sync::{Arc, Mutex};
+
+        /// A register at $A000 counting cycles, and a log of the writes to it.
+        struct Counter {
+            cycles: u64,
+            writes: Arc<Mutex<Vec<u8>>>,
+        }
+
+        impl Device for Counter {
+            fn maps(&self, addr: u16) -> bool {
+                addr == 0xA000
+            }
+
+            fn read_byte(&self, _addr: u16) -> u8 {
+                self.cycles as u8
+            }
+
+            fn write_byte(&mut self, _addr: u16, val: u8) {
+                self.writes.lock().unwrap().push(val);
+            }
+
+            fn step(&mut self, cycles: u64) {
+                self.cycles += cycles;
+            }
+        }
+
+        let mut rom = vec![0; 0x8000];
+        // LD A,$42; LD ($A000),A; NOP
+        rom[0x100..][..6].copy_from_slice(&[0x3E, 0x42, 0xEA, 0x00, 0xA0, 0x00]);
+        let mut cpu = CPU::new_without_sound(rom);
+        let writes = Arc::new(Mutex::new(vec![]));
+        cpu.attach_device(Box::new(Counter {
+            cycles: 0,
+            writes: writes.clone(),
+        }));
+
+        cpu.cycle();
+        cpu.cycle();
+        assert_eq!(*writes.lock().unwrap(), [0x42]);
+        // LD A,n takes 8 cycles and LD (nn),A 16.
+        assert_eq!(cpu.memory.read_byte(0xA000), 24);
+        // Other addresses still go to the cartridge.
+        let cartridge = cpu.memory.read_byte(0xA001);
+        assert_ne!(cartridge, 24);
+
+        assert_eq!(cpu.detach_devices().len(), 1);
+        assert_eq!(cpu.memory.read_byte(0xA000), cartridge);
+    }
+
+    #[test]
+    fn frame_callback_stops_emulation() {
+        let mut rom = vec![0; 0x8000];
+        // JR -2
+        rom[0x100] = 0x18;
+        rom[0x101] = 0xFE;
+        let mut cpu = CPU::new_without_sound(rom);
+
+        let (snd, rcv) = std::sync::mpsc::channel();
+        cpu.on_frame(Box::new(move |frame| {
+            snd.send(frame.number).unwrap();
+            if frame.number == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        }));
+
+        assert!(cpu.run(crate::TICKS_PER_FRAME * 10).is_break());
+        assert_eq!(rcv.try_iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        assert!(cpu.run(crate::TICKS_PER_FRAME / 2).is_continue());
+    }
+
+    #[test]
+    fn temp_breakpoints() {
+        let mut rom = vec![0; 0x8000];
+        // NOP, NOP, NOP, JR -2
+        rom[0x103] = 0x18;
+        rom[0x104] = 0xFE;
+        let mut cpu = CPU::new_without_sound(rom);
+
+        cpu.add_temp_breakpoint(Breakpoint::Pc(0x102));
+        assert!(cpu.run(1_000).is_break());
+        assert_eq!(cpu.pc, 0x102);
+        assert_eq!(cpu.take_breakpoint_hit(), Some(Breakpoint::Pc(0x102)));
+        assert_eq!(cpu.take_breakpoint_hit(), None);
+
+        // Breakpoints are removed once hit.
+        assert!(cpu.run(1_000).is_continue());
+
+        let frames = cpu.gpu().frame_count();
+        cpu.add_temp_breakpoint(Breakpoint::Scanline(0));
+        cpu.add_temp_breakpoint(Breakpoint::Scanline(10));
+        assert!(cpu.run(crate::TICKS_PER_FRAME * 2).is_break());
+        assert_eq!(cpu.take_breakpoint_hit(), Some(Breakpoint::Scanline(0)));
+        assert!(cpu.run(crate::TICKS_PER_FRAME * 2).is_break());
+        assert_eq!(cpu.take_breakpoint_hit(), Some(Breakpoint::Scanline(10)));
+        assert_eq!(cpu.gpu().lcd_status.line(), 10);
+        assert_eq!(cpu.gpu().frame_count(), frames + 1);
+    }
+
+    #[test]
+    fn opcode_table_matches_execute() {
+        const START: u16 = 0x200;
+
+        for (idx, opcode) in crate::opcodes::TABLE.iter().enumerate() {
+            let (byte, prefixed) = (idx as u8, idx >= 256);
+            let instruction = Instruction::from_byte(byte, prefixed);
+            assert_eq!(
+                instruction.is_none(),
+                opcode.is_illegal() || opcode.mnemonic == "PREFIX",
+                "{}",
+                opcode.mnemonic
+            );
+            // STOP is not emulated.
+            let Some(instruction) = instruction.filter(|_| opcode.mnemonic != "STOP") else {
+                continue;
+            };
+
+            let mut jumps = 0;
+            // Run twice, so conditional instructions are both taken and not taken.
+            for flags in [false, true] {
+                let mut cpu = CPU::new_without_sound(vec![0; 0x8000]);
+                cpu.pc = START;
+                cpu.registers.f.zero = flags;
+                cpu.registers.f.carry = flags;
+                let next_pc = START + opcode.length as u16;
+                jumps += cpu.execute(instruction, next_pc).is_some() as usize;
+            }
+
+            let is_jump = ["JR", "JP", "CALL", "RET", "RST"]
+                .iter()
+                .any(|jump| opcode.mnemonic.starts_with(jump));
+            let expected = match (is_jump, opcode.cycles_taken) {
+                (false, _) => 0,
+                (true, Some(_)) => 1,
+                (true, None) => 2,
+            };
+            assert_eq!(jumps, expected, "{}", opcode.mnemonic);
+        }
+    }
+
     #[test]
     fn different_n8_cast() {
         let a = -10i8;
@@ -1191,4 +2541,23 @@ mod test {
         assert_eq!(-126i8 as u8, 130);
         assert_eq!(130u8 as i8, -126);
     }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn peek_and_poke() {
+        let mut cpu = CPU::new_without_sound(vec![0; 0x8000]);
+        cpu.poke(0xC123, 0x42);
+        assert_eq!(cpu.peek(0xC123), 0x42);
+        // Echo RAM.
+        assert_eq!(cpu.peek(0xE123), 0x42);
+
+        // Runs `inc a` from WRAM.
+        cpu.poke(0xC000, 0x3C);
+        cpu.set_pc(0xC000);
+        cpu.set_sp(0xDFF0);
+        cpu.registers_mut().a = 0x41;
+        cpu.cycle();
+        assert_eq!(cpu.registers().a, 0x42);
+        assert_eq!((cpu.pc(), cpu.sp()), (0xC001, 0xDFF0));
+    }
 }
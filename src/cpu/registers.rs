@@ -1,4 +1,4 @@
-use crate::bit;
+use crate::{bit, model::Model};
 
 pub const HALF_CARRY_MASK: u8 = 0xF;
 
@@ -38,6 +38,60 @@ impl CpuRegisters {
         }
     }
 
+    /// Values before the boot ROM runs. Random on hardware, the boot ROM sets what it needs.
+    pub fn power_on() -> Self {
+        Self {
+            a: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            f: FlagsRegister::from(0),
+            h: 0,
+            l: 0,
+        }
+    }
+
+    /// Values left by the boot ROM.
+    /// https://gbdev.io/pandocs/Power_Up_Sequence.html#cpu-registers
+    pub fn after_boot(model: Model, header_checksum: u8) -> Self {
+        let mut regs = Self::new();
+        match model {
+            Model::Dmg => (),
+            Model::Mgb => regs.a = 0xFF,
+            Model::Cgb => {
+                regs = Self {
+                    a: 0x11,
+                    b: 0x00,
+                    c: 0x00,
+                    d: 0xFF,
+                    e: 0x56,
+                    f: FlagsRegister::from(0x80),
+                    h: 0x00,
+                    l: 0x0D,
+                };
+            }
+            Model::Sgb => {
+                regs = Self {
+                    a: 0x01,
+                    b: 0x00,
+                    c: 0x14,
+                    d: 0x00,
+                    e: 0x00,
+                    f: FlagsRegister::from(0x00),
+                    h: 0xC0,
+                    l: 0x60,
+                };
+            }
+        }
+        // DMG boot ROMs leave H and C set unless the header checksum is 0.
+        if matches!(model, Model::Dmg | Model::Mgb) && header_checksum == 0 {
+            regs.f.half_carry = false;
+            regs.f.carry = false;
+        }
+        regs
+    }
+
     pub fn af(&self) -> u16 {
         (self.a as u16) << (u8::BITS as u16) | (u8::from(self.f) as u16)
     }
@@ -126,6 +180,21 @@ mod test {
         assert_eq!(reg.bc(), 0xab13);
     }
 
+    #[test]
+    fn boot_values_by_model() {
+        let dmg = CpuRegisters::after_boot(Model::Dmg, 0x42);
+        let mgb = CpuRegisters::after_boot(Model::Mgb, 0x42);
+        assert_eq!(dmg.af(), 0x01B0);
+        assert_eq!(mgb.af(), 0xFFB0);
+        assert_eq!(
+            (dmg.bc(), dmg.de(), dmg.hl()),
+            (mgb.bc(), mgb.de(), mgb.hl())
+        );
+
+        assert_eq!(CpuRegisters::after_boot(Model::Dmg, 0).af(), 0x0180);
+        assert_eq!(CpuRegisters::after_boot(Model::Cgb, 0).af(), 0x1180);
+    }
+
     #[test]
     fn flags_register_from_u8_test() {
         assert_eq!(
@@ -0,0 +1,186 @@
+// A tiny ROM built into the emulator, run by `gbemu --demo` to check video, input and audio
+// without looking for games: the d-pad moves a black square and every button beeps.
+
+use crate::{
+    cpu::{JoypadKey, CPU},
+    SCREEN_WIDTH,
+};
+
+const TITLE: &[u8] = b"GBEMU DEMO";
+
+/// Starts at $0150, hand assembled.
+#[rustfmt::skip]
+const PROGRAM: [u8; 164] = [
+    0xF3,                   // DI
+    0x31, 0xFE, 0xFF,       // LD SP,$FFFE
+    // The LCD can be turned off in VBlank only.
+    0xF0, 0x44,             // LDH A,(LY)
+    0xFE, 0x90,             // CP 144
+    0x20, 0xFA,             // JR NZ,-6
+    0xAF,                   // XOR A
+    0xE0, 0x40,             // LDH (LCDC),A
+    0xE0, 0x80,             // LDH ($FF80),A     buttons of the last frame
+    // Clears tiles and maps, then hides every object.
+    0x21, 0x00, 0x80,       // LD HL,$8000
+    0x22,                   // LD (HL+),A
+    0xCB, 0x6C,             // BIT 5,H           until $A000
+    0x28, 0xFB,             // JR Z,-5
+    0x21, 0x00, 0xFE,       // LD HL,$FE00
+    0x06, 0xA0,             // LD B,160
+    0x22,                   // LD (HL+),A
+    0x05,                   // DEC B
+    0x20, 0xFC,             // JR NZ,-4
+    // Tile 1 is black.
+    0x21, 0x10, 0x80,       // LD HL,$8010
+    0x06, 0x10,             // LD B,16
+    0x3E, 0xFF,             // LD A,$FF
+    0x22,                   // LD (HL+),A
+    0x05,                   // DEC B
+    0x20, 0xFC,             // JR NZ,-4
+    // Object 0 is tile 1 in the middle of the screen.
+    0x21, 0x00, 0xFE,       // LD HL,$FE00
+    0x36, 0x58,             // LD (HL),88        Y
+    0x23,                   // INC HL
+    0x36, 0x50,             // LD (HL),80        X
+    0x23,                   // INC HL
+    0x36, 0x01,             // LD (HL),1         tile
+    0x3E, 0xE4,             // LD A,$E4
+    0xE0, 0x47,             // LDH (BGP),A
+    0xE0, 0x48,             // LDH (OBP0),A
+    // APU on, full volume, channel 1 on both sides.
+    0x3E, 0x80,             // LD A,$80
+    0xE0, 0x26,             // LDH (NR52),A
+    0x3E, 0x77,             // LD A,$77
+    0xE0, 0x24,             // LDH (NR50),A
+    0x3E, 0x11,             // LD A,$11
+    0xE0, 0x25,             // LDH (NR51),A
+    // LCD, objects and background on, tiles at $8000.
+    0x3E, 0x93,             // LD A,$93
+    0xE0, 0x40,             // LDH (LCDC),A
+    // frame: once per VBlank.
+    0xF0, 0x44,             // LDH A,(LY)
+    0xFE, 0x90,             // CP 144
+    0x20, 0xFA,             // JR NZ,-6
+    // Moves the object by the pressed directions.
+    0x3E, 0x20,             // LD A,$20
+    0xE0, 0x00,             // LDH (P1),A        select the d-pad
+    0xF0, 0x00,             // LDH A,(P1)
+    0xF0, 0x00,             // LDH A,(P1)
+    0x2F,                   // CPL
+    0x47,                   // LD B,A            pressed directions are 1
+    0x21, 0x01, 0xFE,       // LD HL,$FE01
+    0xCB, 0x40,             // BIT 0,B
+    0x28, 0x01,             // JR Z,+1
+    0x34,                   // INC (HL)          right
+    0xCB, 0x48,             // BIT 1,B
+    0x28, 0x01,             // JR Z,+1
+    0x35,                   // DEC (HL)          left
+    0x2B,                   // DEC HL
+    0xCB, 0x50,             // BIT 2,B
+    0x28, 0x01,             // JR Z,+1
+    0x35,                   // DEC (HL)          up
+    0xCB, 0x58,             // BIT 3,B
+    0x28, 0x01,             // JR Z,+1
+    0x34,                   // INC (HL)          down
+    // Beeps when a button goes down.
+    0x3E, 0x10,             // LD A,$10
+    0xE0, 0x00,             // LDH (P1),A        select the buttons
+    0xF0, 0x00,             // LDH A,(P1)
+    0xF0, 0x00,             // LDH A,(P1)
+    0x2F,                   // CPL
+    0xE6, 0x0F,             // AND $0F
+    0x47,                   // LD B,A
+    0xF0, 0x80,             // LDH A,($FF80)
+    0x2F,                   // CPL
+    0xA0,                   // AND B             pressed since the last frame
+    0x4F,                   // LD C,A
+    0x78,                   // LD A,B
+    0xE0, 0x80,             // LDH ($FF80),A
+    0x79,                   // LD A,C
+    0xA7,                   // AND A
+    0x28, 0x10,             // JR Z,+16
+    0x3E, 0x80,             // LD A,$80
+    0xE0, 0x11,             // LDH (NR11),A      50% duty
+    0x3E, 0xF3,             // LD A,$F3
+    0xE0, 0x12,             // LDH (NR12),A      loud, fading out
+    0x3E, 0x83,             // LD A,$83
+    0xE0, 0x13,             // LDH (NR13),A
+    0x3E, 0x87,             // LD A,$87
+    0xE0, 0x14,             // LDH (NR14),A      trigger at 1 kHz
+    // Waits for the end of line 144, so every frame runs once.
+    0xF0, 0x44,             // LDH A,(LY)
+    0xFE, 0x90,             // CP 144
+    0x28, 0xFA,             // JR Z,-6
+    0x18, 0xA8,             // JR frame
+];
+
+/// The demo cartridge: 32 KiB without MBC. There is no Nintendo logo, so boot ROMs lock up on it.
+pub fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    // NOP; JP $0150
+    rom[0x100..][..4].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x134..][..TITLE.len()].copy_from_slice(TITLE);
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html#014d--header-checksum
+    rom[0x14D] = rom[0x134..0x14D]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1));
+    rom[0x150..][..PROGRAM.len()].copy_from_slice(&PROGRAM);
+    rom
+}
+
+/// Runs the demo with key presses and checks the picture and the sound, see `--selftest`.
+pub fn smoke_test() -> Result<(), String> {
+    fn square_left(cpu: &mut CPU) -> Option<usize> {
+        // Row 72 is the top of the square.
+        let row = &cpu.run_frame().pixels[72 * SCREEN_WIDTH..][..SCREEN_WIDTH];
+        row.iter().position(|&pixel| pixel == 0x000000)
+    }
+
+    let mut cpu = CPU::new_without_sound(rom());
+    // Frames are blank until the program turns the LCD back on.
+    for _ in 0..10 {
+        cpu.run_frame();
+    }
+    match square_left(&mut cpu) {
+        Some(72) => (),
+        x => return Err(format!("square at x {x:?}, expected 72")),
+    }
+
+    cpu.key_down(JoypadKey::Right);
+    for _ in 0..10 {
+        cpu.run_frame();
+    }
+    cpu.key_up(JoypadKey::Right);
+    let x = square_left(&mut cpu);
+    if x.is_none_or(|x| x <= 72) {
+        return Err(format!("square at x {x:?} after pressing right"));
+    }
+
+    // NR52 shows the channels that play.
+    if cpu.debug_read(0xFF26) & 1 != 0 {
+        return Err("channel 1 plays before a button is pressed".to_string());
+    }
+    cpu.key_down(JoypadKey::A);
+    cpu.run_frame();
+    cpu.run_frame();
+    if cpu.debug_read(0xFF26) & 1 == 0 {
+        return Err("no beep when A is pressed".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn valid_cartridge() {
+        let rom = rom();
+        assert!(crate::validate_rom(&rom).is_ok());
+        // The checksum cancels the other header bytes (each plus 1) out.
+        let sum = rom[0x134..=0x14D]
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_add(byte).wrapping_add(1));
+        assert_eq!(sum, 1);
+    }
+}
@@ -0,0 +1,35 @@
+// Errors reported to users instead of panicking.
+
+#[derive(Debug)]
+pub enum GbError {
+    Io(std::io::Error),
+    /// The file is not a Game Boy ROM or needs hardware that isn't emulated.
+    InvalidRom(String),
+    /// The save state is damaged or was made by another game or emulator version.
+    InvalidState(String),
+}
+
+impl std::fmt::Display for GbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GbError::Io(err) => write!(f, "{err}"),
+            GbError::InvalidRom(reason) => write!(f, "invalid ROM: {reason}"),
+            GbError::InvalidState(reason) => write!(f, "invalid save state: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for GbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GbError::Io(err) => Some(err),
+            GbError::InvalidRom(_) | GbError::InvalidState(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for GbError {
+    fn from(err: std::io::Error) -> Self {
+        GbError::Io(err)
+    }
+}
@@ -0,0 +1,153 @@
+// Ring buffer of the last bus transactions, for crash reports and glitch investigation.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Who started the transaction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    Cpu,
+    Dma,
+    /// Debug tools inspecting memory, e.g. coverage decoding instruction lengths.
+    Debugger,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BusEvent {
+    /// T-cycles since power-on at the start of the instruction (or DMA) that accessed the bus.
+    pub cycle: u64,
+    pub access: Access,
+    pub addr: u16,
+    pub val: u8,
+    pub origin: Origin,
+}
+
+pub struct FlightRecorder {
+    capacity: usize,
+    // Reads happen through `&MemoryBus`, so the state needs interior mutability.
+    events: RefCell<VecDeque<BusEvent>>,
+    cycle: Cell<u64>,
+    origin: Cell<Origin>,
+}
+
+impl FlightRecorder {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: RefCell::new(VecDeque::with_capacity(capacity)),
+            cycle: Cell::new(0),
+            origin: Cell::new(Origin::Cpu),
+        }
+    }
+
+    pub fn record(&self, access: Access, addr: u16, val: u8) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut events = self.events.borrow_mut();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(BusEvent {
+            cycle: self.cycle.get(),
+            access,
+            addr,
+            val,
+            origin: self.origin.get(),
+        });
+    }
+
+    pub fn advance(&self, cycles: u64) {
+        self.cycle.set(self.cycle.get() + cycles);
+    }
+
+    /// Attributes the following transactions to `origin`.
+    ///
+    /// # Returns
+    ///
+    /// The previous origin to restore.
+    pub fn set_origin(&self, origin: Origin) -> Origin {
+        self.origin.replace(origin)
+    }
+
+    /// Recorded transactions, oldest first.
+    pub fn events(&self) -> Vec<BusEvent> {
+        self.events.borrow().iter().copied().collect()
+    }
+
+    /// One transaction per line: `cycle origin R/W addr=value`.
+    pub fn dump(&self, out: &mut impl std::io::Write) -> std::io::Result<()> {
+        for event in self.events.borrow().iter() {
+            let access = match event.access {
+                Access::Read => 'R',
+                Access::Write => 'W',
+            };
+            writeln!(
+                out,
+                "{:>12} {:<8} {} {:04X}={:02X}",
+                event.cycle,
+                format!("{:?}", event.origin),
+                access,
+                event.addr,
+                event.val
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FlightRecorder {
+    /// The emulator panicked: print what happened on the bus right before it.
+    fn drop(&mut self) {
+        if std::thread::panicking() && !self.events.get_mut().is_empty() {
+            eprintln!("Last {} bus transactions:", self.events.get_mut().len());
+            let _ = self.dump(&mut std::io::stderr());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_last_events() {
+        let recorder = FlightRecorder::new(2);
+        recorder.record(Access::Read, 0x100, 1);
+        recorder.advance(4);
+        let prev = recorder.set_origin(Origin::Dma);
+        recorder.record(Access::Write, 0xFE00, 2);
+        recorder.set_origin(prev);
+        recorder.record(Access::Read, 0x101, 3);
+
+        let events = recorder.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0],
+            BusEvent {
+                cycle: 4,
+                access: Access::Write,
+                addr: 0xFE00,
+                val: 2,
+                origin: Origin::Dma,
+            }
+        );
+        assert_eq!((events[1].addr, events[1].origin), (0x101, Origin::Cpu));
+
+        let mut dump = vec![];
+        recorder.dump(&mut dump).unwrap();
+        assert_eq!(
+            String::from_utf8(dump).unwrap().lines().next(),
+            Some("           4 Dma      W FE00=02")
+        );
+    }
+}
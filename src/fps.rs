@@ -0,0 +1,97 @@
+// Frame rate, emulation speed and frame time, averaged over a second and drawn over the game.
+
+use std::time::Duration;
+
+use crate::osd;
+
+/// How often the shown numbers change.
+pub const FPS_PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+pub struct FpsMeter {
+    /// Time since the numbers were last updated.
+    elapsed: Duration,
+    passes: u32,
+    shown: u32,
+    emulated: u32,
+    /// Time spent emulating and drawing, without waiting for the next frame.
+    busy: Duration,
+    lines: Vec<String>,
+}
+
+impl FpsMeter {
+    /// Counts a pass of the emulation loop that took `busy` before waiting and `total` with the
+    /// wait. Updates the numbers once per [`FPS_PERIOD`].
+    pub fn frame_done(&mut self, emulated: bool, shown: bool, busy: Duration, total: Duration) {
+        self.passes += 1;
+        self.emulated += emulated as u32;
+        self.shown += shown as u32;
+        self.busy += busy;
+        self.elapsed += total;
+        if self.elapsed < FPS_PERIOD {
+            return;
+        }
+
+        let secs = self.elapsed.as_secs_f64();
+        let fps = self.shown as f64 / secs;
+        let real_time = crate::MILLIS_PER_FRAME as f64 / 1000.0 * self.emulated as f64;
+        let speed = real_time / secs * 100.0;
+        let frame_ms = self.busy.as_secs_f64() * 1000.0 / self.passes as f64;
+        *self = Self {
+            lines: vec![
+                format!("{fps:.0} FPS"),
+                format!("{speed:.0}%"),
+                format!("{frame_ms:.1} MS"),
+            ],
+            ..Self::default()
+        };
+    }
+
+    /// Numbers of the last period, empty during the first one.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Draws [`Self::lines`] over the bottom left corner, above the profiler bar of the stats.
+    pub fn draw_overlay(&self, buff: &mut [u32], width: usize) {
+        const BOTTOM: usize = 3;
+        let height = buff.len() / width;
+        let longest = self.lines.iter().map(String::len).max().unwrap_or(0);
+        let box_height = self.lines.len() * osd::GLYPH_HEIGHT + 1;
+        let top = height.saturating_sub(box_height + BOTTOM);
+        osd::dim(
+            buff,
+            width,
+            0,
+            top,
+            longest * osd::GLYPH_WIDTH + 1,
+            box_height,
+        );
+        for (idx, line) in self.lines.iter().enumerate() {
+            let y = top + 1 + idx * osd::GLYPH_HEIGHT;
+            osd::draw_text(buff, width, 1, y, line, 0xFFFFFF);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn averages_over_a_period() {
+        let mut meter = FpsMeter::default();
+        let total = Duration::from_millis(crate::MILLIS_PER_FRAME);
+        let busy = Duration::from_millis(4);
+        for idx in 0..63 {
+            meter.frame_done(true, idx % 2 == 0, busy, total);
+        }
+        assert_eq!(meter.lines(), ["32 FPS", "100%", "4.0 MS"]);
+
+        // Twice the speed: the same frames in half the time.
+        for _ in 0..126 {
+            meter.frame_done(true, false, busy, total / 2);
+        }
+        assert_eq!(meter.lines(), ["0 FPS", "200%", "4.0 MS"]);
+    }
+}
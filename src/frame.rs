@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use crate::{gpu::GPU, CPU_FREQ, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// A completed frame as it was shown on the LCD.
+#[derive(Clone)]
+pub struct Frame {
+    /// 0x00RRGGBB pixels, row by row.
+    pub pixels: Vec<u32>,
+    /// Number of frames completed before this one.
+    pub number: u64,
+    /// T-cycles emulated since power-on when the frame was taken.
+    pub cycle: u64,
+    /// Frames are blank while the LCD is off, and they are timed by the CPU instead of the PPU.
+    pub lcd_on: bool,
+}
+
+impl Frame {
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            number: 0,
+            cycle: 0,
+            lcd_on: false,
+        }
+    }
+
+    pub(crate) fn update(&mut self, gpu: &GPU, cycle: u64) {
+        gpu.to_rgb32(self.pixels.as_mut_slice().try_into().unwrap());
+        self.number = gpu.frame_count().saturating_sub(1);
+        self.cycle = cycle;
+        self.lcd_on = gpu.lcd_control.lcd_enable;
+    }
+
+    /// Emulated time since power-on.
+    pub fn timestamp(&self) -> Duration {
+        Duration::from_secs_f64(self.cycle as f64 / CPU_FREQ as f64)
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * SCREEN_WIDTH + x]
+    }
+
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|p| [(p >> 16) as u8, (p >> 8) as u8, *p as u8])
+            .collect()
+    }
+
+    /// Opaque pixels for browser canvases.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|p| [(p >> 16) as u8, (p >> 8) as u8, *p as u8, 0xFF])
+            .collect()
+    }
+}
@@ -0,0 +1,99 @@
+// Controllers through gilrs, mapped to the joypad by a `PadMap`. Controllers can be plugged in and
+// out while the emulator runs, and all of them play at once.
+
+use gilrs::{Button, EventType, Gilrs};
+
+use crate::{
+    commands::{PadButton, PadMap},
+    cpu::JoypadKey,
+    input_macro::KEY_NAMES,
+};
+
+const BUTTONS: [(PadButton, Button); 14] = [
+    (PadButton::South, Button::South),
+    (PadButton::East, Button::East),
+    (PadButton::North, Button::North),
+    (PadButton::West, Button::West),
+    (PadButton::LeftTrigger, Button::LeftTrigger),
+    (PadButton::RightTrigger, Button::RightTrigger),
+    (PadButton::LeftTrigger2, Button::LeftTrigger2),
+    (PadButton::RightTrigger2, Button::RightTrigger2),
+    (PadButton::Select, Button::Select),
+    (PadButton::Start, Button::Start),
+    (PadButton::DPadUp, Button::DPadUp),
+    (PadButton::DPadDown, Button::DPadDown),
+    (PadButton::DPadLeft, Button::DPadLeft),
+    (PadButton::DPadRight, Button::DPadRight),
+];
+
+fn pad_button(button: Button) -> Option<PadButton> {
+    BUTTONS
+        .iter()
+        .find(|(_, gilrs_button)| *gilrs_button == button)
+        .map(|(pad_button, _)| *pad_button)
+}
+
+fn gilrs_button(button: PadButton) -> Button {
+    BUTTONS.iter().find(|(b, _)| *b == button).unwrap().1
+}
+
+pub struct Gamepads {
+    gilrs: Gilrs,
+}
+
+impl Gamepads {
+    pub fn new() -> Result<Self, String> {
+        let gilrs = Gilrs::new().map_err(|err| err.to_string())?;
+        for (_, gamepad) in gilrs.gamepads() {
+            eprintln!("Controller: {}", gamepad.name());
+        }
+        Ok(Self { gilrs })
+    }
+
+    /// # Returns
+    ///
+    /// Joypad buttons pressed (`true`) and released since the last call. A button is released
+    /// when none of its controller buttons is held anymore.
+    pub fn poll(&mut self, map: &PadMap) -> Vec<(JoypadKey, bool)> {
+        let mut changes = vec![];
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    let buttons = pad_button(button).into_iter().flat_map(|b| map.buttons(b));
+                    changes.extend(buttons.map(|button| (button, true)));
+                }
+                EventType::ButtonReleased(button, _) => {
+                    let buttons = pad_button(button).into_iter().flat_map(|b| map.buttons(b));
+                    for button in buttons {
+                        if !self.is_held(map, button) {
+                            changes.push((button, false));
+                        }
+                    }
+                }
+                EventType::Connected => {
+                    eprintln!(
+                        "Controller connected: {}",
+                        self.gilrs.gamepad(event.id).name()
+                    );
+                }
+                EventType::Disconnected => {
+                    eprintln!("Controller disconnected");
+                    for (_, button) in KEY_NAMES {
+                        if !self.is_held(map, button) {
+                            changes.push((button, false));
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        changes
+    }
+
+    fn is_held(&self, map: &PadMap, button: JoypadKey) -> bool {
+        self.gilrs.gamepads().any(|(_, gamepad)| {
+            map.keys(button)
+                .any(|input| gamepad.is_pressed(gilrs_button(input)))
+        })
+    }
+}
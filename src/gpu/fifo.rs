@@ -0,0 +1,355 @@
+// Dot-clocked renderer: a fetcher reads background and window tiles into a pixel FIFO that shifts
+// out one pixel per dot, and objects are mixed in when the FIFO reaches them. Registers written
+// during mode 3 (SCX, BGP, LCDC...) apply to the rest of the line, like on hardware.
+// https://gbdev.io/pandocs/pixel_fifo.html
+
+use std::collections::VecDeque;
+
+use super::{tile_data_offset, tile_map_offset, Color, Coordinate, Layer, Oam, GPU};
+use crate::SCREEN_WIDTH;
+
+#[derive(Copy, Clone)]
+struct BgPixel {
+    color: u8,
+    window: bool,
+}
+
+#[derive(Copy, Clone, Default)]
+struct ObjPixel {
+    /// 0 is transparent.
+    color: u8,
+    bg_prio: bool,
+    obp1: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum FetchStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+pub(super) struct PixelFifo {
+    /// Screen X of the next pixel shifted out.
+    x: u8,
+    /// Pixels dropped before the first one is shown: the fine scroll of SCX, or the part of the
+    /// window left of the screen.
+    discard: u8,
+    step: FetchStep,
+    /// Dots spent on the current fetcher step, each one takes 2.
+    step_dots: u8,
+    /// Next tile column of the background or window row.
+    tile_x: u8,
+    tile_idx: u8,
+    data: [u8; 2],
+    /// Fetching the window instead of the background.
+    window: bool,
+    bg: VecDeque<BgPixel>,
+    /// Lined up with `bg`: the first object pixel is mixed with the first background pixel.
+    obj: VecDeque<ObjPixel>,
+    /// Objects of the line not fetched yet, the next one last.
+    objs: Vec<Oam>,
+}
+
+impl GPU {
+    /// Runs the FIFO for `dots` of mode 3.
+    pub(super) fn draw_dots(&mut self, dots: u64) {
+        let mut fifo = self.fifo.take().unwrap_or_else(|| self.start_fifo());
+        for _ in 0..dots {
+            if fifo.x as usize == SCREEN_WIDTH {
+                break;
+            }
+            self.fifo_dot(&mut fifo);
+        }
+        self.fifo = Some(fifo);
+    }
+
+    /// Shifts out the pixels left at the end of mode 3.
+    pub(super) fn finish_fifo(&mut self) {
+        crate::profile_scope!(crate::profiler::Scope::PpuDrawLine);
+        let mut fifo = self.fifo.take().unwrap_or_else(|| self.start_fifo());
+        while (fifo.x as usize) < SCREEN_WIDTH {
+            self.fifo_dot(&mut fifo);
+        }
+        self.window_line_done(fifo.window);
+    }
+
+    fn start_fifo(&mut self) -> PixelFifo {
+        let mut objs = self.line_objs.clone();
+        objs.sort_unstable_by(|a, b| b.cmp(a));
+        PixelFifo {
+            x: 0,
+            discard: self.viewport.x % 8,
+            step: FetchStep::Tile,
+            step_dots: 0,
+            tile_x: 0,
+            tile_idx: 0,
+            data: [0; 2],
+            window: false,
+            bg: VecDeque::with_capacity(8),
+            obj: VecDeque::with_capacity(8),
+            objs,
+        }
+    }
+
+    fn fifo_dot(&mut self, fifo: &mut PixelFifo) {
+        // The window replaces the background from its first pixel to the end of the line.
+        let full_line = self.window_full_line && fifo.x == 0;
+        if !fifo.window
+            && self.lcd_control.window_enable
+            && self.window_y_trigger
+            && (full_line || fifo.x + 7 >= self.window.x)
+        {
+            fifo.window = true;
+            fifo.discard = if full_line {
+                0
+            } else {
+                self.window_hidden_pixels()
+            };
+            fifo.bg.clear();
+            fifo.tile_x = 0;
+            fifo.step = FetchStep::Tile;
+            fifo.step_dots = 0;
+        }
+        self.fetch(fifo);
+        self.shift_out(fifo);
+    }
+
+    fn fetch(&self, fifo: &mut PixelFifo) {
+        if fifo.step == FetchStep::Push {
+            // Waits until the FIFO is empty.
+            if fifo.bg.is_empty() {
+                let [low, high] = fifo.data;
+                fifo.bg.extend((0..8).rev().map(|bit| BgPixel {
+                    color: (((high >> bit) & 1) << 1) | ((low >> bit) & 1),
+                    window: fifo.window,
+                }));
+                fifo.tile_x = fifo.tile_x.wrapping_add(1);
+                fifo.step = FetchStep::Tile;
+            }
+            return;
+        }
+
+        fifo.step_dots += 1;
+        if fifo.step_dots < 2 {
+            return;
+        }
+        fifo.step_dots = 0;
+
+        let (map_area, tile) = if fifo.window {
+            (
+                self.lcd_control.window_tile_map_area,
+                Coordinate::new(fifo.tile_x.wrapping_mul(8), self.window_current_y),
+            )
+        } else {
+            (
+                self.lcd_control.bg_tile_map_area,
+                Coordinate::new(
+                    (self.viewport.x / 8).wrapping_add(fifo.tile_x) % 32 * 8,
+                    self.viewport.y.wrapping_add(self.lcd_status.line()),
+                ),
+            )
+        };
+        let data_offset = || {
+            tile_data_offset(self.lcd_control.bg_and_window_tile_data_area, fifo.tile_idx)
+                + (tile.y % 8) as usize * 2
+        };
+        match fifo.step {
+            FetchStep::Tile => {
+                let map_area = if map_area { 0x9C00 } else { 0x9800 };
+                fifo.tile_idx = self.vram[tile_map_offset(map_area, tile)];
+                fifo.step = FetchStep::DataLow;
+            }
+            FetchStep::DataLow => {
+                fifo.data[0] = self.vram[data_offset()];
+                fifo.step = FetchStep::DataHigh;
+            }
+            FetchStep::DataHigh => {
+                fifo.data[1] = self.vram[data_offset() + 1];
+                fifo.step = FetchStep::Push;
+            }
+            FetchStep::Push => unreachable!(),
+        }
+    }
+
+    fn shift_out(&mut self, fifo: &mut PixelFifo) {
+        let Some(bg) = fifo.bg.pop_front() else {
+            return;
+        };
+        if fifo.discard > 0 {
+            fifo.discard -= 1;
+            return;
+        }
+        self.fetch_objects(fifo);
+        let obj = fifo.obj.pop_front().unwrap_or_default();
+
+        let bg_visible = if bg.window {
+            self.visible_layers.window
+        } else {
+            self.visible_layers.background
+        };
+        // With LCDC bit 0 off the background and window are blank, and objects are always on top.
+        let bg_color = if self.lcd_control.bg_and_window_display && bg_visible {
+            bg.color
+        } else {
+            0
+        };
+        let obj_shown = obj.color != 0
+            && self.lcd_control.obj_enable
+            && self.visible_layers.sprites
+            && !(obj.bg_prio && bg_color != 0);
+        let (color, layer) = if obj_shown && obj.obp1 {
+            (self.obj1_colors.get()[obj.color as usize], Layer::Obj1)
+        } else if obj_shown {
+            (self.obj0_colors.get()[obj.color as usize], Layer::Obj0)
+        } else if !self.lcd_control.bg_and_window_display {
+            (Color::White, Layer::Background)
+        } else if bg.window {
+            (self.bg_colors.get()[bg_color as usize], Layer::Window)
+        } else {
+            (self.bg_colors.get()[bg_color as usize], Layer::Background)
+        };
+
+        let (x, line) = (fifo.x as usize, self.lcd_status.line() as usize);
+        self.buffer[x][line] = [color.rgb(); 3];
+        self.layer_buffer[x][line] = layer;
+        fifo.x += 1;
+    }
+
+    /// Mixes the objects that start at the next pixel into the object FIFO. Pixels of objects
+    /// fetched earlier win, which is the drawing priority.
+    fn fetch_objects(&self, fifo: &mut PixelFifo) {
+        let x = fifo.x as i16;
+        while fifo.objs.last().is_some_and(|obj| obj.pos.x <= x) {
+            let obj = fifo.objs.pop().unwrap();
+            if !self.lcd_control.obj_enable {
+                continue;
+            }
+            fifo.obj.resize(8, ObjPixel::default());
+            for (slot, screen_x) in (x..obj.pos.x + 8).enumerate() {
+                if fifo.obj[slot].color != 0 {
+                    continue;
+                }
+                if let Some(color) = self.obj_color_idx(&obj, screen_x, self.obj_height) {
+                    fifo.obj[slot] = ObjPixel {
+                        color,
+                        bg_prio: obj.attrs.bg_prio,
+                        obp1: obj.attrs.dmg_palette,
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::gpu::{BackgroundColors, PpuMode};
+
+    use super::*;
+
+    /// Steps through mode 3 of line 0 and returns its pixels.
+    fn draw_first_line(gpu: &mut GPU, mut mid_line: impl FnMut(&mut GPU, u64)) -> Vec<u8> {
+        let mut dot = 0;
+        while gpu.lcd_status.ppu_mode != PpuMode::HBlank {
+            gpu.step(1);
+            mid_line(gpu, dot);
+            dot += 1;
+        }
+        (0..SCREEN_WIDTH).map(|x| gpu.buffer[x][0][0]).collect()
+    }
+
+    /// LCD on, $8000 tile data, OBJ and BG on, with tile 1 black and a map of tile 1 columns at
+    /// odd tiles.
+    fn striped_gpu() -> GPU {
+        let mut gpu = GPU::new();
+        gpu.vram[16..32].fill(0xFF);
+        for tile_x in (1..32).step_by(2) {
+            gpu.vram[0x1800 + tile_x] = 1;
+        }
+        gpu.set_lcd_control(0b1001_0011);
+        gpu
+    }
+
+    #[test]
+    fn matches_the_fast_renderer() {
+        // The second BGP swaps the shades: objects behind the BG still show over color 0 only.
+        let lines = [0b11_10_01_00, 0b00_01_10_11].map(|bgp| {
+            let mut gpu = striped_gpu();
+            gpu.viewport.x = 3;
+            gpu.vram[32..48].fill(0xF0);
+            gpu.bg_colors = BackgroundColors::from(bgp);
+            gpu.obj0_colors = BackgroundColors::from(0b10_00_00_00);
+            gpu.oam[..8].copy_from_slice(&[16, 12, 2, 0, 16, 30, 2, 0b1000_0000]);
+
+            let fifo = draw_first_line(&mut gpu, |_, _| ());
+            gpu.draw_line();
+            let fast: Vec<u8> = (0..SCREEN_WIDTH).map(|x| gpu.buffer[x][0][0]).collect();
+            assert_eq!(fifo, fast, "BGP {bgp:#010b}");
+            fifo
+        });
+        assert_eq!(
+            lines[0][..8],
+            [0xFF, 0xFF, 0xFF, 0xFF, 0x55, 0x55, 0x55, 0x55]
+        );
+        assert_eq!(lines[1][20..26], [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    /// The first 2 lines with a window of 4 black and 4 white pixel columns from line 0 over a
+    /// white background.
+    fn window_lines(wx: u8, scx: u8, fast_renderer: bool) -> [Vec<u8>; 2] {
+        let mut gpu = GPU::new();
+        gpu.vram[32..48].fill(0xF0);
+        gpu.vram[0x1C00..0x2000].fill(2);
+        gpu.window = Coordinate::new(wx, 0);
+        gpu.viewport.x = scx;
+        gpu.fast_renderer = fast_renderer;
+        gpu.set_lcd_control(0b1111_0001);
+        while !(gpu.lcd_status.line() == 2 && gpu.lcd_status.ppu_mode == PpuMode::OAMScan) {
+            gpu.step(4);
+        }
+        [0, 1].map(|line| (0..SCREEN_WIDTH).map(|x| gpu.buffer[x][line][0]).collect())
+    }
+
+    #[test]
+    fn window_edges() {
+        let (black, white) = (Color::Black.rgb(), Color::White.rgb());
+        let starts = |wx, scx| {
+            let lines = window_lines(wx, scx, false);
+            assert_eq!(lines, window_lines(wx, scx, true), "WX {wx} SCX {scx}");
+            lines[0][..8]
+                .iter()
+                .map(|&pixel| pixel == black)
+                .collect::<Vec<_>>()
+        };
+        let (b, w) = (true, false);
+        assert_eq!(starts(7, 0), [b, b, b, b, w, w, w, w]);
+        // Left of the screen.
+        assert_eq!(starts(5, 0), [b, b, w, w, w, w, b, b]);
+        assert_eq!(starts(0, 0), [w, b, b, b, b, w, w, w]);
+        // WX = 0 is moved by the fine scroll.
+        assert_eq!(starts(0, 3), [b, b, w, w, w, w, b, b]);
+
+        // WX = 166 starts at the last pixel, and the window covers the next line.
+        let lines = window_lines(166, 0, false);
+        assert_eq!(lines, window_lines(166, 0, true));
+        assert_eq!(lines[0][158..], [white, black]);
+        assert_eq!(lines[1][..5], [black, black, black, black, white]);
+    }
+
+    #[test]
+    fn mid_line_writes_apply_to_the_rest_of_the_line() {
+        let mut gpu = striped_gpu();
+        let line = draw_first_line(&mut gpu, |gpu, dot| {
+            // Half way through mode 3: invert the palette.
+            if dot == 80 + 86 {
+                gpu.bg_colors = BackgroundColors::from(0b00_00_00_11);
+            }
+        });
+        assert_eq!(line[..8], [Color::White.rgb(); 8]);
+        assert_eq!(line[8..16], [Color::Black.rgb(); 8]);
+        assert_eq!(line[144..152], [Color::Black.rgb(); 8]);
+        assert_eq!(line[152..], [Color::White.rgb(); 8]);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::bit;
+use crate::{
+    bit,
+    savestate::{SaveState, StateVisitor},
+};
 
 use super::PpuMode;
 
@@ -15,6 +18,8 @@ pub struct LcdStatus {
     // read-only for operations
     same_line_check: bool,
     pub ppu_mode: PpuMode,
+    /// OR of the enabled interrupt sources, the STAT interrupt is requested when it rises.
+    stat_line: bool,
 
     // FF44 — LY: LCD Y coordinate [read-only]
     ly: u8,
@@ -62,6 +67,7 @@ impl LcdStatus {
             hblank_interrupt: false,
             same_line_check: false,
             ppu_mode: PpuMode::HBlank,
+            stat_line: false,
             ly: 0,
             lyc: 0,
         }
@@ -89,18 +95,42 @@ impl LcdStatus {
         self.compare_lines()
     }
 
-    pub fn compare_lines(&mut self) -> bool {
+    #[must_use]
+    fn compare_lines(&mut self) -> bool {
         self.same_line_check = self.ly == self.lyc;
 
-        self.lyc_int_select && self.same_line_check
+        self.update_stat_line()
+    }
+
+    /// Recomputes the STAT interrupt line after a change of its sources.
+    ///
+    /// # Returns
+    ///
+    /// Whether the line rose and the interrupt is requested. A source becoming true while another
+    /// one keeps the line high requests nothing ("STAT blocking").
+    #[must_use]
+    pub fn update_stat_line(&mut self) -> bool {
+        let mode_source = match self.ppu_mode {
+            PpuMode::HBlank => self.hblank_interrupt,
+            PpuMode::VBlank => self.vblank_interrupt,
+            PpuMode::OAMScan => self.oam_scan_interrupt,
+            PpuMode::DrawingPixels => false,
+        };
+        let line = mode_source || (self.lyc_int_select && self.same_line_check);
+        let rising = line && !self.stat_line;
+        self.stat_line = line;
+        rising
     }
 
-    pub fn write_byte_to_status(&mut self, val: u8) {
+    #[must_use]
+    pub fn write_byte_to_status(&mut self, val: u8) -> bool {
         self.lyc_int_select = bit!(val, 6);
         self.oam_scan_interrupt = bit!(val, 5);
         self.vblank_interrupt = bit!(val, 4);
         self.hblank_interrupt = bit!(val, 3);
         // Other fields are read-only.
+
+        self.update_stat_line()
     }
 
     pub fn get_status_byte(&self) -> u8 {
@@ -144,3 +174,41 @@ impl From<LcdControl> for u8 {
             | ((val.bg_and_window_display as u8) << 0)
     }
 }
+
+impl From<u8> for LcdControl {
+    fn from(val: u8) -> Self {
+        Self {
+            lcd_enable: bit!(val, 7),
+            window_tile_map_area: bit!(val, 6),
+            window_enable: bit!(val, 5),
+            bg_and_window_tile_data_area: bit!(val, 4),
+            bg_tile_map_area: bit!(val, 3),
+            obj_size: bit!(val, 2),
+            obj_enable: bit!(val, 1),
+            bg_and_window_display: bit!(val, 0),
+        }
+    }
+}
+
+impl SaveState for LcdStatus {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.bool(&mut self.lyc_int_select);
+        state.bool(&mut self.oam_scan_interrupt);
+        state.bool(&mut self.vblank_interrupt);
+        state.bool(&mut self.hblank_interrupt);
+        state.bool(&mut self.same_line_check);
+        state.bool(&mut self.stat_line);
+        state.as_u8(&mut self.ppu_mode, u8::from, |val| {
+            [
+                PpuMode::HBlank,
+                PpuMode::VBlank,
+                PpuMode::OAMScan,
+                PpuMode::DrawingPixels,
+            ]
+            .get(val as usize)
+            .copied()
+        });
+        state.u8(&mut self.ly);
+        state.u8(&mut self.lyc);
+    }
+}
@@ -1,15 +1,42 @@
+mod fifo;
 mod lcd_registers;
+mod stats;
+
+use std::path::Path;
 
 use crate::{
     bit,
-    memory_bus::{OAM_SIZE, OAM_START, VIDEO_RAM_SIZE, VIDEO_RAM_START},
+    memory_bus::{OAM_SIZE, VIDEO_RAM_SIZE, VIDEO_RAM_START},
+    savestate::{SaveState, StateVisitor},
     SCREEN_HEIGHT, SCREEN_WIDTH,
 };
+use fifo::PixelFifo;
 use lcd_registers::{LcdControl, LcdStatus};
+pub use stats::PpuStats;
+
+const SCANLINE_DOTS: u64 = 456;
+const LAST_SCANLINE: u8 = 153;
+const LAST_VISIBLE_SCANLINE: u8 = 143;
+const OAM_SCAN_DOTS: u64 = 80;
+/// Mode 3 without penalties.
+const DRAWING_PIXELS_DOTS: u64 = 172;
+/// Mode 3 with the fine scroll, the window and 10 objects at worst.
+const MAX_DRAWING_PIXELS_DOTS: u64 = 289;
+
+/// The Game Boy PPU can display up to 40 movable objects (or sprites), each 8×8 or 8×16 pixels.
+/// Because of a limitation of hardware, only 10 objects can be displayed per scanline.
+pub const MAX_OBJS_PER_SCANLINE: usize = 10;
+/// Entries of OAM, the OAM scan checks one every 2 dots.
+const OAM_ENTRIES: usize = OAM_SIZE / 4;
 
 pub struct GPU {
     // 3: RGB
     pub buffer: [[[u8; 3]; SCREEN_HEIGHT]; SCREEN_WIDTH],
+    /// Which layer drew each pixel of `buffer`, for palettes per layer.
+    layer_buffer: [[Layer; SCREEN_HEIGHT]; SCREEN_WIDTH],
+    /// BG and window color indexes of the line drawn by the fast renderer, before BGP. Objects
+    /// with BG priority are only drawn over index 0.
+    line_bg_colors: [u8; SCREEN_WIDTH],
     pub vram: [u8; VIDEO_RAM_SIZE],
     pub oam: [u8; OAM_SIZE],
     pub lcd_control: LcdControl,
@@ -29,12 +56,42 @@ pub struct GPU {
     /// The Y position is selected by an internal counter, which is reset to 0 during VBlank and
     /// only incremented when the Window starts being rendered on a given scanline.
     window_current_y: u8,
+    /// The window reached the last pixel of the previous line with WX = 166, so it covers the
+    /// whole current line.
+    window_full_line: bool,
+    /// OBJ height (LCDC bit 2) latched at the start of the OAM scan, so changes apply from the next
+    /// scanline.
+    obj_height: u16,
 
     pub bg_colors: BackgroundColors,
     pub obj0_colors: BackgroundColors,
     pub obj1_colors: BackgroundColors,
 
+    /// Layers drawn to the buffer, a debug switch independent of LCDC.
+    pub visible_layers: Layers,
+    /// Objects drawn per scanline. Hardware draws 10, more removes the flicker of crowded games.
+    pub sprites_per_line: usize,
+    /// Draws whole lines at the start of HBlank instead of running the pixel FIFO during mode 3.
+    /// Faster, but writes to PPU registers during mode 3 are only seen at its end.
+    pub fast_renderer: bool,
+    /// Colors of the 4 shades in converted frames, see [`crate::palette`].
+    pub palette: [u32; 4],
+    /// Pixel FIFO of the line being drawn, during mode 3.
+    fifo: Option<PixelFifo>,
+    /// Objects selected by the OAM scan of the current line, in OAM order.
+    line_objs: Vec<Oam>,
+    /// Next OAM entry the OAM scan checks.
+    scan_entry: usize,
+
     cycles: u64,
+    /// Length of mode 3 on the current line, computed when it starts.
+    drawing_dots: u64,
+    /// Number of frames completed since power-on (counted on VBlank entry).
+    frames: u64,
+
+    /// Statistics of the frame being drawn and of the last complete one (LY 0 to 153).
+    current_stats: PpuStats,
+    stats: PpuStats,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -45,6 +102,36 @@ pub enum PpuMode {
     DrawingPixels,
 }
 
+/// Debug switches to find which layer a glitch is in. A hidden background or window is drawn as
+/// color 0, so sprites behind it are still shown.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Layers {
+    pub background: bool,
+    pub window: bool,
+    pub sprites: bool,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self {
+            background: true,
+            window: true,
+            sprites: true,
+        }
+    }
+}
+
+/// The layer a pixel comes from. Objects are split by their palette register, like the DMG
+/// colorization of the Game Boy Color.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub enum Layer {
+    #[default]
+    Background,
+    Window,
+    Obj0,
+    Obj1,
+}
+
 #[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
 pub struct Coordinate<T> {
     pub x: T,
@@ -124,6 +211,8 @@ impl GPU {
     pub fn new() -> Self {
         Self {
             buffer: [[[0; 3]; SCREEN_HEIGHT]; SCREEN_WIDTH],
+            layer_buffer: [[Layer::Background; SCREEN_HEIGHT]; SCREEN_WIDTH],
+            line_bg_colors: [0; SCREEN_WIDTH],
             vram: [0; VIDEO_RAM_SIZE],
             oam: [0; OAM_SIZE],
             lcd_control: LcdControl::new(),
@@ -133,55 +222,84 @@ impl GPU {
 
             window_current_y: 0,
             window_y_trigger: false,
+            window_full_line: false,
+            obj_height: 8,
 
             bg_colors: BackgroundColors::new(),
             obj0_colors: BackgroundColors::new(),
             obj1_colors: BackgroundColors::new(),
 
+            visible_layers: Layers::default(),
+            palette: crate::palette::GRAY,
+            sprites_per_line: MAX_OBJS_PER_SCANLINE,
+            fast_renderer: false,
+            fifo: None,
+            line_objs: Vec::with_capacity(OAM_ENTRIES),
+            scan_entry: 0,
+
             cycles: 0,
+            drawing_dots: DRAWING_PIXELS_DOTS,
+            frames: 0,
+
+            current_stats: PpuStats::new(),
+            stats: PpuStats::new(),
         }
     }
 
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn stats(&self) -> &PpuStats {
+        &self.stats
+    }
+
     pub fn to_rgb8(&self, buff: &mut [u8; SCREEN_HEIGHT * SCREEN_WIDTH * 3]) {
+        crate::profile_scope!(crate::profiler::Scope::FrameConversion);
         for row in 0..SCREEN_HEIGHT {
             for col in 0..SCREEN_WIDTH {
                 let idx = (row * SCREEN_WIDTH + col) * 3;
-                buff[idx] = self.buffer[col][row][0];
-                buff[idx + 1] = self.buffer[col][row][1];
-                buff[idx + 2] = self.buffer[col][row][2];
+                let color = crate::palette::recolor(self.buffer[col][row][0], &self.palette);
+                buff[idx..idx + 3].copy_from_slice(&color.to_be_bytes()[1..]);
             }
         }
     }
 
+    /// Saves the screen as a PNG with the colors shown.
+    pub fn to_png(&self, path: &Path) -> std::io::Result<()> {
+        let mut pixels = [0; SCREEN_HEIGHT * SCREEN_WIDTH * 3];
+        self.to_rgb8(&mut pixels);
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        crate::png::write_rgb(&mut out, SCREEN_WIDTH, SCREEN_HEIGHT, &pixels)
+    }
+
     pub fn to_rgb32(&self, buff: &mut [u32; SCREEN_HEIGHT * SCREEN_WIDTH]) {
+        crate::profile_scope!(crate::profiler::Scope::FrameConversion);
         for row in 0..SCREEN_HEIGHT {
             for col in 0..SCREEN_WIDTH {
-                buff[row * SCREEN_WIDTH + col] = ((self.buffer[col][row][0] as u32) << 16)
-                    | ((self.buffer[col][row][0] as u32) << 8)
-                    | (self.buffer[col][row][0] as u32);
+                buff[row * SCREEN_WIDTH + col] =
+                    crate::palette::recolor(self.buffer[col][row][0], &self.palette);
             }
         }
     }
 
-    pub fn set_lcd_control(&mut self, val: u8) -> GpuInterrupts {
-        use crate::bit;
-
-        let new = LcdControl {
-            lcd_enable: bit!(val, 7),
-            window_tile_map_area: bit!(val, 6),
-            window_enable: bit!(val, 5),
-            bg_and_window_tile_data_area: bit!(val, 4),
-            bg_tile_map_area: bit!(val, 3),
-            obj_size: bit!(val, 2),
-            obj_enable: bit!(val, 1),
-            bg_and_window_display: bit!(val, 0),
-        };
+    /// Layers of the pixels in the order of [`GPU::to_rgb32`].
+    pub fn to_layers(&self, buff: &mut [Layer; SCREEN_HEIGHT * SCREEN_WIDTH]) {
+        for row in 0..SCREEN_HEIGHT {
+            for col in 0..SCREEN_WIDTH {
+                buff[row * SCREEN_WIDTH + col] = self.layer_buffer[col][row];
+            }
+        }
+    }
 
+    pub fn set_lcd_control(&mut self, val: u8) -> GpuInterrupts {
+        let new = LcdControl::from(val);
+        let old = std::mem::replace(&mut self.lcd_control, new);
         let mut inter = GpuInterrupts::default();
-        if !self.lcd_control.lcd_enable && new.lcd_enable {
+        if !old.lcd_enable && new.lcd_enable {
             self.switch_to_mode(PpuMode::OAMScan, &mut inter);
             self.cycles = 4;
-        } else if self.lcd_control.lcd_enable && !new.lcd_enable {
+        } else if old.lcd_enable && !new.lcd_enable {
             self.cycles = 0;
             if self.lcd_status.set_line(0) {
                 inter.lcd = true;
@@ -190,23 +308,53 @@ impl GPU {
             self.clear_screen();
         }
 
-        self.lcd_control = new;
-
         inter
     }
 
+    /// Writes STAT.
+    ///
+    /// # Returns
+    ///
+    /// Whether the STAT interrupt is requested. The PPU doesn't drive the STAT line while the LCD
+    /// is off.
+    #[must_use]
+    pub fn write_stat(&mut self, val: u8) -> bool {
+        self.lcd_status.write_byte_to_status(val) && self.lcd_control.lcd_enable
+    }
+
+    /// Writes LYC, see [`GPU::write_stat`] for the result.
+    #[must_use]
+    pub fn write_lyc(&mut self, val: u8) -> bool {
+        self.lcd_status.set_lyc(val) && self.lcd_control.lcd_enable
+    }
+
     fn clear_screen(&mut self) {
         self.buffer.fill([[Color::White.rgb(); 3]; SCREEN_HEIGHT]);
+        self.layer_buffer.fill([Layer::Background; SCREEN_HEIGHT]);
     }
 
-    pub fn step(&mut self, mut cycles: u64) -> GpuInterrupts {
-        const SCANLINE_DOTS: u64 = 456;
-        const LAST_SCANLINE: u8 = 153;
-        const LAST_VISIBLE_SCANLINE: u8 = 143;
-
-        const OAM_SCAN_DOTS: u64 = 80;
-        const DRAWING_PIXELS_DOTS: u64 = 172;
+    /// Dots until the PPU switches modes or lines, when the LCD is on.
+    pub fn cycles_until_mode_change(&self) -> Option<u64> {
+        if !self.lcd_control.lcd_enable {
+            return None;
+        }
+        let boundaries: &[u64] = if self.lcd_status.line() <= LAST_VISIBLE_SCANLINE {
+            // Modes change once the dot counter is past their end.
+            &[
+                OAM_SCAN_DOTS + 1,
+                OAM_SCAN_DOTS + self.drawing_dots + 1,
+                SCANLINE_DOTS,
+            ]
+        } else {
+            &[SCANLINE_DOTS]
+        };
+        boundaries
+            .iter()
+            .find(|&&dots| dots > self.cycles)
+            .map(|dots| dots - self.cycles)
+    }
 
+    pub fn step(&mut self, mut cycles: u64) -> GpuInterrupts {
         let mut inter = GpuInterrupts::default();
 
         if !self.lcd_control.lcd_enable {
@@ -229,10 +377,12 @@ impl GPU {
             let cycles_now = std::cmp::min(cycles, 80);
             cycles -= cycles_now;
 
+            let mut line_start = self.cycles;
             self.cycles += cycles_now;
 
             if self.cycles >= SCANLINE_DOTS {
                 self.cycles -= SCANLINE_DOTS;
+                line_start = 0;
                 if self
                     .lcd_status
                     .set_line((self.lcd_status.line() + 1) % (LAST_SCANLINE + 1))
@@ -240,6 +390,10 @@ impl GPU {
                     inter.lcd = true;
                 }
 
+                if self.lcd_status.line() == 0 {
+                    self.stats = std::mem::replace(&mut self.current_stats, PpuStats::new());
+                }
+
                 if self.lcd_status.ppu_mode != PpuMode::VBlank
                     && self.lcd_status.line() > LAST_VISIBLE_SCANLINE
                 {
@@ -252,16 +406,31 @@ impl GPU {
                     if self.lcd_status.ppu_mode != PpuMode::OAMScan {
                         self.switch_to_mode(PpuMode::OAMScan, &mut inter);
                     }
-                } else if self.cycles <= OAM_SCAN_DOTS + DRAWING_PIXELS_DOTS {
-                    if self.lcd_status.ppu_mode != PpuMode::DrawingPixels {
+                    self.scan_oam((self.cycles / 2) as usize);
+                } else {
+                    // Mode 3 started during these cycles.
+                    if line_start <= OAM_SCAN_DOTS
+                        && self.lcd_status.ppu_mode != PpuMode::DrawingPixels
+                    {
                         self.switch_to_mode(PpuMode::DrawingPixels, &mut inter);
                     }
-                } else {
-                    if self.lcd_status.ppu_mode != PpuMode::HBlank {
+                    let drawing_end = OAM_SCAN_DOTS + self.drawing_dots;
+                    if self.lcd_status.ppu_mode == PpuMode::DrawingPixels && !self.fast_renderer {
+                        let dots = self
+                            .cycles
+                            .min(drawing_end)
+                            .saturating_sub(line_start.max(OAM_SCAN_DOTS));
+                        self.draw_dots(dots);
+                    }
+                    if self.cycles > drawing_end
+                        && self.lcd_status.ppu_mode == PpuMode::DrawingPixels
+                    {
                         self.switch_to_mode(PpuMode::HBlank, &mut inter);
                     }
                 }
             }
+
+            self.current_stats.mode_dots[u8::from(self.lcd_status.ppu_mode) as usize] += cycles_now;
         }
 
         inter
@@ -272,47 +441,82 @@ impl GPU {
 
         match new_mode {
             PpuMode::HBlank => {
-                self.draw_line();
-                if self.lcd_status.hblank_interrupt {
-                    inter.lcd = true;
+                if self.fast_renderer {
+                    self.draw_line();
+                } else {
+                    self.finish_fifo();
                 }
             }
             PpuMode::VBlank => {
                 inter.vblank = true;
+                self.frames += 1;
 
                 self.window_current_y = 0;
                 self.window_y_trigger = false;
-
-                if self.lcd_status.vblank_interrupt {
-                    inter.lcd = true;
-                }
+                self.window_full_line = false;
             }
             PpuMode::OAMScan => {
-                if self.lcd_status.oam_scan_interrupt {
-                    inter.lcd = true;
-                }
+                self.obj_height = if self.lcd_control.obj_size { 16 } else { 8 };
+                self.start_oam_scan();
             }
             PpuMode::DrawingPixels => {
+                self.scan_oam(OAM_ENTRIES);
                 if self.lcd_control.window_enable && self.lcd_status.line() == self.window.y {
                     self.window_y_trigger = true;
+                    self.current_stats
+                        .window_trigger_line
+                        .get_or_insert(self.lcd_status.line());
+                }
+                self.drawing_dots = self.drawing_dots();
+            }
+        }
+
+        if self.lcd_status.update_stat_line() {
+            inter.lcd = true;
+        }
+    }
+
+    /// Length of mode 3 on the current line: 172 dots, plus the pixels of SCX fine scroll thrown
+    /// away, 6 dots to start fetching the window and 6 to 11 dots per object.
+    /// https://gbdev.io/pandocs/Rendering.html#mode-3-length
+    fn drawing_dots(&mut self) -> u64 {
+        let fine_scroll = (self.viewport.x % 8) as i16;
+        let mut dots = DRAWING_PIXELS_DOTS + fine_scroll as u64;
+        if self.lcd_control.window_enable && self.window_y_trigger && self.window.x <= 166 {
+            dots += 6;
+        }
+        if self.lcd_control.obj_enable {
+            // Background tiles under the left edge of an object, only the first one waits for the
+            // fetch of its tile.
+            let mut tiles_considered = 0u32;
+            for obj in &self.line_objs {
+                let oam_x = obj.pos.x + 8;
+                if oam_x >= SCREEN_WIDTH as i16 + 8 {
+                    continue;
                 }
+                let (tile, pixel) = ((oam_x + fine_scroll) / 8, (oam_x + fine_scroll) % 8);
+                if tiles_considered & (1 << tile) == 0 {
+                    tiles_considered |= 1 << tile;
+                    // Pixels of the tile right of the object's edge, minus 2.
+                    dots += (5 - pixel).max(0) as u64;
+                }
+                dots += 6;
             }
         }
+        dots.min(MAX_DRAWING_PIXELS_DOTS)
     }
 
     fn draw_line(&mut self) {
+        crate::profile_scope!(crate::profiler::Scope::PpuDrawLine);
         self.draw_tiles();
-
-        // PERF: Profile this shit: copying w*h*3 before drawing every line is too much.
-        let bg_state = self.buffer;
-
-        self.draw_sprites(&bg_state);
+        self.draw_sprites();
     }
 
     fn draw_tiles(&mut self) {
         // background is 256x256. Each tile is 8x8 pixels x2 (for color) = 16 byte.
         // background is 32x32 tiles. Each tile 16 bytes.
 
+        self.line_bg_colors = [0; SCREEN_WIDTH];
         if !self.lcd_control.bg_and_window_display {
             return;
         }
@@ -321,140 +525,178 @@ impl GPU {
             let tile = self.get_tile_addr(screen_x);
             let bg_mem = self.get_bg_mem(screen_x);
 
-            let tile_data = if self.lcd_control.bg_and_window_tile_data_area {
-                0x8000u16
-            } else {
-                0x8800
-            };
-
-            let tile_map_idx = (tile.y as u16 / 8) * 32 + tile.x as u16 / 8;
-
-            let tile_addr = {
-                let addr = bg_mem + tile_map_idx;
-                // https://gbdev.io/pandocs/Tile_Data.html#vram-tile-data
-                let v = self.vram[(addr - VIDEO_RAM_START) as usize];
-                tile_data
-                    + (if tile_data == 0x8000 {
-                        v as u16
-                    } else {
-                        (v as i8 as i16 + 128) as u16
-                    }) * 16
-            };
-
-            let line = (tile.y % 8) as u16 * 2;
+            let tile_idx = self.vram[tile_map_offset(bg_mem, tile)];
+            let tile_offset =
+                tile_data_offset(self.lcd_control.bg_and_window_tile_data_area, tile_idx);
+            let line = (tile.y % 8) as usize * 2;
 
             let data = [
-                self.vram[(tile_addr + line - VIDEO_RAM_START) as usize],
-                self.vram[(tile_addr + line + 1 - VIDEO_RAM_START) as usize],
+                self.vram[tile_offset + line],
+                self.vram[tile_offset + line + 1],
             ];
 
             let pixel = 7 - tile.x % 8;
-            let color = {
-                let color_raw = (((data[1] >> pixel) & 1) << 1) | ((data[0] >> pixel) & 1);
-                self.bg_colors.get()[color_raw as usize].rgb()
+            let (layer, visible) = if self.is_window_visible(screen_x) {
+                (Layer::Window, self.visible_layers.window)
+            } else {
+                (Layer::Background, self.visible_layers.background)
             };
+            let color_raw = if visible {
+                (((data[1] >> pixel) & 1) << 1) | ((data[0] >> pixel) & 1)
+            } else {
+                0
+            };
+            let color = self.bg_colors.get()[color_raw as usize].rgb();
+            self.line_bg_colors[screen_x as usize] = color_raw;
 
             self.buffer[screen_x as usize][self.lcd_status.line() as usize] = [color, color, color];
+            self.layer_buffer[screen_x as usize][self.lcd_status.line() as usize] = layer;
         }
 
-        if self.is_window_visible(SCREEN_WIDTH as u8 - 1) {
+        self.window_line_done(self.is_window_visible(SCREEN_WIDTH as u8 - 1));
+    }
+
+    /// The window line counter only advances on lines the window was drawn on.
+    fn window_line_done(&mut self, drawn: bool) {
+        if drawn {
             self.window_current_y += 1;
         }
+        // With WX = 166 the window starts at the last pixel and carries on over the next line.
+        self.window_full_line = drawn && self.window.x == 166;
     }
 
-    fn draw_sprites(&mut self, bg_state: &[[[u8; 3]; SCREEN_HEIGHT]; SCREEN_WIDTH]) {
-        // The Game Boy PPU can display up to 40 movable objects (or sprites), each 8×8 or
-        // 8×16 pixels. Because of a limitation of hardware, only 10 objects can be displayed per
-        // scanline.
-        const MAX_OBJS_PER_SCANLINE: usize = 10;
+    /// Window pixels left of the screen when WX < 7. With WX = 0 the fine scroll of SCX moves the
+    /// window too ("stutter").
+    /// https://gbdev.io/pandocs/Scrolling.html#ff4aff4b--wy-wx-window-y-position-x-position-plus-7
+    fn window_hidden_pixels(&self) -> u8 {
+        match self.window.x {
+            0 => 7 + self.viewport.x % 8,
+            wx => 7u8.saturating_sub(wx),
+        }
+    }
 
+    fn draw_sprites(&mut self) {
         if !self.lcd_control.obj_enable {
             return;
         }
 
-        let obj_height = if self.lcd_control.obj_size { 16u16 } else { 8 };
-        let mut objs_to_draw = Vec::with_capacity(40);
+        if !self.visible_layers.sprites {
+            return;
+        }
 
-        for sprite_attr_addr in ((0xFE00 - OAM_START)..=(0xFE9F - OAM_START)).step_by(4) {
-            if objs_to_draw.len() == MAX_OBJS_PER_SCANLINE {
-                break;
-            }
+        let obj_height = self.obj_height;
+        let mut objs_to_draw = self.line_objs.clone();
+        // https://gbdev.io/pandocs/OAM.html#drawing-priority
+        // The smaller the X coordinate, the higher the priority. When X coordinates are the same,
+        // the object located first in OAM has higher priority.
+        objs_to_draw.sort_unstable();
 
-            let mem: [u8; 4] = self.oam[sprite_attr_addr as usize..(sprite_attr_addr + 4) as usize]
-                .try_into()
-                .unwrap();
-            let obj = Oam::new(sprite_attr_addr as usize / 4, obj_height, mem);
+        let line = self.lcd_status.line() as usize;
+        for screen_x in 0..SCREEN_WIDTH as i16 {
+            // Only the highest priority non-transparent pixel is drawn, even if it ends up hidden
+            // behind the background.
+            let Some((obj, color_raw)) = objs_to_draw.iter().find_map(|obj| {
+                self.obj_color_idx(obj, screen_x, obj_height)
+                    .map(|color_raw| (obj, color_raw))
+            }) else {
+                continue;
+            };
 
-            if !(obj.pos.y <= self.lcd_status.line() as i16
-                && (self.lcd_status.line() as i16) < obj.pos.y + obj_height as i16)
-            {
+            if obj.attrs.bg_prio && self.line_bg_colors[screen_x as usize] != 0 {
                 continue;
             }
+            let pixel = &mut self.buffer[screen_x as usize][line];
 
-            objs_to_draw.push(obj);
-        }
-        objs_to_draw.sort_unstable();
-        objs_to_draw.reverse();
-
-        for obj in objs_to_draw {
-            let line = if obj.attrs.y_flip {
-                obj_height - 1 - (self.lcd_status.line() as i16 - obj.pos.y) as u16
+            let (layer, color) = if obj.attrs.dmg_palette {
+                (
+                    Layer::Obj1,
+                    self.obj1_colors.get()[color_raw as usize].rgb(),
+                )
             } else {
-                (self.lcd_status.line() as i16 - obj.pos.y) as u16
+                (
+                    Layer::Obj0,
+                    self.obj0_colors.get()[color_raw as usize].rgb(),
+                )
             };
+            *pixel = [color, color, color];
+            self.layer_buffer[screen_x as usize][line] = layer;
+        }
+    }
 
-            let addr = 0x8000 + obj.tile_idx as u16 * 16 + line * 2 - VIDEO_RAM_START;
+    fn start_oam_scan(&mut self) {
+        self.line_objs.clear();
+        self.scan_entry = 0;
+    }
 
-            let data = [self.vram[addr as usize], self.vram[addr as usize + 1]];
+    /// Runs the OAM scan up to entry `end`: objects on the current line are selected in OAM
+    /// order until the buffer holds the limit per line, later ones are dropped even if they'd have
+    /// a higher drawing priority.
+    fn scan_oam(&mut self, end: usize) {
+        let line = self.lcd_status.line() as i16;
+        let end = end.min(OAM_ENTRIES);
+        while self.scan_entry < end {
+            let idx = self.scan_entry;
+            self.scan_entry += 1;
+            if self.line_objs.len() == self.sprites_per_line {
+                continue;
+            }
+            let mem: [u8; 4] = self.oam[idx * 4..][..4].try_into().unwrap();
+            let obj = Oam::new(idx, self.obj_height, mem);
+            // Objects left or right of the screen take a slot too.
+            if obj.pos.y <= line && line < obj.pos.y + self.obj_height as i16 {
+                self.line_objs.push(obj);
+            }
+        }
+        if self.scan_entry == OAM_ENTRIES && (line as usize) < SCREEN_HEIGHT {
+            self.current_stats.sprites_per_line[line as usize] = self.line_objs.len() as u8;
+        }
+    }
 
-            for pixel_x in (0..8).rev() {
-                if !(0 <= obj.pos.x + pixel_x && obj.pos.x + pixel_x < SCREEN_WIDTH as i16) {
-                    continue;
-                }
+    /// Color index of the object pixel on the current line at `screen_x`, `None` if the object
+    /// doesn't cover it or the pixel is transparent.
+    fn obj_color_idx(&self, obj: &Oam, screen_x: i16, obj_height: u16) -> Option<u8> {
+        let pixel_x = screen_x - obj.pos.x;
+        if !(0..8).contains(&pixel_x) {
+            return None;
+        }
 
-                let color_bit = if obj.attrs.x_flip {
-                    pixel_x
-                } else {
-                    7 - pixel_x
-                };
-
-                let color = {
-                    let color_raw =
-                        (((data[1] >> color_bit) & 1) << 1) | ((data[0] >> color_bit) & 1);
-                    // Note that while 4 colors are stored per OBJ palette, color #0
-                    // is never used, as it’s always transparent.
-                    if color_raw == 0 {
-                        continue;
-                    }
-                    if obj.attrs.dmg_palette {
-                        self.obj1_colors.get()[color_raw as usize].rgb()
-                    } else {
-                        self.obj0_colors.get()[color_raw as usize].rgb()
-                    }
-                };
+        let line = if obj.attrs.y_flip {
+            obj_height - 1 - (self.lcd_status.line() as i16 - obj.pos.y) as u16
+        } else {
+            (self.lcd_status.line() as i16 - obj.pos.y) as u16
+        };
 
-                let buffer_x = pixel_x + obj.pos.x;
+        let addr = 0x8000 + obj.tile_idx as u16 * 16 + line * 2 - VIDEO_RAM_START;
+        let data = [self.vram[addr as usize], self.vram[addr as usize + 1]];
 
-                if obj.attrs.bg_prio
-                    && bg_state[buffer_x as usize][self.lcd_status.line() as usize][0]
-                        != Color::White.rgb()
-                {
-                    continue;
-                }
+        let color_bit = if obj.attrs.x_flip {
+            pixel_x
+        } else {
+            7 - pixel_x
+        };
+        let color_raw = (((data[1] >> color_bit) & 1) << 1) | ((data[0] >> color_bit) & 1);
 
-                self.buffer[buffer_x as usize][self.lcd_status.line() as usize] =
-                    [color, color, color];
-            }
-        }
+        // Note that while 4 colors are stored per OBJ palette, color #0
+        // is never used, as it’s always transparent.
+        (color_raw != 0).then_some(color_raw)
     }
 
     fn is_window_visible(&self, screen_x: u8) -> bool {
-        self.lcd_control.window_enable && self.window_y_trigger && self.window.x <= screen_x + 7
+        self.lcd_control.window_enable
+            && self.window_y_trigger
+            && (self.window_full_line || self.window.x <= screen_x + 7)
     }
 
     fn get_tile_addr(&mut self, screen_x: u8) -> Coordinate<u8> {
         if self.is_window_visible(screen_x) {
-            Coordinate::new(screen_x + 7 - self.window.x, self.window_current_y)
+            let window_x = if self.window_full_line {
+                screen_x
+            } else if self.window.x < 7 {
+                screen_x + self.window_hidden_pixels()
+            } else {
+                screen_x + 7 - self.window.x
+            };
+            Coordinate::new(window_x, self.window_current_y)
         } else {
             Coordinate::new(
                 self.viewport.x.wrapping_add(screen_x),
@@ -480,6 +722,25 @@ impl GPU {
     }
 }
 
+/// Index in VRAM of the tile map entry with the tile that contains the pixel at `tile` in the map
+/// at `map_area` ($9800 or $9C00).
+pub(crate) fn tile_map_offset(map_area: u16, tile: Coordinate<u8>) -> usize {
+    let tile_map_idx = (tile.y as usize / 8) * 32 + tile.x as usize / 8;
+    (map_area.wrapping_sub(VIDEO_RAM_START) as usize + tile_map_idx) & (VIDEO_RAM_SIZE - 1)
+}
+
+/// Index in VRAM of the first byte of a BG/window tile.
+/// https://gbdev.io/pandocs/Tile_Data.html#vram-tile-data
+pub(crate) fn tile_data_offset(unsigned_addressing: bool, tile_idx: u8) -> usize {
+    if unsigned_addressing {
+        // $8000 method: blocks 0 and 1.
+        tile_idx as usize * 16
+    } else {
+        // $8800 method: blocks 2 and 1, 0..=127 from $9000, -128..=-1 from $8800.
+        (0x1000 + tile_idx as i8 as isize * 16) as usize & (VIDEO_RAM_SIZE - 1)
+    }
+}
+
 impl From<PpuMode> for u8 {
     fn from(val: PpuMode) -> Self {
         match val {
@@ -563,6 +824,47 @@ impl From<u8> for OamAttributes {
     }
 }
 
+impl SaveState for GPU {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.bytes(self.buffer.as_flattened_mut().as_flattened_mut());
+        state.bytes(&mut self.vram);
+        state.bytes(&mut self.oam);
+        state.as_u8(&mut self.lcd_control, u8::from, |val| Some(val.into()));
+        state.visit(&mut self.lcd_status);
+        for coordinate in [&mut self.viewport, &mut self.window] {
+            state.u8(&mut coordinate.x);
+            state.u8(&mut coordinate.y);
+        }
+        state.bool(&mut self.window_y_trigger);
+        state.u8(&mut self.window_current_y);
+        state.bool(&mut self.window_full_line);
+        state.u16(&mut self.obj_height);
+        for colors in [
+            &mut self.bg_colors,
+            &mut self.obj0_colors,
+            &mut self.obj1_colors,
+        ] {
+            state.as_u8(colors, u8::from, |val| Some(val.into()));
+        }
+        state.u64(&mut self.cycles);
+        state.u64(&mut self.drawing_dots);
+        state.u64(&mut self.frames);
+        // Not saved: a state loaded during mode 3 draws the line again from its first pixel, and
+        // the objects of the line are scanned again up to the current dot.
+        if state.is_loading() {
+            self.fifo = None;
+            self.start_oam_scan();
+            if self.lcd_status.line() <= LAST_VISIBLE_SCANLINE {
+                match self.lcd_status.ppu_mode {
+                    PpuMode::OAMScan => self.scan_oam((self.cycles / 2) as usize),
+                    PpuMode::DrawingPixels => self.scan_oam(OAM_ENTRIES),
+                    PpuMode::HBlank | PpuMode::VBlank => {}
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -577,4 +879,314 @@ mod test {
         let _ = gpu.lcd_status.set_line(100);
         assert_eq!(gpu.get_tile_addr(100), Coordinate::new(44, 44));
     }
+
+    #[test]
+    fn tile_data_addressing() {
+        // $8000 method.
+        assert_eq!(tile_data_offset(true, 0), 0x8000 - VIDEO_RAM_START as usize);
+        assert_eq!(
+            tile_data_offset(true, 127),
+            0x87F0 - VIDEO_RAM_START as usize
+        );
+        assert_eq!(
+            tile_data_offset(true, 128),
+            0x8800 - VIDEO_RAM_START as usize
+        );
+        assert_eq!(
+            tile_data_offset(true, 255),
+            0x8FF0 - VIDEO_RAM_START as usize
+        );
+        // $8800 method.
+        assert_eq!(
+            tile_data_offset(false, 0),
+            0x9000 - VIDEO_RAM_START as usize
+        );
+        assert_eq!(
+            tile_data_offset(false, 127),
+            0x97F0 - VIDEO_RAM_START as usize
+        );
+        assert_eq!(
+            tile_data_offset(false, 128),
+            0x8800 - VIDEO_RAM_START as usize
+        );
+        assert_eq!(
+            tile_data_offset(false, 255),
+            0x8FF0 - VIDEO_RAM_START as usize
+        );
+
+        assert_eq!(
+            tile_map_offset(0x9C00, Coordinate::new(255, 255)),
+            0x9FFF - VIDEO_RAM_START as usize
+        );
+        assert_eq!(
+            tile_map_offset(0x9800, Coordinate::new(8, 16)),
+            0x9841 - VIDEO_RAM_START as usize
+        );
+    }
+
+    #[test]
+    fn draw_tiles_at_vram_edges() {
+        let mut gpu = GPU::new();
+        // LCD on, $8800 tile data, $9C00 BG map, BG on.
+        gpu.set_lcd_control(0b1000_1001);
+        gpu.viewport = Coordinate::new(255, 255);
+        // The last tile of the map uses the last tile of block 1 at $97F0.
+        gpu.vram[0x9FFF - VIDEO_RAM_START as usize] = 127;
+        gpu.vram[0x97FE - VIDEO_RAM_START as usize..][..2].copy_from_slice(&[0x01, 0x01]);
+
+        gpu.draw_tiles();
+        assert_eq!(gpu.buffer[0][0], [0; 3]);
+        assert_eq!(gpu.buffer[1][0], [0xFF; 3]);
+    }
+
+    /// Runs the whole OAM scan of the current line, before drawing it directly.
+    fn scan_line(gpu: &mut GPU) {
+        gpu.start_oam_scan();
+        gpu.scan_oam(OAM_ENTRIES);
+    }
+
+    #[test]
+    fn sprite_priority_is_resolved_per_pixel() {
+        let mut gpu = GPU::new();
+        // LCD on, 0x8000 tile data, OBJ on, BG on.
+        gpu.set_lcd_control(0b1001_0011);
+
+        // BG: black tile 1 at x 0..8, white tile 0 elsewhere.
+        gpu.vram[(0x9800 - VIDEO_RAM_START) as usize] = 1;
+        gpu.vram[16..18].copy_from_slice(&[0xFF, 0xFF]);
+        // OBJ: tile 2 is fully opaque, tile 3 is opaque only on the left half.
+        gpu.vram[32..34].copy_from_slice(&[0xFF, 0xFF]);
+        gpu.vram[48..50].copy_from_slice(&[0xF0, 0xF0]);
+        gpu.obj0_colors = BackgroundColors::from(0b10_00_00_00);
+        gpu.obj1_colors = BackgroundColors::from(0b01_00_00_00);
+
+        let oam = [
+            // Hidden behind the black BG.
+            [16, 8, 2, 0b1000_0000],
+            // Same X, lower priority: must not show through the hidden object above.
+            [16, 8, 2, 0b0001_0000],
+            // Same X with different palettes: OBP0 wins where it is opaque.
+            [16, 24, 3, 0b0000_0000],
+            [16, 24, 2, 0b0001_0000],
+        ];
+        for (idx, obj) in oam.iter().enumerate() {
+            gpu.oam[idx * 4..idx * 4 + 4].copy_from_slice(obj);
+        }
+
+        scan_line(&mut gpu);
+        gpu.draw_line();
+
+        let line: Vec<u8> = (0..SCREEN_WIDTH).map(|x| gpu.buffer[x][0][0]).collect();
+        assert_eq!(line[0..8], [Color::Black.rgb(); 8]);
+        assert_eq!(line[8..16], [Color::White.rgb(); 8]);
+        assert_eq!(line[16..20], [Color::DarkGray.rgb(); 4]);
+        assert_eq!(line[20..24], [Color::LightGray.rgb(); 4]);
+    }
+
+    #[test]
+    fn hidden_layers() {
+        let mut gpu = GPU::new();
+        // LCD on, 0x8000 tile data, OBJ on, BG on.
+        gpu.set_lcd_control(0b1001_0011);
+        // BG: black tile 1 at x 0..8, white tile 0 elsewhere.
+        gpu.vram[(0x9800 - VIDEO_RAM_START) as usize] = 1;
+        gpu.vram[16..18].copy_from_slice(&[0xFF, 0xFF]);
+        gpu.vram[32..34].copy_from_slice(&[0xFF, 0xFF]);
+        gpu.obj0_colors = BackgroundColors::from(0b10_00_00_00);
+        // Behind the black BG and in front of the white BG.
+        gpu.oam[..8].copy_from_slice(&[16, 8, 2, 0b1000_0000, 16, 24, 2, 0]);
+
+        let pixels = |gpu: &GPU| (gpu.buffer[0][0][0], gpu.buffer[16][0][0]);
+        scan_line(&mut gpu);
+        gpu.draw_line();
+        assert_eq!(pixels(&gpu), (Color::Black.rgb(), Color::DarkGray.rgb()));
+
+        gpu.visible_layers.background = false;
+        gpu.draw_line();
+        assert_eq!(pixels(&gpu), (Color::DarkGray.rgb(), Color::DarkGray.rgb()));
+        assert_eq!(gpu.layer_buffer[0][0], Layer::Obj0);
+        assert_eq!(gpu.layer_buffer[8][0], Layer::Background);
+
+        gpu.visible_layers = Layers {
+            sprites: false,
+            ..Layers::default()
+        };
+        gpu.draw_line();
+        assert_eq!(pixels(&gpu), (Color::Black.rgb(), Color::White.rgb()));
+    }
+
+    #[test]
+    fn sprite_limit() {
+        let mut gpu = GPU::new();
+        // LCD on, OBJ on, white BG of tile 0.
+        gpu.set_lcd_control(0b1000_0011);
+        gpu.vram[32..34].copy_from_slice(&[0xFF, 0xFF]);
+        gpu.obj0_colors = BackgroundColors::from(0b11_00_00_00);
+        // 11 objects side by side on line 0, the last one in OAM is the rightmost.
+        for obj in 0..11 {
+            gpu.oam[obj * 4..][..4].copy_from_slice(&[16, 8 + 8 * obj as u8, 2, 0]);
+        }
+        let last_drawn = |gpu: &GPU| gpu.buffer[80][0][0] == Color::Black.rgb();
+
+        scan_line(&mut gpu);
+        gpu.draw_line();
+        assert!(!last_drawn(&gpu));
+        assert_eq!(gpu.current_stats.sprites_per_line[0], 10);
+
+        gpu.sprites_per_line = 40;
+        scan_line(&mut gpu);
+        gpu.draw_line();
+        assert!(last_drawn(&gpu));
+        assert_eq!(gpu.current_stats.sprites_per_line[0], 11);
+    }
+
+    #[test]
+    fn oam_scan_selects_in_oam_order() {
+        let mut gpu = GPU::new();
+        gpu.set_lcd_control(0b1000_0011);
+        // 10 objects on line 1, the first one off-screen at X 0.
+        for obj in 0..10 {
+            gpu.oam[obj * 4..][..4].copy_from_slice(&[17, 8 * obj as u8, 0, 0]);
+        }
+        // Leftmost, so it would be drawn first, but the buffer is full when it's scanned.
+        gpu.oam[20 * 4..][..4].copy_from_slice(&[17, 1, 0, 0]);
+        step_to(&mut gpu, 1, PpuMode::DrawingPixels);
+        let selected = |gpu: &GPU| {
+            gpu.line_objs
+                .iter()
+                .map(|obj| obj.oam_idx)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(selected(&gpu), (0..10).collect::<Vec<_>>());
+
+        // Entries are checked 2 dots each: an object moved to entry 39 before the scan reaches it
+        // is selected, one moved to entry 0 after that isn't.
+        gpu.oam.fill(0);
+        step_to(&mut gpu, 2, PpuMode::OAMScan);
+        gpu.step(4);
+        gpu.oam[39 * 4..][..4].copy_from_slice(&[18, 8, 0, 0]);
+        gpu.oam[..4].copy_from_slice(&[18, 8, 0, 0]);
+        step_to(&mut gpu, 2, PpuMode::DrawingPixels);
+        assert_eq!(selected(&gpu), [39]);
+    }
+
+    /// Steps until the PPU enters `mode` on `line`.
+    fn step_to(gpu: &mut GPU, line: u8, mode: PpuMode) {
+        while !(gpu.lcd_status.line() == line && gpu.lcd_status.ppu_mode == mode) {
+            gpu.step(4);
+        }
+    }
+
+    #[test]
+    fn obj_size_is_latched_per_scanline() {
+        // With the white background of tile 0.
+        const OBJ_8X8: u8 = 0b1000_0011;
+        const OBJ_8X16: u8 = 0b1000_0111;
+
+        let mut gpu = GPU::new();
+        gpu.set_lcd_control(OBJ_8X8);
+        // Tiles 2 and 3 of an 8x16 object at the top of the screen, only the bottom one is opaque.
+        gpu.vram[48..64].fill(0xFF);
+        gpu.obj0_colors = BackgroundColors::from(0b11_00_00_00);
+        gpu.oam[..4].copy_from_slice(&[16, 8, 2, 0]);
+        let drawn = |gpu: &GPU, line: usize| gpu.buffer[0][line][0] == Color::Black.rgb();
+
+        // Changed while drawing line 8: the OAM scan already saw 8x8 objects.
+        step_to(&mut gpu, 8, PpuMode::DrawingPixels);
+        gpu.set_lcd_control(OBJ_8X16);
+        step_to(&mut gpu, 9, PpuMode::OAMScan);
+        assert!(!drawn(&gpu, 8));
+        step_to(&mut gpu, 10, PpuMode::OAMScan);
+        assert!(drawn(&gpu, 9));
+
+        // Changed during the OAM scan of line 12: still 8x16 for it.
+        step_to(&mut gpu, 12, PpuMode::OAMScan);
+        gpu.step(4);
+        gpu.set_lcd_control(OBJ_8X8);
+        step_to(&mut gpu, 13, PpuMode::OAMScan);
+        assert!(drawn(&gpu, 12));
+        step_to(&mut gpu, 14, PpuMode::OAMScan);
+        assert!(!drawn(&gpu, 13));
+    }
+
+    #[test]
+    fn mode_3_length() {
+        let mut gpu = GPU::new();
+        gpu.set_lcd_control(0b1000_0011);
+        let drawing_dots = |gpu: &mut GPU, line: u8| {
+            step_to(gpu, line, PpuMode::OAMScan);
+            while gpu.lcd_status.ppu_mode != PpuMode::DrawingPixels {
+                gpu.step(1);
+            }
+            let mut dots = 0;
+            while gpu.lcd_status.ppu_mode == PpuMode::DrawingPixels {
+                gpu.step(1);
+                dots += 1;
+            }
+            dots
+        };
+        assert_eq!(drawing_dots(&mut gpu, 1), 172);
+
+        gpu.viewport.x = 3;
+        assert_eq!(drawing_dots(&mut gpu, 2), 175);
+
+        // An object 3 pixels into its background tile waits 2 dots for it, then takes 6.
+        gpu.oam[..4].copy_from_slice(&[16 + 3, 8, 0, 0]);
+        assert_eq!(drawing_dots(&mut gpu, 3), 183);
+        // The second object over the same tile only takes 6.
+        gpu.oam[4..8].copy_from_slice(&[16 + 4, 10, 0, 0]);
+        assert_eq!(drawing_dots(&mut gpu, 4), 189);
+    }
+
+    #[test]
+    fn stat_interrupt_on_rising_edge() {
+        let lcd_interrupts =
+            |gpu: &mut GPU, dots: u64| (0..dots).filter(|_| gpu.step(1).lcd).count();
+
+        let mut gpu = GPU::new();
+        gpu.set_lcd_control(0b1000_0000);
+        step_to(&mut gpu, 1, PpuMode::DrawingPixels);
+        // HBlank and OAM scan are adjacent: the line stays high from HBlank to the next mode 3.
+        assert!(!gpu.lcd_status.write_byte_to_status(0b0010_1000));
+        assert_eq!(lcd_interrupts(&mut gpu, 456), 1);
+        assert_eq!(lcd_interrupts(&mut gpu, 456), 1);
+
+        // LY=LYC during HBlank is blocked by the HBlank source.
+        step_to(&mut gpu, 5, PpuMode::OAMScan);
+        assert!(!gpu.lcd_status.write_byte_to_status(0b0100_1000));
+        assert!(!gpu.lcd_status.set_lyc(6));
+        assert_eq!(lcd_interrupts(&mut gpu, 456), 1);
+        // Enabling a source while its condition holds raises the line.
+        step_to(&mut gpu, 8, PpuMode::OAMScan);
+        assert!(gpu.lcd_status.write_byte_to_status(0b0010_0000));
+    }
+
+    #[test]
+    fn stats_cover_whole_frame() {
+        const FRAME_DOTS: u64 = 456 * 154;
+
+        let mut gpu = GPU::new();
+        gpu.set_lcd_control(0b1000_0010);
+        gpu.oam[..4].copy_from_slice(&[16 + 10, 8, 0, 0]);
+
+        // Power on in the middle of the frame, then draw the next one completely.
+        for _ in 0..(2 * FRAME_DOTS / 4) {
+            gpu.step(4);
+        }
+
+        let stats = gpu.stats();
+        assert_eq!(stats.total_dots(), FRAME_DOTS);
+        assert_eq!(stats.dots_in(PpuMode::VBlank), 456 * 10);
+        assert_eq!(
+            stats.dots_in(PpuMode::OAMScan)
+                + stats.dots_in(PpuMode::DrawingPixels)
+                + stats.dots_in(PpuMode::HBlank),
+            456 * 144
+        );
+        assert_eq!(stats.sprites_per_line[9], 0);
+        assert_eq!(stats.sprites_per_line[10], 1);
+        assert_eq!(stats.sprites_per_line[17], 1);
+        assert_eq!(stats.sprites_per_line[18], 0);
+        assert_eq!(stats.window_trigger_line, None);
+    }
 }
@@ -0,0 +1,73 @@
+// Per-frame PPU statistics: how the raster budget was spent.
+
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use super::PpuMode;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PpuStats {
+    /// Dots spent in each mode, indexed by the STAT mode number (0: HBlank, 1: VBlank, 2: OAM
+    /// scan, 3: drawing pixels).
+    pub mode_dots: [u64; 4],
+    /// Number of objects selected during OAM scan for each visible line.
+    pub sprites_per_line: [u8; SCREEN_HEIGHT],
+    /// The line where WY matched LY, if the window was triggered this frame.
+    pub window_trigger_line: Option<u8>,
+}
+
+impl PpuStats {
+    pub fn new() -> Self {
+        Self {
+            mode_dots: [0; 4],
+            sprites_per_line: [0; SCREEN_HEIGHT],
+            window_trigger_line: None,
+        }
+    }
+
+    pub fn dots_in(&self, mode: PpuMode) -> u64 {
+        self.mode_dots[u8::from(mode) as usize]
+    }
+
+    pub fn total_dots(&self) -> u64 {
+        self.mode_dots.iter().sum()
+    }
+
+    pub fn max_sprites_per_line(&self) -> u8 {
+        self.sprites_per_line.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Draws the statistics on top of a frame:
+    /// - a stacked bar of mode budgets in the top 3 rows;
+    /// - a bar per line at the right edge with the number of objects on it (2 pixels per object);
+    /// - a marker at the left edge on the window trigger line.
+    pub fn draw_overlay(&self, buff: &mut [u32; SCREEN_HEIGHT * SCREEN_WIDTH]) {
+        const MODE_COLORS: [u32; 4] = [0x3060FF, 0x808080, 0xFFC000, 0xFF3030];
+        const BAR_HEIGHT: usize = 3;
+        const SPRITE_COLOR: u32 = 0xFF00FF;
+        const WINDOW_COLOR: u32 = 0x00FFFF;
+
+        let total = self.total_dots().max(1);
+        let mut col = 0;
+        for (mode, &dots) in self.mode_dots.iter().enumerate() {
+            let width = (dots * SCREEN_WIDTH as u64 / total) as usize;
+            for row in buff.chunks_exact_mut(SCREEN_WIDTH).take(BAR_HEIGHT) {
+                row[col..(col + width).min(SCREEN_WIDTH)].fill(MODE_COLORS[mode]);
+            }
+            col += width;
+        }
+
+        for (row, &count) in buff
+            .chunks_exact_mut(SCREEN_WIDTH)
+            .zip(&self.sprites_per_line)
+        {
+            let width = count as usize * 2;
+            row[SCREEN_WIDTH - width..].fill(SPRITE_COLOR);
+        }
+
+        if let Some(line) = self.window_trigger_line {
+            if let Some(row) = buff.chunks_exact_mut(SCREEN_WIDTH).nth(line as usize) {
+                row[..8].fill(WINDOW_COLOR);
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// Memory access statistics for finding hot variables and DMA loops.
+
+use std::cell::Cell;
+
+use crate::{
+    memory_bus::{WORKING_RAM_END, WORKING_RAM_SIZE, WORKING_RAM_START},
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+const PAGES: usize = 256;
+
+// 16x16 pages fill the whole screen: 10x9 pixels per page.
+const GRID_SIZE: usize = 16;
+const CELL_WIDTH: usize = SCREEN_WIDTH / GRID_SIZE;
+const CELL_HEIGHT: usize = SCREEN_HEIGHT / GRID_SIZE;
+
+pub struct MemoryHeatmap {
+    // Reads happen through `&MemoryBus`, so counters need interior mutability.
+    page_reads: [Cell<u64>; PAGES],
+    page_writes: [Cell<u64>; PAGES],
+    /// Per-byte counters for WRAM, empty when disabled.
+    wram_reads: Vec<Cell<u32>>,
+    wram_writes: Vec<Cell<u32>>,
+}
+
+impl MemoryHeatmap {
+    pub fn new(wram_detail: bool) -> Self {
+        let wram_len = if wram_detail { WORKING_RAM_SIZE } else { 0 };
+        Self {
+            page_reads: std::array::from_fn(|_| Cell::new(0)),
+            page_writes: std::array::from_fn(|_| Cell::new(0)),
+            wram_reads: (0..wram_len).map(|_| Cell::new(0)).collect(),
+            wram_writes: (0..wram_len).map(|_| Cell::new(0)).collect(),
+        }
+    }
+
+    pub fn record_read(&self, addr: u16) {
+        increment(&self.page_reads[(addr >> 8) as usize]);
+        if let Some(counter) = self.wram_counter(&self.wram_reads, addr) {
+            counter.set(counter.get().saturating_add(1));
+        }
+    }
+
+    pub fn record_write(&self, addr: u16) {
+        increment(&self.page_writes[(addr >> 8) as usize]);
+        if let Some(counter) = self.wram_counter(&self.wram_writes, addr) {
+            counter.set(counter.get().saturating_add(1));
+        }
+    }
+
+    /// Number of reads from addresses `page << 8..=(page << 8) | 0xFF`.
+    pub fn page_reads(&self, page: u8) -> u64 {
+        self.page_reads[page as usize].get()
+    }
+
+    pub fn page_writes(&self, page: u8) -> u64 {
+        self.page_writes[page as usize].get()
+    }
+
+    /// Read and write counts of a single WRAM byte, if per-byte tracking is enabled.
+    pub fn wram_byte(&self, addr: u16) -> Option<(u32, u32)> {
+        Some((
+            self.wram_counter(&self.wram_reads, addr)?.get(),
+            self.wram_counter(&self.wram_writes, addr)?.get(),
+        ))
+    }
+
+    pub fn reset(&self) {
+        self.page_reads
+            .iter()
+            .chain(&self.page_writes)
+            .for_each(|c| c.set(0));
+        self.wram_reads
+            .iter()
+            .chain(&self.wram_writes)
+            .for_each(|c| c.set(0));
+    }
+
+    /// Draws a 16x16 grid of pages (0x00 in the top-left corner, 0xFF in the bottom-right one).
+    /// Green shows reads and red shows writes, both on a logarithmic scale.
+    pub fn to_rgb32(&self, buff: &mut [u32; SCREEN_HEIGHT * SCREEN_WIDTH]) {
+        let max_reads = self.page_reads.iter().map(Cell::get).max().unwrap_or(0);
+        let max_writes = self.page_writes.iter().map(Cell::get).max().unwrap_or(0);
+
+        for page in 0..PAGES {
+            let red = intensity(self.page_writes[page].get(), max_writes) as u32;
+            let green = intensity(self.page_reads[page].get(), max_reads) as u32;
+            let color = (red << 16) | (green << 8);
+
+            let (grid_x, grid_y) = (page % GRID_SIZE, page / GRID_SIZE);
+            for row in 0..CELL_HEIGHT {
+                for col in 0..CELL_WIDTH {
+                    // Keep 1 pixel border between pages.
+                    let border = row == CELL_HEIGHT - 1 || col == CELL_WIDTH - 1;
+                    buff[(grid_y * CELL_HEIGHT + row) * SCREEN_WIDTH + grid_x * CELL_WIDTH + col] =
+                        if border { 0x202020 } else { color };
+                }
+            }
+        }
+    }
+
+    fn wram_counter<'a>(&self, counters: &'a [Cell<u32>], addr: u16) -> Option<&'a Cell<u32>> {
+        if (WORKING_RAM_START..=WORKING_RAM_END).contains(&addr) {
+            counters.get((addr - WORKING_RAM_START) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+fn increment(counter: &Cell<u64>) {
+    counter.set(counter.get().saturating_add(1));
+}
+
+fn intensity(count: u64, max: u64) -> u8 {
+    if count == 0 || max == 0 {
+        return 0;
+    }
+    // Keep visited pages visible even if they are much colder than the hottest one.
+    let scaled = ((count as f64).ln_1p() / (max as f64).ln_1p() * 223.0) as u8;
+    32 + scaled
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn page_and_wram_counters() {
+        let heatmap = MemoryHeatmap::new(true);
+
+        heatmap.record_read(0xC012);
+        heatmap.record_read(0xC0FF);
+        heatmap.record_write(0xC012);
+        heatmap.record_write(0xFF40);
+
+        assert_eq!(heatmap.page_reads(0xC0), 2);
+        assert_eq!(heatmap.page_writes(0xC0), 1);
+        assert_eq!(heatmap.page_writes(0xFF), 1);
+        assert_eq!(heatmap.wram_byte(0xC012), Some((1, 1)));
+        assert_eq!(heatmap.wram_byte(0xFF40), None);
+
+        heatmap.reset();
+        assert_eq!(heatmap.page_reads(0xC0), 0);
+        assert_eq!(heatmap.wram_byte(0xC012), Some((0, 0)));
+    }
+
+    #[test]
+    fn wram_detail_is_optional() {
+        let heatmap = MemoryHeatmap::new(false);
+        heatmap.record_read(0xC012);
+        assert_eq!(heatmap.wram_byte(0xC012), None);
+    }
+}
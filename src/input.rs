@@ -0,0 +1,135 @@
+// Joypad buttons held in the frontend. Turbo keys press and release their button every few
+// frames while held, e.g. to fire shots or skip text.
+
+use crate::{cpu::JoypadKey, input_macro::KEY_NAMES, GPU_FPS};
+
+/// Turbo presses per second.
+pub const DEFAULT_TURBO_RATE: u32 = 10;
+
+pub struct Input {
+    /// Indexed by [`JoypadKey`].
+    held: [bool; 8],
+    /// Frame where the turbo key of a button was pressed.
+    turbo_since: [Option<u64>; 8],
+    /// Frames of each half of a turbo press: pressed, then released.
+    half_period: u64,
+    frame: u64,
+    /// Buttons as last reported by [`Input::changes`].
+    pressed: [bool; 8],
+}
+
+impl Input {
+    pub fn new(turbo_rate: u32) -> Self {
+        let mut input = Self {
+            held: [false; 8],
+            turbo_since: [None; 8],
+            half_period: 1,
+            frame: 0,
+            pressed: [false; 8],
+        };
+        input.set_turbo_rate(turbo_rate);
+        input
+    }
+
+    /// Rounded to whole frames, at most one press every 2 frames.
+    pub fn set_turbo_rate(&mut self, presses_per_second: u32) {
+        let frames = GPU_FPS as f64 / presses_per_second.max(1) as f64;
+        self.half_period = ((frames / 2.0).round() as u64).max(1);
+    }
+
+    pub fn key_down(&mut self, button: JoypadKey) {
+        self.held[button as usize] = true;
+    }
+
+    pub fn key_up(&mut self, button: JoypadKey) {
+        self.held[button as usize] = false;
+    }
+
+    /// The button is pressed right away, then toggles every half period.
+    pub fn turbo_down(&mut self, button: JoypadKey) {
+        let since = &mut self.turbo_since[button as usize];
+        if since.is_none() {
+            *since = Some(self.frame);
+        }
+    }
+
+    pub fn turbo_up(&mut self, button: JoypadKey) {
+        self.turbo_since[button as usize] = None;
+    }
+
+    /// Advances turbo presses by an emulated frame.
+    pub fn frame_done(&mut self) {
+        self.frame += 1;
+    }
+
+    /// # Returns
+    ///
+    /// Buttons pressed (`true`) or released since the last call.
+    pub fn changes(&mut self) -> Vec<(JoypadKey, bool)> {
+        let mut changes = vec![];
+        for &(_, button) in &KEY_NAMES {
+            let idx = button as usize;
+            let turbo = self.turbo_since[idx]
+                .is_some_and(|since| ((self.frame - since) / self.half_period).is_multiple_of(2));
+            let pressed = self.held[idx] || turbo;
+            if pressed != self.pressed[idx] {
+                self.pressed[idx] = pressed;
+                changes.push((button, pressed));
+            }
+        }
+        changes
+    }
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new(DEFAULT_TURBO_RATE)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn turbo_toggles_with_frames() {
+        // 15 presses per second: 2 frames pressed, 2 released.
+        let mut input = Input::new(15);
+        input.turbo_down(JoypadKey::A);
+        let mut states = vec![];
+        for _ in 0..6 {
+            states.push(input.changes());
+            input.frame_done();
+        }
+        assert_eq!(
+            states,
+            [
+                vec![(JoypadKey::A, true)],
+                vec![],
+                vec![(JoypadKey::A, false)],
+                vec![],
+                vec![(JoypadKey::A, true)],
+                vec![],
+            ]
+        );
+
+        input.turbo_up(JoypadKey::A);
+        assert_eq!(input.changes(), [(JoypadKey::A, false)]);
+    }
+
+    #[test]
+    fn held_button_stays_pressed() {
+        let mut input = Input::new(30);
+        input.key_down(JoypadKey::B);
+        input.turbo_down(JoypadKey::B);
+        assert_eq!(input.changes(), [(JoypadKey::B, true)]);
+        for _ in 0..4 {
+            input.frame_done();
+            assert_eq!(input.changes(), []);
+        }
+
+        input.key_up(JoypadKey::B);
+        input.turbo_up(JoypadKey::B);
+        assert_eq!(input.changes(), [(JoypadKey::B, false)]);
+    }
+}
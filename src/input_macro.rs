@@ -0,0 +1,120 @@
+// Recorded sequences of timed button presses, replayed frame-accurately by `CPU::play_macro`.
+//
+// Stored as text, one event per line:
+// # frame key action
+// 0 start down
+// 2 start up
+// 30 a down
+
+use crate::joypad::JoypadKey;
+
+/// Indexed by `JoypadKey`.
+pub const KEY_NAMES: [(&str, JoypadKey); 8] = [
+    ("right", JoypadKey::Right),
+    ("left", JoypadKey::Left),
+    ("up", JoypadKey::Up),
+    ("down", JoypadKey::Down),
+    ("a", JoypadKey::A),
+    ("b", JoypadKey::B),
+    ("select", JoypadKey::Select),
+    ("start", JoypadKey::Start),
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputMacro {
+    /// Sorted by frame.
+    pub events: Vec<MacroEvent>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MacroEvent {
+    /// Frames since the start of the macro.
+    pub frame: u64,
+    pub key: JoypadKey,
+    pub pressed: bool,
+}
+
+impl InputMacro {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events: Vec<MacroEvent> = vec![];
+
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let err = |msg: String| format!("line {}: {msg}", line_idx + 1);
+            let [frame, key, action] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(err("expected `frame key down|up`".to_string()));
+            };
+            let frame: u64 = frame.parse().map_err(|e| err(format!("{frame}: {e}")))?;
+            if events.last().is_some_and(|last| last.frame > frame) {
+                return Err(err(format!("frame {frame} is before the previous event")));
+            }
+            let key = KEY_NAMES
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, key)| *key)
+                .ok_or_else(|| err(format!("unknown key {key}")))?;
+            let pressed = match action {
+                "down" => true,
+                "up" => false,
+                _ => return Err(err(format!("unknown action {action}"))),
+            };
+            events.push(MacroEvent {
+                frame,
+                key,
+                pressed,
+            });
+        }
+
+        Ok(Self { events })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = "# frame key action\n".to_string();
+        for event in &self.events {
+            let name = KEY_NAMES
+                .iter()
+                .find(|(_, key)| *key == event.key)
+                .unwrap()
+                .0;
+            let action = if event.pressed { "down" } else { "up" };
+            text += &format!("{} {name} {action}\n", event.frame);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_print() {
+        let text = "# frame key action\n0 start down\n2 start up\n30 a down\n";
+        let input_macro = InputMacro::parse(text).unwrap();
+        assert_eq!(
+            input_macro.events[2],
+            MacroEvent {
+                frame: 30,
+                key: JoypadKey::A,
+                pressed: true,
+            }
+        );
+        assert_eq!(input_macro.to_text(), text);
+
+        for (text, err) in [
+            ("0 a", "line 1: expected `frame key down|up`"),
+            (
+                "\n1 a down\n0 a up",
+                "line 3: frame 0 is before the previous event",
+            ),
+            ("0 x down", "line 1: unknown key x"),
+            ("0 a press", "line 1: unknown action press"),
+        ] {
+            assert_eq!(InputMacro::parse(text), Err(err.to_string()));
+        }
+    }
+}
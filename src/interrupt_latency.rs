@@ -0,0 +1,134 @@
+// Delay between an interrupt request (its IF bit set) and the start of its handler, to check how
+// long `di` sections, `halt` loops or other handlers hold back e.g. the VBlank handler.
+//
+// Requests are noticed at the end of the instruction that raised them, and the handler starts
+// after the 20 cycles of the dispatch, so the latency of an interrupt served right away is 20.
+
+use crate::osd;
+
+/// Interrupt sources in the order of IF bits.
+pub const SOURCES: [&str; 5] = ["VBLANK", "STAT", "TIMER", "SERIAL", "JOYPAD"];
+
+/// Latencies of one source, in T-cycles.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub min: u64,
+    pub max: u64,
+    total: u64,
+}
+
+impl LatencyStats {
+    fn add(&mut self, latency: u64) {
+        if self.count == 0 || latency < self.min {
+            self.min = latency;
+        }
+        self.max = self.max.max(latency);
+        self.total += latency;
+        self.count += 1;
+    }
+
+    pub fn average(&self) -> Option<u64> {
+        self.total.checked_div(self.count)
+    }
+}
+
+#[derive(Default)]
+pub struct InterruptLatency {
+    /// Cycle the request of each source was noticed at, until its handler starts or the game
+    /// clears the IF bit itself.
+    requested: [Option<u64>; 5],
+    stats: [LatencyStats; 5],
+}
+
+impl InterruptLatency {
+    /// Notes new requests and requests acknowledged by writing IF, called between instructions.
+    pub fn observe(&mut self, interrupt_flags: u8, cycle: u64) {
+        for (bit, requested) in self.requested.iter_mut().enumerate() {
+            if interrupt_flags & (1 << bit) == 0 {
+                *requested = None;
+            } else if requested.is_none() {
+                *requested = Some(cycle);
+            }
+        }
+    }
+
+    /// The handler of `source` (an IF bit) starts at `cycle`.
+    pub fn handler_started(&mut self, source: usize, cycle: u64) {
+        if let Some(requested) = self.requested[source].take() {
+            self.stats[source].add(cycle - requested);
+        }
+    }
+
+    /// Forgets pending requests, e.g. after loading a state.
+    pub fn clear_requests(&mut self) {
+        self.requested = [None; 5];
+    }
+
+    pub fn stats(&self) -> &[LatencyStats; 5] {
+        &self.stats
+    }
+
+    /// A line per served source: min, average and max latency.
+    pub fn lines(&self) -> Vec<String> {
+        SOURCES
+            .iter()
+            .zip(&self.stats)
+            .filter_map(|(name, stats)| {
+                let avg = stats.average()?;
+                Some(format!("{name:<6} {}/{avg}/{}", stats.min, stats.max))
+            })
+            .collect()
+    }
+
+    /// Draws [`Self::lines`] over the top left corner, below the mode bar of the PPU stats.
+    pub fn draw_overlay(&self, buff: &mut [u32], width: usize) {
+        const TOP: usize = 4;
+        let lines = self.lines();
+        let longest = lines.iter().map(String::len).max().unwrap_or(0);
+        osd::dim(
+            buff,
+            width,
+            0,
+            TOP,
+            longest * osd::GLYPH_WIDTH + 1,
+            lines.len() * osd::GLYPH_HEIGHT + 1,
+        );
+        for (idx, line) in lines.iter().enumerate() {
+            let y = TOP + 1 + idx * osd::GLYPH_HEIGHT;
+            osd::draw_text(buff, width, 1, y, line, 0xFFFFFF);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn latency_per_source() {
+        let mut latency = InterruptLatency::default();
+        latency.observe(0b00001, 100);
+        latency.observe(0b00101, 108);
+        latency.handler_started(0, 120);
+        latency.observe(0b00100, 140);
+        latency.handler_started(2, 200);
+
+        // Cleared by the game without a handler.
+        latency.observe(0b10000, 300);
+        latency.observe(0, 310);
+        latency.handler_started(4, 330);
+
+        latency.observe(0b00001, 1000);
+        latency.handler_started(0, 1060);
+
+        let stats = latency.stats();
+        assert_eq!(
+            (stats[0].min, stats[0].max, stats[0].average()),
+            (20, 60, Some(40))
+        );
+        assert_eq!(stats[2].min, 92);
+        assert_eq!(stats[4].count, 0);
+        assert_eq!(latency.lines(), ["VBLANK 20/40/60", "TIMER  92/92/92"]);
+    }
+}
@@ -1,6 +1,9 @@
-use crate::bit;
+use crate::{
+    bit,
+    savestate::{SaveState, StateVisitor},
+};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum JoypadKey {
     Right,
     Left,
@@ -59,7 +62,7 @@ impl Joypad {
     fn key_press(&mut self, key: JoypadKey, is_pressed: bool) -> bool {
         use JoypadKey::*;
 
-        let old = u8::from(*self) & 0xF;
+        let old = self.lines();
 
         match key {
             Right => self.right = is_pressed,
@@ -72,16 +75,32 @@ impl Joypad {
             Start => self.start = is_pressed,
         }
 
-        let new = u8::from(*self) & 0xF;
-
-        // Sets the JP bit in IF any time the low 4 bits of the joypad register go from all 1s to
-        // any 0s.
-        old == 0xF && new != 0xF
+        self.is_interrupt(old)
     }
 
-    pub fn set_mode(&mut self, val: u8) {
+    /// # Returns
+    ///
+    /// Whether an interrupt should occur: selecting a group with a pressed key also pulls its line
+    /// low.
+    pub fn set_mode(&mut self, val: u8) -> bool {
+        let old = self.lines();
+
         self.is_dpad = !bit!(val, 4);
         self.is_buttons = !bit!(val, 5);
+
+        self.is_interrupt(old)
+    }
+
+    /// P10-P13 input lines, 0 - pressed key of a selected group.
+    fn lines(&self) -> u8 {
+        u8::from(*self) & 0xF
+    }
+
+    /// https://gbdev.io/pandocs/Interrupt_Sources.html#int-60--joypad-interrupt
+    /// The interrupt is requested when any of the lines goes from high to low. Keys of unselected
+    /// groups don't affect the lines, so they never trigger it.
+    fn is_interrupt(&self, old_lines: u8) -> bool {
+        old_lines & !self.lines() != 0
     }
 
     fn bit0(&self) -> bool {
@@ -114,6 +133,25 @@ impl From<Joypad> for u8 {
     }
 }
 
+impl SaveState for Joypad {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        for val in [
+            &mut self.is_dpad,
+            &mut self.down,
+            &mut self.up,
+            &mut self.left,
+            &mut self.right,
+            &mut self.is_buttons,
+            &mut self.start,
+            &mut self.select,
+            &mut self.b,
+            &mut self.a,
+        ] {
+            state.bool(val);
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -142,4 +180,40 @@ mod test {
         joypad.set_mode(16);
         assert_eq!(u8::from(joypad), 0xDF);
     }
+
+    #[test]
+    fn interrupt_only_for_selected_group() {
+        let mut joypad = Joypad::new();
+
+        // Only d-pad is selected.
+        joypad.set_mode(0b10_0000);
+        assert!(!joypad.key_down(JoypadKey::A));
+        assert!(!joypad.key_down(JoypadKey::Start));
+        assert_eq!(u8::from(joypad) & 0xF, 0xF);
+
+        assert!(joypad.key_down(JoypadKey::Right));
+        assert!(!joypad.key_up(JoypadKey::Right));
+    }
+
+    #[test]
+    fn interrupt_for_each_line_going_low() {
+        let mut joypad = Joypad::new();
+        joypad.set_mode(0b01_0000);
+
+        assert!(joypad.key_down(JoypadKey::A));
+        assert!(joypad.key_down(JoypadKey::B));
+        // The line is already low.
+        assert!(!joypad.key_down(JoypadKey::Right));
+    }
+
+    #[test]
+    fn interrupt_on_selecting_group_with_pressed_key() {
+        let mut joypad = Joypad::new();
+        joypad.set_mode(0b10_0000);
+
+        assert!(!joypad.key_down(JoypadKey::Select));
+        assert!(joypad.set_mode(0b01_0000));
+        assert!(!joypad.set_mode(0b00_0000));
+        assert!(!joypad.set_mode(0b11_0000));
+    }
 }
@@ -15,19 +15,66 @@ pub const CPU_FREQ: u64 = 4194304;
 pub const GPU_FPS: u64 = 60;
 pub const MILLIS_PER_FRAME: u64 = 1000 / GPU_FPS;
 pub const TICKS_PER_FRAME: u64 = CPU_FREQ / 1000 * MILLIS_PER_FRAME;
+pub const TICKS_PER_SCANLINE: u64 = 456;
+/// One LCD frame: 154 scanlines of 456 dots, ~59.73 Hz.
+pub const TICKS_PER_LCD_FRAME: u64 = 154 * TICKS_PER_SCANLINE;
 
 pub const AUDIO_BUF_LEN: usize = 2000;
 pub const SAMPLE_RATE: u64 = 44100;
 pub type AudioBuff = ([f32; AUDIO_BUF_LEN], [f32; AUDIO_BUF_LEN]);
 
+pub mod address_map;
 pub mod args;
 pub mod audio_player;
+pub mod cheat;
+pub mod clock;
+#[cfg(feature = "frontend")]
+pub mod commands;
+pub mod compat;
+pub mod compositor;
+pub mod coverage;
 pub mod cpu;
+pub mod demo;
+pub mod error;
+pub mod flight_recorder;
+pub mod fps;
+pub mod frame;
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
 pub(crate) mod gpu;
+pub mod heatmap;
+pub mod input;
+pub mod input_macro;
+pub mod interrupt_latency;
 pub(crate) mod joypad;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod link_cable;
 pub(crate) mod mbc;
 pub(crate) mod memory_bus;
+pub mod model;
+pub mod movie;
+pub mod opcodes;
+pub mod osd;
+pub mod palette;
+pub mod patch;
+pub mod png;
+pub mod printer;
+pub mod profiler;
+pub mod rewind;
+pub mod rip;
+pub mod rng_script;
+pub mod savestate;
+pub mod scale;
+pub mod selftest;
+pub(crate) mod serial;
+#[cfg(feature = "frontend")]
+pub mod settings;
+pub mod sgb;
 pub(crate) mod sound;
+pub(crate) mod timer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod watchdog;
 
 #[macro_export]
 macro_rules! bit {
@@ -36,6 +83,16 @@ macro_rules! bit {
     };
 }
 
+/// Adds the time until the end of the block to the profiler scope when the `profiler` feature is
+/// enabled, see [`profiler`].
+#[macro_export]
+macro_rules! profile_scope {
+    ($scope:expr) => {
+        #[cfg(feature = "profiler")]
+        let _profile_guard = $crate::profiler::ScopeGuard::new($scope);
+    };
+}
+
 #[macro_export]
 macro_rules! hex {
     ($val:expr) => {
@@ -43,17 +100,68 @@ macro_rules! hex {
     };
 }
 
-pub fn read_rom(path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+/// Reads and validates a ROM file.
+pub fn read_rom(path: &std::path::Path) -> Result<Vec<u8>, error::GbError> {
     let mut f = std::fs::File::open(path)?;
     let mut content = vec![];
 
     use std::io::Read;
     f.read_to_end(&mut content)?;
 
-    // Remove EOF.
-    content.resize(content.len() - 1, 0);
+    validate_rom(&content)?;
+
     Ok(content)
 }
 
+/// Checks that the cartridge header describes a ROM that can be emulated, like
+/// [`cpu::CPU::try_new`] does.
+pub fn validate_rom(rom: &[u8]) -> Result<(), error::GbError> {
+    mbc::validate(rom)
+}
+
+/// `.gb` and `.gbc` files in a directory, sorted so runs over a ROM library are reproducible.
+pub fn list_roms(dir: &std::path::Path) -> std::io::Result<Vec<std::path::PathBuf>> {
+    let mut roms = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gb") || ext.eq_ignore_ascii_case("gbc"))
+        {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+    Ok(roms)
+}
+
 #[cfg(test)]
-mod test {}
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_invalid_roms() {
+        let dir = std::env::temp_dir().join(format!("gbemu-read-rom-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (name, len) in [("empty.gb", 0), ("one-byte.gb", 1), ("header.gb", 0x14F)] {
+            let path = dir.join(name);
+            std::fs::write(&path, vec![0; len]).unwrap();
+            assert!(
+                matches!(read_rom(&path), Err(error::GbError::InvalidRom(_))),
+                "{name}"
+            );
+        }
+
+        let path = dir.join("header-only.gb");
+        std::fs::write(&path, vec![0; 0x150]).unwrap();
+        assert_eq!(read_rom(&path).unwrap().len(), 0x150);
+
+        assert!(matches!(
+            read_rom(&dir.join("missing.gb")),
+            Err(error::GbError::Io(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
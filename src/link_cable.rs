@@ -0,0 +1,174 @@
+// Link cable between two emulators over TCP, e.g. for 2-player games and trades.
+//
+// Every message is 2 bytes: a kind and the byte of SB. The Game Boy that drives the clock sends
+// `TRANSFER` and waits for the `REPLY` of the other one, which answers once its game requests a
+// transfer with the external clock.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use crate::cpu::SerialDevice;
+
+const TRANSFER: u8 = 1;
+const REPLY: u8 = 2;
+
+/// The clocked side stops waiting after this, as if the cable was unplugged.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(200);
+
+pub struct TcpLink {
+    stream: TcpStream,
+    /// Bytes of an incomplete message.
+    received: Vec<u8>,
+    /// Transfer driven by the other side that this side didn't answer yet.
+    pending: Option<u8>,
+    /// Replies that came after `REPLY_TIMEOUT`, dropped when they arrive.
+    late_replies: usize,
+    connected: bool,
+    /// Why the cable was disconnected, until the frontend takes it.
+    warnings: Vec<String>,
+}
+
+impl TcpLink {
+    /// Waits for the other emulator to connect.
+    pub fn accept(listener: &TcpListener) -> std::io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        Self::new(stream)
+    }
+
+    pub fn connect(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        Self::new(TcpStream::connect(addr)?)
+    }
+
+    fn new(stream: TcpStream) -> std::io::Result<Self> {
+        // Messages are tiny and latency is everything.
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            received: vec![],
+            pending: None,
+            late_replies: 0,
+            connected: true,
+            warnings: vec![],
+        })
+    }
+
+    fn send(&mut self, kind: u8, byte: u8) {
+        if let Err(err) = self.stream.write_all(&[kind, byte]) {
+            self.disconnect(&err.to_string());
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The next message, waiting for it up to `timeout` if it's set.
+    fn receive(&mut self, timeout: Option<Duration>) -> Option<[u8; 2]> {
+        while self.connected && self.received.len() < 2 {
+            let result = match timeout {
+                Some(timeout) => self
+                    .stream
+                    .set_nonblocking(false)
+                    .and_then(|()| self.stream.set_read_timeout(Some(timeout))),
+                None => self.stream.set_nonblocking(true),
+            };
+            let mut buf = [0; 64];
+            match result.and_then(|()| self.stream.read(&mut buf)) {
+                Ok(0) => self.disconnect("closed by the other side"),
+                Ok(len) => self.received.extend_from_slice(&buf[..len]),
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return None;
+                }
+                Err(err) => self.disconnect(&err.to_string()),
+            }
+        }
+
+        if self.received.len() < 2 {
+            return None;
+        }
+        let message = [self.received[0], self.received[1]];
+        self.received.drain(..2);
+        Some(message)
+    }
+
+    /// Handles a message that isn't the reply being waited for.
+    fn handle(&mut self, message: [u8; 2]) {
+        match message {
+            [TRANSFER, byte] => self.pending = Some(byte),
+            [REPLY, _] => self.late_replies = self.late_replies.saturating_sub(1),
+            [kind, _] => self.disconnect(&format!("unknown message 0x{kind:02X}")),
+        }
+    }
+
+    fn disconnect(&mut self, reason: &str) {
+        if self.connected {
+            self.warnings
+                .push(format!("Link cable disconnected: {reason}."));
+            self.connected = false;
+        }
+    }
+}
+
+impl SerialDevice for TcpLink {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.send(TRANSFER, byte);
+
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+            match self.receive(Some(timeout)) {
+                Some([REPLY, reply]) if self.late_replies == 0 => return reply,
+                Some(message) => self.handle(message),
+                None => break,
+            }
+        }
+
+        if self.connected {
+            self.late_replies += 1;
+        }
+        0xFF
+    }
+
+    fn poll_external(&mut self, reply: u8) -> Option<u8> {
+        while self.pending.is_none() {
+            let message = self.receive(None)?;
+            self.handle(message);
+        }
+
+        let byte = self.pending.take()?;
+        self.send(REPLY, reply);
+        Some(byte)
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exchange_over_localhost() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let clocked = std::thread::spawn(move || {
+            let mut link = TcpLink::connect(addr).unwrap();
+            [link.exchange(0x42), link.exchange(0x43)]
+        });
+
+        let mut link = TcpLink::accept(&listener).unwrap();
+        let mut received = vec![];
+        while received.len() < 2 {
+            received.extend(link.poll_external(0x99 + received.len() as u8));
+        }
+
+        assert_eq!(received, [0x42, 0x43]);
+        assert_eq!(clocked.join().unwrap(), [0x99, 0x9A]);
+        // The other side is gone.
+        assert_eq!(link.exchange(0x44), 0xFF);
+        assert!(!link.connected);
+        assert_eq!(link.take_warnings().len(), 1);
+    }
+}
@@ -1,201 +1,1696 @@
-use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, SyncSender},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use gbemu::{
+    address_map,
     args::parse_args,
-    audio_player::CpalAudioPlayer,
-    cpu::{JoypadKey, CPU},
+    audio_player::{
+        AudioPlayer, AudioRing, ControlledAudioPlayer, CpalAudioPlayer, OutputControl,
+        ResampledAudioPlayer, SpeedAudioPlayer, StretchRatio, StretchedAudioPlayer,
+        VoidAudioPlayer, WavAudioPlayer,
+    },
+    clock::{Clock, FramePacer, ScaledClock, Speed, SystemClock},
+    commands::{self, Bindings, Chord, Command, CommandPalette, Keymap, PaletteAction, Scope},
+    compositor::{self, Compositor, ScreenView, View, ViewContext},
+    cpu::{Breakpoint, JoypadKey, Layer, SerialDevice, CPU},
+    fps::FpsMeter,
+    input::Input,
+    input_macro::InputMacro,
+    link_cable::TcpLink,
+    movie::Movie,
+    printer::Printer,
+    rewind::Rewind,
+    rip::TileRipper,
+    rng_script::RngScript,
+    settings::{Settings, SettingsWatcher},
+    sgb::{SGB_HEIGHT, SGB_WIDTH},
     SCREEN_HEIGHT, SCREEN_WIDTH,
 };
 use minifb::{Key, Window};
 
-type GuiFrame = [u32; SCREEN_HEIGHT * SCREEN_WIDTH];
+/// Pixels of every window: the game screen or the compositor output first, then debug views.
+type GuiFrame = Vec<Vec<u32>>;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 enum GuiEvent {
     KeyUp(JoypadKey),
     KeyDown(JoypadKey),
-    // Debug keys:
+    TurboUp(JoypadKey),
+    TurboDown(JoypadKey),
+    // Debug commands:
     ToggleCpuPause,
+    FrameAdvance,
+    ToggleHeatmap,
+    TogglePpuStats,
+    ToggleFps,
+    ToggleBackgroundLayer,
+    ToggleWindowLayer,
+    ToggleSpriteLayer,
+    /// Mute or unmute audio channel 0-3.
+    ToggleChannel(usize),
+    SoftReset,
+    ToggleMacroRecording,
+    PlayMacro,
+    QuickSave,
+    QuickLoad,
+    DumpBusLog,
+    DumpRegisters,
+    DumpMemory,
+    ExportFrame,
+    Screenshot,
+    ApplySettings(Box<Settings>),
+    /// Resume emulation until the breakpoint is hit.
+    RunTo(Breakpoint),
+    /// Whether the rewind key is held.
+    Rewind(bool),
+}
+
+/// `key` with the modifiers held in `window`.
+fn held_chord(window: &Window, key: Key) -> Chord {
+    let held = |keys: [Key; 2]| keys.into_iter().any(|key| window.is_key_down(key));
+    Chord {
+        ctrl: held([Key::LeftCtrl, Key::RightCtrl]),
+        shift: held([Key::LeftShift, Key::RightShift]),
+        alt: held([Key::LeftAlt, Key::RightAlt]),
+        key,
+    }
+}
+
+/// Keys held by the player would stay pressed forever when the keys go elsewhere otherwise.
+fn release_joypad(key_events: &mpsc::Sender<GuiEvent>) {
+    for (_, button) in gbemu::input_macro::KEY_NAMES {
+        let _ = key_events.send(GuiEvent::KeyUp(button));
+        let _ = key_events.send(GuiEvent::TurboUp(button));
+    }
 }
 
-pub fn minifb_key_to_joypad(key: minifb::Key) -> Option<JoypadKey> {
-    match key {
-        Key::Up => Some(JoypadKey::Up),
-        Key::Down => Some(JoypadKey::Down),
-        Key::Left => Some(JoypadKey::Left),
-        Key::Right => Some(JoypadKey::Right),
-        Key::Enter => Some(JoypadKey::Start),
-        Key::Space => Some(JoypadKey::Select),
-        Key::Z => Some(JoypadKey::A),
-        Key::X => Some(JoypadKey::B),
-        _ => None,
+/// Sends the events of a command that runs in the emulator thread.
+fn send_command(command: Command, key_events: &mpsc::Sender<GuiEvent>, args: &gbemu::args::Args) {
+    // No unwrap because the CPU may already be stopped (channels are closed).
+    let send = |ev| {
+        let _ = key_events.send(ev);
+    };
+    match command {
+        Command::Pause => send(GuiEvent::ToggleCpuPause),
+        Command::FrameAdvance => send(GuiEvent::FrameAdvance),
+        Command::SoftReset => send(GuiEvent::SoftReset),
+        Command::DumpBusLog => send(GuiEvent::DumpBusLog),
+        Command::DumpRegisters => send(GuiEvent::DumpRegisters),
+        Command::DumpMemory => send(GuiEvent::DumpMemory),
+        Command::ExportFrame => send(GuiEvent::ExportFrame),
+        Command::Screenshot => send(GuiEvent::Screenshot),
+        Command::ToggleHeatmap => send(GuiEvent::ToggleHeatmap),
+        Command::TogglePpuStats => send(GuiEvent::TogglePpuStats),
+        Command::ToggleFps => send(GuiEvent::ToggleFps),
+        Command::ToggleBackground => send(GuiEvent::ToggleBackgroundLayer),
+        Command::ToggleWindow => send(GuiEvent::ToggleWindowLayer),
+        Command::ToggleSprites => send(GuiEvent::ToggleSpriteLayer),
+        Command::ToggleChannel1 => send(GuiEvent::ToggleChannel(0)),
+        Command::ToggleChannel2 => send(GuiEvent::ToggleChannel(1)),
+        Command::ToggleChannel3 => send(GuiEvent::ToggleChannel(2)),
+        Command::ToggleChannel4 => send(GuiEvent::ToggleChannel(3)),
+        Command::RecordMacro => send(GuiEvent::ToggleMacroRecording),
+        Command::PlayMacro => send(GuiEvent::PlayMacro),
+        Command::QuickSave => send(GuiEvent::QuickSave),
+        Command::QuickLoad => send(GuiEvent::QuickLoad),
+        // Re-arm the breakpoints from the command line.
+        Command::RearmBreakpoints => {
+            for &addr in &args.break_pcs {
+                send(GuiEvent::RunTo(Breakpoint::Pc(addr)));
+            }
+        }
+        Command::BreakAtLine => {
+            let line = args.break_line.unwrap_or(0);
+            send(GuiEvent::RunTo(Breakpoint::Scanline(line)));
+        }
+        // Handled by the frontend.
+        Command::SwitchFocus
+        | Command::ToggleFullscreen
+        | Command::SlowMotion
+        | Command::FastForward
+        | Command::Rewind
+        | Command::Help => (),
     }
 }
 
 fn main() {
     let args = parse_args().unwrap();
 
-    let content = gbemu::read_rom(&args.rom_path).unwrap();
+    let mut content = if args.demo {
+        gbemu::demo::rom()
+    } else {
+        gbemu::read_rom(&args.rom_path).unwrap_or_else(|err| {
+            eprintln!("Can't load {}: {}", args.rom_path.display(), err);
+            std::process::exit(1);
+        })
+    };
+    for path in &args.patch_paths {
+        let patch = std::fs::read(path).unwrap_or_else(|err| {
+            eprintln!("Can't read patch {}: {err}", path.display());
+            std::process::exit(1);
+        });
+        content = gbemu::patch::apply(content, &patch).unwrap_or_else(|err| {
+            eprintln!("Can't apply patch {}: {err}", path.display());
+            std::process::exit(1);
+        });
+    }
+    for note in gbemu::compat::check(&content) {
+        eprintln!("Compatibility: {note}");
+    }
 
-    let audio_buf = mpsc::channel();
+    let audio_ring = AudioRing::new(CpalAudioPlayer::MAX_QUEUED * gbemu::AUDIO_BUF_LEN);
+    let (audio_stream, device_rate) = create_cpal_player(audio_ring.clone());
 
-    let audio_stream = create_cpal_player(audio_buf.1);
+    let pacing = match (args.vsync_hz, args.speed) {
+        _ if args.audio_sync => Pacing::AudioSync(audio_ring.clone()),
+        (Some(_), _) => Pacing::Vsync(StretchRatio::new()),
+        (None, Speed::Times(speed)) => {
+            let clock = ScaledClock::new(std::sync::Arc::new(SystemClock::new()));
+            clock.set_speed(speed);
+            Pacing::Limiter(clock)
+        }
+        (None, Speed::Uncapped) => Pacing::Uncapped,
+    };
+    let base_speed = match args.speed {
+        Speed::Times(speed) => speed,
+        Speed::Uncapped => 1.0,
+    };
 
-    let cpu = CPU::new(content, Box::new(CpalAudioPlayer::new(audio_buf.0)));
+    let player = CpalAudioPlayer::new(audio_ring, device_rate);
+    let player = ResampledAudioPlayer::new(player);
+    let player: Box<dyn AudioPlayer> = match &pacing {
+        Pacing::AudioSync(_) => Box::new(player),
+        Pacing::Vsync(ratio) => Box::new(StretchedAudioPlayer::new(player, ratio.clone())),
+        Pacing::Limiter(clock) => Box::new(SpeedAudioPlayer::new(player, clock.clone())),
+        Pacing::Uncapped => Box::new(VoidAudioPlayer::new()),
+    };
+    let mut settings = Settings {
+        palette: args.palette,
+        dc_blocker: args.dc_blocker,
+        ..Settings::default()
+    };
+    let mut settings_watcher = args
+        .config_path
+        .clone()
+        .map(|path| SettingsWatcher::new(path, settings));
+    if let Some(loaded) = settings_watcher.as_mut().and_then(SettingsWatcher::poll) {
+        settings = loaded.unwrap_or_else(|err| {
+            eprintln!("Can't load settings: {err}");
+            std::process::exit(1);
+        });
+    }
+    let output = OutputControl::new(settings.volume, settings.dc_blocker);
+    let mut player: Box<dyn AudioPlayer> =
+        Box::new(ControlledAudioPlayer::new(player, output.clone()));
+    if let Some(path) = &args.wav_path {
+        player = match WavAudioPlayer::create(player, path) {
+            Ok(recorder) => Box::new(recorder),
+            Err(err) => {
+                eprintln!("Can't record audio to {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        };
+    }
 
-    let mut window = Window::new(
+    let mut cpu = create_cpu(&args, content.clone(), player);
+    if args.coverage_path.is_some() {
+        cpu.enable_coverage();
+    }
+    // Only the first instance persists its RAM, so split screen doesn't save twice. Movies start
+    // with cleared RAM and leave the save alone.
+    let sav_path = args.rom_path.with_extension("sav");
+    let movie = args.record_movie_path.is_some() || args.play_movie_path.is_some();
+    if cpu.has_battery() && !movie {
+        match std::fs::read(&sav_path) {
+            Ok(data) => cpu.load_cartridge_ram(&data),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => eprintln!("Can't load {}: {}", sav_path.display(), err),
+        }
+    }
+    if let Some(link) = connect_link_cable(&args) {
+        cpu.set_serial_device(Some(link));
+    }
+    if args.printer {
+        cpu.set_serial_device(Some(Box::new(Printer::new(args.rip_dir.clone()))));
+    }
+    if args.record_movie_path.is_some() {
+        cpu.start_movie_recording();
+    }
+    if let Some(path) = &args.play_movie_path {
+        let movie = std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| Movie::parse(&text))
+            .and_then(|movie| cpu.play_movie(&movie));
+        if let Err(err) = movie {
+            eprintln!("Can't play {}: {err}", path.display());
+            std::process::exit(1);
+        }
+    }
+
+    let compositor = create_compositor(&args);
+    let (width, height) = match &compositor {
+        Some(compositor) => compositor.size(),
+        None if args.sgb_border => (SGB_WIDTH, SGB_HEIGHT),
+        None => (SCREEN_WIDTH, SCREEN_HEIGHT),
+    };
+
+    let mut windows = WindowManager::new();
+    let (window_width, window_height) = if args.split_screen {
+        split_screen_size(width, height)
+    } else {
+        (width, height)
+    };
+    windows.add(
         "DMG-01",
-        SCREEN_WIDTH,
-        SCREEN_HEIGHT,
+        WindowRole::Game,
+        window_width,
+        window_height,
+        args.scale,
+    );
+    if let Some(hz) = args.vsync_hz {
+        windows.limit_game_rate(hz);
+    }
+    let views: Vec<_> = args.windows.iter().map(|kind| kind.create()).collect();
+    for (kind, view) in args.windows.iter().zip(&views) {
+        let (width, height) = view.size();
+        // Debug views are small.
+        windows.add(&format!("{kind:?}"), WindowRole::Debug, width, height, 2);
+    }
+
+    if args.rip_tiles {
+        if let Err(err) = std::fs::create_dir_all(&args.rip_dir) {
+            eprintln!("Can't create {}: {err}", args.rip_dir.display());
+            std::process::exit(1);
+        }
+    }
+    let rip = Rip {
+        tiles: args
+            .rip_tiles
+            .then(|| TileRipper::new(args.rip_dir.clone())),
+        dir: args.rip_dir.clone(),
+    };
+
+    let start = |emulator| {
+        if args.single_thread {
+            Instance::inline(emulator)
+        } else {
+            Instance::spawn(emulator)
+        }
+    };
+    let autosave = args
+        .autosave
+        .filter(|_| cpu.has_battery() && !movie)
+        .map(|interval| {
+            let clock = std::sync::Arc::new(SystemClock::new());
+            Autosave::new(sav_path.clone(), interval, clock)
+        });
+    let mut instances = vec![start(Emulator::new(
+        cpu,
+        compositor,
+        views,
+        rip,
+        pacing.clone(),
+        Some(args.rom_path.with_extension("macro")),
+        args.sgb_border,
+        autosave,
+    ))];
+    if args.split_screen {
+        // Only the first instance is heard.
+        let cpu = create_cpu(&args, content, Box::new(VoidAudioPlayer::new()));
+        instances.push(start(Emulator::new(
+            cpu,
+            create_compositor(&args),
+            vec![],
+            Rip {
+                tiles: None,
+                dir: args.rip_dir.clone(),
+            },
+            pacing.clone(),
+            None,
+            false,
+            None,
+        )));
+    }
+    for instance in &instances {
+        let _ = instance
+            .key_events
+            .send(GuiEvent::ApplySettings(Box::new(settings)));
+    }
+    let mut bindings = settings.bindings;
+    let mut keymap = settings.keymap;
+    let mut turbo = settings.turbo;
+    #[cfg(feature = "gamepad")]
+    let mut gamepads = gbemu::gamepad::Gamepads::new()
+        .map_err(|err| eprintln!("Controllers are not available: {err}"))
+        .ok();
+    #[cfg(feature = "gamepad")]
+    let mut pad_map = settings.pad_map;
+    let mut focus = 0;
+    // Factor of the limiter speed, changed with the slow motion key.
+    let mut slow_motion = 1.0;
+    // The instance rewinding while the rewind key is held.
+    let mut rewinding: Option<usize> = None;
+    let mut gui_frames = 0u64;
+    // Takes the keys of the game window while it's open.
+    let mut palette: Option<CommandPalette> = None;
+
+    while windows.is_open(palette.is_none()) {
+        // Checked twice per second, changes apply from the next frame.
+        gui_frames += 1;
+        if let Some(watcher) = settings_watcher
+            .as_mut()
+            .filter(|_| gui_frames.is_multiple_of(gbemu::GPU_FPS / 2))
+        {
+            match watcher.poll() {
+                Some(Ok(settings)) => {
+                    output.set(settings.volume, settings.dc_blocker);
+                    bindings = settings.bindings;
+                    keymap = settings.keymap;
+                    turbo = settings.turbo;
+                    #[cfg(feature = "gamepad")]
+                    {
+                        pad_map = settings.pad_map;
+                    }
+                    release_joypad(&instances[focus].key_events);
+                    for instance in &instances {
+                        let _ = instance
+                            .key_events
+                            .send(GuiEvent::ApplySettings(Box::new(settings)));
+                    }
+                    eprintln!("Settings reloaded");
+                }
+                Some(Err(err)) => eprintln!("Can't apply settings: {err}"),
+                None => (),
+            }
+        }
+
+        let focused = &instances[focus].key_events;
+        let commands = match &mut palette {
+            Some(open) => {
+                let mut commands = vec![];
+                for key in windows.game_keys_pressed() {
+                    let help = bindings[Command::Help as usize].is_some_and(|c| c.key == key);
+                    match open.type_key(key) {
+                        PaletteAction::Run(command) => {
+                            commands.push(Command::Help);
+                            if command != Command::Help {
+                                commands.push(command);
+                            }
+                        }
+                        PaletteAction::Close => commands.push(Command::Help),
+                        PaletteAction::Edit if help => commands.push(Command::Help),
+                        PaletteAction::Edit => (),
+                    }
+                }
+                commands
+            }
+            None => windows.route_keys(focused, &bindings, &keymap, &turbo),
+        };
+        #[cfg(feature = "gamepad")]
+        if let Some(gamepads) = &mut gamepads {
+            for (button, pressed) in gamepads.poll(&pad_map) {
+                // Like the keyboard, the game doesn't get buttons while the palette is open.
+                if palette.is_none() {
+                    let ev = if pressed {
+                        GuiEvent::KeyDown(button)
+                    } else {
+                        GuiEvent::KeyUp(button)
+                    };
+                    let _ = focused.send(ev);
+                }
+            }
+        }
+        for command in commands {
+            match command {
+                Command::Help => {
+                    palette = match palette {
+                        Some(_) => None,
+                        None => {
+                            release_joypad(focused);
+                            Some(CommandPalette::default())
+                        }
+                    }
+                }
+                Command::ToggleFullscreen => {
+                    // The new window doesn't see the held keys released.
+                    release_joypad(focused);
+                    windows.toggle_fullscreen();
+                }
+                Command::SwitchFocus => {
+                    if args.split_screen {
+                        release_joypad(focused);
+                        focus = (focus + 1) % instances.len();
+                    }
+                }
+                Command::SlowMotion => match &pacing {
+                    Pacing::Limiter(_) => {
+                        slow_motion = next_slow_motion_speed(slow_motion);
+                        eprintln!("Speed: {}x", base_speed * slow_motion);
+                    }
+                    Pacing::Vsync(_) | Pacing::AudioSync(_) | Pacing::Uncapped => eprintln!(
+                        "Slow motion is not supported with --vsync, --audio-sync or --speed max"
+                    ),
+                },
+                _ => send_command(command, focused, &args),
+            }
+        }
+        if let Pacing::Limiter(clock) = &pacing {
+            let fast_forward = palette.is_none()
+                && bindings[Command::FastForward as usize].is_some_and(|c| windows.is_held(c));
+            let speed = if fast_forward {
+                FAST_FORWARD_SPEED
+            } else {
+                base_speed * slow_motion
+            };
+            if clock.speed() != speed {
+                clock.set_speed(speed);
+            }
+        }
+        let rewind = (palette.is_none()
+            && bindings[Command::Rewind as usize].is_some_and(|c| windows.is_held(c)))
+        .then_some(focus);
+        if rewind != rewinding {
+            for (instance, held) in [(rewinding, false), (rewind, true)] {
+                if let Some(instance) = instance {
+                    let _ = instances[instance].key_events.send(GuiEvent::Rewind(held));
+                }
+            }
+            rewinding = rewind;
+        }
+
+        let Some(mut frames) = instances
+            .iter_mut()
+            .map(Instance::next_frame)
+            .collect::<Option<Vec<_>>>()
+        else {
+            break;
+        };
+        let mut frame = if args.split_screen {
+            compose_split_screen(frames, focus, width, height)
+        } else {
+            frames.remove(0)
+        };
+        if let Some(palette) = &palette {
+            palette.draw(&bindings, &mut frame[0], window_width);
+        }
+        windows.update(&frame);
+    }
+
+    // Drop the channels, so the CPUs will stop because no one is sending/listening for updates.
+    let runners: Vec<_> = instances
+        .into_iter()
+        .map(|instance| instance.runner)
+        .collect();
+    drop(audio_stream);
+
+    let mut cpus: Vec<CPU> = runners.into_iter().map(Runner::join).collect();
+
+    if cpus[0].has_battery() && !movie {
+        if let Err(err) = write_replacing(&sav_path, &cpus[0].cartridge_ram()) {
+            eprintln!("Can't save {}: {}", sav_path.display(), err);
+        }
+    }
+    if let (Some(path), Some(movie)) = (&args.record_movie_path, cpus[0].stop_movie_recording()) {
+        if let Err(err) = std::fs::write(path, movie.to_text()) {
+            eprintln!("Can't save {}: {}", path.display(), err);
+        }
+    }
+    if let (Some(path), Some(coverage)) = (&args.coverage_path, cpus[0].coverage()) {
+        if let Err(err) = coverage.export_cdl(path) {
+            eprintln!("Can't write coverage to {}: {}", path.display(), err);
+        }
+    }
+}
+
+/// Connects the link cable of the first instance to another emulator, waiting for it with
+/// `--link-listen`.
+fn connect_link_cable(args: &gbemu::args::Args) -> Option<Box<dyn SerialDevice>> {
+    let link = if let Some(addr) = &args.link_listen {
+        eprintln!("Waiting for the other emulator on {addr}...");
+        std::net::TcpListener::bind(addr).and_then(|listener| TcpLink::accept(&listener))
+    } else {
+        TcpLink::connect(args.link_connect.as_deref()?)
+    };
+    match link {
+        Ok(link) => Some(Box::new(link)),
+        Err(err) => {
+            eprintln!("Can't connect the link cable: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Creates the CPU with the debug features from the arguments, except coverage which is exported
+/// only for the first instance.
+fn create_cpu(args: &gbemu::args::Args, rom: Vec<u8>, player: Box<dyn AudioPlayer>) -> CPU {
+    let mut cpu = CPU::try_with_model(rom, player, args.model).unwrap_or_else(|err| {
+        eprintln!("Can't load the ROM: {err}");
+        std::process::exit(1);
+    });
+    if args.heatmap {
+        cpu.enable_heatmap(true);
+    }
+    if let Some(capacity) = args.flight_recorder {
+        cpu.enable_flight_recorder(capacity);
+    }
+    if args.bank_diagnostics {
+        cpu.enable_bank_diagnostics();
+    }
+    if args.interrupt_latency {
+        cpu.enable_interrupt_latency();
+    }
+    cpu.set_dma_conflicts(args.dma_conflicts);
+    cpu.set_sprite_limit(args.sprite_limit);
+    cpu.set_fast_renderer(args.fast_ppu);
+    if let Some(path) = &args.boot_rom_path {
+        let boot_rom = std::fs::read(path).unwrap_or_else(|err| {
+            eprintln!("Can't read boot ROM {}: {err}", path.display());
+            std::process::exit(1);
+        });
+        if let Err(err) = cpu.set_boot_rom(boot_rom) {
+            eprintln!("Can't use boot ROM {}: {err}", path.display());
+            std::process::exit(1);
+        }
+    }
+    if let Some(path) = &args.rng_script_path {
+        let script = std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| RngScript::parse(&text));
+        match script {
+            Ok(script) => cpu.set_rng_script(Some(script)),
+            Err(err) => {
+                eprintln!("Can't use RNG script {}: {err}", path.display());
+                std::process::exit(1);
+            }
+        }
+    }
+    for code in &args.cheats {
+        if let Err(err) = cpu.add_cheat(code) {
+            eprintln!("Can't use cheat {err}");
+            std::process::exit(1);
+        }
+    }
+    for &addr in &args.break_pcs {
+        cpu.add_temp_breakpoint(Breakpoint::Pc(addr));
+    }
+    if let Some(line) = args.break_line {
+        cpu.add_temp_breakpoint(Breakpoint::Scanline(line));
+    }
+    cpu
+}
+
+fn create_compositor(args: &gbemu::args::Args) -> Option<Compositor> {
+    (!args.views.is_empty()).then(|| {
+        let mut compositor = Compositor::new(args.layout);
+        compositor.add(Box::new(ScreenView));
+        for view in &args.views {
+            compositor.add(view.create());
+        }
+        compositor
+    })
+}
+
+/// An emulator and the channel of its events.
+struct Instance {
+    key_events: mpsc::Sender<GuiEvent>,
+    runner: Runner,
+}
+
+enum Runner {
+    /// Runs in its own thread, one frame ahead of the window.
+    Thread {
+        thread: std::thread::JoinHandle<CPU>,
+        gui_frame: Receiver<GuiFrame>,
+    },
+    /// Runs on the GUI thread between window updates, with `--single-thread`.
+    Inline {
+        emulator: Box<Emulator>,
+        key_events: Receiver<GuiEvent>,
+    },
+}
+
+impl Instance {
+    fn spawn(emulator: Emulator) -> Self {
+        let key_events = mpsc::channel();
+        // sync_channel because we want the previous frame to be drawn before the next frame is
+        // transmitted.
+        let gui_frame = mpsc::sync_channel(1);
+
+        // At the moment I don't understand why the default stack size of 2MB is not enough: buffer
+        // array ~200KB.
+        let thread = std::thread::Builder::new()
+            .stack_size(1024 * 1024 * 10)
+            .spawn(move || run(emulator, gui_frame.0, key_events.1))
+            .unwrap();
+
+        Self {
+            key_events: key_events.0,
+            runner: Runner::Thread {
+                thread,
+                gui_frame: gui_frame.1,
+            },
+        }
+    }
+
+    fn inline(emulator: Emulator) -> Self {
+        let key_events = mpsc::channel();
+        Self {
+            key_events: key_events.0,
+            runner: Runner::Inline {
+                emulator: Box::new(emulator),
+                key_events: key_events.1,
+            },
+        }
+    }
+
+    /// Waits for the next frame of the emulator thread, or runs the emulator until it shows one.
+    ///
+    /// # Returns
+    ///
+    /// None when the emulator stopped.
+    fn next_frame(&mut self) -> Option<GuiFrame> {
+        match &mut self.runner {
+            Runner::Thread { gui_frame, .. } => gui_frame.recv().ok(),
+            Runner::Inline {
+                emulator,
+                key_events,
+            } => {
+                let mut shown = None;
+                while shown.is_none() {
+                    let mut present = |frame, _| {
+                        shown = Some(frame);
+                        true
+                    };
+                    if !emulator.pass(key_events, &mut present) {
+                        return None;
+                    }
+                }
+                shown
+            }
+        }
+    }
+}
+
+impl Runner {
+    /// Stops the emulator, the frontend must have dropped its events channel.
+    fn join(self) -> CPU {
+        match self {
+            Runner::Thread { thread, gui_frame } => {
+                // The thread stops because no one is listening for frames.
+                drop(gui_frame);
+                thread.join().unwrap()
+            }
+            Runner::Inline { emulator, .. } => emulator.cpu,
+        }
+    }
+}
+
+const SPLIT_SCREEN_MARGIN: usize = 4;
+const FOCUS_BAR_HEIGHT: usize = 3;
+
+fn split_screen_size(width: usize, height: usize) -> (usize, usize) {
+    (width * 2 + SPLIT_SCREEN_MARGIN, height + FOCUS_BAR_HEIGHT)
+}
+
+/// Puts the game screens of both instances side by side with a bar under the focused one. Debug
+/// windows show the first instance.
+fn compose_split_screen(
+    frames: Vec<GuiFrame>,
+    focus: usize,
+    width: usize,
+    height: usize,
+) -> GuiFrame {
+    const BACKGROUND: u32 = 0x202020;
+    const FOCUS_COLOR: u32 = 0x30A0FF;
+
+    let (split_width, split_height) = split_screen_size(width, height);
+    let mut screen = vec![BACKGROUND; split_width * split_height];
+    for (idx, frame) in frames.iter().enumerate() {
+        let left = idx * (width + SPLIT_SCREEN_MARGIN);
+        for (row, pixels) in frame[0].chunks_exact(width).enumerate() {
+            screen[row * split_width + left..][..width].copy_from_slice(pixels);
+        }
+        if idx == focus {
+            for row in height..split_height {
+                screen[row * split_width + left..][..width].fill(FOCUS_COLOR);
+            }
+        }
+    }
+
+    let mut frames = frames.into_iter();
+    let mut frame = frames.next().unwrap();
+    frame[0] = screen;
+    frame
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum WindowRole {
+    /// Shows the game and receives joypad and debug keys. Closing it quits the emulator.
+    Game,
+    /// Shows a debug view. Receives only keys controlling emulation.
+    Debug,
+}
+
+struct ManagedWindow {
+    window: Window,
+    title: String,
+    role: WindowRole,
+    /// Index of the window's buffer in [`GuiFrame`].
+    frame_idx: usize,
+    width: usize,
+    height: usize,
+    /// Window size in multiples of the buffer when it's not fullscreen.
+    scale: usize,
+    fullscreen: bool,
+    /// The buffer scaled to the window size.
+    scaled: Vec<u32>,
+}
+
+/// All windows of the frontend. They are updated together from the same frame, each one handles
+/// keys according to its role.
+struct WindowManager {
+    windows: Vec<ManagedWindow>,
+    /// Update rate of the game window with `--vsync`.
+    game_fps: Option<usize>,
+}
+
+/// Creates a resizable window, `fullscreen` fills the screen without decorations instead.
+fn open_window(title: &str, width: usize, height: usize, scale: usize, fullscreen: bool) -> Window {
+    let options = if fullscreen {
+        minifb::WindowOptions {
+            borderless: true,
+            title: false,
+            topmost: true,
+            scale: minifb::Scale::FitScreen,
+            scale_mode: minifb::ScaleMode::UpperLeft,
+            ..Default::default()
+        }
+    } else {
         minifb::WindowOptions {
             resize: true,
+            scale_mode: minifb::ScaleMode::UpperLeft,
             ..Default::default()
-        },
-    )
-    .unwrap();
+        }
+    };
+    let (width, height) = if fullscreen {
+        (width, height)
+    } else {
+        (width * scale, height * scale)
+    };
+    Window::new(title, width, height, options).unwrap()
+}
 
-    let key_events = mpsc::channel();
-    // sync_channel because we want the previous frame to be drawn before the next frame is
-    // transmitted.
-    let gui_frame = mpsc::sync_channel(1);
+impl WindowManager {
+    fn new() -> Self {
+        Self {
+            windows: vec![],
+            game_fps: None,
+        }
+    }
 
-    // At the moment I don't understand why the default stack size of 2MB is not enough: buffer
-    // array ~200KB.
-    let cpu_run = std::thread::Builder::new()
-        .stack_size(1024 * 1024 * 10)
-        .spawn(|| run(cpu, gui_frame.0, key_events.1))
-        .unwrap();
+    fn add(&mut self, title: &str, role: WindowRole, width: usize, height: usize, scale: usize) {
+        let window = open_window(title, width, height, scale, false);
+        let frame_idx = self.windows.len();
+        self.windows.push(ManagedWindow {
+            window,
+            title: title.to_owned(),
+            role,
+            frame_idx,
+            width,
+            height,
+            scale,
+            fullscreen: false,
+            scaled: vec![],
+        });
+    }
 
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        if window.is_key_pressed(Key::P, minifb::KeyRepeat::No) {
-            let _ = key_events.0.send(GuiEvent::ToggleCpuPause);
+    /// Paces the whole frontend by the game window: updates wait for the next display refresh.
+    fn limit_game_rate(&mut self, hz: f64) {
+        let fps = hz.round() as usize;
+        self.game_fps = Some(fps);
+        for managed in &mut self.windows {
+            if managed.role == WindowRole::Game {
+                managed.window.set_target_fps(fps);
+            }
         }
+    }
 
-        for key in window.get_keys_pressed(minifb::KeyRepeat::No) {
-            if let Some(ev) = minifb_key_to_joypad(key) {
-                // No unwrap because the CPU may already be stopped (channels are closed).
-                let _ = key_events.0.send(GuiEvent::KeyDown(ev));
+    /// minifb can't change the window mode of an open window, so it's reopened.
+    fn toggle_fullscreen(&mut self) {
+        for managed in &mut self.windows {
+            if managed.role == WindowRole::Game {
+                managed.fullscreen = !managed.fullscreen;
+                managed.window = open_window(
+                    &managed.title,
+                    managed.width,
+                    managed.height,
+                    managed.scale,
+                    managed.fullscreen,
+                );
+                if let Some(fps) = self.game_fps {
+                    managed.window.set_target_fps(fps);
+                }
             }
         }
-        for key in window.get_keys_released() {
-            if let Some(ev) = minifb_key_to_joypad(key) {
-                let _ = key_events.0.send(GuiEvent::KeyUp(ev));
+    }
+
+    /// `escape_quits` is false while Escape is used by something else in the game window.
+    fn is_open(&self, escape_quits: bool) -> bool {
+        self.windows.iter().any(|managed| {
+            managed.role == WindowRole::Game
+                && managed.window.is_open()
+                && !(escape_quits
+                    && managed
+                        .window
+                        .is_key_pressed(Key::Escape, minifb::KeyRepeat::No))
+        })
+    }
+
+    /// Whether `chord` is held down in the game window.
+    fn is_held(&self, chord: Chord) -> bool {
+        self.windows.iter().any(|managed| {
+            managed.role == WindowRole::Game
+                && managed.window.is_key_down(chord.key)
+                && held_chord(&managed.window, chord.key) == chord
+        })
+    }
+
+    fn game_keys_pressed(&self) -> Vec<Key> {
+        self.windows
+            .iter()
+            .filter(|managed| managed.role == WindowRole::Game)
+            .flat_map(|managed| managed.window.get_keys_pressed(minifb::KeyRepeat::No))
+            .collect()
+    }
+
+    /// Sends the joypad buttons of `keymap` and the turbo buttons of `turbo` to the emulator.
+    ///
+    /// # Returns
+    ///
+    /// Commands of the pressed chords.
+    fn route_keys(
+        &mut self,
+        key_events: &mpsc::Sender<GuiEvent>,
+        bindings: &Bindings,
+        keymap: &Keymap,
+        turbo: &Keymap,
+    ) -> Vec<Command> {
+        // Debug windows are closed with Escape, the game window quits the emulator.
+        self.windows.retain(|managed| {
+            managed.role == WindowRole::Game
+                || (managed.window.is_open() && !managed.window.is_key_down(Key::Escape))
+        });
+
+        let mut commands = vec![];
+        for managed in &self.windows {
+            let window = &managed.window;
+            let scope = match managed.role {
+                WindowRole::Game => Scope::Game,
+                WindowRole::Debug => Scope::AnyWindow,
+            };
+            // No unwrap because the CPU may already be stopped (channels are closed).
+            let send = |ev| {
+                let _ = key_events.send(ev);
+            };
+
+            for key in window.get_keys_pressed(minifb::KeyRepeat::No) {
+                match commands::lookup(bindings, held_chord(window, key), scope) {
+                    Some(command) => commands.push(command),
+                    None if scope == Scope::Game => {
+                        keymap
+                            .buttons(key)
+                            .for_each(|button| send(GuiEvent::KeyDown(button)));
+                        turbo
+                            .buttons(key)
+                            .for_each(|button| send(GuiEvent::TurboDown(button)));
+                    }
+                    None => (),
+                }
+            }
+
+            if managed.role != WindowRole::Game {
+                continue;
+            }
+            for key in window.get_keys_released() {
+                // The button stays pressed while another of its keys is held.
+                for button in keymap.buttons(key) {
+                    if !keymap.keys(button).any(|key| window.is_key_down(key)) {
+                        send(GuiEvent::KeyUp(button));
+                    }
+                }
+                for button in turbo.buttons(key) {
+                    if !turbo.keys(button).any(|key| window.is_key_down(key)) {
+                        send(GuiEvent::TurboUp(button));
+                    }
+                }
             }
         }
 
-        if let Ok(new_frame) = gui_frame.1.recv() {
-            window
-                .update_with_buffer(&new_frame, SCREEN_WIDTH, SCREEN_HEIGHT)
+        commands
+    }
+
+    /// Shows the frame at the largest integer scale fitting each window, centered.
+    fn update(&mut self, frame: &GuiFrame) {
+        for managed in &mut self.windows {
+            let buffer = &frame[managed.frame_idx];
+            let (width, height) = managed.window.get_size();
+            if width == 0 || height == 0 || (width, height) == (managed.width, managed.height) {
+                managed
+                    .window
+                    .update_with_buffer(buffer, managed.width, managed.height)
+                    .unwrap();
+                continue;
+            }
+            gbemu::scale::letterbox(
+                buffer,
+                managed.width,
+                managed.height,
+                &mut managed.scaled,
+                width,
+                height,
+            );
+            managed
+                .window
+                .update_with_buffer(&managed.scaled, width, height)
                 .unwrap();
-        } else {
-            break;
         }
     }
+}
 
-    // Drop, so the CPU will stop because no one is sending/listening for updates.
-    drop(gui_frame.1);
-    drop(key_events.0);
-    drop(audio_stream);
+/// Runs the emulator in its thread until the frontend is gone.
+fn run(
+    mut emulator: Emulator,
+    gui_frame: SyncSender<GuiFrame>,
+    key_events: Receiver<GuiEvent>,
+) -> CPU {
+    let mut present = |frame, droppable| {
+        if droppable {
+            // Dropped when the window is still busy with the previous one.
+            !matches!(
+                gui_frame.try_send(frame),
+                Err(mpsc::TrySendError::Disconnected(_))
+            )
+        } else {
+            gui_frame.send(frame).is_ok()
+        }
+    };
+    while emulator.pass(&key_events, &mut present) {}
+    emulator.cpu
+}
+
+/// The emulation loop of an instance, run one pass at a time.
+struct Emulator {
+    cpu: CPU,
+    compositor: Option<Compositor>,
+    views: Vec<Box<dyn View>>,
+    rip: Rip,
+    pacing: Pacing,
+    macro_path: Option<PathBuf>,
+    sgb_border: bool,
+    autosave: Option<Autosave>,
+
+    pacer: Option<FramePacer<ScaledClock>>,
+    ticks_per_frame: u64,
+    drift: DriftMeter,
+    emulated_frames: u64,
+    last_shown: Instant,
+    /// Measures the speed of --speed max.
+    speed_meter: (Instant, u64),
 
-    cpu_run.join().unwrap();
+    gui_buf: Box<[u32; SCREEN_HEIGHT * SCREEN_WIDTH]>,
+    layer_buf: Box<[Layer; SCREEN_HEIGHT * SCREEN_WIDTH]>,
+
+    ticks: u64,
+    cpu_pause: bool,
+    /// Runs one frame while paused.
+    frame_advance: bool,
+    show_heatmap: bool,
+    show_ppu_stats: bool,
+    show_fps: bool,
+    fps: FpsMeter,
+    settings: Settings,
+    input: Input,
+    profiled_frames: u64,
+    /// Quick save slot (F5 saves, F9 loads).
+    quick_save: Option<Vec<u8>>,
+    /// Recorded with F2 and played with F3, kept in a file next to the ROM.
+    input_macro: Option<InputMacro>,
+    rewind: Rewind,
+    rewinding: bool,
 }
 
-fn run(mut cpu: CPU, gui_frame: SyncSender<GuiFrame>, key_events: Receiver<GuiEvent>) {
-    // Inspired by https://github.com/mvdnes/rboy/blob/1e46c6d5fc61140e8e1919dea9f799d9d4e41345/src/main.rs#L317
-    let limiter = spawn_limiter(gbemu::MILLIS_PER_FRAME);
+impl Emulator {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        cpu: CPU,
+        compositor: Option<Compositor>,
+        views: Vec<Box<dyn View>>,
+        rip: Rip,
+        pacing: Pacing,
+        macro_path: Option<PathBuf>,
+        sgb_border: bool,
+        autosave: Option<Autosave>,
+    ) -> Self {
+        let frame_time = Duration::from_millis(gbemu::MILLIS_PER_FRAME);
+        let pacer = match &pacing {
+            Pacing::Limiter(clock) => Some(FramePacer::new(clock.clone(), frame_time)),
+            Pacing::Vsync(_) | Pacing::AudioSync(_) | Pacing::Uncapped => None,
+        };
+        let ticks_per_frame = match pacing {
+            Pacing::Limiter(_) | Pacing::Uncapped => gbemu::TICKS_PER_FRAME,
+            Pacing::Vsync(_) | Pacing::AudioSync(_) => gbemu::TICKS_PER_LCD_FRAME,
+        };
+        let input_macro = macro_path.as_deref().and_then(load_macro);
 
-    let mut gui_buf = [0u32; SCREEN_HEIGHT * SCREEN_WIDTH];
+        Self {
+            cpu,
+            compositor,
+            views,
+            rip,
+            pacing,
+            macro_path,
+            sgb_border,
+            autosave,
+            pacer,
+            ticks_per_frame,
+            drift: DriftMeter::new(),
+            emulated_frames: 0,
+            last_shown: Instant::now(),
+            speed_meter: (Instant::now(), 0),
+            gui_buf: Box::new([0; SCREEN_HEIGHT * SCREEN_WIDTH]),
+            layer_buf: Box::new([Layer::Background; SCREEN_HEIGHT * SCREEN_WIDTH]),
+            ticks: 0,
+            cpu_pause: false,
+            frame_advance: false,
+            show_heatmap: false,
+            show_ppu_stats: false,
+            show_fps: false,
+            fps: FpsMeter::default(),
+            settings: Settings::default(),
+            input: Input::default(),
+            profiled_frames: 0,
+            quick_save: None,
+            input_macro,
+            rewind: Rewind::new(REWIND_INTERVAL, REWIND_BUDGET),
+            rewinding: false,
+        }
+    }
 
-    let mut ticks = 0;
-    let mut cpu_pause = false;
+    /// Emulates a frame unless paused, shows it with `present`, handles the events of the
+    /// frontend and waits for the pacing.
+    ///
+    /// `present` gets the frame and whether it may be dropped because the window is still busy,
+    /// it returns false when the frontend is gone.
+    ///
+    /// # Returns
+    ///
+    /// False when the frontend is gone.
+    fn pass(
+        &mut self,
+        key_events: &Receiver<GuiEvent>,
+        present: &mut dyn FnMut(GuiFrame, bool) -> bool,
+    ) -> bool {
+        let frame_time = Duration::from_millis(gbemu::MILLIS_PER_FRAME);
+        let pass_start = Instant::now();
+        let running = !self.cpu_pause || std::mem::take(&mut self.frame_advance);
+        let mut emulated = false;
+        if self.rewinding && running {
+            // One captured state per frame: rewinds at REWIND_INTERVAL times the normal speed.
+            if let Some(state) = self.rewind.pop() {
+                if let Err(err) = self.cpu.load_state(&state) {
+                    eprintln!("Can't rewind: {err}");
+                }
+            }
+            emulated = true;
+        } else if running {
+            while self.ticks < self.ticks_per_frame {
+                self.ticks += self.cpu.cycle();
 
-    'main: loop {
-        if !cpu_pause {
-            while ticks < gbemu::TICKS_PER_FRAME {
-                ticks += cpu.cycle();
+                if let Some(breakpoint) = self.cpu.take_breakpoint_hit() {
+                    eprintln!("Paused at {breakpoint:?}, PC: {:#06X}", self.cpu.pc());
+                    self.cpu_pause = true;
+                    break;
+                }
+            }
+            for warning in self.cpu.take_warnings() {
+                eprintln!("{warning}");
+            }
+            // Paused in the middle of a frame: finish it after resuming or advancing.
+            if self.ticks >= self.ticks_per_frame {
+                self.ticks -= self.ticks_per_frame;
+                self.rewind.frame_done(&mut self.cpu);
+                emulated = true;
             }
-            ticks -= gbemu::TICKS_PER_FRAME;
         }
 
-        cpu.gpu().to_rgb32(&mut gui_buf);
+        if let Some(ripper) = &mut self.rip.tiles {
+            if let Err(err) = ripper.scan(&self.cpu.gpu().vram) {
+                eprintln!("Can't save tiles: {err}");
+            }
+        }
 
-        if gui_frame.send(gui_buf).is_err() {
-            break;
+        if emulated {
+            self.emulated_frames += 1;
+            self.input.frame_done();
+        }
+        // Faster than real time the display can't keep up, so only some frames are shown.
+        let shown = match &self.pacing {
+            Pacing::Limiter(clock) => self
+                .emulated_frames
+                .is_multiple_of(clock.speed().ceil().max(1.0) as u64),
+            Pacing::Vsync(_) | Pacing::AudioSync(_) => true,
+            Pacing::Uncapped => self.cpu_pause || self.last_shown.elapsed() >= frame_time,
+        };
+        if shown {
+            self.last_shown = Instant::now();
+            match self.cpu.heatmap() {
+                Some(heatmap) if self.show_heatmap => heatmap.to_rgb32(&mut self.gui_buf),
+                _ => match self.cpu.sgb() {
+                    Some(sgb) if sgb.is_active() => sgb.to_rgb32(self.cpu.gpu(), &mut self.gui_buf),
+                    _ => {
+                        self.cpu.gpu().to_rgb32(&mut self.gui_buf);
+                        self.cpu.gpu().to_layers(&mut self.layer_buf);
+                        self.settings
+                            .apply_palette(&mut *self.gui_buf, &*self.layer_buf);
+                    }
+                },
+            }
+            let profile = gbemu::profiler::take_frame();
+            if self.show_ppu_stats {
+                self.cpu.gpu().stats().draw_overlay(&mut self.gui_buf);
+                if let Some(latency) = self.cpu.interrupt_latency() {
+                    latency.draw_overlay(&mut *self.gui_buf, SCREEN_WIDTH);
+                }
+                if gbemu::profiler::enabled() && emulated {
+                    profile.draw_overlay(&mut self.gui_buf);
+                    // Numbers to attach to performance reports, once per second.
+                    if self.profiled_frames.is_multiple_of(gbemu::GPU_FPS) {
+                        eprintln!("Frame time: {profile}");
+                    }
+                    self.profiled_frames += 1;
+                }
+            }
+            if self.show_fps {
+                self.fps.draw_overlay(&mut *self.gui_buf, SCREEN_WIDTH);
+            }
+
+            let ctx = ViewContext {
+                cpu: &self.cpu,
+                screen: &self.gui_buf,
+            };
+            let mut frame = Vec::with_capacity(self.views.len() + 1);
+            frame.push(match (&mut self.compositor, self.cpu.sgb()) {
+                (Some(compositor), _) => compositor.compose(&ctx).to_vec(),
+                (None, Some(sgb)) if self.sgb_border => {
+                    let mut buff = vec![0; SGB_WIDTH * SGB_HEIGHT];
+                    sgb.draw_border(&self.gui_buf, &mut buff);
+                    buff
+                }
+                (None, _) => self.gui_buf.to_vec(),
+            });
+            for view in &self.views {
+                let mut buff = vec![];
+                compositor::render(view.as_ref(), &ctx, &mut buff);
+                frame.push(buff);
+            }
+            let droppable = matches!(self.pacing, Pacing::Uncapped) && !self.cpu_pause;
+            if !present(frame, droppable) {
+                return false;
+            }
+            if let Pacing::Vsync(ratio) = &self.pacing {
+                // The window waits for the display, so frames are presented at its rate.
+                self.drift.frame_presented(ratio, self.cpu_pause);
+            }
         }
 
         loop {
             match key_events.try_recv() {
                 Ok(ev) => match ev {
-                    GuiEvent::KeyUp(joypad_key) => cpu.key_up(joypad_key),
-                    GuiEvent::KeyDown(joypad_key) => cpu.key_down(joypad_key),
-                    GuiEvent::ToggleCpuPause => cpu_pause = !cpu_pause,
+                    GuiEvent::KeyUp(joypad_key) => self.input.key_up(joypad_key),
+                    GuiEvent::KeyDown(joypad_key) => self.input.key_down(joypad_key),
+                    GuiEvent::TurboUp(joypad_key) => self.input.turbo_up(joypad_key),
+                    GuiEvent::TurboDown(joypad_key) => self.input.turbo_down(joypad_key),
+                    GuiEvent::ToggleCpuPause => self.cpu_pause = !self.cpu_pause,
+                    GuiEvent::FrameAdvance => self.frame_advance = self.cpu_pause,
+                    GuiEvent::ToggleHeatmap => self.show_heatmap = !self.show_heatmap,
+                    GuiEvent::TogglePpuStats => self.show_ppu_stats = !self.show_ppu_stats,
+                    GuiEvent::ToggleFps => self.show_fps = !self.show_fps,
+                    GuiEvent::ToggleBackgroundLayer => {
+                        let mut layers = self.cpu.visible_layers();
+                        layers.background = !layers.background;
+                        self.cpu.set_visible_layers(layers);
+                    }
+                    GuiEvent::ToggleWindowLayer => {
+                        let mut layers = self.cpu.visible_layers();
+                        layers.window = !layers.window;
+                        self.cpu.set_visible_layers(layers);
+                    }
+                    GuiEvent::ToggleSpriteLayer => {
+                        let mut layers = self.cpu.visible_layers();
+                        layers.sprites = !layers.sprites;
+                        self.cpu.set_visible_layers(layers);
+                    }
+                    GuiEvent::ToggleChannel(idx) => {
+                        let enabled = !self.cpu.audio_channel_enabled(idx);
+                        self.cpu.set_audio_channel_enabled(idx, enabled);
+                        let state = if enabled { "unmuted" } else { "muted" };
+                        eprintln!("Audio channel {} {state}", idx + 1);
+                    }
+                    GuiEvent::SoftReset => self.cpu.soft_reset(),
+                    GuiEvent::ToggleMacroRecording => match self.cpu.stop_macro_recording() {
+                        Some(recorded) => {
+                            eprintln!("Macro recorded: {} key events", recorded.events.len());
+                            if let Some(path) = &self.macro_path {
+                                if let Err(err) = std::fs::write(path, recorded.to_text()) {
+                                    eprintln!("Can't save {}: {}", path.display(), err);
+                                }
+                            }
+                            self.input_macro = Some(recorded);
+                        }
+                        None => {
+                            self.cpu.start_macro_recording();
+                            eprintln!("Recording a macro, press F2 again to stop");
+                        }
+                    },
+                    GuiEvent::PlayMacro => match &self.input_macro {
+                        Some(recorded) => self.cpu.play_macro(recorded),
+                        None => eprintln!("No macro recorded, press F2 to record one"),
+                    },
+                    GuiEvent::QuickSave => {
+                        self.quick_save = Some(self.cpu.save_state());
+                        eprintln!("State saved");
+                    }
+                    GuiEvent::QuickLoad => match &self.quick_save {
+                        Some(state) => match self.cpu.load_state(state) {
+                            Ok(()) => eprintln!("State loaded"),
+                            Err(err) => eprintln!("Can't load the state: {err}"),
+                        },
+                        None => eprintln!("No state saved, press F5 first"),
+                    },
+                    GuiEvent::DumpBusLog => match self.cpu.flight_recorder() {
+                        Some(recorder) => recorder.dump(&mut std::io::stderr()).unwrap(),
+                        None => eprintln!("Enable the flight recorder with --flight-recorder N"),
+                    },
+                    GuiEvent::DumpRegisters => print_registers(&self.cpu),
+                    GuiEvent::DumpMemory => print_memory(&self.cpu),
+                    GuiEvent::ApplySettings(new) => {
+                        self.cpu.set_palette(new.palette);
+                        self.input.set_turbo_rate(new.turbo_rate);
+                        self.settings = *new;
+                    }
+                    GuiEvent::ExportFrame => {
+                        let screen = self.cpu.screen();
+                        let path = self.rip.dir.join(format!("frame-{:06}.png", screen.number));
+                        match gbemu::rip::export_screen(
+                            &screen.pixels,
+                            &self.settings.palette,
+                            &path,
+                        ) {
+                            Ok(()) => eprintln!("Saved {}", path.display()),
+                            Err(err) => eprintln!("Can't save {}: {}", path.display(), err),
+                        }
+                    }
+                    GuiEvent::Screenshot => {
+                        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                        let path = PathBuf::from(format!("screenshot-{}.png", time.as_millis()));
+                        match self.cpu.gpu().to_png(&path) {
+                            Ok(()) => eprintln!("Saved {}", path.display()),
+                            Err(err) => eprintln!("Can't save {}: {}", path.display(), err),
+                        }
+                    }
+                    GuiEvent::RunTo(breakpoint) => {
+                        self.cpu.add_temp_breakpoint(breakpoint);
+                        self.cpu_pause = false;
+                    }
+                    GuiEvent::Rewind(held) => self.rewinding = held,
                 },
                 Err(mpsc::TryRecvError::Empty) => break,
-                Err(mpsc::TryRecvError::Disconnected) => break 'main,
+                Err(mpsc::TryRecvError::Disconnected) => return false,
+            }
+        }
+        for (button, pressed) in self.input.changes() {
+            if pressed {
+                self.cpu.key_down(button);
+            } else {
+                self.cpu.key_up(button);
             }
         }
+        if let Some(autosave) = &mut self.autosave {
+            autosave.poll(&mut self.cpu);
+        }
 
-        limiter.recv().unwrap();
+        let busy = pass_start.elapsed();
+        if let Some(pacer) = &mut self.pacer {
+            pacer.wait();
+        }
+        if let Pacing::AudioSync(ring) = &self.pacing {
+            if self.cpu_pause {
+                std::thread::sleep(frame_time);
+            } else {
+                // The next buffer may be played within this frame.
+                ring.wait_for_room(gbemu::AUDIO_BUF_LEN, frame_time * 4);
+            }
+        }
+        if let Pacing::Uncapped = self.pacing {
+            self.speed_meter.1 += 1;
+            let elapsed = self.speed_meter.0.elapsed();
+            if elapsed >= Duration::from_secs(1) {
+                let emulated = frame_time * self.speed_meter.1 as u32;
+                eprintln!(
+                    "Emulation speed: {:.1}x",
+                    emulated.as_secs_f64() / elapsed.as_secs_f64()
+                );
+                self.speed_meter = (Instant::now(), 0);
+            }
+        }
+        self.fps
+            .frame_done(emulated, shown, busy, pass_start.elapsed());
+        true
     }
 }
 
-fn spawn_limiter(ms: u64) -> Receiver<()> {
-    let (snd, rcv) = mpsc::sync_channel(1);
-    std::thread::spawn(move || loop {
-        std::thread::sleep(std::time::Duration::from_millis(ms));
-        snd.send(()).unwrap();
-    });
-    rcv
+/// Reads the macro of a game, if it was recorded.
+fn load_macro(path: &Path) -> Option<InputMacro> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(err) => {
+            eprintln!("Can't load {}: {}", path.display(), err);
+            return None;
+        }
+    };
+    InputMacro::parse(&text)
+        .map_err(|err| eprintln!("Can't load {}: {}", path.display(), err))
+        .ok()
 }
 
-fn create_cpal_player(audio_buf: Receiver<gbemu::AudioBuff>) -> cpal::Stream {
-    let device = cpal::default_host().default_output_device().unwrap();
-
-    let err_cb = |err| eprintln!("Error during playing audio: {}", err);
+/// Writes the cartridge RAM to the `.sav` file while playing, so a crash doesn't lose the progress
+/// saved by the game.
+struct Autosave {
+    path: PathBuf,
+    interval: Duration,
+    clock: std::sync::Arc<dyn Clock>,
+    last_check: Duration,
+}
 
-    let available_configs = device.supported_output_configs().unwrap();
+impl Autosave {
+    fn new(path: PathBuf, interval: Duration, clock: std::sync::Arc<dyn Clock>) -> Self {
+        Self {
+            path,
+            interval,
+            last_check: clock.now(),
+            clock,
+        }
+    }
 
-    let sample_rate = cpal::SampleRate(gbemu::SAMPLE_RATE as u32);
-    let mut config = None;
+    /// Whether the interval passed since the last time it did.
+    fn due(&mut self) -> bool {
+        let now = self.clock.now();
+        if now - self.last_check < self.interval {
+            return false;
+        }
+        self.last_check = now;
+        true
+    }
 
-    for curr_config in available_configs {
-        if curr_config.channels() == 2 && curr_config.sample_format() == cpal::SampleFormat::F32 {
-            if curr_config.min_sample_rate() <= sample_rate
-                && sample_rate <= curr_config.max_sample_rate()
-            {
-                config = Some(curr_config.with_sample_rate(sample_rate));
-            } else {
-                panic!("Sample rate is not supported!");
+    /// Writes the RAM once per interval, if the game changed it.
+    fn poll(&mut self, cpu: &mut CPU) {
+        if self.due() && cpu.take_cartridge_ram_dirty() {
+            if let Err(err) = write_replacing(&self.path, &cpu.cartridge_ram()) {
+                eprintln!("Can't save {}: {}", self.path.display(), err);
             }
         }
     }
+}
+
+/// Writes a temporary file next to `path` and renames it to `path`, so a crash in the middle of
+/// the write keeps the previous file.
+fn write_replacing(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    std::fs::write(&tmp_path, data)?;
+    std::fs::rename(&tmp_path, path)
+}
+
+/// Asset ripping of an instance.
+struct Rip {
+    /// Saves unique tiles every frame.
+    tiles: Option<TileRipper>,
+    /// Where exported frames are written.
+    dir: std::path::PathBuf,
+}
+
+/// How the emulation thread keeps real-time speed.
+#[derive(Clone)]
+enum Pacing {
+    /// Emulates 16 ms every 16 ms of the clock, which runs faster or slower than real time to
+    /// change the speed.
+    Limiter(ScaledClock),
+    /// Emulates one LCD frame per frame shown: the game window limits updates to the display
+    /// refresh. Audio is stretched by the difference between the rates.
+    Vsync(StretchRatio),
+    /// Emulates one LCD frame whenever the audio queue has room for another buffer: the audio
+    /// device's clock sets the speed, so audio never runs dry or piles up.
+    AudioSync(AudioRing),
+    /// Emulates as fast as possible, muted. Frames are shown at most every 16 ms.
+    Uncapped,
+}
+
+/// Measures how much faster or slower than the LCD frames are presented.
+struct DriftMeter {
+    last_frame: std::time::Instant,
+    /// Smoothed time between frames.
+    interval: f64,
+    reported: bool,
+}
+
+impl DriftMeter {
+    const LCD_FRAME_SECS: f64 = gbemu::TICKS_PER_LCD_FRAME as f64 / gbemu::CPU_FREQ as f64;
+    const SMOOTHING: f64 = 0.02;
+
+    fn new() -> Self {
+        Self {
+            last_frame: std::time::Instant::now(),
+            interval: Self::LCD_FRAME_SECS,
+            reported: false,
+        }
+    }
+
+    fn frame_presented(&mut self, ratio: &StretchRatio, paused: bool) {
+        let now = std::time::Instant::now();
+        let elapsed = now.duration_since(self.last_frame).as_secs_f64();
+        self.last_frame = now;
+        if paused {
+            return;
+        }
+
+        self.interval += (elapsed - self.interval) * Self::SMOOTHING;
+        if !ratio.set((self.interval / Self::LCD_FRAME_SECS) as f32) && !self.reported {
+            self.reported = true;
+            eprintln!(
+                "Frames are shown every {:.2} ms, too far from the LCD's {:.2} ms for audio to keep \
+                up. Use --vsync with the display refresh rate.",
+                self.interval * 1000.0,
+                Self::LCD_FRAME_SECS * 1000.0
+            );
+        }
+    }
+}
 
-    let config = config.expect("Can't select audio config!");
+fn print_registers(cpu: &CPU) {
+    let regs = cpu.registers();
+    eprintln!(
+        "PC={:04X} SP={:04X} AF={:04X} BC={:04X} DE={:04X} HL={:04X}",
+        cpu.pc(),
+        cpu.sp(),
+        regs.af(),
+        regs.bc(),
+        regs.de(),
+        regs.hl()
+    );
+
+    const PANEL_REGISTERS: [u16; 7] = [0xFF40, 0xFF41, 0xFF44, 0xFF45, 0xFFFF, 0xFF0F, 0xFF07];
+    let io: Vec<String> = PANEL_REGISTERS
+        .iter()
+        .map(|&addr| {
+            format!(
+                "{}={:02X}",
+                address_map::io_register_name(addr).unwrap(),
+                cpu.debug_read(addr)
+            )
+        })
+        .collect();
+    eprintln!("{}", io.join(" "));
+
+    let mbc = cpu.mbc_state();
+    eprintln!(
+        "MBC: ROM bank={:02X} RAM bank={} RAM enabled={} advanced mode={}",
+        mbc.rom_bank, mbc.ram_bank, mbc.ram_enabled, mbc.advanced_mode
+    );
+}
+
+/// Hexdumps the code around PC, the top of the stack and the IO registers.
+fn print_memory(cpu: &CPU) {
+    use std::io::Write;
+
+    let pc = cpu.pc() & !0xF;
+    let sp = cpu.sp() & !0xF;
+    let mut out = std::io::stderr().lock();
+    for (start, end) in [
+        (pc, pc.saturating_add(0x3F)),
+        (sp, sp.saturating_add(0x1F)),
+        (0xFF00, 0xFF4F),
+    ] {
+        address_map::hexdump(cpu, start, end, &mut out).unwrap();
+        writeln!(out).unwrap();
+    }
+}
+
+/// Frames between the states captured for rewinding.
+const REWIND_INTERVAL: u32 = 4;
+/// Memory for rewinding, about a minute of Tetris.
+const REWIND_BUDGET: usize = 8 << 20;
+
+/// Speed while the fast-forward key is held.
+const FAST_FORWARD_SPEED: f64 = 4.0;
+
+/// Cycles through normal speed and slow motion.
+fn next_slow_motion_speed(speed: f64) -> f64 {
+    const SPEEDS: [f64; 3] = [1.0, 0.5, 0.25];
+    let idx = SPEEDS.iter().position(|&s| s == speed).unwrap_or(0);
+    SPEEDS[(idx + 1) % SPEEDS.len()]
+}
+
+/// Opens the default output device close to [`gbemu::SAMPLE_RATE`] in stereo.
+///
+/// # Returns
+///
+/// The stream and its sample rate.
+fn create_cpal_player(ring: AudioRing) -> (cpal::Stream, u32) {
+    let device = cpal::default_host().default_output_device().unwrap();
+
+    let err_cb = |err| eprintln!("Error during playing audio: {}", err);
+
+    let wanted = cpal::SampleRate(gbemu::SAMPLE_RATE as u32);
+    let format_rank = |format| {
+        [
+            cpal::SampleFormat::F32,
+            cpal::SampleFormat::I16,
+            cpal::SampleFormat::U16,
+        ]
+        .iter()
+        .position(|&supported| supported == format)
+    };
+    // Stereo first, then the preferred sample format, then the closest rate.
+    let config = device
+        .supported_output_configs()
+        .unwrap()
+        .filter(|config| format_rank(config.sample_format()).is_some())
+        .map(|config| {
+            let rate = wanted.clamp(config.min_sample_rate(), config.max_sample_rate());
+            config.with_sample_rate(rate)
+        })
+        .min_by_key(|config| {
+            (
+                config.channels() != 2,
+                format_rank(config.sample_format()),
+                config.sample_rate().0.abs_diff(wanted.0),
+            )
+        })
+        .expect("Can't select audio config!");
     let sample_format = config.sample_format();
     let config = config.config();
 
     let stream = match sample_format {
-        cpal::SampleFormat::F32 => device.build_output_stream(
-            &config,
-            move |data: &mut [f32], _callback_info: &cpal::OutputCallbackInfo| {
-                if let Ok(buff) = audio_buf.try_recv() {
-                    let max_len = std::cmp::min(data.len() / 2, buff.0.len());
-                    for (idx, (lb, rb)) in buff.0.into_iter().zip(buff.1).enumerate().take(max_len)
-                    {
-                        data[idx * 2] = lb;
-                        data[idx * 2 + 1] = rb;
-                    }
-                }
-            },
-            err_cb,
-            None,
-        ),
-        _ => panic!("Unsupported sample format '{sample_format}'!"),
+        cpal::SampleFormat::F32 => build_output_stream::<f32>(&device, &config, ring, err_cb),
+        cpal::SampleFormat::I16 => build_output_stream::<i16>(&device, &config, ring, err_cb),
+        cpal::SampleFormat::U16 => build_output_stream::<u16>(&device, &config, ring, err_cb),
+        _ => unreachable!("Unsupported sample format '{sample_format}'!"),
     }
     .unwrap();
 
     stream.play().unwrap();
 
-    stream
+    (stream, config.sample_rate.0)
+}
+
+/// Converts queued samples to frames of `T` with `config.channels`: mono is the average of both
+/// sides, channels past the first 2 are silent. Frames are silent when the queue runs dry.
+fn build_output_stream<T: cpal::SizedSample + cpal::FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    ring: AudioRing,
+    err_cb: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let channels = config.channels as usize;
+    let mut samples = vec![];
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _callback_info: &cpal::OutputCallbackInfo| {
+            samples.resize(data.len() / channels, (0.0, 0.0));
+            let queued = ring.pop_into(&mut samples);
+            samples[queued..].fill((0.0, 0.0));
+            for (frame, &(left, right)) in data.chunks_mut(channels).zip(&samples) {
+                if let [mono] = frame {
+                    *mono = T::from_sample((left + right) / 2.0);
+                    continue;
+                }
+                for (out, sample) in frame
+                    .iter_mut()
+                    .zip([left, right].into_iter().chain(std::iter::repeat(0.0)))
+                {
+                    *out = T::from_sample(sample);
+                }
+            }
+        },
+        err_cb,
+        None,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use gbemu::clock::MockClock;
+
+    use super::*;
+
+    #[test]
+    fn autosave_interval() {
+        let clock = MockClock::new();
+        let interval = Duration::from_secs(10);
+        let mut autosave = Autosave::new(
+            "game.sav".into(),
+            interval,
+            std::sync::Arc::new(clock.clone()),
+        );
+
+        assert!(!autosave.due());
+        clock.advance(Duration::from_secs(9));
+        assert!(!autosave.due());
+        clock.advance(Duration::from_secs(1));
+        assert!(autosave.due());
+        assert!(!autosave.due());
+        clock.advance(Duration::from_secs(25));
+        assert!(autosave.due());
+        clock.advance(Duration::from_secs(5));
+        assert!(!autosave.due());
+    }
+
+    #[test]
+    fn replaces_files() {
+        let dir = std::env::temp_dir().join(format!("gbemu-autosave-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.sav");
+
+        write_replacing(&path, &[1, 2]).unwrap();
+        write_replacing(&path, &[3]).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), [3]);
+        assert!(!dir.join("game.sav.tmp").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
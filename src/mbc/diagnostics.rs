@@ -0,0 +1,120 @@
+// Reports of games misusing the cartridge. Real hardware tolerates it, but it usually means a bug
+// in the game (often homebrew) or in the emulator, so it's worth knowing which one ran into it.
+
+use std::cell::Cell;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BankingIssue {
+    /// The selected ROM bank is beyond the ROM, it wraps around.
+    RomBankOutOfRange { bank: usize, banks: usize },
+    /// Cartridge RAM is accessed while disabled: reads give 0xFF and writes are lost.
+    DisabledRamAccess { addr: u16, write: bool },
+}
+
+impl BankingIssue {
+    fn kind(&self) -> usize {
+        match self {
+            BankingIssue::RomBankOutOfRange { .. } => 0,
+            BankingIssue::DisabledRamAccess { .. } => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for BankingIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            BankingIssue::RomBankOutOfRange { bank, banks } => write!(
+                f,
+                "selects ROM bank {bank}, but the ROM has {banks} (wraps to {})",
+                bank % banks
+            ),
+            BankingIssue::DisabledRamAccess { addr, write } => {
+                let access = if write { "writes" } else { "reads" };
+                write!(f, "{access} ${addr:04X} while cartridge RAM is disabled")
+            }
+        }
+    }
+}
+
+/// Counts banking issues and logs them to stderr with the PC of the instruction, throttled: an
+/// issue of the same kind is logged again only after 2, 4, 8... occurrences.
+pub struct BankDiagnostics {
+    /// From the cartridge header.
+    rom_banks: usize,
+    // Reads happen through `&MemoryBus`, so the counts need interior mutability.
+    counts: [Cell<u64>; 2],
+}
+
+impl BankDiagnostics {
+    pub fn new(rom_banks: usize) -> Self {
+        Self {
+            rom_banks,
+            counts: Default::default(),
+        }
+    }
+
+    pub fn check_rom_bank(&self, pc: u16, bank: usize) {
+        if bank >= self.rom_banks {
+            let banks = self.rom_banks;
+            self.report(pc, BankingIssue::RomBankOutOfRange { bank, banks });
+        }
+    }
+
+    pub fn report(&self, pc: u16, issue: BankingIssue) {
+        if let Some(message) = self.count(pc, issue) {
+            eprintln!("{message}");
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The message to log, if the issue isn't throttled.
+    fn count(&self, pc: u16, issue: BankingIssue) -> Option<String> {
+        let count = &self.counts[issue.kind()];
+        count.set(count.get() + 1);
+        if !count.get().is_power_of_two() {
+            return None;
+        }
+        let mut message = format!("PC ${pc:04X} {issue}");
+        if count.get() > 1 {
+            message += &format!(" (seen {} times)", count.get());
+        }
+        Some(message)
+    }
+
+    /// Occurrences of ROM banks out of range and of disabled RAM accesses.
+    pub fn counts(&self) -> (u64, u64) {
+        (self.counts[0].get(), self.counts[1].get())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn throttled_messages() {
+        let diagnostics = BankDiagnostics::new(8);
+        let issue = BankingIssue::RomBankOutOfRange { bank: 9, banks: 8 };
+        assert_eq!(
+            diagnostics.count(0x1234, issue).unwrap(),
+            "PC $1234 selects ROM bank 9, but the ROM has 8 (wraps to 1)"
+        );
+        let logged: Vec<_> = (2..=8)
+            .filter_map(|_| diagnostics.count(0x1234, issue))
+            .collect();
+        assert_eq!(logged.len(), 3);
+        assert!(logged[2].ends_with("(seen 8 times)"));
+
+        let issue = BankingIssue::DisabledRamAccess {
+            addr: 0xA010,
+            write: true,
+        };
+        assert_eq!(
+            diagnostics.count(0x0150, issue).unwrap(),
+            "PC $0150 writes $A010 while cartridge RAM is disabled"
+        );
+        diagnostics.check_rom_bank(0, 7);
+        assert_eq!(diagnostics.counts(), (8, 1));
+    }
+}
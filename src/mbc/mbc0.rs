@@ -1,6 +1,6 @@
-use crate::memory_bus::{EXTERNAL_RAM_START, ROM_BANK_0_START};
+use crate::memory_bus::{EXTERNAL_RAM_START, ROM_BANK_0_START, ROM_BANK_N_START};
 
-use super::KB;
+use super::{MbcState, KB};
 
 pub struct MBC0 {
     rom: [u8; 32 * KB],
@@ -41,4 +41,51 @@ impl super::MBC for MBC0 {
             .get_mut((addr - EXTERNAL_RAM_START) as usize)
             .unwrap() = val;
     }
+
+    fn reset(&mut self, clear_ram: bool) {
+        if clear_ram {
+            self.ram.fill(0);
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        (addr >= ROM_BANK_N_START) as usize
+    }
+
+    fn rom_size(&self) -> usize {
+        self.rom.len()
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn ram_dump(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn has_battery(&self) -> bool {
+        false
+    }
+
+    fn take_ram_dirty(&mut self) -> bool {
+        false
+    }
+
+    fn state(&self) -> MbcState {
+        MbcState {
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: true,
+            advanced_mode: false,
+        }
+    }
+
+    fn set_state(&mut self, _state: MbcState) {
+        // No banking registers.
+    }
 }
@@ -1,4 +1,6 @@
-use super::{RAM_SIZE_ADDR, ROM_SIZE_ADDR};
+use std::cell::{Cell, RefCell};
+
+use super::{MbcState, CARTRIDGE_TYPE_ADDR, RAM_SIZE_ADDR, ROM_SIZE_ADDR};
 
 pub struct MBC1 {
     rom: Vec<u8>,
@@ -9,12 +11,25 @@ pub struct MBC1 {
     current_ram_bank: usize,
     ram_enabled: bool,
     advanced_mode: bool,
+    battery: bool,
+    /// RAM was written since it was last saved.
+    ram_dirty: bool,
+    /// A RAM access didn't fit the RAM size from the header, reported only once.
+    reported_ram_mismatch: Cell<bool>,
+    /// Reported through `&self` on reads, so it needs interior mutability.
+    warnings: RefCell<Vec<String>>,
 }
 
 impl MBC1 {
-    pub fn new(data: Vec<u8>) -> Self {
+    pub fn new(data: Vec<u8>, battery: bool) -> Self {
         let (rom_banks, rom_size) = super::rom_info_reg(data[ROM_SIZE_ADDR]);
-        let (ram_banks, ram_size) = super::ram_info_reg(data[RAM_SIZE_ADDR]);
+        let (mut ram_banks, mut ram_size) = super::ram_info_reg(data[RAM_SIZE_ADDR]);
+        let mut warnings = vec![];
+        // Some homebrew declares a cartridge with RAM, but forgets its size.
+        if data[CARTRIDGE_TYPE_ADDR] != 0x01 && ram_size == 0 {
+            warnings.push("Cartridge has RAM, but the header declares none: using 8 KiB.".into());
+            (ram_banks, ram_size) = super::ram_info_reg(0x2);
+        }
         assert!(
             data.len() <= rom_size,
             "ROM size detected 0x{rom_size:X}, but cartridge size 0x{:X}.",
@@ -30,23 +45,41 @@ impl MBC1 {
             current_ram_bank: 0,
             ram_enabled: false,
             advanced_mode: false,
+            battery,
+            ram_dirty: false,
+            reported_ram_mismatch: Cell::new(false),
+            warnings: RefCell::new(warnings),
         }
     }
-}
 
-impl super::MBC for MBC1 {
-    fn read_rom(&self, addr: u16) -> u8 {
-        let bank = if addr <= 0x3FFF {
-            if self.advanced_mode {
-                self.current_rom_bank & !0b11111
-            } else {
-                0
-            }
+    /// Offset in RAM of `addr` (0xA000-0xBFFF). Accesses past the RAM size from the header are
+    /// wrapped around the allocated RAM instead of failing.
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        let bank = if self.advanced_mode {
+            self.current_ram_bank
         } else {
-            self.current_rom_bank
+            0
         };
+        let offset = (bank * 0x2000) | (addr as usize & 0x1FFF);
+        if offset < self.ram.len() {
+            return Some(offset);
+        }
 
-        let addr = (bank * 0x4000) | (addr as usize & 0x3FFF);
+        if !self.reported_ram_mismatch.replace(true) {
+            self.warnings.borrow_mut().push(format!(
+                "Cartridge RAM access at 0x{addr:X} (bank {bank}) is past the RAM size 0x{:X} \
+                from the header: wrapping it around.",
+                self.ram.len()
+            ));
+        }
+        // RAM sizes are powers of 2.
+        (!self.ram.is_empty()).then(|| offset & (self.ram.len() - 1))
+    }
+}
+
+impl super::MBC for MBC1 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        let addr = (self.rom_bank(addr) * 0x4000) | (addr as usize & 0x3FFF);
         *self.rom.get(addr).unwrap_or(&0xFF)
     }
 
@@ -72,31 +105,110 @@ impl super::MBC for MBC1 {
         }
     }
 
+    fn selected_rom_bank(&self, addr: u16, val: u8) -> Option<usize> {
+        match addr {
+            0x2000..=0x3FFF => {
+                let bank = std::cmp::max(val & 0b11111, 1) as usize;
+                Some((self.current_rom_bank & !0b11111) | bank)
+            }
+            0x4000..=0x5FFF if self.rom_banks > 32 => {
+                Some((self.current_rom_bank & 0b11111) | ((val as usize & 0b11) << 5))
+            }
+            _ => None,
+        }
+    }
+
     fn read_ram(&self, addr: u16) -> u8 {
         if !self.ram_enabled {
             return 0xFF;
         }
-        let bank = if self.advanced_mode {
-            self.current_ram_bank
-        } else {
-            0
-        };
-        let addr = (bank * 0x2000) | (addr as usize & 0x1FFF);
-        *self.ram.get(addr).unwrap()
+        self.ram_offset(addr)
+            .map_or(0xFF, |offset| self.ram[offset])
     }
 
     fn write_ram(&mut self, addr: u16, val: u8) {
         if !self.ram_enabled {
             return;
         }
-        let bank = if self.advanced_mode {
-            self.current_ram_bank
+        if let Some(offset) = self.ram_offset(addr) {
+            self.ram_dirty |= self.ram[offset] != val;
+            self.ram[offset] = val;
+        }
+    }
+
+    fn reset(&mut self, clear_ram: bool) {
+        self.current_rom_bank = 1;
+        self.current_ram_bank = 0;
+        self.ram_enabled = false;
+        self.advanced_mode = false;
+        if clear_ram {
+            self.ram.fill(0);
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        if addr <= 0x3FFF {
+            if self.advanced_mode {
+                self.current_rom_bank & !0b11111
+            } else {
+                0
+            }
+        } else {
+            self.current_rom_bank
+        }
+    }
+
+    fn rom_size(&self) -> usize {
+        self.rom.len()
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn ram_dump(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        self.warnings.take()
+    }
+
+    fn take_ram_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.ram_dirty)
+    }
+
+    fn state(&self) -> MbcState {
+        MbcState {
+            rom_bank: self.current_rom_bank,
+            ram_bank: self.current_ram_bank,
+            ram_enabled: self.ram_enabled,
+            advanced_mode: self.advanced_mode,
+        }
+    }
+
+    fn set_state(&mut self, state: MbcState) {
+        // Like the bank register, 0 in the lower 5 bits selects the next bank.
+        let rom_bank = if state.rom_bank & 0b11111 == 0 {
+            state.rom_bank + 1
+        } else {
+            state.rom_bank
+        };
+        self.current_rom_bank = rom_bank % self.rom_banks;
+        self.current_ram_bank = if self.ram_banks == 4 {
+            state.ram_bank & 0b11
         } else {
             0
         };
-        let addr = (bank * 0x2000) | (addr as usize & 0x1FFF);
-        if let Some(mem) = self.ram.get_mut(addr) {
-            *mem = val;
-        }
+        self.ram_enabled = state.ram_enabled;
+        self.advanced_mode = state.advanced_mode;
     }
 }
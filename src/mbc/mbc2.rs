@@ -0,0 +1,140 @@
+use super::{MbcState, ROM_SIZE_ADDR};
+
+/// 512 half-bytes built into the MBC.
+const RAM_SIZE: usize = 512;
+
+// https://gbdev.io/pandocs/MBC2.html
+pub struct MBC2 {
+    rom: Vec<u8>,
+    /// Only the lower 4 bits of each byte are used.
+    ram: [u8; RAM_SIZE],
+    rom_banks: usize,
+    current_rom_bank: usize,
+    ram_enabled: bool,
+    battery: bool,
+    /// RAM was written since it was last saved.
+    ram_dirty: bool,
+}
+
+impl MBC2 {
+    pub fn new(data: Vec<u8>, battery: bool) -> Self {
+        let (rom_banks, rom_size) = super::rom_info_reg(data[ROM_SIZE_ADDR]);
+        assert!(
+            data.len() <= rom_size,
+            "ROM size detected 0x{rom_size:X}, but cartridge size 0x{:X}.",
+            data.len()
+        );
+
+        Self {
+            rom: data,
+            ram: [0; RAM_SIZE],
+            rom_banks,
+            current_rom_bank: 1,
+            ram_enabled: false,
+            battery,
+            ram_dirty: false,
+        }
+    }
+}
+
+impl super::MBC for MBC2 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        let addr = (self.rom_bank(addr) * 0x4000) | (addr as usize & 0x3FFF);
+        *self.rom.get(addr).unwrap_or(&0xFF)
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        if addr > 0x3FFF {
+            return;
+        }
+        // Bit 8 of the address selects the register.
+        if addr & 0x100 == 0 {
+            self.ram_enabled = val & 0xF == 0xA;
+        } else {
+            let bank = std::cmp::max(val & 0xF, 1);
+            self.current_rom_bank = bank as usize % self.rom_banks;
+        }
+    }
+
+    fn selected_rom_bank(&self, addr: u16, val: u8) -> Option<usize> {
+        (addr <= 0x3FFF && addr & 0x100 != 0).then(|| std::cmp::max(val & 0xF, 1) as usize)
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        // RAM is echoed over the whole area, the upper 4 bits are open bus.
+        0xF0 | self.ram[addr as usize & (RAM_SIZE - 1)]
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) {
+        if self.ram_enabled {
+            let cell = &mut self.ram[addr as usize & (RAM_SIZE - 1)];
+            self.ram_dirty |= *cell != val & 0xF;
+            *cell = val & 0xF;
+        }
+    }
+
+    fn reset(&mut self, clear_ram: bool) {
+        self.current_rom_bank = 1;
+        self.ram_enabled = false;
+        if clear_ram {
+            self.ram.fill(0);
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        if addr <= 0x3FFF {
+            0
+        } else {
+            self.current_rom_bank
+        }
+    }
+
+    fn rom_size(&self) -> usize {
+        self.rom.len()
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn ram_dump(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn ram_load(&mut self, data: &[u8]) -> Option<String> {
+        let warning = super::load_ram(&mut self.ram, data);
+        for val in &mut self.ram {
+            *val &= 0xF;
+        }
+        warning
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn take_ram_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.ram_dirty)
+    }
+
+    fn state(&self) -> MbcState {
+        MbcState {
+            rom_bank: self.current_rom_bank,
+            ram_bank: 0,
+            ram_enabled: self.ram_enabled,
+            advanced_mode: false,
+        }
+    }
+
+    fn set_state(&mut self, state: MbcState) {
+        self.current_rom_bank = std::cmp::max(state.rom_bank & 0xF, 1) % self.rom_banks;
+        self.ram_enabled = state.ram_enabled;
+    }
+}
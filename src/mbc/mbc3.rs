@@ -0,0 +1,506 @@
+use std::{
+    cell::{Cell, RefCell},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::savestate::StateVisitor;
+
+use super::{MbcState, CARTRIDGE_TYPE_ADDR, RAM_SIZE_ADDR, ROM_SIZE_ADDR};
+
+/// Seconds since the Unix epoch, for the RTC to catch up with the time the game was off.
+fn unix_time() -> u64 {
+    // `std` can't read the time in browsers, the clock just doesn't advance there between sessions.
+    if cfg!(target_arch = "wasm32") {
+        return 0;
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+// https://gbdev.io/pandocs/MBC3.html
+pub struct MBC3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_banks: usize,
+    current_rom_bank: usize,
+    /// 0x00-0x03 select a RAM bank, 0x08-0x0C an RTC register.
+    ram_bank_or_rtc: usize,
+    ram_and_rtc_enabled: bool,
+    battery: bool,
+    /// RAM was written since it was last saved.
+    ram_dirty: bool,
+    rtc: Option<Rtc>,
+    /// A RAM access didn't fit the RAM size from the header, reported only once.
+    reported_ram_mismatch: Cell<bool>,
+    /// Reported through `&self` on reads, so it needs interior mutability.
+    warnings: RefCell<Vec<String>>,
+}
+
+/// Real-time clock, counted in emulated time so it stays in sync with save states and emulation
+/// speed. Time that passed while the emulator was closed is added when a `.sav` file is loaded.
+#[derive(Default)]
+struct Rtc {
+    clock: RtcRegisters,
+    /// Copy of the clock readable by the game, updated by the latch.
+    latched: RtcRegisters,
+    /// The last value written to the latch register, the clock is latched on 0x00 -> 0x01.
+    latch_write: u8,
+    /// T-cycles since the last second.
+    cycles: u64,
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+struct RtcRegisters {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    /// 9 bits.
+    days: u16,
+    halted: bool,
+    day_carry: bool,
+}
+
+/// Size of the RTC appended to `.sav` files in the format of VBA-M and BGB: 5 current and 5
+/// latched registers as u32, then the UNIX time of the save as u64.
+const RTC_SAVE_SIZE: usize = 48;
+
+impl MBC3 {
+    pub fn new(data: Vec<u8>, battery: bool) -> Self {
+        let (rom_banks, rom_size) = super::rom_info_reg(data[ROM_SIZE_ADDR]);
+        let (_, ram_size) = super::ram_info_reg(data[RAM_SIZE_ADDR]);
+        assert!(
+            data.len() <= rom_size,
+            "ROM size detected 0x{rom_size:X}, but cartridge size 0x{:X}.",
+            data.len()
+        );
+        let rtc = matches!(data[CARTRIDGE_TYPE_ADDR], 0x0F | 0x10).then(Rtc::default);
+
+        Self {
+            rom: data,
+            ram: vec![0; ram_size],
+            rom_banks,
+            current_rom_bank: 1,
+            ram_bank_or_rtc: 0,
+            ram_and_rtc_enabled: false,
+            battery,
+            ram_dirty: false,
+            rtc,
+            reported_ram_mismatch: Cell::new(false),
+            warnings: RefCell::default(),
+        }
+    }
+
+    /// Offset in RAM of `addr` (0xA000-0xBFFF) in the selected bank, wrapped around the RAM size.
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        let offset = (self.ram_bank_or_rtc * 0x2000) | (addr as usize & 0x1FFF);
+        if offset < self.ram.len() {
+            return Some(offset);
+        }
+
+        if !self.reported_ram_mismatch.replace(true) {
+            self.warnings.borrow_mut().push(format!(
+                "Cartridge RAM access at 0x{addr:X} (bank {}) is past the RAM size 0x{:X} from \
+                the header: wrapping it around.",
+                self.ram_bank_or_rtc,
+                self.ram.len()
+            ));
+        }
+        (!self.ram.is_empty()).then(|| offset & (self.ram.len() - 1))
+    }
+
+    fn rtc_selected(&self) -> bool {
+        (0x08..=0x0C).contains(&self.ram_bank_or_rtc)
+    }
+}
+
+impl super::MBC for MBC3 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        let addr = (self.rom_bank(addr) * 0x4000) | (addr as usize & 0x3FFF);
+        *self.rom.get(addr).unwrap_or(&0xFF)
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        if addr <= 0x1FFF {
+            self.ram_and_rtc_enabled = val & 0xF == 0xA;
+        } else if addr <= 0x3FFF {
+            // Unlike MBC1, all 7 bits are compared with 0.
+            let bank = std::cmp::max(val & 0x7F, 1);
+            self.current_rom_bank = bank as usize % self.rom_banks;
+        } else if addr <= 0x5FFF {
+            self.ram_bank_or_rtc = val as usize & 0x0F;
+        } else if let Some(rtc) = &mut self.rtc {
+            if rtc.latch_write == 0x00 && val == 0x01 {
+                rtc.latched = rtc.clock;
+            }
+            rtc.latch_write = val;
+        }
+    }
+
+    fn selected_rom_bank(&self, addr: u16, val: u8) -> Option<usize> {
+        (0x2000..=0x3FFF)
+            .contains(&addr)
+            .then(|| std::cmp::max(val & 0x7F, 1) as usize)
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        if !self.ram_and_rtc_enabled {
+            return 0xFF;
+        }
+        if self.rtc_selected() {
+            return self
+                .rtc
+                .as_ref()
+                .map_or(0xFF, |rtc| rtc.latched.read(self.ram_bank_or_rtc));
+        }
+        self.ram_offset(addr)
+            .map_or(0xFF, |offset| self.ram[offset])
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) {
+        if !self.ram_and_rtc_enabled {
+            return;
+        }
+        if self.rtc_selected() {
+            if let Some(rtc) = &mut self.rtc {
+                rtc.write(self.ram_bank_or_rtc, val);
+                self.ram_dirty = true;
+            }
+            return;
+        }
+        if let Some(offset) = self.ram_offset(addr) {
+            self.ram_dirty |= self.ram[offset] != val;
+            self.ram[offset] = val;
+        }
+    }
+
+    fn step(&mut self, cycles: u64) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.step(cycles);
+        }
+    }
+
+    fn reset(&mut self, clear_ram: bool) {
+        self.current_rom_bank = 1;
+        self.ram_bank_or_rtc = 0;
+        self.ram_and_rtc_enabled = false;
+        if clear_ram {
+            self.ram.fill(0);
+        }
+        // The clock has its own battery and keeps running.
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        if addr <= 0x3FFF {
+            0
+        } else {
+            self.current_rom_bank
+        }
+    }
+
+    fn rom_size(&self) -> usize {
+        self.rom.len()
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn ram_dump(&self) -> Vec<u8> {
+        let mut dump = self.ram.clone();
+        if let Some(rtc) = &self.rtc {
+            for registers in [rtc.clock, rtc.latched] {
+                for idx in 0x08..=0x0C {
+                    dump.extend_from_slice(&(registers.read(idx) as u32).to_le_bytes());
+                }
+            }
+            dump.extend_from_slice(&unix_time().to_le_bytes());
+        }
+        dump
+    }
+
+    fn ram_load(&mut self, data: &[u8]) -> Option<String> {
+        let rtc = match &mut self.rtc {
+            Some(rtc) if data.len() == self.ram.len() + RTC_SAVE_SIZE => rtc,
+            // Without the clock, e.g. saved by an emulator without RTC support.
+            _ => return super::load_ram(&mut self.ram, data),
+        };
+
+        let (ram, saved_rtc) = data.split_at(self.ram.len());
+        self.ram.copy_from_slice(ram);
+        let word = |idx: usize| saved_rtc[idx * 4];
+        for (registers, first) in [(&mut rtc.clock, 0), (&mut rtc.latched, 5)] {
+            for (idx, reg) in (0x08..=0x0C).zip(first..) {
+                registers.write(idx, word(reg));
+            }
+        }
+        let saved_at = u64::from_le_bytes(saved_rtc[40..48].try_into().unwrap());
+        if !rtc.clock.halted {
+            rtc.clock.advance(unix_time().saturating_sub(saved_at));
+        }
+        None
+    }
+
+    fn visit_extra_state(&mut self, state: &mut StateVisitor) {
+        if let Some(rtc) = &mut self.rtc {
+            for registers in [&mut rtc.clock, &mut rtc.latched] {
+                state.u8(&mut registers.seconds);
+                state.u8(&mut registers.minutes);
+                state.u8(&mut registers.hours);
+                state.u16(&mut registers.days);
+                state.bool(&mut registers.halted);
+                state.bool(&mut registers.day_carry);
+            }
+            state.u8(&mut rtc.latch_write);
+            state.u64(&mut rtc.cycles);
+        }
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        self.warnings.take()
+    }
+
+    fn take_ram_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.ram_dirty)
+    }
+
+    fn state(&self) -> MbcState {
+        MbcState {
+            rom_bank: self.current_rom_bank,
+            ram_bank: self.ram_bank_or_rtc,
+            ram_enabled: self.ram_and_rtc_enabled,
+            advanced_mode: false,
+        }
+    }
+
+    fn set_state(&mut self, state: MbcState) {
+        self.current_rom_bank = std::cmp::max(state.rom_bank & 0x7F, 1) % self.rom_banks;
+        self.ram_bank_or_rtc = state.ram_bank & 0x0F;
+        self.ram_and_rtc_enabled = state.ram_enabled;
+    }
+}
+
+impl Rtc {
+    fn step(&mut self, cycles: u64) {
+        if self.clock.halted {
+            return;
+        }
+        self.cycles += cycles;
+        while self.cycles >= crate::CPU_FREQ {
+            self.cycles -= crate::CPU_FREQ;
+            self.clock.tick_second();
+        }
+    }
+
+    fn write(&mut self, register: usize, val: u8) {
+        if register == 0x08 {
+            // Writing the seconds restarts the current second.
+            self.cycles = 0;
+        }
+        self.clock.write(register, val);
+    }
+}
+
+impl RtcRegisters {
+    fn read(&self, register: usize) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days as u8,
+            0x0C => {
+                ((self.day_carry as u8) << 7)
+                    | ((self.halted as u8) << 6)
+                    | (self.days >> 8) as u8
+                    | 0b0011_1110
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, register: usize, val: u8) {
+        match register {
+            0x08 => self.seconds = val & 0x3F,
+            0x09 => self.minutes = val & 0x3F,
+            0x0A => self.hours = val & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | val as u16,
+            0x0C => {
+                self.days = (self.days & 0xFF) | ((val as u16 & 1) << 8);
+                self.halted = crate::bit!(val, 6);
+                self.day_carry = crate::bit!(val, 7);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn advance(&mut self, seconds: u64) {
+        if self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24 {
+            // Invalid values don't carry, let them wrap first.
+            for _ in 0..seconds.min(64 * 64 * 32) {
+                self.tick_second();
+            }
+            return;
+        }
+
+        let total = seconds
+            + self.seconds as u64
+            + self.minutes as u64 * 60
+            + self.hours as u64 * 3600
+            + self.days as u64 * 86400;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        let days = total / 86400;
+        self.days = (days % 512) as u16;
+        self.day_carry |= days >= 512;
+    }
+
+    /// Counters past their range (e.g. 61 seconds) count up to the register size and wrap to 0
+    /// without a carry to the next counter.
+    fn tick_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days += 1;
+        if self.days == 512 {
+            self.days = 0;
+            self.day_carry = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::MBC;
+
+    fn mbc3_with_rtc() -> MBC3 {
+        let mut rom = vec![0; 0x8000];
+        rom[CARTRIDGE_TYPE_ADDR] = 0x10;
+        rom[RAM_SIZE_ADDR] = 0x3;
+        let mut mbc = MBC3::new(rom, true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc
+    }
+
+    fn read_rtc(mbc: &mut MBC3, register: u8) -> u8 {
+        mbc.write_rom(0x4000, register);
+        mbc.read_ram(0xA000)
+    }
+
+    fn latch(mbc: &mut MBC3) {
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+    }
+
+    #[test]
+    fn rtc_counts_and_latches() {
+        let mut mbc = mbc3_with_rtc();
+        mbc.write_rom(0x4000, 0x0A);
+        mbc.write_ram(0xA000, 23);
+        mbc.write_rom(0x4000, 0x09);
+        mbc.write_ram(0xA000, 59);
+        mbc.write_rom(0x4000, 0x08);
+        mbc.write_ram(0xA000, 59);
+
+        mbc.step(crate::CPU_FREQ - 4);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, 0x08), 59);
+
+        mbc.step(4);
+        // The latched value stays until the next latch.
+        assert_eq!(read_rtc(&mut mbc, 0x08), 59);
+        latch(&mut mbc);
+        let time: Vec<_> = (0x08..=0x0C).map(|reg| read_rtc(&mut mbc, reg)).collect();
+        assert_eq!(time, [0, 0, 0, 1, 0b0011_1110]);
+
+        // Halted.
+        mbc.write_rom(0x4000, 0x0C);
+        mbc.write_ram(0xA000, 0x40);
+        mbc.step(crate::CPU_FREQ * 2);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc, 0x08), 0);
+    }
+
+    #[test]
+    fn rtc_day_carry() {
+        let mut registers = RtcRegisters {
+            seconds: 59,
+            minutes: 59,
+            hours: 23,
+            days: 511,
+            ..Default::default()
+        };
+        registers.tick_second();
+        assert_eq!(registers.read(0x0C), 0b1011_1110);
+        assert_eq!(registers.days, 0);
+
+        // Invalid seconds wrap at the register size without a carry.
+        registers.seconds = 63;
+        registers.tick_second();
+        assert_eq!((registers.seconds, registers.minutes), (0, 0));
+
+        registers.advance(2 * 86400 + 3661);
+        assert_eq!(
+            (
+                registers.days,
+                registers.hours,
+                registers.minutes,
+                registers.seconds
+            ),
+            (2, 1, 1, 1)
+        );
+        registers.day_carry = false;
+        registers.advance(510 * 86400);
+        assert_eq!((registers.days, registers.day_carry), (0, true));
+    }
+
+    #[test]
+    fn banking_and_sav() {
+        let mut rom = vec![0; 0x8000 * 4];
+        rom[CARTRIDGE_TYPE_ADDR] = 0x10;
+        rom[ROM_SIZE_ADDR] = 0x2;
+        rom[RAM_SIZE_ADDR] = 0x3;
+        rom[0x4000 * 5] = 0x42;
+        let mut mbc = MBC3::new(rom, true);
+        mbc.write_rom(0x2000, 0x85);
+        assert_eq!(mbc.read_rom(0x4000), 0x42);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.rom_bank(0x4000), 1);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x03);
+        mbc.write_ram(0xA000, 0x24);
+        mbc.write_rom(0x4000, 0x09);
+        mbc.write_ram(0xA000, 30);
+
+        let dump = mbc.ram_dump();
+        assert_eq!(dump.len(), 32 * 1024 + RTC_SAVE_SIZE);
+        let mut loaded = mbc3_with_rtc();
+        loaded.ram_load(&dump);
+        loaded.write_rom(0x4000, 0x03);
+        assert_eq!(loaded.read_ram(0xA000), 0x24);
+        latch(&mut loaded);
+        assert_eq!(read_rtc(&mut loaded, 0x09), 30);
+    }
+}
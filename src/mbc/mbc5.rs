@@ -0,0 +1,183 @@
+use std::cell::{Cell, RefCell};
+
+use super::{MbcState, CARTRIDGE_TYPE_ADDR, RAM_SIZE_ADDR, ROM_SIZE_ADDR};
+
+// https://gbdev.io/pandocs/MBC5.html
+pub struct MBC5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_banks: usize,
+    /// 9 bits, bank 0 can be mapped at 0x4000-0x7FFF too.
+    current_rom_bank: usize,
+    current_ram_bank: usize,
+    ram_enabled: bool,
+    /// Bit 3 of the RAM bank register drives the motor instead of selecting a bank.
+    rumble: bool,
+    battery: bool,
+    /// RAM was written since it was last saved.
+    ram_dirty: bool,
+    /// A RAM access didn't fit the RAM size from the header, reported only once.
+    reported_ram_mismatch: Cell<bool>,
+    /// Reported through `&self` on reads, so it needs interior mutability.
+    warnings: RefCell<Vec<String>>,
+}
+
+impl MBC5 {
+    pub fn new(data: Vec<u8>, battery: bool) -> Self {
+        let (rom_banks, rom_size) = super::rom_info_reg(data[ROM_SIZE_ADDR]);
+        let (_, ram_size) = super::ram_info_reg(data[RAM_SIZE_ADDR]);
+        assert!(
+            data.len() <= rom_size,
+            "ROM size detected 0x{rom_size:X}, but cartridge size 0x{:X}.",
+            data.len()
+        );
+        let rumble = matches!(data[CARTRIDGE_TYPE_ADDR], 0x1C..=0x1E);
+
+        Self {
+            rom: data,
+            ram: vec![0; ram_size],
+            rom_banks,
+            current_rom_bank: 1,
+            current_ram_bank: 0,
+            ram_enabled: false,
+            rumble,
+            battery,
+            ram_dirty: false,
+            reported_ram_mismatch: Cell::new(false),
+            warnings: RefCell::default(),
+        }
+    }
+
+    /// Offset in RAM of `addr` (0xA000-0xBFFF) in the selected bank, wrapped around the RAM size.
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        let offset = (self.current_ram_bank * 0x2000) | (addr as usize & 0x1FFF);
+        if offset < self.ram.len() {
+            return Some(offset);
+        }
+
+        if !self.reported_ram_mismatch.replace(true) {
+            self.warnings.borrow_mut().push(format!(
+                "Cartridge RAM access at 0x{addr:X} (bank {}) is past the RAM size 0x{:X} from \
+                the header: wrapping it around.",
+                self.current_ram_bank,
+                self.ram.len()
+            ));
+        }
+        (!self.ram.is_empty()).then(|| offset & (self.ram.len() - 1))
+    }
+
+    fn ram_bank_mask(&self) -> usize {
+        if self.rumble {
+            0b0111
+        } else {
+            0b1111
+        }
+    }
+}
+
+impl super::MBC for MBC5 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        let addr = (self.rom_bank(addr) * 0x4000) | (addr as usize & 0x3FFF);
+        *self.rom.get(addr).unwrap_or(&0xFF)
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        if addr <= 0x1FFF {
+            // Unlike other MBCs, only exactly 0x0A enables RAM.
+            self.ram_enabled = val == 0x0A;
+        } else if addr <= 0x2FFF {
+            self.current_rom_bank = (self.current_rom_bank & 0x100) | val as usize;
+        } else if addr <= 0x3FFF {
+            self.current_rom_bank = (self.current_rom_bank & 0xFF) | ((val as usize & 1) << 8);
+        } else if addr <= 0x5FFF {
+            self.current_ram_bank = val as usize & self.ram_bank_mask();
+        }
+    }
+
+    fn selected_rom_bank(&self, addr: u16, val: u8) -> Option<usize> {
+        match addr {
+            0x2000..=0x2FFF => Some((self.current_rom_bank & 0x100) | val as usize),
+            0x3000..=0x3FFF => Some((self.current_rom_bank & 0xFF) | ((val as usize & 1) << 8)),
+            _ => None,
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        self.ram_offset(addr)
+            .map_or(0xFF, |offset| self.ram[offset])
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        if let Some(offset) = self.ram_offset(addr) {
+            self.ram_dirty |= self.ram[offset] != val;
+            self.ram[offset] = val;
+        }
+    }
+
+    fn reset(&mut self, clear_ram: bool) {
+        self.current_rom_bank = 1;
+        self.current_ram_bank = 0;
+        self.ram_enabled = false;
+        if clear_ram {
+            self.ram.fill(0);
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        if addr <= 0x3FFF {
+            0
+        } else {
+            // Banks past the ROM size mirror it.
+            self.current_rom_bank % self.rom_banks
+        }
+    }
+
+    fn rom_size(&self) -> usize {
+        self.rom.len()
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn ram_dump(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        self.warnings.take()
+    }
+
+    fn take_ram_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.ram_dirty)
+    }
+
+    fn state(&self) -> MbcState {
+        MbcState {
+            rom_bank: self.current_rom_bank,
+            ram_bank: self.current_ram_bank,
+            ram_enabled: self.ram_enabled,
+            advanced_mode: false,
+        }
+    }
+
+    fn set_state(&mut self, state: MbcState) {
+        self.current_rom_bank = state.rom_bank & 0x1FF;
+        self.current_ram_bank = state.ram_bank & self.ram_bank_mask();
+        self.ram_enabled = state.ram_enabled;
+    }
+}
@@ -1,7 +1,17 @@
+use crate::{
+    error::GbError,
+    patch::crc32,
+    savestate::{SaveState, StateVisitor},
+};
+
+mod diagnostics;
 mod mbc0;
 mod mbc1;
-// mod mbc2;
-// mod mbc5;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+
+pub use diagnostics::{BankDiagnostics, BankingIssue};
 
 pub const KB: usize = 1024;
 #[allow(dead_code)]
@@ -10,25 +20,170 @@ pub const MB: usize = 1024 * KB;
 pub const CARTRIDGE_TYPE_ADDR: usize = 0x147;
 pub const ROM_SIZE_ADDR: usize = 0x148;
 pub const RAM_SIZE_ADDR: usize = 0x149;
+pub const HEADER_CHECKSUM_ADDR: usize = 0x14D;
+/// Size of the boot area and the cartridge header.
+pub const HEADER_SIZE: usize = 0x150;
+
+/// Banking registers of a cartridge, for debugging.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MbcState {
+    /// Bank mapped at 0x4000-0x7FFF.
+    pub rom_bank: usize,
+    /// Selected RAM bank, used only in advanced banking mode.
+    pub ram_bank: usize,
+    pub ram_enabled: bool,
+    /// MBC1 banking mode select: ROM bank 0 and RAM are banked too.
+    pub advanced_mode: bool,
+}
 
 pub trait MBC: Send {
     fn read_rom(&self, addr: u16) -> u8;
     fn write_rom(&mut self, addr: u16, val: u8);
+    /// ROM bank that writing `val` to `addr` selects, before it wraps to the size of the ROM.
+    fn selected_rom_bank(&self, _addr: u16, _val: u8) -> Option<usize> {
+        None
+    }
 
     fn read_ram(&self, addr: u16) -> u8;
     fn write_ram(&mut self, addr: u16, val: u8);
+
+    /// Advances hardware that runs on its own, like the MBC3 clock.
+    fn step(&mut self, _cycles: u64) {}
+
+    /// Resets banking registers to their power-on values.
+    fn reset(&mut self, clear_ram: bool);
+
+    /// ROM bank currently mapped at `addr` (0x0000-0x7FFF).
+    fn rom_bank(&self, addr: u16) -> usize;
+    fn rom_size(&self) -> usize;
+    fn rom(&self) -> &[u8];
+    /// The whole cartridge RAM, all banks.
+    fn ram_mut(&mut self) -> &mut [u8];
+    /// Contents of a `.sav` file: the whole cartridge RAM and the state of the clock, if any.
+    fn ram_dump(&self) -> Vec<u8>;
+    /// Restores RAM from [`MBC::ram_dump`]. A dump of another size fills RAM from the start.
+    ///
+    /// # Returns
+    ///
+    /// A warning for the user if the dump has another size.
+    fn ram_load(&mut self, data: &[u8]) -> Option<String> {
+        load_ram(self.ram_mut(), data)
+    }
+    /// RAM is kept by a battery when the console is off, so it should be saved to a file.
+    fn has_battery(&self) -> bool;
+    /// Whether the game changed RAM (or the clock) since the last call, so the file is outdated.
+    fn take_ram_dirty(&mut self) -> bool;
+    /// Warnings for the user about a cartridge that doesn't match its header, since the last call.
+    fn take_warnings(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn state(&self) -> MbcState;
+    /// Overrides the banking registers, as if the game wrote them. Values the cartridge can't
+    /// hold are wrapped the same way.
+    fn set_state(&mut self, state: MbcState);
+    /// Saves or loads state beyond the banking registers and RAM, e.g. the clock.
+    fn visit_extra_state(&mut self, _state: &mut StateVisitor) {}
+}
+
+impl SaveState for dyn MBC {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        let rom_crc = crc32(self.rom());
+        let mut saved_crc = rom_crc;
+        state.u32(&mut saved_crc);
+        state.check(saved_crc == rom_crc, || {
+            "it was made with another game".to_string()
+        });
+
+        let mut banking = self.state();
+        state.usize(&mut banking.rom_bank);
+        state.usize(&mut banking.ram_bank);
+        state.bool(&mut banking.ram_enabled);
+        state.bool(&mut banking.advanced_mode);
+        if state.is_loading() {
+            self.set_state(banking);
+        }
+        state.bytes(self.ram_mut());
+        self.visit_extra_state(state);
+    }
 }
 
-pub fn init(cartridge: Vec<u8>) -> Box<dyn MBC> {
-    assert!(cartridge.len() >= RAM_SIZE_ADDR);
+pub fn init(cartridge: Vec<u8>) -> Result<Box<dyn MBC>, GbError> {
+    validate(&cartridge)?;
 
-    match cartridge[CARTRIDGE_TYPE_ADDR] {
+    let battery = has_battery(cartridge[CARTRIDGE_TYPE_ADDR]);
+    Ok(match cartridge[CARTRIDGE_TYPE_ADDR] {
         0x00 => Box::new(mbc0::MBC0::new(cartridge)),
-        0x01..=0x03 => Box::new(mbc1::MBC1::new(cartridge)),
-        // 0x05..=0x06 => Box::new(mbc2::MBC2::new(cartridge)),
-        // 0x19..=0x1E => Box::new(mbc5::MBC5::new(cartridge)),
-        code => unimplemented!("Cartridge type with code 0x{:X} is not supported.", code),
+        0x01..=0x03 => Box::new(mbc1::MBC1::new(cartridge, battery)),
+        0x05..=0x06 => Box::new(mbc2::MBC2::new(cartridge, battery)),
+        0x0F..=0x13 => Box::new(mbc3::MBC3::new(cartridge, battery)),
+        0x19..=0x1E => Box::new(mbc5::MBC5::new(cartridge, battery)),
+        _ => unreachable!(),
+    })
+}
+
+fn load_ram(ram: &mut [u8], data: &[u8]) -> Option<String> {
+    let len = ram.len().min(data.len());
+    ram[..len].copy_from_slice(&data[..len]);
+    (ram.len() != data.len()).then(|| {
+        format!(
+            "Saved cartridge RAM has 0x{:X} bytes, but the cartridge has 0x{:X}.",
+            data.len(),
+            ram.len()
+        )
+    })
+}
+
+/// https://gbdev.io/pandocs/The_Cartridge_Header.html#0147--cartridge-type
+pub fn has_battery(cartridge_type: u8) -> bool {
+    matches!(
+        cartridge_type,
+        0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+    )
+}
+
+/// Checks that the cartridge header is complete and describes a cartridge that can be emulated.
+/// ROMs shorter than the size in the header are allowed: the MBCs pad missing banks.
+pub fn validate(cartridge: &[u8]) -> Result<(), GbError> {
+    let invalid = |reason: String| Err(GbError::InvalidRom(reason));
+
+    if cartridge.len() < HEADER_SIZE {
+        return invalid(format!(
+            "{} bytes is too short for the cartridge header (0x{HEADER_SIZE:X} bytes)",
+            cartridge.len()
+        ));
+    }
+
+    let cartridge_type = cartridge[CARTRIDGE_TYPE_ADDR];
+    if !matches!(cartridge_type, 0x00..=0x03 | 0x05..=0x06 | 0x0F..=0x13 | 0x19..=0x1E) {
+        return invalid(format!(
+            "cartridge type 0x{cartridge_type:02X} is not supported"
+        ));
     }
+
+    let rom_size_code = cartridge[ROM_SIZE_ADDR];
+    if rom_size_code > 0x8 {
+        return invalid(format!(
+            "ROM size code 0x{rom_size_code:02X} does not exist"
+        ));
+    }
+    let (_, rom_size) = rom_info_reg(rom_size_code);
+    if cartridge.len() > rom_size {
+        return invalid(format!(
+            "the header declares 0x{rom_size:X} bytes, but the file has 0x{:X}",
+            cartridge.len()
+        ));
+    }
+
+    // MBC0 ignores the RAM size, MBC2 has its own RAM.
+    let ram_size_code = cartridge[RAM_SIZE_ADDR];
+    if !matches!(cartridge_type, 0x00 | 0x05 | 0x06) && ram_size_code > 0x5 {
+        return invalid(format!(
+            "RAM size code 0x{ram_size_code:02X} is not supported"
+        ));
+    }
+
+    Ok(())
 }
 
 /// # Returns
@@ -41,11 +196,12 @@ pub fn rom_info_reg(value: u8) -> (usize, usize) {
 
 /// # Returns
 ///
-/// Number of ROM banks and ROM size.
+/// Number of RAM banks and RAM size.
 pub fn ram_info_reg(value: u8) -> (usize, usize) {
     match value {
         0x0 => (0, 0),
-        0x1 => unimplemented!("https://gbdev.io/pandocs/The_Cartridge_Header.html#2kib_sram"),
+        // https://gbdev.io/pandocs/The_Cartridge_Header.html#2kib_sram
+        0x1 => (1, 2 * KB),
         0x2 => (1, 8 * KB),
         0x3 => (4, 32 * KB),
         0x4 => (16, 128 * KB),
@@ -53,3 +209,189 @@ pub fn ram_info_reg(value: u8) -> (usize, usize) {
         _ => panic!("Cartridge RAM size value 0x{value:X} does not exist."),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn header_only(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+        let mut rom = vec![0; HEADER_SIZE];
+        rom[CARTRIDGE_TYPE_ADDR] = cartridge_type;
+        rom[ROM_SIZE_ADDR] = rom_size;
+        rom[RAM_SIZE_ADDR] = ram_size;
+        rom
+    }
+
+    #[test]
+    fn invalid_cartridges() {
+        for rom in [
+            vec![],
+            vec![0],
+            vec![0; HEADER_SIZE - 1],
+            header_only(0x04, 0, 0),
+            header_only(0x01, 0x9, 0),
+            header_only(0x01, 0, 0x6),
+            vec![0; 64 * KB],
+        ] {
+            assert!(
+                matches!(init(rom.clone()), Err(GbError::InvalidRom(_))),
+                "{} bytes",
+                rom.len()
+            );
+        }
+    }
+
+    #[test]
+    fn header_only_cartridges() {
+        let mbc0 = init(header_only(0x00, 0, 0x1)).unwrap();
+        assert_eq!(mbc0.read_rom(0x7FFF), 0);
+
+        let mbc1 = init(header_only(0x03, 0x1, 0x2)).unwrap();
+        assert_eq!(mbc1.read_rom(0x7FFF), 0xFF);
+    }
+
+    #[test]
+    fn mbc1_ram_size_mismatch() {
+        // 2 KiB RAM is mirrored over the whole RAM area.
+        let mut mbc = init(header_only(0x03, 0, 0x1)).unwrap();
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA001, 0x42);
+        assert_eq!(mbc.read_ram(0xA801), 0x42);
+        mbc.write_ram(0xBFFF, 0x24);
+        assert_eq!(mbc.read_ram(0xA7FF), 0x24);
+
+        // No RAM at all.
+        let mut mbc = init(header_only(0x01, 0, 0)).unwrap();
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        // The cartridge type has RAM, but the header doesn't declare its size.
+        let mut mbc = init(header_only(0x03, 0, 0)).unwrap();
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xBFFF, 0x42);
+        assert_eq!(mbc.read_ram(0xBFFF), 0x42);
+        assert_eq!(mbc.take_warnings().len(), 1);
+    }
+
+    #[test]
+    fn battery_ram() {
+        assert!(!init(header_only(0x02, 0, 0x2)).unwrap().has_battery());
+
+        let mut mbc = init(header_only(0x03, 0, 0x2)).unwrap();
+        assert!(mbc.has_battery());
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x42);
+        let dump = mbc.ram_dump();
+        assert_eq!(dump.len(), 8 * KB);
+
+        let mut mbc = init(header_only(0x03, 0, 0x2)).unwrap();
+        mbc.write_rom(0x0000, 0x0A);
+        assert_eq!(mbc.ram_load(&dump), None);
+        assert_eq!(mbc.read_ram(0xA000), 0x42);
+        // A short dump keeps the rest.
+        mbc.write_ram(0xA001, 0x24);
+        assert!(mbc.ram_load(&[0x11]).is_some());
+        assert_eq!((mbc.read_ram(0xA000), mbc.read_ram(0xA001)), (0x11, 0x24));
+    }
+
+    #[test]
+    fn ram_dirty() {
+        for cartridge_type in [0x03, 0x06, 0x13, 0x1B] {
+            let mut mbc = init(header_only(cartridge_type, 0, 0x2)).unwrap();
+            // Disabled RAM ignores writes.
+            mbc.write_ram(0xA000, 0x0A);
+            assert!(!mbc.take_ram_dirty(), "{cartridge_type:#X}");
+
+            mbc.write_rom(0x0000, 0x0A);
+            mbc.write_ram(0xA000, 0x0A);
+            assert!(mbc.take_ram_dirty(), "{cartridge_type:#X}");
+            assert!(!mbc.take_ram_dirty(), "{cartridge_type:#X}");
+            // Writing the same value changes nothing.
+            mbc.write_ram(0xA000, 0x0A);
+            assert!(!mbc.take_ram_dirty(), "{cartridge_type:#X}");
+        }
+    }
+
+    #[test]
+    fn mbc2_addressing() {
+        let mut rom = header_only(0x06, 0x3, 0);
+        rom.resize(256 * KB, 0);
+        rom[0x4000 * 0xF] = 0x42;
+        let mut mbc = init(rom).unwrap();
+        // Bit 8 of the address is set: ROM bank.
+        mbc.write_rom(0x2100, 0x0F);
+        assert_eq!(mbc.read_rom(0x4000), 0x42);
+        // Bit 8 is clear: RAM enable, even in the ROM bank range.
+        mbc.write_rom(0x2000, 0x0A);
+        assert_eq!(mbc.state().rom_bank, 0xF);
+        assert!(mbc.state().ram_enabled);
+
+        // Half-bytes echoed over the whole RAM area.
+        mbc.write_ram(0xA000, 0x5C);
+        assert_eq!(mbc.read_ram(0xA000), 0xFC);
+        assert_eq!(mbc.read_ram(0xBE00), 0xFC);
+        assert_eq!(mbc.ram_dump().len(), 512);
+        assert!(mbc.has_battery());
+    }
+
+    #[test]
+    fn mbc5_banking() {
+        // 8 MiB, 128 KiB RAM.
+        let mut rom = header_only(0x1B, 0x8, 0x4);
+        rom.resize(8 * MB, 0);
+        rom[0x4000 * 0x1A5] = 0x42;
+        let mut mbc = init(rom).unwrap();
+        mbc.write_rom(0x2000, 0xA5);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(mbc.read_rom(0x4000), 0x42);
+        // Unlike MBC1, bank 0 can be mapped.
+        mbc.write_rom(0x2000, 0x00);
+        mbc.write_rom(0x3000, 0x00);
+        assert_eq!(mbc.rom_bank(0x4000), 0);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0F);
+        mbc.write_ram(0xA000, 0x24);
+        assert_eq!(mbc.ram_dump()[0xF * 0x2000], 0x24);
+
+        // The rumble motor bit doesn't select a bank.
+        let mut mbc = init(header_only(0x1E, 0, 0x4)).unwrap();
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0B);
+        mbc.write_ram(0xA000, 0x24);
+        assert_eq!(mbc.state().ram_bank, 3);
+        assert_eq!(mbc.ram_dump()[3 * 0x2000], 0x24);
+    }
+
+    #[test]
+    fn mbc1_state() {
+        let mut mbc = init(header_only(0x03, 0x6, 0x3)).unwrap();
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x2000, 0x05);
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_rom(0x6000, 0x01);
+        let state = mbc.state();
+        assert_eq!(
+            state,
+            MbcState {
+                rom_bank: 0x45,
+                ram_bank: 2,
+                ram_enabled: true,
+                advanced_mode: true,
+            }
+        );
+
+        mbc.reset(false);
+        assert_ne!(mbc.state(), state);
+        mbc.set_state(state);
+        assert_eq!(mbc.state(), state);
+        assert_eq!(mbc.rom_bank(0x0000), 0x40);
+
+        mbc.set_state(MbcState {
+            rom_bank: 0x20,
+            ..state
+        });
+        assert_eq!(mbc.state().rom_bank, 0x21);
+    }
+}
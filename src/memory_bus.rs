@@ -1,12 +1,24 @@
 // https://gbdev.io/pandocs/Memory_Map.html
 
+use std::ops::ControlFlow;
+
 use crate::{
     audio_player::AudioPlayer,
     bit,
-    gpu::GPU,
+    cheat::Cheat,
+    error::GbError,
+    flight_recorder::{Access, FlightRecorder, Origin},
+    gpu::{PpuMode, GPU},
+    heatmap::MemoryHeatmap,
     joypad::{Joypad, JoypadKey},
-    mbc::MBC,
+    mbc::{BankDiagnostics, BankingIssue, MbcState, MBC, ROM_SIZE_ADDR},
+    model::Model,
+    rng_script::RngScript,
+    savestate::{SaveState, StateVisitor},
+    serial::{Serial, SerialDevice},
+    sgb::Sgb,
     sound::Sound,
+    timer::Timer,
 };
 
 pub const ROM_BANK_0_START: u16 = 0x0000;
@@ -15,7 +27,6 @@ pub const ROM_BANK_0_END: u16 = 0x3FFF;
 #[allow(dead_code)]
 pub const ROM_BANK_0_SIZE: usize = (ROM_BANK_0_END - ROM_BANK_0_START + 1) as usize;
 
-#[allow(dead_code)]
 pub const ROM_BANK_N_START: u16 = 0x4000;
 pub const ROM_BANK_N_END: u16 = 0x7FFF;
 #[allow(dead_code)]
@@ -51,7 +62,6 @@ pub const UNUSED_SIZE: usize = (UNUSED_END - UNUSED_START + 1) as usize;
 
 pub const IO_REGISTERS_START: u16 = 0xFF00;
 pub const IO_REGISTERS_END: u16 = 0xFF7F;
-#[allow(dead_code)]
 pub const IO_REGISTERS_SIZE: usize = (IO_REGISTERS_END - IO_REGISTERS_START + 1) as usize;
 
 pub const HIGH_RAM_AREA_START: u16 = 0xFF80;
@@ -60,8 +70,69 @@ pub const HIGH_RAM_AREA_SIZE: usize = (HIGH_RAM_AREA_END - HIGH_RAM_AREA_START +
 
 pub const INTERRUPT_ENABLED_REGISTER: u16 = 0xFFFF;
 
+/// The DMG boot ROM is mapped over 0x0000-0x00FF.
+/// https://gbdev.io/pandocs/Power_Up_Sequence.html
+pub const BOOT_ROM_SIZE: usize = 0x100;
+/// Writing a non-zero value unmaps the boot ROM until the next reset.
+const BOOT_ROM_DISABLE_REGISTER: u16 = 0xFF50;
+
+/// Bits of each IO register that always read as 1 on DMG: unused bits, write-only bits and
+/// unmapped registers.
+// https://gbdev.io/pandocs/Hardware_Reg_List.html
+#[rustfmt::skip]
+const IO_UNUSED_BITS: [u8; IO_REGISTERS_SIZE] = [
+    // FF00: P1, SB, SC, -, DIV, TIMA, TMA, TAC, -, ..., IF
+    0xC0, 0x00, 0x7E, 0xFF, 0x00, 0x00, 0x00, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xE0,
+    // FF10: NR10-NR14, -, NR21-NR24, NR30-NR34, -
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+    // FF20: NR41-NR44, NR50-NR52, -
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    // FF30: wave RAM
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // FF40: LCDC, STAT, SCY, SCX, LY, LYC, DMA, BGP, OBP0, OBP1, WY, WX, -
+    0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF,
+    // FF50: BOOT, CGB registers
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+/// A write to an IO register, with the values read from the register before and after it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IoWrite {
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+pub type IoWriteCallback = Box<dyn FnMut(IoWrite) -> ControlFlow<()> + Send>;
+
+/// Custom hardware attached to the memory bus, e.g. a cartridge peripheral, a debugger or a fuzzer.
+/// Reads and writes of the addresses it maps go to it instead of the built-in hardware, including
+/// OAM DMA and debugger reads.
+///
+/// Devices overlay addresses, they don't replace the bus: timers, the PPU and the interrupt
+/// registers keep running inside [`MemoryBus`]. Mapping IE or IF changes what the game reads, not
+/// which interrupts the CPU takes.
+pub trait Device: Send {
+    fn maps(&self, addr: u16) -> bool;
+
+    /// Reads can't change the device, except through interior mutability: debuggers read without
+    /// side effects.
+    fn read_byte(&self, addr: u16) -> u8;
+
+    fn write_byte(&mut self, addr: u16, val: u8);
+
+    /// Called after every instruction and interrupt dispatch with the T-cycles it took.
+    fn step(&mut self, _cycles: u64) {}
+}
+
 pub struct MemoryBus {
     mbc: Box<dyn MBC>,
+    /// Run on power-on and after every reset instead of starting the cartridge at 0x100.
+    boot_rom: Option<Box<[u8; BOOT_ROM_SIZE]>>,
+    /// The boot ROM hides the start of the cartridge ROM until it's unmapped.
+    boot_rom_mapped: bool,
     /// Working RAM.
     wram: [u8; WORKING_RAM_SIZE],
 
@@ -72,31 +143,55 @@ pub struct MemoryBus {
     interrupt_enable: InterruptFlags,
     interrupt_flag: InterruptFlags,
     joypad: Joypad,
-    divider: Timer,
+    serial: Serial,
     timer: Timer,
+    /// Packets sent through the joypad register, on the SGB model.
+    sgb: Option<Sgb>,
 
     /// Hight RAM.
     hram: [u8; HIGH_RAM_AREA_SIZE],
-}
 
-#[derive(Copy, Clone, Default)]
-pub enum TimerRateHz {
-    #[default]
-    F4096,
-    F262144,
-    F65536,
-    F16384,
+    pub heatmap: Option<MemoryHeatmap>,
+    pub flight_recorder: Option<FlightRecorder>,
+    pub bank_diagnostics: Option<BankDiagnostics>,
+    /// PC of the instruction accessing the bus, set by the CPU to tag its transactions.
+    pub instruction_pc: u16,
+    /// Replaces DIV and LY reads with scripted values.
+    pub rng_script: Option<RngScript>,
+    /// Game Genie codes patching ROM reads.
+    pub rom_cheats: Vec<Cheat>,
+    /// Custom hardware, the first one mapping an address answers.
+    devices: Vec<Box<dyn Device>>,
+
+    /// One bit per IO register, so each ignored write is reported only once.
+    reported_ignored_writes: u128,
+    /// Warnings for the user not taken yet, see [`MemoryBus::take_warnings`].
+    warnings: Vec<String>,
+    /// Writes to IO registers since power-on, a sign of life for the watchdog.
+    io_writes: u64,
+    /// T-cycles emulated since power-on, the timestamp of frames.
+    cycles: u64,
+    /// Callbacks subscribed to writes of an IO register.
+    io_write_hooks: Vec<(u16, IoWriteCallback)>,
+    /// One of the IO write callbacks asked to stop emulation.
+    hook_stop_requested: bool,
+
+    /// OAM DMA transfer in progress.
+    dma: Option<OamDma>,
+    /// While DMA is active, the CPU can't access memory outside HRAM and IO registers: reads
+    /// return the byte DMA is transferring and writes are ignored.
+    pub dma_conflicts: bool,
 }
 
 #[derive(Copy, Clone, Default)]
-pub struct Timer {
-    freq: TimerRateHz,
+struct OamDma {
+    source: u16,
     cycles: u64,
-    pub val: u8,
-    /// When TIMA overflows, it is reset to the value in this register and an
-    /// interrupt is requested.
-    pub modulo: u8,
-    pub enable: bool,
+}
+
+impl OamDma {
+    /// 160 M-cycles.
+    const DURATION: u64 = 640;
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -111,28 +206,130 @@ pub struct InterruptFlags {
 }
 
 impl MemoryBus {
-    pub fn new(game_rom: Vec<u8>, player: Box<dyn AudioPlayer>) -> Self {
+    /// # Panics
+    ///
+    /// If the ROM can't be emulated, see [`MemoryBus::try_with_model`].
+    pub fn with_model(game_rom: Vec<u8>, player: Box<dyn AudioPlayer>, model: Model) -> Self {
+        Self::try_with_model(game_rom, player, model).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Fails with [`GbError::InvalidRom`] if the cartridge header is truncated or describes a
+    /// cartridge that isn't emulated.
+    pub fn try_with_model(
+        game_rom: Vec<u8>,
+        player: Box<dyn AudioPlayer>,
+        model: Model,
+    ) -> Result<Self, GbError> {
+        let sgb = (model == Model::Sgb).then(|| Sgb::new(&game_rom));
         let mut bus = Self {
-            mbc: crate::mbc::init(game_rom),
+            mbc: crate::mbc::init(game_rom)?,
+            boot_rom: None,
+            boot_rom_mapped: false,
             wram: [0; WORKING_RAM_SIZE],
 
             gpu: GPU::new(),
-            sound: Sound::new(player),
+            sound: Sound::with_model(player, model),
 
             joypad: Joypad::new(),
-            divider: Timer::new_enabled(TimerRateHz::F16384),
-            timer: Timer::new_disabled(TimerRateHz::F4096),
+            serial: Serial::new(),
+            timer: Timer::new(),
+            sgb,
             interrupt_enable: InterruptFlags::new(),
             interrupt_flag: InterruptFlags::new(),
 
             hram: [0; HIGH_RAM_AREA_SIZE],
-        };
 
-        bus.divider.enable = true;
+            heatmap: None,
+            flight_recorder: None,
+            bank_diagnostics: None,
+            instruction_pc: 0,
+            rng_script: None,
+            rom_cheats: vec![],
+            devices: vec![],
+
+            reported_ignored_writes: 0,
+            warnings: vec![],
+            io_writes: 0,
+            cycles: 0,
+            io_write_hooks: vec![],
+            hook_stop_requested: false,
+
+            dma: None,
+            dma_conflicts: false,
+        };
 
         bus.set_init_values();
 
-        bus
+        Ok(bus)
+    }
+
+    /// Puts everything except the cartridge ROM into the power-on state. Cartridge RAM is kept
+    /// unless `clear_cartridge_ram` is set.
+    pub fn reset(&mut self, clear_cartridge_ram: bool) {
+        self.mbc.reset(clear_cartridge_ram);
+        self.wram = [0; WORKING_RAM_SIZE];
+
+        let (visible_layers, sprites_per_line, fast_renderer, palette) = (
+            self.gpu.visible_layers,
+            self.gpu.sprites_per_line,
+            self.gpu.fast_renderer,
+            self.gpu.palette,
+        );
+        self.gpu = GPU::new();
+        self.gpu.visible_layers = visible_layers;
+        self.gpu.sprites_per_line = sprites_per_line;
+        self.gpu.fast_renderer = fast_renderer;
+        self.gpu.palette = palette;
+        self.sound.reset();
+
+        self.joypad = Joypad::new();
+        let device = self.serial.device.take();
+        self.serial = Serial::new();
+        self.serial.device = device;
+        self.timer = Timer::new();
+        if self.sgb.is_some() {
+            self.sgb = Some(Sgb::new(self.mbc.rom()));
+        }
+        self.interrupt_enable = InterruptFlags::new();
+        self.interrupt_flag = InterruptFlags::new();
+
+        self.hram = [0; HIGH_RAM_AREA_SIZE];
+        self.dma = None;
+        self.io_writes = 0;
+        self.cycles = 0;
+
+        if let Some(script) = &self.rng_script {
+            script.rewind();
+        }
+
+        // The boot ROM sets up the hardware itself.
+        self.boot_rom_mapped = self.boot_rom.is_some();
+        if !self.boot_rom_mapped {
+            self.set_init_values();
+        }
+    }
+
+    /// Takes effect on the next reset.
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<(), GbError> {
+        let boot_rom = boot_rom
+            .into_boxed_slice()
+            .try_into()
+            .map_err(|rom: Box<[u8]>| {
+                GbError::InvalidRom(format!(
+                    "a DMG boot ROM is {BOOT_ROM_SIZE} bytes, not {}",
+                    rom.len()
+                ))
+            })?;
+        self.boot_rom = Some(boot_rom);
+        Ok(())
+    }
+
+    pub fn set_serial_device(&mut self, device: Option<Box<dyn SerialDevice>>) {
+        self.serial.device = device;
+    }
+
+    pub fn is_boot_rom_mapped(&self) -> bool {
+        self.boot_rom_mapped
     }
 
     fn set_init_values(&mut self) {
@@ -182,7 +379,8 @@ impl MemoryBus {
     }
 
     pub fn step(&mut self, cycles: u64) -> u64 {
-        self.divider.step(cycles);
+        self.cycles += cycles;
+        self.mbc.step(cycles);
 
         if self.timer.step(cycles) {
             self.interrupt_flag.timer = true;
@@ -194,9 +392,44 @@ impl MemoryBus {
 
         self.sound.cycle(cycles);
 
+        if let Some(recorder) = &self.flight_recorder {
+            recorder.advance(cycles);
+        }
+
+        if self.serial.step(cycles) {
+            self.interrupt_flag.serial = true;
+        }
+
+        self.step_dma(cycles);
+
+        for device in &mut self.devices {
+            device.step(cycles);
+        }
+
         cycles
     }
 
+    /// Cycles until the next timer overflow, PPU mode change, APU event, serial transfer or end
+    /// of DMA. Stepping less than that only advances counters, so a halted CPU can skip it at once.
+    pub fn cycles_until_event(&self) -> Option<u64> {
+        [
+            self.timer.cycles_until_interrupt(),
+            self.gpu.cycles_until_mode_change(),
+            self.sound.cycles_until_event(),
+            self.serial.cycles_until_interrupt(),
+            self.dma
+                .map(|dma| OamDma::DURATION.saturating_sub(dma.cycles)),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// IF register, without the unused bits.
+    pub fn interrupt_flags(&self) -> u8 {
+        u8::from(self.interrupt_flag) & 0x1F
+    }
+
     pub fn pending_interrupt(&self) -> bool {
         u8::from(self.interrupt_enable) & u8::from(self.interrupt_flag) != 0
     }
@@ -237,13 +470,80 @@ impl MemoryBus {
     }
 
     pub fn read_byte(&self, addr: u16) -> u8 {
+        if let Some(heatmap) = &self.heatmap {
+            heatmap.record_read(addr);
+        }
+
+        let val = match self.dma {
+            Some(dma) if self.dma_conflicts && addr < IO_REGISTERS_START => {
+                let idx = (dma.cycles / 4).min(OAM_SIZE as u64 - 1) as u16;
+                self.read_mapped(dma.source + idx)
+            }
+            _ => self.read_mapped(addr),
+        };
+
+        if let Some(recorder) = &self.flight_recorder {
+            recorder.record(Access::Read, addr, val);
+        }
+        if (EXTERNAL_RAM_START..=EXTERNAL_RAM_END).contains(&addr) {
+            self.check_ram_enabled(addr, false);
+        }
+
+        val
+    }
+
+    /// Starts logging games selecting ROM banks beyond the ROM and accessing disabled cartridge
+    /// RAM, see [`BankDiagnostics`].
+    pub fn enable_bank_diagnostics(&mut self) {
+        let (rom_banks, _) = crate::mbc::rom_info_reg(self.mbc.rom()[ROM_SIZE_ADDR]);
+        self.bank_diagnostics = Some(BankDiagnostics::new(rom_banks));
+    }
+
+    fn check_ram_enabled(&self, addr: u16, write: bool) {
+        if let Some(diagnostics) = &self.bank_diagnostics {
+            if !self.mbc.state().ram_enabled {
+                let issue = BankingIssue::DisabledRamAccess { addr, write };
+                diagnostics.report(self.instruction_pc, issue);
+            }
+        }
+    }
+
+    /// Reads memory for debug tools: the read isn't counted in the heatmap and is attributed to the
+    /// debugger in the flight recorder.
+    pub fn debug_read(&self, addr: u16) -> u8 {
+        let val = self.read_mapped(addr);
+        if let Some(recorder) = &self.flight_recorder {
+            let prev = recorder.set_origin(Origin::Debugger);
+            recorder.record(Access::Read, addr, val);
+            recorder.set_origin(prev);
+        }
+        val
+    }
+
+    fn read_mapped(&self, addr: u16) -> u8 {
+        // Usually nothing is attached, so every access skips the search.
+        if !self.devices.is_empty() {
+            if let Some(device) = self.devices.iter().find(|device| device.maps(addr)) {
+                return device.read_byte(addr);
+            }
+        }
         match addr {
-            ROM_BANK_0_START..=ROM_BANK_N_END => self.mbc.read_rom(addr),
+            _ if self.boot_rom_mapped && (addr as usize) < BOOT_ROM_SIZE => {
+                self.boot_rom.as_ref().unwrap()[addr as usize]
+            }
+            ROM_BANK_0_START..=ROM_BANK_N_END => {
+                let val = self.mbc.read_rom(addr);
+                self.rom_cheats
+                    .iter()
+                    .fold(val, |val, cheat| cheat.patch_rom(addr, val))
+            }
             VIDEO_RAM_START..=VIDEO_RAM_END => self.gpu.vram[(addr - VIDEO_RAM_START) as usize],
             EXTERNAL_RAM_START..=EXTERNAL_RAM_END => self.mbc.read_ram(addr),
             WORKING_RAM_START..=WORKING_RAM_END => self.wram[(addr - WORKING_RAM_START) as usize],
             ECHO_RAM_START..=ECHO_RAM_END => self.wram[(addr - ECHO_RAM_START) as usize],
             OAM_START..=OAM_END => self.gpu.oam[(addr - OAM_START) as usize],
+            // DMG reads 0, or $FF while the PPU or OAM DMA block OAM.
+            UNUSED_START..=UNUSED_END if self.oam_blocked() => 0xFF,
             UNUSED_START..=UNUSED_END => 0,
             IO_REGISTERS_START..=IO_REGISTERS_END => self.read_io_register(addr),
             HIGH_RAM_AREA_START..=HIGH_RAM_AREA_END => {
@@ -253,18 +553,133 @@ impl MemoryBus {
         }
     }
 
+    fn oam_blocked(&self) -> bool {
+        self.dma.is_some()
+            || matches!(
+                self.gpu.lcd_status.ppu_mode,
+                PpuMode::OAMScan | PpuMode::DrawingPixels
+            )
+    }
+
+    /// Offset inside the cartridge ROM that is currently visible at `addr`.
+    pub fn rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            _ if self.boot_rom_mapped && (addr as usize) < BOOT_ROM_SIZE => None,
+            ROM_BANK_0_START..=ROM_BANK_N_END => {
+                Some(crate::coverage::rom_offset(self.mbc.rom_bank(addr), addr))
+            }
+            _ => None,
+        }
+    }
+
+    /// ROM bank mapped at `addr` in $0000-$7FFF.
+    pub fn rom_bank(&self, addr: u16) -> usize {
+        self.mbc.rom_bank(addr)
+    }
+
+    pub fn rom_size(&self) -> usize {
+        self.mbc.rom_size()
+    }
+
+    pub fn mbc_state(&self) -> MbcState {
+        self.mbc.state()
+    }
+
+    pub fn set_mbc_state(&mut self, state: MbcState) {
+        self.mbc.set_state(state);
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.mbc.has_battery()
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        self.mbc.rom()
+    }
+
+    pub fn sgb(&self) -> Option<&Sgb> {
+        self.sgb.as_ref()
+    }
+
+    pub fn cartridge_ram(&self) -> Vec<u8> {
+        self.mbc.ram_dump()
+    }
+
+    pub fn attach_device(&mut self, device: Box<dyn Device>) {
+        self.devices.push(device);
+    }
+
+    pub fn detach_devices(&mut self) -> Vec<Box<dyn Device>> {
+        std::mem::take(&mut self.devices)
+    }
+
+    pub fn load_cartridge_ram(&mut self, data: &[u8]) {
+        self.warnings.extend(self.mbc.ram_load(data));
+    }
+
+    /// Warnings about the game, the cartridge or the serial device since the last call, for the
+    /// frontend to show.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        let mut warnings = std::mem::take(&mut self.warnings);
+        warnings.append(&mut self.mbc.take_warnings());
+        if let Some(device) = &mut self.serial.device {
+            warnings.append(&mut device.take_warnings());
+        }
+        warnings
+    }
+
+    pub fn take_cartridge_ram_dirty(&mut self) -> bool {
+        self.mbc.take_ram_dirty()
+    }
+
+    pub fn io_writes(&self) -> u64 {
+        self.io_writes
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
     pub fn read_high_byte(&self, addr: u8) -> u8 {
         let addr = IO_REGISTERS_START | addr as u16;
         self.read_byte(addr)
     }
 
     pub fn write_byte(&mut self, addr: u16, val: u8) {
+        if let Some(heatmap) = &self.heatmap {
+            heatmap.record_write(addr);
+        }
+
+        if let Some(recorder) = &self.flight_recorder {
+            recorder.record(Access::Write, addr, val);
+        }
+
+        if self.dma.is_some() && self.dma_conflicts && addr < IO_REGISTERS_START {
+            return;
+        }
+        if !self.devices.is_empty() {
+            if let Some(device) = self.devices.iter_mut().find(|device| device.maps(addr)) {
+                device.write_byte(addr, val);
+                return;
+            }
+        }
+
         match addr {
-            ROM_BANK_0_START..=ROM_BANK_N_END => self.mbc.write_rom(addr, val),
+            ROM_BANK_0_START..=ROM_BANK_N_END => {
+                if let Some(diagnostics) = &self.bank_diagnostics {
+                    if let Some(bank) = self.mbc.selected_rom_bank(addr, val) {
+                        diagnostics.check_rom_bank(self.instruction_pc, bank);
+                    }
+                }
+                self.mbc.write_rom(addr, val)
+            }
             VIDEO_RAM_START..=VIDEO_RAM_END => {
                 self.gpu.vram[(addr - VIDEO_RAM_START) as usize] = val
             }
-            EXTERNAL_RAM_START..=EXTERNAL_RAM_END => self.mbc.write_ram(addr, val),
+            EXTERNAL_RAM_START..=EXTERNAL_RAM_END => {
+                self.check_ram_enabled(addr, true);
+                self.mbc.write_ram(addr, val)
+            }
             WORKING_RAM_START..=WORKING_RAM_END => {
                 self.wram[(addr - WORKING_RAM_START) as usize] = val
             }
@@ -273,7 +688,17 @@ impl MemoryBus {
             UNUSED_START..=UNUSED_END => {
                 // Writing here does nothing.
             }
-            IO_REGISTERS_START..=IO_REGISTERS_END => self.write_io_register(addr, val),
+            IO_REGISTERS_START..=IO_REGISTERS_END => {
+                if self
+                    .io_write_hooks
+                    .iter()
+                    .any(|(hooked, _)| *hooked == addr)
+                {
+                    self.write_hooked_io_register(addr, val);
+                } else {
+                    self.write_io_register(addr, val);
+                }
+            }
             HIGH_RAM_AREA_START..=HIGH_RAM_AREA_END => {
                 self.hram[(addr - HIGH_RAM_AREA_START) as usize] = val
             }
@@ -290,30 +715,32 @@ impl MemoryBus {
     fn read_io_register(&self, addr: u16) -> u8 {
         assert!((IO_REGISTERS_START..=IO_REGISTERS_END).contains(&addr));
 
-        match addr {
-            0xFF00 => u8::from(self.joypad),
-            0xFF01..=0xFF02 => 0xFF,
-            0xFF04 => self.divider.val,
-            0xFF05 => self.timer.val,
-            0xFF06 => self.timer.modulo,
-            0xFF07 => {
-                0b11111000
-                    | (match self.timer.freq {
-                        TimerRateHz::F4096 => 0,
-                        TimerRateHz::F262144 => 1,
-                        TimerRateHz::F65536 => 2,
-                        TimerRateHz::F16384 => 3,
-                    })
-                    | ((self.timer.enable as u8) << 2)
+        let val = match addr {
+            0xFF00 => {
+                let val = u8::from(self.joypad);
+                self.sgb.as_ref().map_or(val, |sgb| sgb.read_joypad(val))
             }
-            0xFF0F => 0b11100000 | u8::from(self.interrupt_flag),
+            0xFF01..=0xFF02 => self.serial.read_byte(addr),
+            0xFF04 => self
+                .rng_script
+                .as_ref()
+                .and_then(RngScript::next_div)
+                .unwrap_or(self.timer.div()),
+            0xFF05 => self.timer.tima(),
+            0xFF06 => self.timer.tma(),
+            0xFF07 => self.timer.tac(),
+            0xFF0F => u8::from(self.interrupt_flag),
             0xFF10..=0xFF26 => self.sound.read_byte(addr),
             0xFF30..=0xFF3F => self.sound.read_byte(addr),
             0xFF40 => u8::from(self.gpu.lcd_control),
-            0xFF41 => (1 << 7) | self.gpu.lcd_status.get_status_byte(),
+            0xFF41 => self.gpu.lcd_status.get_status_byte(),
             0xFF42 => self.gpu.viewport.y,
             0xFF43 => self.gpu.viewport.x,
-            0xFF44 => self.gpu.lcd_status.ly(),
+            0xFF44 => self
+                .rng_script
+                .as_ref()
+                .and_then(RngScript::next_ly)
+                .unwrap_or(self.gpu.lcd_status.ly()),
             0xFF45 => self.gpu.lcd_status.lyc(),
             0xFF47 => u8::from(self.gpu.bg_colors),
             0xFF48 => u8::from(self.gpu.obj0_colors),
@@ -321,28 +748,55 @@ impl MemoryBus {
             0xFF4A => self.gpu.window.y,
             0xFF4B => self.gpu.window.x,
             _ => 0xFF,
+        };
+        val | IO_UNUSED_BITS[(addr - IO_REGISTERS_START) as usize]
+    }
+
+    /// Subscribes `callback` to writes of the IO register at `addr`.
+    pub fn on_io_write(&mut self, addr: u16, callback: IoWriteCallback) {
+        assert!((IO_REGISTERS_START..=IO_REGISTERS_END).contains(&addr));
+        self.io_write_hooks.push((addr, callback));
+    }
+
+    /// Returns whether an IO write callback asked to stop emulation since the last call.
+    pub fn take_hook_stop_request(&mut self) -> bool {
+        std::mem::take(&mut self.hook_stop_requested)
+    }
+
+    fn write_hooked_io_register(&mut self, addr: u16, val: u8) {
+        // Reads of the hooks must not consume scripted values.
+        let rng_script = self.rng_script.take();
+        let old = self.read_io_register(addr);
+        self.write_io_register(addr, val);
+        let new = self.read_io_register(addr);
+        self.rng_script = rng_script;
+
+        let write = IoWrite { addr, old, new };
+        for (hooked, callback) in &mut self.io_write_hooks {
+            if *hooked == addr && callback(write).is_break() {
+                self.hook_stop_requested = true;
+            }
         }
     }
 
     fn write_io_register(&mut self, addr: u16, val: u8) {
         assert!((IO_REGISTERS_START..=IO_REGISTERS_END).contains(&addr));
+        self.io_writes += 1;
 
         match addr {
-            0xFF00 => self.joypad.set_mode(val),
-            0xFF01..=0xFF02 => {}
-            0xFF04 => self.divider.val = 0,
-            0xFF05 => self.timer.val = val,
-            0xFF06 => self.timer.modulo = val,
-            0xFF07 => {
-                self.timer.freq = match val & 0b11 {
-                    0 => TimerRateHz::F4096,
-                    1 => TimerRateHz::F262144,
-                    2 => TimerRateHz::F65536,
-                    3 => TimerRateHz::F16384,
-                    _ => unreachable!("Unknown timer frequency rate {}", val & 0b11),
-                };
-                self.timer.enable = val & (1 << 2) != 0;
+            0xFF00 => {
+                if self.joypad.set_mode(val) {
+                    self.interrupt_flag.joypad = true;
+                }
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_joypad(val, &self.gpu);
+                }
             }
+            0xFF01..=0xFF02 => self.serial.write_byte(addr, val),
+            0xFF04 => self.timer.reset_div(),
+            0xFF05 => self.timer.write_tima(val),
+            0xFF06 => self.timer.write_tma(val),
+            0xFF07 => self.timer.write_tac(val),
             0xFF0F => self.interrupt_flag = InterruptFlags::from(val),
             0xFF10..=0xFF26 => self.sound.write_byte(addr, val),
             0xFF30..=0xFF3F => self.sound.write_byte(addr, val),
@@ -351,18 +805,15 @@ impl MemoryBus {
                 self.interrupt_flag.vblank |= inter.vblank;
                 self.interrupt_flag.lcd |= inter.lcd;
             }
-            0xFF41 => self.gpu.lcd_status.write_byte_to_status(val),
+            0xFF41 => self.interrupt_flag.lcd |= self.gpu.write_stat(val),
             0xFF42 => self.gpu.viewport.y = val,
             0xFF43 => self.gpu.viewport.x = val,
             0xFF44 => {
                 // LCD Y coordinate is read-only. But there are buggy ROMs that try to write to this
-                // register, so just ignore it.
-            }
-            0xFF45 => {
-                if self.gpu.lcd_status.set_lyc(val) {
-                    self.interrupt_flag.lcd = true;
-                }
+                // register, so just ignore it like DMG does.
+                self.report_ignored_write(addr, val, "LY is read-only");
             }
+            0xFF45 => self.interrupt_flag.lcd |= self.gpu.write_lyc(val),
             0xFF46 => {
                 // Writing to this register starts a DMA transfer from ROM or
                 // RAM to OAM (Object Attribute Memory). The transfer takes 160
@@ -375,6 +826,7 @@ impl MemoryBus {
             0xFF49 => self.gpu.obj1_colors = super::gpu::BackgroundColors::from(val & !0b11),
             0xFF4A => self.gpu.window.y = val,
             0xFF4B => self.gpu.window.x = val,
+            BOOT_ROM_DISABLE_REGISTER if val != 0 => self.boot_rom_mapped = false,
             0xFF7F..=0xFF7F => {
                 // Writing here does nothing.
             }
@@ -382,64 +834,88 @@ impl MemoryBus {
         }
     }
 
-    fn dma_transfer(&mut self, addr: u16) {
-        for dest_addr in OAM_START..=OAM_END {
-            self.write_byte(dest_addr, self.read_byte(addr + (dest_addr - OAM_START)));
+    fn report_ignored_write(&mut self, addr: u16, val: u8, reason: &str) {
+        let mask = 1u128 << (addr - IO_REGISTERS_START);
+        if self.reported_ignored_writes & mask == 0 {
+            self.reported_ignored_writes |= mask;
+            self.warnings.push(format!(
+                "Ignoring write of 0x{val:02X} to 0x{addr:X}: {reason}."
+            ));
         }
     }
-}
 
-impl TimerRateHz {
-    pub const fn per_cpu_cycle(&self) -> u64 {
-        use crate::CPU_FREQ;
-        match self {
-            TimerRateHz::F4096 => CPU_FREQ / 4096,
-            TimerRateHz::F262144 => CPU_FREQ / 262144,
-            TimerRateHz::F65536 => CPU_FREQ / 65536,
-            TimerRateHz::F16384 => CPU_FREQ / 16384,
-        }
+    fn dma_transfer(&mut self, addr: u16) {
+        // Restarting replaces the running transfer, the bytes it didn't copy yet stay as they were.
+        self.dma = Some(OamDma {
+            source: addr,
+            cycles: 0,
+        });
     }
-}
 
-impl Timer {
-    pub fn new_disabled(freq: TimerRateHz) -> Self {
-        Self {
-            freq,
-            ..Default::default()
+    /// Copies the bytes of the running DMA transfer that are due after `cycles`, one per M-cycle.
+    fn step_dma(&mut self, cycles: u64) {
+        let Some(dma) = &mut self.dma else {
+            return;
+        };
+        let copied = (dma.cycles / 4).min(OAM_SIZE as u64) as u16;
+        dma.cycles += cycles;
+        let due = (dma.cycles / 4).min(OAM_SIZE as u64) as u16;
+        let source = dma.source;
+        if dma.cycles >= OamDma::DURATION {
+            self.dma = None;
         }
-    }
 
-    pub fn new_enabled(freq: TimerRateHz) -> Self {
-        Self {
-            enable: true,
-            freq,
-            ..Default::default()
+        let prev_origin = self
+            .flight_recorder
+            .as_ref()
+            .map(|recorder| recorder.set_origin(Origin::Dma));
+        for idx in copied..due {
+            let val = self.read_mapped(source + idx);
+            if let Some(heatmap) = &self.heatmap {
+                heatmap.record_read(source + idx);
+                heatmap.record_write(OAM_START + idx);
+            }
+            if let Some(recorder) = &self.flight_recorder {
+                recorder.record(Access::Read, source + idx, val);
+                recorder.record(Access::Write, OAM_START + idx, val);
+            }
+            self.gpu.oam[idx as usize] = val;
         }
-    }
-
-    /// # Returns
-    ///
-    /// Whether overflow occurs.
-    pub fn step(&mut self, cpu_cycles: u64) -> bool {
-        if !self.enable {
-            return false;
+        if let (Some(recorder), Some(origin)) = (&self.flight_recorder, prev_origin) {
+            recorder.set_origin(origin);
         }
+    }
+}
 
-        self.cycles += cpu_cycles;
-
-        let mut overflow = false;
-
-        while self.cycles >= self.freq.per_cpu_cycle() {
-            let (new_val, overflow_cur) = self.val.overflowing_add(1);
-
-            overflow |= overflow_cur;
-
-            self.cycles -= self.freq.per_cpu_cycle();
+impl SaveState for MemoryBus {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.visit(&mut *self.mbc);
+        state.bytes(&mut self.wram);
+        state.visit(&mut self.gpu);
+        state.visit(&mut self.sound);
 
-            self.val = if overflow_cur { self.modulo } else { new_val };
+        for flags in [&mut self.interrupt_enable, &mut self.interrupt_flag] {
+            state.as_u8(flags, u8::from, |val| Some(val.into()));
         }
+        state.visit(&mut self.joypad);
+        state.visit(&mut self.serial);
+        state.visit(&mut self.timer);
+        state.bytes(&mut self.hram);
+        state.u64(&mut self.io_writes);
+        state.u64(&mut self.cycles);
+        state.option(&mut self.dma);
+        state.option(&mut self.sgb);
+        state.bool(&mut self.boot_rom_mapped);
+        state.check(!self.boot_rom_mapped || self.boot_rom.is_some(), || {
+            "it was made while a boot ROM was running".to_string()
+        });
+    }
+}
 
-        overflow
+impl SaveState for OamDma {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.u16(&mut self.source);
+        state.u64(&mut self.cycles);
     }
 }
 
@@ -499,28 +975,213 @@ impl std::ops::BitAnd for InterruptFlags {
 mod test {
     use super::*;
 
+    fn dmg_bus() -> MemoryBus {
+        MemoryBus::with_model(
+            vec![0; 0x8000],
+            Box::new(crate::audio_player::VoidAudioPlayer::new()),
+            Model::Dmg,
+        )
+    }
+
     #[test]
-    fn multiple_overflows_in_one_timer_cycle() {
-        let mut timer = Timer::new_enabled(TimerRateHz::F262144);
-        timer.step(36);
+    fn skipping_to_next_event() {
+        let new_bus = || {
+            let mut bus = dmg_bus();
+            // The fastest timer, a noise channel and a serial transfer.
+            bus.write_byte(0xFF07, 0b101);
+            bus.write_byte(0xFF21, 0xF0);
+            bus.write_byte(0xFF22, 0x01);
+            bus.write_byte(0xFF23, 0x80);
+            bus.write_byte(0xFF02, 0x81);
+            bus
+        };
+        let save = |bus: &mut MemoryBus| {
+            let mut state = StateVisitor::saving();
+            bus.visit_state(&mut state);
+            state.finish_save()
+        };
 
-        assert_eq!(timer.val, 2);
-        assert_eq!(timer.cycles, 4);
+        let (mut stepped, mut skipped) = (new_bus(), new_bus());
+        for iteration in 0..5000 {
+            // Without the APU, the PPU and the timer are left.
+            if iteration == 2500 {
+                stepped.write_byte(0xFF26, 0);
+                skipped.write_byte(0xFF26, 0);
+            }
+            let cycles = skipped.cycles_until_event().unwrap().div_ceil(4).max(1) * 4;
+            skipped.step(cycles);
+            for _ in 0..cycles / 4 {
+                stepped.step(4);
+            }
+            assert_eq!(save(&mut stepped), save(&mut skipped));
+            assert_eq!(
+                u8::from(stepped.interrupt_flag),
+                u8::from(skipped.interrupt_flag)
+            );
+        }
+    }
+
+    #[test]
+    fn no_stat_interrupt_with_lcd_off() {
+        let mut bus = dmg_bus();
+        bus.write_byte(0xFF40, 0);
+        bus.write_byte(0xFF0F, 0);
+
+        // LY is 0 while the LCD is off.
+        bus.write_byte(0xFF45, 1);
+        bus.write_byte(0xFF41, 0x40);
+        bus.write_byte(0xFF45, 0);
+        assert!(!bus.interrupt_flag.lcd);
+        bus.write_byte(0xFF45, 1);
+        bus.write_byte(0xFF41, 0x48);
+        assert!(!bus.interrupt_flag.lcd);
+
+        bus.write_byte(0xFF40, 0x80);
+        bus.write_byte(0xFF41, 0x40);
+        bus.write_byte(0xFF0F, 0);
+        bus.write_byte(0xFF45, 0);
+        assert!(bus.interrupt_flag.lcd);
     }
 
     #[test]
-    fn timer_overflow() {
-        let freq = TimerRateHz::F262144;
+    fn bank_diagnostics() {
+        // MBC1 with 4 ROM banks and RAM.
+        let mut rom = vec![0; 0x10000];
+        rom[0x147] = 0x03;
+        rom[ROM_SIZE_ADDR] = 0x01;
+        rom[0x149] = 0x02;
+        let mut bus = MemoryBus::with_model(
+            rom,
+            Box::new(crate::audio_player::VoidAudioPlayer::new()),
+            Model::Dmg,
+        );
+        bus.enable_bank_diagnostics();
+        bus.instruction_pc = 0x0150;
+
+        bus.write_byte(0x2000, 3);
+        bus.write_byte(0x2000, 0x1F);
+        bus.read_byte(0xA000);
+        bus.write_byte(0x0000, 0x0A);
+        bus.write_byte(0xA000, 1);
+        assert_eq!(bus.read_byte(0xA000), 1);
+        assert_eq!(bus.bank_diagnostics.as_ref().unwrap().counts(), (1, 1));
+        // Wrapped like without diagnostics.
+        assert_eq!(bus.rom_bank(0x4000), 0x1F % 4);
+    }
+
+    #[test]
+    fn ly_writes_are_ignored() {
+        let mut bus = dmg_bus();
+        let ly = bus.read_byte(0xFF44);
+
+        bus.write_byte(0xFF44, ly.wrapping_add(1));
+        bus.write_byte(0xFF44, 0);
+
+        assert_eq!(bus.read_byte(0xFF44), ly);
+        assert_eq!(bus.take_warnings().len(), 1);
+        assert_eq!(bus.take_warnings(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unused_bits_read_as_1() {
+        let mut bus = dmg_bus();
+        bus.write_byte(0xFF0F, 0);
+        bus.write_byte(0xFF13, 0);
+        bus.write_byte(0xFF1A, 0);
+        assert_eq!(bus.read_byte(0xFF0F), 0xE0);
+        assert_eq!(bus.read_byte(0xFF13), 0xFF);
+        assert_eq!(bus.read_byte(0xFF1A), 0x7F);
+        assert_eq!(bus.read_byte(0xFF03), 0xFF);
+        assert_eq!(bus.read_byte(0xFF4D), 0xFF);
+
+        // $FEA0-$FEFF reads $FF while OAM is blocked.
+        bus.write_byte(0xFF40, 0);
+        assert_eq!(bus.read_byte(0xFEA0), 0);
+        bus.write_byte(0xFF40, 0x80);
+        assert_eq!(bus.read_byte(0xFEFF), 0xFF);
+    }
+
+    #[test]
+    fn dma_conflicts() {
+        let mut bus = dmg_bus();
+        bus.dma_conflicts = true;
+        for idx in 0..OAM_SIZE as u16 {
+            bus.write_byte(0xC000 + idx, idx as u8 + 1);
+        }
+        bus.write_byte(0xD000, 0x42);
+
+        bus.write_byte(0xFF46, 0xC0);
+        // The CPU sees the byte DMA is transferring.
+        assert_eq!(bus.read_byte(0xD000), 1);
+        bus.step(8);
+        assert_eq!(bus.read_byte(0xD000), 3);
+        // HRAM is still accessible, other writes are ignored.
+        bus.write_byte(0xFF80, 0x12);
+        assert_eq!(bus.read_byte(0xFF80), 0x12);
+        bus.write_byte(0xD000, 0);
+
+        bus.step(OamDma::DURATION);
+        assert_eq!(bus.read_byte(0xD000), 0x42);
+        assert_eq!(bus.gpu.oam[OAM_SIZE - 1], OAM_SIZE as u8);
+    }
+
+    #[test]
+    fn dma_copies_one_byte_per_m_cycle() {
+        let mut bus = dmg_bus();
+        for idx in 0..OAM_SIZE as u16 {
+            bus.write_byte(0xC000 + idx, idx as u8 + 1);
+        }
+
+        bus.write_byte(0xFF46, 0xC0);
+        assert_eq!(bus.gpu.oam[0], 0);
+        bus.step(4);
+        assert_eq!(bus.gpu.oam[..2], [1, 0]);
+        bus.step(4 * 100);
+        assert_eq!(bus.gpu.oam[100..102], [101, 0]);
+        bus.step(OamDma::DURATION);
+        assert!(bus.dma.is_none());
+        assert_eq!(bus.gpu.oam[OAM_SIZE - 1], OAM_SIZE as u8);
+    }
+
+    #[test]
+    fn io_write_hooks() {
+        let mut bus = dmg_bus();
+        let writes = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let log = std::sync::Arc::clone(&writes);
+        bus.on_io_write(
+            0xFF40,
+            Box::new(move |write| {
+                log.lock().unwrap().push(write);
+                if write.new & 0x80 == 0 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            }),
+        );
 
-        let mut timer = Timer::new_enabled(freq);
-        assert!(timer.step(freq.per_cpu_cycle() * (u8::MAX as u64 + 1)));
-        assert_eq!((timer.val, timer.cycles), (0, 0));
+        let lcdc = bus.read_byte(0xFF40);
+        bus.write_byte(0xFF47, 0x12);
+        bus.write_byte(0xFF40, lcdc | 0x80);
+        assert!(!bus.take_hook_stop_request());
+        bus.write_byte(0xFF40, 0x11);
+        assert!(bus.take_hook_stop_request());
+        assert!(!bus.take_hook_stop_request());
 
-        let mut timer = Timer::new_enabled(freq);
-        assert!(!timer.step(freq.per_cpu_cycle() * (u8::MAX as u64) + freq.per_cpu_cycle() - 1));
         assert_eq!(
-            (timer.val, timer.cycles),
-            (u8::MAX, freq.per_cpu_cycle() - 1)
+            *writes.lock().unwrap(),
+            [
+                IoWrite {
+                    addr: 0xFF40,
+                    old: lcdc,
+                    new: lcdc | 0x80,
+                },
+                IoWrite {
+                    addr: 0xFF40,
+                    old: lcdc | 0x80,
+                    new: 0x11,
+                },
+            ]
         );
     }
 }
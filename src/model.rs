@@ -0,0 +1,47 @@
+// Hardware revisions with observable differences.
+
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Model {
+    /// Original Game Boy.
+    #[default]
+    Dmg,
+    /// Game Boy Pocket. Behaves like DMG, but the boot ROM leaves a different value in A.
+    Mgb,
+    /// Game Boy Color. Only the APU differences are emulated, so it can't be chosen by name until
+    /// CGB mode exists.
+    Cgb,
+    /// Super Game Boy. Runs games like DMG, those with the SGB flag can color the screen and draw
+    /// a border, see [`crate::sgb`].
+    Sgb,
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dmg" => Ok(Model::Dmg),
+            "mgb" => Ok(Model::Mgb),
+            "cgb" => Err("model cgb is not supported".to_string()),
+            "sgb" => Ok(Model::Sgb),
+            _ => Err(format!("unknown model {s} (expected dmg, mgb or sgb)")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("mgb".parse(), Ok(Model::Mgb));
+        assert_eq!(
+            "cgb".parse::<Model>(),
+            Err("model cgb is not supported".to_string())
+        );
+        assert!("gba".parse::<Model>().is_err());
+    }
+}
@@ -0,0 +1,111 @@
+// Key presses of a whole game session from power-on, replayed exactly by `CPU::play_movie`.
+//
+// Stored as text like a macro, after the global checksum of the ROM it was recorded with and the
+// DIV/LY script the game ran with, if any. Frames are counted from power-on:
+// rom 16BF
+// rng DIV 0x12 0x34
+// # frame key action
+// 61 start down
+// 64 start up
+
+use crate::{input_macro::InputMacro, rng_script::RngScript};
+
+const RNG_PREFIX: &str = "rng ";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Movie {
+    /// See [`crate::compat::global_checksum`].
+    pub rom_checksum: u16,
+    /// Replaces the script of the emulator while the movie plays, see [`RngScript`].
+    pub rng_script: Option<RngScript>,
+    pub inputs: InputMacro,
+}
+
+impl Movie {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (header_idx, header) = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap().trim())
+            .enumerate()
+            .find(|(_, line)| !line.is_empty())
+            .unwrap_or((0, ""));
+        let err = |msg: String| format!("line {}: {msg}", header_idx + 1);
+        let checksum = header
+            .strip_prefix("rom ")
+            .ok_or_else(|| err("expected `rom CHECKSUM`".to_string()))?
+            .trim();
+        let rom_checksum =
+            u16::from_str_radix(checksum, 16).map_err(|e| err(format!("{checksum}: {e}")))?;
+
+        // The other lines are blanked out to keep the line numbers of errors.
+        let mut script = vec![];
+        let mut events = vec![];
+        for (idx, line) in text.lines().enumerate() {
+            let rng = line.trim_start().strip_prefix(RNG_PREFIX);
+            script.push(rng.unwrap_or(""));
+            events.push(if idx == header_idx || rng.is_some() {
+                ""
+            } else {
+                line
+            });
+        }
+        let rng_script = if script.iter().any(|line| !line.is_empty()) {
+            Some(RngScript::parse(&script.join("\n"))?)
+        } else {
+            None
+        };
+        let inputs = InputMacro::parse(&events.join("\n"))?;
+        Ok(Self {
+            rom_checksum,
+            rng_script,
+            inputs,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("rom {:04X}\n", self.rom_checksum);
+        if let Some(script) = &self.rng_script {
+            for line in script.to_text().lines() {
+                text += &format!("{RNG_PREFIX}{line}\n");
+            }
+        }
+        text + &self.inputs.to_text()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_print() {
+        let text = "rom 16BF\n# frame key action\n61 start down\n64 start up\n";
+        let movie = Movie::parse(text).unwrap();
+        assert_eq!(movie.rom_checksum, 0x16BF);
+        assert_eq!(movie.inputs.events.len(), 2);
+        assert_eq!(movie.to_text(), text);
+        assert_eq!(movie.rng_script, None);
+
+        let text = "rom 16BF\nrng DIV 0x12 0x34\nrng LY 0x90\n# frame key action\n61 start down\n";
+        let movie = Movie::parse(text).unwrap();
+        assert_eq!(
+            movie.rng_script,
+            Some(RngScript::new(vec![0x12, 0x34], vec![0x90]))
+        );
+        assert_eq!(movie.inputs.events.len(), 1);
+        assert_eq!(movie.to_text(), text);
+
+        for (text, err) in [
+            ("", "line 1: expected `rom CHECKSUM`"),
+            ("# comment\n\n1 a down", "line 3: expected `rom CHECKSUM`"),
+            ("rom XYZ", "line 1: XYZ: invalid digit found in string"),
+            (
+                "rom 0000\n1 a down\n0 a up",
+                "line 3: frame 0 is before the previous event",
+            ),
+            ("rom 0000\n\nrng TIMA 1", "line 3: unknown register TIMA"),
+        ] {
+            assert_eq!(Movie::parse(text), Err(err.to_string()));
+        }
+    }
+}
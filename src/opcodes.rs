@@ -0,0 +1,648 @@
+// Machine-readable metadata of all SM83 opcodes.
+//
+// https://gbdev.io/gb-opcodes/optables/
+// Mnemonics follow https://rgbds.gbdev.io/docs/v0.9.0/gbz80.7
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FlagEffect {
+    Unaffected,
+    Reset,
+    Set,
+    /// Depends on the result.
+    Affected,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    /// Length in bytes including operands (and the 0xCB prefix for prefixed opcodes).
+    pub length: u8,
+    /// T-cycles. For conditional instructions, when the condition is not met.
+    pub cycles: u8,
+    /// T-cycles of conditional instructions when the condition is met.
+    pub cycles_taken: Option<u8>,
+    /// Effect on Z, N, H and C flags.
+    pub flags: [FlagEffect; 4],
+}
+
+impl Opcode {
+    /// Opcodes that hang the real CPU.
+    pub fn is_illegal(&self) -> bool {
+        self.length == 0
+    }
+}
+
+/// 256 not prefixed opcodes followed by 256 opcodes prefixed with 0xCB.
+pub static TABLE: [Opcode; 512] = [
+    // 0x00
+    op("NOP", 1, 4, "----"),
+    op("LD BC, n16", 3, 12, "----"),
+    op("LD (BC), A", 1, 8, "----"),
+    op("INC BC", 1, 8, "----"),
+    op("INC B", 1, 4, "Z0H-"),
+    op("DEC B", 1, 4, "Z1H-"),
+    op("LD B, n8", 2, 8, "----"),
+    op("RLCA", 1, 4, "000C"),
+    op("LD (a16), SP", 3, 20, "----"),
+    op("ADD HL, BC", 1, 8, "-0HC"),
+    op("LD A, (BC)", 1, 8, "----"),
+    op("DEC BC", 1, 8, "----"),
+    op("INC C", 1, 4, "Z0H-"),
+    op("DEC C", 1, 4, "Z1H-"),
+    op("LD C, n8", 2, 8, "----"),
+    op("RRCA", 1, 4, "000C"),
+    // 0x10
+    op("STOP", 2, 4, "----"),
+    op("LD DE, n16", 3, 12, "----"),
+    op("LD (DE), A", 1, 8, "----"),
+    op("INC DE", 1, 8, "----"),
+    op("INC D", 1, 4, "Z0H-"),
+    op("DEC D", 1, 4, "Z1H-"),
+    op("LD D, n8", 2, 8, "----"),
+    op("RLA", 1, 4, "000C"),
+    op("JR e8", 2, 12, "----"),
+    op("ADD HL, DE", 1, 8, "-0HC"),
+    op("LD A, (DE)", 1, 8, "----"),
+    op("DEC DE", 1, 8, "----"),
+    op("INC E", 1, 4, "Z0H-"),
+    op("DEC E", 1, 4, "Z1H-"),
+    op("LD E, n8", 2, 8, "----"),
+    op("RRA", 1, 4, "000C"),
+    // 0x20
+    branch("JR NZ, e8", 2, 8, 12),
+    op("LD HL, n16", 3, 12, "----"),
+    op("LD (HL+), A", 1, 8, "----"),
+    op("INC HL", 1, 8, "----"),
+    op("INC H", 1, 4, "Z0H-"),
+    op("DEC H", 1, 4, "Z1H-"),
+    op("LD H, n8", 2, 8, "----"),
+    op("DAA", 1, 4, "Z-0C"),
+    branch("JR Z, e8", 2, 8, 12),
+    op("ADD HL, HL", 1, 8, "-0HC"),
+    op("LD A, (HL+)", 1, 8, "----"),
+    op("DEC HL", 1, 8, "----"),
+    op("INC L", 1, 4, "Z0H-"),
+    op("DEC L", 1, 4, "Z1H-"),
+    op("LD L, n8", 2, 8, "----"),
+    op("CPL", 1, 4, "-11-"),
+    // 0x30
+    branch("JR NC, e8", 2, 8, 12),
+    op("LD SP, n16", 3, 12, "----"),
+    op("LD (HL-), A", 1, 8, "----"),
+    op("INC SP", 1, 8, "----"),
+    op("INC (HL)", 1, 12, "Z0H-"),
+    op("DEC (HL)", 1, 12, "Z1H-"),
+    op("LD (HL), n8", 2, 12, "----"),
+    op("SCF", 1, 4, "-001"),
+    branch("JR C, e8", 2, 8, 12),
+    op("ADD HL, SP", 1, 8, "-0HC"),
+    op("LD A, (HL-)", 1, 8, "----"),
+    op("DEC SP", 1, 8, "----"),
+    op("INC A", 1, 4, "Z0H-"),
+    op("DEC A", 1, 4, "Z1H-"),
+    op("LD A, n8", 2, 8, "----"),
+    op("CCF", 1, 4, "-00C"),
+    // 0x40
+    op("LD B, B", 1, 4, "----"),
+    op("LD B, C", 1, 4, "----"),
+    op("LD B, D", 1, 4, "----"),
+    op("LD B, E", 1, 4, "----"),
+    op("LD B, H", 1, 4, "----"),
+    op("LD B, L", 1, 4, "----"),
+    op("LD B, (HL)", 1, 8, "----"),
+    op("LD B, A", 1, 4, "----"),
+    op("LD C, B", 1, 4, "----"),
+    op("LD C, C", 1, 4, "----"),
+    op("LD C, D", 1, 4, "----"),
+    op("LD C, E", 1, 4, "----"),
+    op("LD C, H", 1, 4, "----"),
+    op("LD C, L", 1, 4, "----"),
+    op("LD C, (HL)", 1, 8, "----"),
+    op("LD C, A", 1, 4, "----"),
+    // 0x50
+    op("LD D, B", 1, 4, "----"),
+    op("LD D, C", 1, 4, "----"),
+    op("LD D, D", 1, 4, "----"),
+    op("LD D, E", 1, 4, "----"),
+    op("LD D, H", 1, 4, "----"),
+    op("LD D, L", 1, 4, "----"),
+    op("LD D, (HL)", 1, 8, "----"),
+    op("LD D, A", 1, 4, "----"),
+    op("LD E, B", 1, 4, "----"),
+    op("LD E, C", 1, 4, "----"),
+    op("LD E, D", 1, 4, "----"),
+    op("LD E, E", 1, 4, "----"),
+    op("LD E, H", 1, 4, "----"),
+    op("LD E, L", 1, 4, "----"),
+    op("LD E, (HL)", 1, 8, "----"),
+    op("LD E, A", 1, 4, "----"),
+    // 0x60
+    op("LD H, B", 1, 4, "----"),
+    op("LD H, C", 1, 4, "----"),
+    op("LD H, D", 1, 4, "----"),
+    op("LD H, E", 1, 4, "----"),
+    op("LD H, H", 1, 4, "----"),
+    op("LD H, L", 1, 4, "----"),
+    op("LD H, (HL)", 1, 8, "----"),
+    op("LD H, A", 1, 4, "----"),
+    op("LD L, B", 1, 4, "----"),
+    op("LD L, C", 1, 4, "----"),
+    op("LD L, D", 1, 4, "----"),
+    op("LD L, E", 1, 4, "----"),
+    op("LD L, H", 1, 4, "----"),
+    op("LD L, L", 1, 4, "----"),
+    op("LD L, (HL)", 1, 8, "----"),
+    op("LD L, A", 1, 4, "----"),
+    // 0x70
+    op("LD (HL), B", 1, 8, "----"),
+    op("LD (HL), C", 1, 8, "----"),
+    op("LD (HL), D", 1, 8, "----"),
+    op("LD (HL), E", 1, 8, "----"),
+    op("LD (HL), H", 1, 8, "----"),
+    op("LD (HL), L", 1, 8, "----"),
+    op("HALT", 1, 4, "----"),
+    op("LD (HL), A", 1, 8, "----"),
+    op("LD A, B", 1, 4, "----"),
+    op("LD A, C", 1, 4, "----"),
+    op("LD A, D", 1, 4, "----"),
+    op("LD A, E", 1, 4, "----"),
+    op("LD A, H", 1, 4, "----"),
+    op("LD A, L", 1, 4, "----"),
+    op("LD A, (HL)", 1, 8, "----"),
+    op("LD A, A", 1, 4, "----"),
+    // 0x80
+    op("ADD A, B", 1, 4, "Z0HC"),
+    op("ADD A, C", 1, 4, "Z0HC"),
+    op("ADD A, D", 1, 4, "Z0HC"),
+    op("ADD A, E", 1, 4, "Z0HC"),
+    op("ADD A, H", 1, 4, "Z0HC"),
+    op("ADD A, L", 1, 4, "Z0HC"),
+    op("ADD A, (HL)", 1, 8, "Z0HC"),
+    op("ADD A, A", 1, 4, "Z0HC"),
+    op("ADC A, B", 1, 4, "Z0HC"),
+    op("ADC A, C", 1, 4, "Z0HC"),
+    op("ADC A, D", 1, 4, "Z0HC"),
+    op("ADC A, E", 1, 4, "Z0HC"),
+    op("ADC A, H", 1, 4, "Z0HC"),
+    op("ADC A, L", 1, 4, "Z0HC"),
+    op("ADC A, (HL)", 1, 8, "Z0HC"),
+    op("ADC A, A", 1, 4, "Z0HC"),
+    // 0x90
+    op("SUB A, B", 1, 4, "Z1HC"),
+    op("SUB A, C", 1, 4, "Z1HC"),
+    op("SUB A, D", 1, 4, "Z1HC"),
+    op("SUB A, E", 1, 4, "Z1HC"),
+    op("SUB A, H", 1, 4, "Z1HC"),
+    op("SUB A, L", 1, 4, "Z1HC"),
+    op("SUB A, (HL)", 1, 8, "Z1HC"),
+    op("SUB A, A", 1, 4, "Z1HC"),
+    op("SBC A, B", 1, 4, "Z1HC"),
+    op("SBC A, C", 1, 4, "Z1HC"),
+    op("SBC A, D", 1, 4, "Z1HC"),
+    op("SBC A, E", 1, 4, "Z1HC"),
+    op("SBC A, H", 1, 4, "Z1HC"),
+    op("SBC A, L", 1, 4, "Z1HC"),
+    op("SBC A, (HL)", 1, 8, "Z1HC"),
+    op("SBC A, A", 1, 4, "Z1HC"),
+    // 0xA0
+    op("AND A, B", 1, 4, "Z010"),
+    op("AND A, C", 1, 4, "Z010"),
+    op("AND A, D", 1, 4, "Z010"),
+    op("AND A, E", 1, 4, "Z010"),
+    op("AND A, H", 1, 4, "Z010"),
+    op("AND A, L", 1, 4, "Z010"),
+    op("AND A, (HL)", 1, 8, "Z010"),
+    op("AND A, A", 1, 4, "Z010"),
+    op("XOR A, B", 1, 4, "Z000"),
+    op("XOR A, C", 1, 4, "Z000"),
+    op("XOR A, D", 1, 4, "Z000"),
+    op("XOR A, E", 1, 4, "Z000"),
+    op("XOR A, H", 1, 4, "Z000"),
+    op("XOR A, L", 1, 4, "Z000"),
+    op("XOR A, (HL)", 1, 8, "Z000"),
+    op("XOR A, A", 1, 4, "Z000"),
+    // 0xB0
+    op("OR A, B", 1, 4, "Z000"),
+    op("OR A, C", 1, 4, "Z000"),
+    op("OR A, D", 1, 4, "Z000"),
+    op("OR A, E", 1, 4, "Z000"),
+    op("OR A, H", 1, 4, "Z000"),
+    op("OR A, L", 1, 4, "Z000"),
+    op("OR A, (HL)", 1, 8, "Z000"),
+    op("OR A, A", 1, 4, "Z000"),
+    op("CP A, B", 1, 4, "Z1HC"),
+    op("CP A, C", 1, 4, "Z1HC"),
+    op("CP A, D", 1, 4, "Z1HC"),
+    op("CP A, E", 1, 4, "Z1HC"),
+    op("CP A, H", 1, 4, "Z1HC"),
+    op("CP A, L", 1, 4, "Z1HC"),
+    op("CP A, (HL)", 1, 8, "Z1HC"),
+    op("CP A, A", 1, 4, "Z1HC"),
+    // 0xC0
+    branch("RET NZ", 1, 8, 20),
+    op("POP BC", 1, 12, "----"),
+    branch("JP NZ, a16", 3, 12, 16),
+    op("JP a16", 3, 16, "----"),
+    branch("CALL NZ, a16", 3, 12, 24),
+    op("PUSH BC", 1, 16, "----"),
+    op("ADD A, n8", 2, 8, "Z0HC"),
+    op("RST $00", 1, 16, "----"),
+    branch("RET Z", 1, 8, 20),
+    op("RET", 1, 16, "----"),
+    branch("JP Z, a16", 3, 12, 16),
+    op("PREFIX", 1, 4, "----"),
+    branch("CALL Z, a16", 3, 12, 24),
+    op("CALL a16", 3, 24, "----"),
+    op("ADC A, n8", 2, 8, "Z0HC"),
+    op("RST $08", 1, 16, "----"),
+    // 0xD0
+    branch("RET NC", 1, 8, 20),
+    op("POP DE", 1, 12, "----"),
+    branch("JP NC, a16", 3, 12, 16),
+    illegal(),
+    branch("CALL NC, a16", 3, 12, 24),
+    op("PUSH DE", 1, 16, "----"),
+    op("SUB A, n8", 2, 8, "Z1HC"),
+    op("RST $10", 1, 16, "----"),
+    branch("RET C", 1, 8, 20),
+    op("RETI", 1, 16, "----"),
+    branch("JP C, a16", 3, 12, 16),
+    illegal(),
+    branch("CALL C, a16", 3, 12, 24),
+    illegal(),
+    op("SBC A, n8", 2, 8, "Z1HC"),
+    op("RST $18", 1, 16, "----"),
+    // 0xE0
+    op("LDH (a8), A", 2, 12, "----"),
+    op("POP HL", 1, 12, "----"),
+    op("LDH (C), A", 1, 8, "----"),
+    illegal(),
+    illegal(),
+    op("PUSH HL", 1, 16, "----"),
+    op("AND A, n8", 2, 8, "Z010"),
+    op("RST $20", 1, 16, "----"),
+    op("ADD SP, e8", 2, 16, "00HC"),
+    op("JP HL", 1, 4, "----"),
+    op("LD (a16), A", 3, 16, "----"),
+    illegal(),
+    illegal(),
+    illegal(),
+    op("XOR A, n8", 2, 8, "Z000"),
+    op("RST $28", 1, 16, "----"),
+    // 0xF0
+    op("LDH A, (a8)", 2, 12, "----"),
+    op("POP AF", 1, 12, "ZNHC"),
+    op("LDH A, (C)", 1, 8, "----"),
+    op("DI", 1, 4, "----"),
+    illegal(),
+    op("PUSH AF", 1, 16, "----"),
+    op("OR A, n8", 2, 8, "Z000"),
+    op("RST $30", 1, 16, "----"),
+    op("LD HL, SP + e8", 2, 12, "00HC"),
+    op("LD SP, HL", 1, 8, "----"),
+    op("LD A, (a16)", 3, 16, "----"),
+    op("EI", 1, 4, "----"),
+    illegal(),
+    illegal(),
+    op("CP A, n8", 2, 8, "Z1HC"),
+    op("RST $38", 1, 16, "----"),
+    // 0xCB 0x00
+    op("RLC B", 2, 8, "Z00C"),
+    op("RLC C", 2, 8, "Z00C"),
+    op("RLC D", 2, 8, "Z00C"),
+    op("RLC E", 2, 8, "Z00C"),
+    op("RLC H", 2, 8, "Z00C"),
+    op("RLC L", 2, 8, "Z00C"),
+    op("RLC (HL)", 2, 16, "Z00C"),
+    op("RLC A", 2, 8, "Z00C"),
+    op("RRC B", 2, 8, "Z00C"),
+    op("RRC C", 2, 8, "Z00C"),
+    op("RRC D", 2, 8, "Z00C"),
+    op("RRC E", 2, 8, "Z00C"),
+    op("RRC H", 2, 8, "Z00C"),
+    op("RRC L", 2, 8, "Z00C"),
+    op("RRC (HL)", 2, 16, "Z00C"),
+    op("RRC A", 2, 8, "Z00C"),
+    // 0xCB 0x10
+    op("RL B", 2, 8, "Z00C"),
+    op("RL C", 2, 8, "Z00C"),
+    op("RL D", 2, 8, "Z00C"),
+    op("RL E", 2, 8, "Z00C"),
+    op("RL H", 2, 8, "Z00C"),
+    op("RL L", 2, 8, "Z00C"),
+    op("RL (HL)", 2, 16, "Z00C"),
+    op("RL A", 2, 8, "Z00C"),
+    op("RR B", 2, 8, "Z00C"),
+    op("RR C", 2, 8, "Z00C"),
+    op("RR D", 2, 8, "Z00C"),
+    op("RR E", 2, 8, "Z00C"),
+    op("RR H", 2, 8, "Z00C"),
+    op("RR L", 2, 8, "Z00C"),
+    op("RR (HL)", 2, 16, "Z00C"),
+    op("RR A", 2, 8, "Z00C"),
+    // 0xCB 0x20
+    op("SLA B", 2, 8, "Z00C"),
+    op("SLA C", 2, 8, "Z00C"),
+    op("SLA D", 2, 8, "Z00C"),
+    op("SLA E", 2, 8, "Z00C"),
+    op("SLA H", 2, 8, "Z00C"),
+    op("SLA L", 2, 8, "Z00C"),
+    op("SLA (HL)", 2, 16, "Z00C"),
+    op("SLA A", 2, 8, "Z00C"),
+    op("SRA B", 2, 8, "Z00C"),
+    op("SRA C", 2, 8, "Z00C"),
+    op("SRA D", 2, 8, "Z00C"),
+    op("SRA E", 2, 8, "Z00C"),
+    op("SRA H", 2, 8, "Z00C"),
+    op("SRA L", 2, 8, "Z00C"),
+    op("SRA (HL)", 2, 16, "Z00C"),
+    op("SRA A", 2, 8, "Z00C"),
+    // 0xCB 0x30
+    op("SWAP B", 2, 8, "Z000"),
+    op("SWAP C", 2, 8, "Z000"),
+    op("SWAP D", 2, 8, "Z000"),
+    op("SWAP E", 2, 8, "Z000"),
+    op("SWAP H", 2, 8, "Z000"),
+    op("SWAP L", 2, 8, "Z000"),
+    op("SWAP (HL)", 2, 16, "Z000"),
+    op("SWAP A", 2, 8, "Z000"),
+    op("SRL B", 2, 8, "Z00C"),
+    op("SRL C", 2, 8, "Z00C"),
+    op("SRL D", 2, 8, "Z00C"),
+    op("SRL E", 2, 8, "Z00C"),
+    op("SRL H", 2, 8, "Z00C"),
+    op("SRL L", 2, 8, "Z00C"),
+    op("SRL (HL)", 2, 16, "Z00C"),
+    op("SRL A", 2, 8, "Z00C"),
+    // 0xCB 0x40
+    op("BIT 0, B", 2, 8, "Z01-"),
+    op("BIT 0, C", 2, 8, "Z01-"),
+    op("BIT 0, D", 2, 8, "Z01-"),
+    op("BIT 0, E", 2, 8, "Z01-"),
+    op("BIT 0, H", 2, 8, "Z01-"),
+    op("BIT 0, L", 2, 8, "Z01-"),
+    op("BIT 0, (HL)", 2, 12, "Z01-"),
+    op("BIT 0, A", 2, 8, "Z01-"),
+    op("BIT 1, B", 2, 8, "Z01-"),
+    op("BIT 1, C", 2, 8, "Z01-"),
+    op("BIT 1, D", 2, 8, "Z01-"),
+    op("BIT 1, E", 2, 8, "Z01-"),
+    op("BIT 1, H", 2, 8, "Z01-"),
+    op("BIT 1, L", 2, 8, "Z01-"),
+    op("BIT 1, (HL)", 2, 12, "Z01-"),
+    op("BIT 1, A", 2, 8, "Z01-"),
+    // 0xCB 0x50
+    op("BIT 2, B", 2, 8, "Z01-"),
+    op("BIT 2, C", 2, 8, "Z01-"),
+    op("BIT 2, D", 2, 8, "Z01-"),
+    op("BIT 2, E", 2, 8, "Z01-"),
+    op("BIT 2, H", 2, 8, "Z01-"),
+    op("BIT 2, L", 2, 8, "Z01-"),
+    op("BIT 2, (HL)", 2, 12, "Z01-"),
+    op("BIT 2, A", 2, 8, "Z01-"),
+    op("BIT 3, B", 2, 8, "Z01-"),
+    op("BIT 3, C", 2, 8, "Z01-"),
+    op("BIT 3, D", 2, 8, "Z01-"),
+    op("BIT 3, E", 2, 8, "Z01-"),
+    op("BIT 3, H", 2, 8, "Z01-"),
+    op("BIT 3, L", 2, 8, "Z01-"),
+    op("BIT 3, (HL)", 2, 12, "Z01-"),
+    op("BIT 3, A", 2, 8, "Z01-"),
+    // 0xCB 0x60
+    op("BIT 4, B", 2, 8, "Z01-"),
+    op("BIT 4, C", 2, 8, "Z01-"),
+    op("BIT 4, D", 2, 8, "Z01-"),
+    op("BIT 4, E", 2, 8, "Z01-"),
+    op("BIT 4, H", 2, 8, "Z01-"),
+    op("BIT 4, L", 2, 8, "Z01-"),
+    op("BIT 4, (HL)", 2, 12, "Z01-"),
+    op("BIT 4, A", 2, 8, "Z01-"),
+    op("BIT 5, B", 2, 8, "Z01-"),
+    op("BIT 5, C", 2, 8, "Z01-"),
+    op("BIT 5, D", 2, 8, "Z01-"),
+    op("BIT 5, E", 2, 8, "Z01-"),
+    op("BIT 5, H", 2, 8, "Z01-"),
+    op("BIT 5, L", 2, 8, "Z01-"),
+    op("BIT 5, (HL)", 2, 12, "Z01-"),
+    op("BIT 5, A", 2, 8, "Z01-"),
+    // 0xCB 0x70
+    op("BIT 6, B", 2, 8, "Z01-"),
+    op("BIT 6, C", 2, 8, "Z01-"),
+    op("BIT 6, D", 2, 8, "Z01-"),
+    op("BIT 6, E", 2, 8, "Z01-"),
+    op("BIT 6, H", 2, 8, "Z01-"),
+    op("BIT 6, L", 2, 8, "Z01-"),
+    op("BIT 6, (HL)", 2, 12, "Z01-"),
+    op("BIT 6, A", 2, 8, "Z01-"),
+    op("BIT 7, B", 2, 8, "Z01-"),
+    op("BIT 7, C", 2, 8, "Z01-"),
+    op("BIT 7, D", 2, 8, "Z01-"),
+    op("BIT 7, E", 2, 8, "Z01-"),
+    op("BIT 7, H", 2, 8, "Z01-"),
+    op("BIT 7, L", 2, 8, "Z01-"),
+    op("BIT 7, (HL)", 2, 12, "Z01-"),
+    op("BIT 7, A", 2, 8, "Z01-"),
+    // 0xCB 0x80
+    op("RES 0, B", 2, 8, "----"),
+    op("RES 0, C", 2, 8, "----"),
+    op("RES 0, D", 2, 8, "----"),
+    op("RES 0, E", 2, 8, "----"),
+    op("RES 0, H", 2, 8, "----"),
+    op("RES 0, L", 2, 8, "----"),
+    op("RES 0, (HL)", 2, 16, "----"),
+    op("RES 0, A", 2, 8, "----"),
+    op("RES 1, B", 2, 8, "----"),
+    op("RES 1, C", 2, 8, "----"),
+    op("RES 1, D", 2, 8, "----"),
+    op("RES 1, E", 2, 8, "----"),
+    op("RES 1, H", 2, 8, "----"),
+    op("RES 1, L", 2, 8, "----"),
+    op("RES 1, (HL)", 2, 16, "----"),
+    op("RES 1, A", 2, 8, "----"),
+    // 0xCB 0x90
+    op("RES 2, B", 2, 8, "----"),
+    op("RES 2, C", 2, 8, "----"),
+    op("RES 2, D", 2, 8, "----"),
+    op("RES 2, E", 2, 8, "----"),
+    op("RES 2, H", 2, 8, "----"),
+    op("RES 2, L", 2, 8, "----"),
+    op("RES 2, (HL)", 2, 16, "----"),
+    op("RES 2, A", 2, 8, "----"),
+    op("RES 3, B", 2, 8, "----"),
+    op("RES 3, C", 2, 8, "----"),
+    op("RES 3, D", 2, 8, "----"),
+    op("RES 3, E", 2, 8, "----"),
+    op("RES 3, H", 2, 8, "----"),
+    op("RES 3, L", 2, 8, "----"),
+    op("RES 3, (HL)", 2, 16, "----"),
+    op("RES 3, A", 2, 8, "----"),
+    // 0xCB 0xA0
+    op("RES 4, B", 2, 8, "----"),
+    op("RES 4, C", 2, 8, "----"),
+    op("RES 4, D", 2, 8, "----"),
+    op("RES 4, E", 2, 8, "----"),
+    op("RES 4, H", 2, 8, "----"),
+    op("RES 4, L", 2, 8, "----"),
+    op("RES 4, (HL)", 2, 16, "----"),
+    op("RES 4, A", 2, 8, "----"),
+    op("RES 5, B", 2, 8, "----"),
+    op("RES 5, C", 2, 8, "----"),
+    op("RES 5, D", 2, 8, "----"),
+    op("RES 5, E", 2, 8, "----"),
+    op("RES 5, H", 2, 8, "----"),
+    op("RES 5, L", 2, 8, "----"),
+    op("RES 5, (HL)", 2, 16, "----"),
+    op("RES 5, A", 2, 8, "----"),
+    // 0xCB 0xB0
+    op("RES 6, B", 2, 8, "----"),
+    op("RES 6, C", 2, 8, "----"),
+    op("RES 6, D", 2, 8, "----"),
+    op("RES 6, E", 2, 8, "----"),
+    op("RES 6, H", 2, 8, "----"),
+    op("RES 6, L", 2, 8, "----"),
+    op("RES 6, (HL)", 2, 16, "----"),
+    op("RES 6, A", 2, 8, "----"),
+    op("RES 7, B", 2, 8, "----"),
+    op("RES 7, C", 2, 8, "----"),
+    op("RES 7, D", 2, 8, "----"),
+    op("RES 7, E", 2, 8, "----"),
+    op("RES 7, H", 2, 8, "----"),
+    op("RES 7, L", 2, 8, "----"),
+    op("RES 7, (HL)", 2, 16, "----"),
+    op("RES 7, A", 2, 8, "----"),
+    // 0xCB 0xC0
+    op("SET 0, B", 2, 8, "----"),
+    op("SET 0, C", 2, 8, "----"),
+    op("SET 0, D", 2, 8, "----"),
+    op("SET 0, E", 2, 8, "----"),
+    op("SET 0, H", 2, 8, "----"),
+    op("SET 0, L", 2, 8, "----"),
+    op("SET 0, (HL)", 2, 16, "----"),
+    op("SET 0, A", 2, 8, "----"),
+    op("SET 1, B", 2, 8, "----"),
+    op("SET 1, C", 2, 8, "----"),
+    op("SET 1, D", 2, 8, "----"),
+    op("SET 1, E", 2, 8, "----"),
+    op("SET 1, H", 2, 8, "----"),
+    op("SET 1, L", 2, 8, "----"),
+    op("SET 1, (HL)", 2, 16, "----"),
+    op("SET 1, A", 2, 8, "----"),
+    // 0xCB 0xD0
+    op("SET 2, B", 2, 8, "----"),
+    op("SET 2, C", 2, 8, "----"),
+    op("SET 2, D", 2, 8, "----"),
+    op("SET 2, E", 2, 8, "----"),
+    op("SET 2, H", 2, 8, "----"),
+    op("SET 2, L", 2, 8, "----"),
+    op("SET 2, (HL)", 2, 16, "----"),
+    op("SET 2, A", 2, 8, "----"),
+    op("SET 3, B", 2, 8, "----"),
+    op("SET 3, C", 2, 8, "----"),
+    op("SET 3, D", 2, 8, "----"),
+    op("SET 3, E", 2, 8, "----"),
+    op("SET 3, H", 2, 8, "----"),
+    op("SET 3, L", 2, 8, "----"),
+    op("SET 3, (HL)", 2, 16, "----"),
+    op("SET 3, A", 2, 8, "----"),
+    // 0xCB 0xE0
+    op("SET 4, B", 2, 8, "----"),
+    op("SET 4, C", 2, 8, "----"),
+    op("SET 4, D", 2, 8, "----"),
+    op("SET 4, E", 2, 8, "----"),
+    op("SET 4, H", 2, 8, "----"),
+    op("SET 4, L", 2, 8, "----"),
+    op("SET 4, (HL)", 2, 16, "----"),
+    op("SET 4, A", 2, 8, "----"),
+    op("SET 5, B", 2, 8, "----"),
+    op("SET 5, C", 2, 8, "----"),
+    op("SET 5, D", 2, 8, "----"),
+    op("SET 5, E", 2, 8, "----"),
+    op("SET 5, H", 2, 8, "----"),
+    op("SET 5, L", 2, 8, "----"),
+    op("SET 5, (HL)", 2, 16, "----"),
+    op("SET 5, A", 2, 8, "----"),
+    // 0xCB 0xF0
+    op("SET 6, B", 2, 8, "----"),
+    op("SET 6, C", 2, 8, "----"),
+    op("SET 6, D", 2, 8, "----"),
+    op("SET 6, E", 2, 8, "----"),
+    op("SET 6, H", 2, 8, "----"),
+    op("SET 6, L", 2, 8, "----"),
+    op("SET 6, (HL)", 2, 16, "----"),
+    op("SET 6, A", 2, 8, "----"),
+    op("SET 7, B", 2, 8, "----"),
+    op("SET 7, C", 2, 8, "----"),
+    op("SET 7, D", 2, 8, "----"),
+    op("SET 7, E", 2, 8, "----"),
+    op("SET 7, H", 2, 8, "----"),
+    op("SET 7, L", 2, 8, "----"),
+    op("SET 7, (HL)", 2, 16, "----"),
+    op("SET 7, A", 2, 8, "----"),
+];
+
+pub fn get(byte: u8, prefixed: bool) -> &'static Opcode {
+    &TABLE[byte as usize + if prefixed { 256 } else { 0 }]
+}
+
+/// Metadata of the instruction starting with `bytes`, `None` if there are not enough bytes to
+/// decode it.
+pub fn decode(bytes: &[u8]) -> Option<&'static Opcode> {
+    match bytes {
+        [0xCB, byte, ..] => Some(get(*byte, true)),
+        [0xCB] | [] => None,
+        [byte, ..] => Some(get(*byte, false)),
+    }
+}
+
+const fn op(mnemonic: &'static str, length: u8, cycles: u8, flags: &str) -> Opcode {
+    let flags = flags.as_bytes();
+    Opcode {
+        mnemonic,
+        length,
+        cycles,
+        cycles_taken: None,
+        flags: [
+            flag(flags[0]),
+            flag(flags[1]),
+            flag(flags[2]),
+            flag(flags[3]),
+        ],
+    }
+}
+
+const fn branch(mnemonic: &'static str, length: u8, cycles: u8, cycles_taken: u8) -> Opcode {
+    Opcode {
+        cycles_taken: Some(cycles_taken),
+        ..op(mnemonic, length, cycles, "----")
+    }
+}
+
+const fn illegal() -> Opcode {
+    op("ILLEGAL", 0, 0, "----")
+}
+
+const fn flag(val: u8) -> FlagEffect {
+    match val {
+        b'-' => FlagEffect::Unaffected,
+        b'0' => FlagEffect::Reset,
+        b'1' => FlagEffect::Set,
+        _ => FlagEffect::Affected,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_prefixed() {
+        assert_eq!(decode(&[0xCB, 0x7E]).unwrap().mnemonic, "BIT 7, (HL)");
+        assert_eq!(decode(&[0xCB]), None);
+        assert_eq!(decode(&[0xD3]).map(Opcode::is_illegal), Some(true));
+        assert_eq!(
+            get(0xF1, false).flags,
+            [FlagEffect::Affected; 4],
+            "POP AF restores all flags"
+        );
+    }
+}
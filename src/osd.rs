@@ -0,0 +1,125 @@
+// On-screen text drawn into frontend buffers with a 3x5 pixel font. Letters are uppercase only.
+
+/// Size of a character, with one column and one row of spacing.
+pub const GLYPH_WIDTH: usize = 4;
+pub const GLYPH_HEIGHT: usize = 6;
+
+/// Rows of a glyph from the top, bit 2 is the left column.
+#[rustfmt::skip]
+const FONT: [(char, [u8; 5]); 58] = [
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b110, 0b001, 0b010, 0b100, 0b111]),
+    ('3', [0b110, 0b001, 0b010, 0b001, 0b110]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b110, 0b001, 0b110]),
+    ('6', [0b011, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b110]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    (';', [0b000, 0b010, 0b000, 0b010, 0b100]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('\\', [0b100, 0b100, 0b010, 0b001, 0b001]),
+    ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+    ('<', [0b001, 0b010, 0b100, 0b010, 0b001]),
+    ('?', [0b110, 0b001, 0b010, 0b000, 0b010]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('[', [0b011, 0b010, 0b010, 0b010, 0b011]),
+    (']', [0b110, 0b010, 0b010, 0b010, 0b110]),
+    ('\'', [0b010, 0b010, 0b000, 0b000, 0b000]),
+    ('`', [0b010, 0b001, 0b000, 0b000, 0b000]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+];
+
+fn glyph(c: char) -> [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    FONT.iter()
+        .find(|(glyph_char, _)| *glyph_char == c)
+        .or_else(|| FONT.iter().find(|(glyph_char, _)| *glyph_char == '?'))
+        .unwrap()
+        .1
+}
+
+/// Draws `text` with its top left corner at `x`, `y` of a buffer `width` pixels wide. Characters
+/// outside of the buffer are cut.
+pub fn draw_text(buff: &mut [u32], width: usize, x: usize, y: usize, text: &str, color: u32) {
+    let height = buff.len() / width;
+    for (idx, c) in text.chars().enumerate() {
+        let left = x + idx * GLYPH_WIDTH;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..3 {
+                let (px, py) = (left + col, y + row);
+                if bits & (0b100 >> col) != 0 && px < width && py < height {
+                    buff[py * width + px] = color;
+                }
+            }
+        }
+    }
+}
+
+/// Darkens a rectangle so text over the game stays readable.
+pub fn dim(buff: &mut [u32], width: usize, x: usize, y: usize, w: usize, h: usize) {
+    for row in buff.chunks_exact_mut(width).skip(y).take(h) {
+        for pixel in row.iter_mut().skip(x).take(w) {
+            *pixel = (*pixel >> 2) & 0x3F3F3F;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn draws_clipped_text() {
+        const W: usize = 6;
+        let mut buff = [0; W * 5];
+        draw_text(&mut buff, W, 1, 0, "t", 1);
+        assert_eq!(&buff[..W], &[0, 1, 1, 1, 0, 0]);
+        assert_eq!(&buff[W..2 * W], &[0, 0, 1, 0, 0, 0]);
+
+        // The second glyph is cut at the right border, the last row at the bottom.
+        let mut buff = [0; W * 4];
+        draw_text(&mut buff, W, 0, 0, "-~", 1);
+        assert_eq!(&buff[2 * W..3 * W], &[1, 1, 1, 0, 0, 1]);
+
+        dim(&mut buff, W, 0, 2, 1, 1);
+        assert_eq!(buff[2 * W], 0);
+    }
+}
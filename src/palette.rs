@@ -0,0 +1,51 @@
+// Colors of the 4 DMG shades. The PPU draws shades and they are colored by a palette when frames
+// are converted to RGB, like the tint of the different Game Boy screens.
+
+/// Shades of the DMG from color 0 (white) to 3 (black), the default.
+pub const GRAY: [u32; 4] = [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000];
+/// Greens of the original Game Boy screen.
+pub const GREEN: [u32; 4] = [0xE0F8D0, 0x88C070, 0x346856, 0x081820];
+/// Shades far apart in brightness and hue, easier to tell apart.
+pub const CONTRAST: [u32; 4] = [0xFFFFFF, 0xFFD000, 0xC02000, 0x000000];
+
+pub const NAMED: [(&str, [u32; 4]); 3] = [("gray", GRAY), ("green", GREEN), ("contrast", CONTRAST)];
+
+/// Parses a palette name of [`NAMED`] or 4 hex colors from white to black, e.g.
+/// `E0F8D0 88C070 346856 081820`.
+pub fn parse(text: &str) -> Result<[u32; 4], String> {
+    if let Some((_, palette)) = NAMED
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(text))
+    {
+        return Ok(*palette);
+    }
+    let colors = text
+        .split_whitespace()
+        .map(|color| u32::from_str_radix(color, 16).map_err(|e| format!("{color}: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    colors
+        .try_into()
+        .map_err(|_| "palette needs 4 colors".to_string())
+}
+
+/// Color of `gray`, a shade of [`GRAY`], in `palette`.
+pub fn recolor(gray: u8, palette: &[u32; 4]) -> u32 {
+    palette[3 - (gray / 0x55) as usize]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_and_colors() {
+        assert_eq!(parse("Green"), Ok(GREEN));
+        assert_eq!(parse("0 1 2 FFFFFF"), Ok([0, 1, 2, 0xFFFFFF]));
+        assert_eq!(parse("0 1 2"), Err("palette needs 4 colors".to_string()));
+        assert!(parse("blue").unwrap_err().starts_with("blue: "));
+
+        for (shade, gray) in [0xFF, 0xAA, 0x55, 0x00].into_iter().enumerate() {
+            assert_eq!(recolor(gray, &GREEN), GREEN[shade]);
+        }
+    }
+}
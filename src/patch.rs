@@ -0,0 +1,404 @@
+// ROM patches applied in memory before the cartridge header is parsed.
+//
+// IPS: https://zerosoft.zophar.net/ips.php
+// BPS: https://github.com/blakesmith/rombp/blob/master/docs/bps_spec.md
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: usize = 0x454F46;
+
+const BPS_MAGIC: &[u8] = b"BPS1";
+const BPS_FOOTER_SIZE: usize = 12;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchError {
+    UnknownFormat,
+    Truncated,
+    InvalidCommand(String),
+    ChecksumMismatch {
+        what: &'static str,
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "unknown patch format (expected IPS or BPS)"),
+            PatchError::Truncated => write!(f, "patch file is truncated"),
+            PatchError::InvalidCommand(msg) => write!(f, "invalid patch command: {msg}"),
+            PatchError::ChecksumMismatch {
+                what,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{what} checksum mismatch: expected 0x{expected:08X}, got 0x{actual:08X}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Detects the patch format by its header and applies it.
+pub fn apply(rom: Vec<u8>, patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(&rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+pub fn apply_ips(mut rom: Vec<u8>, patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut reader = Reader::new(patch);
+    if reader.bytes(IPS_MAGIC.len())? != IPS_MAGIC {
+        return Err(PatchError::UnknownFormat);
+    }
+
+    loop {
+        let offset = reader.be(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+
+        let size = reader.be(2)?;
+        let (len, data) = if size == 0 {
+            // RLE record.
+            let count = reader.be(2)?;
+            (count, vec![reader.byte()?; count])
+        } else {
+            (size, reader.bytes(size)?.to_vec())
+        };
+
+        if rom.len() < offset + len {
+            rom.resize(offset + len, 0);
+        }
+        rom[offset..offset + len].copy_from_slice(&data);
+    }
+
+    // Optional truncation extension.
+    if let Ok(new_len) = reader.be(3) {
+        rom.truncate(new_len);
+    }
+
+    Ok(rom)
+}
+
+pub fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE || !patch.starts_with(BPS_MAGIC) {
+        return Err(PatchError::UnknownFormat);
+    }
+
+    let (body, footer) = patch.split_at(patch.len() - BPS_FOOTER_SIZE);
+    let footer_u32 =
+        |idx: usize| u32::from_le_bytes(footer[idx * 4..idx * 4 + 4].try_into().unwrap());
+
+    verify_crc32("patch", footer_u32(2), crc32(&patch[..patch.len() - 4]))?;
+    verify_crc32("source", footer_u32(0), crc32(source))?;
+
+    let mut reader = Reader::new(body);
+    reader.bytes(BPS_MAGIC.len())?;
+
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+
+    if source_size != source.len() {
+        return Err(PatchError::InvalidCommand(format!(
+            "source size is 0x{:X}, but patch expects 0x{source_size:X}",
+            source.len()
+        )));
+    }
+
+    // The header can claim any size, the output only grows as far as the commands go.
+    let mut target = Vec::with_capacity(target_size.min(source.len() + patch.len()));
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+
+    while !reader.is_empty() {
+        let data = reader.varint()?;
+        let len = (data >> 2) + 1;
+        let end = target.len() + len;
+        if end > target_size {
+            return Err(PatchError::InvalidCommand(format!(
+                "writes up to 0x{end:X}, past the target size 0x{target_size:X}"
+            )));
+        }
+
+        match data & 0b11 {
+            // SourceRead
+            0 => {
+                let chunk = source.get(target.len()..end).ok_or(PatchError::Truncated)?;
+                target.extend_from_slice(chunk);
+            }
+            // TargetRead
+            1 => target.extend_from_slice(reader.bytes(len)?),
+            // SourceCopy
+            2 => {
+                source_offset = reader.relative_offset(source_offset)?;
+                let chunk = source_offset
+                    .checked_add(len)
+                    .and_then(|source_end| source.get(source_offset..source_end))
+                    .ok_or(PatchError::Truncated)?;
+                target.extend_from_slice(chunk);
+                source_offset += len;
+            }
+            // TargetCopy
+            3 => {
+                target_offset = reader.relative_offset(target_offset)?;
+                // Regions can overlap, so copy byte by byte.
+                for _ in 0..len {
+                    let byte = *target.get(target_offset).ok_or_else(|| {
+                        PatchError::InvalidCommand("TargetCopy reads past the output".to_string())
+                    })?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+            _ => unreachable!("command is 2 bits long"),
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(PatchError::InvalidCommand(format!(
+            "target size is 0x{:X}, but patch expects 0x{target_size:X}",
+            target.len()
+        )));
+    }
+    verify_crc32("target", footer_u32(1), crc32(&target))?;
+
+    Ok(target)
+}
+
+fn verify_crc32(what: &'static str, expected: u32, actual: u32) -> Result<(), PatchError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(PatchError::ChecksumMismatch {
+            what,
+            expected,
+            actual,
+        })
+    }
+}
+
+/// CRC-32 (ISO-HDLC), the one used by zip and BPS.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.pos.checked_add(len).ok_or(PatchError::Truncated)?;
+        let res = self.data.get(self.pos..end).ok_or(PatchError::Truncated)?;
+        self.pos = end;
+        Ok(res)
+    }
+
+    /// Big-endian number of `len` bytes.
+    fn be(&mut self, len: usize) -> Result<usize, PatchError> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |acc, &b| (acc << 8) | b as usize))
+    }
+
+    /// # Returns
+    ///
+    /// An error if the number doesn't fit in `usize`, it can't be a size or an offset.
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let overflow = || PatchError::InvalidCommand("number is too large".to_string());
+        let mut data = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let x = self.byte()?;
+            data = ((x & 0x7F) as usize)
+                .checked_mul(shift)
+                .and_then(|val| data.checked_add(val))
+                .ok_or_else(overflow)?;
+            if x & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(overflow)?;
+            data = data.checked_add(shift).ok_or_else(overflow)?;
+        }
+    }
+
+    fn relative_offset(&mut self, current: usize) -> Result<usize, PatchError> {
+        let data = self.varint()?;
+        let delta = data >> 1;
+        let res = if data & 1 == 1 {
+            current.checked_sub(delta)
+        } else {
+            current.checked_add(delta)
+        };
+        res.ok_or_else(|| PatchError::InvalidCommand("relative offset out of range".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bps_varint(mut data: usize, out: &mut Vec<u8>) {
+        loop {
+            let x = (data & 0x7F) as u8;
+            data >>= 7;
+            if data == 0 {
+                out.push(0x80 | x);
+                break;
+            }
+            out.push(x);
+            data -= 1;
+        }
+    }
+
+    fn bps_patch(source: &[u8], target: &[u8], commands: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        bps_varint(source.len(), &mut patch);
+        bps_varint(target.len(), &mut patch);
+        bps_varint(0, &mut patch);
+        patch.extend_from_slice(commands);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn ips_records() {
+        let patch = [
+            b"PATCH".as_slice(),
+            // Write 2 bytes at 0x0001.
+            &[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB],
+            // RLE: 3 times 0x11 at 0x0005 (extends the ROM).
+            &[0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x03, 0x11],
+            b"EOF",
+        ]
+        .concat();
+
+        let rom = apply(vec![0; 6], &patch).unwrap();
+        assert_eq!(rom, vec![0, 0xAA, 0xBB, 0, 0, 0x11, 0x11, 0x11]);
+    }
+
+    #[test]
+    fn ips_truncation() {
+        let patch = [b"PATCH".as_slice(), b"EOF", &[0x00, 0x00, 0x02]].concat();
+        assert_eq!(apply(vec![1, 2, 3, 4], &patch).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn ips_truncated_record() {
+        let patch = [b"PATCH".as_slice(), &[0x00, 0x00, 0x01, 0x00, 0x05, 0xAA]].concat();
+        assert_eq!(apply(vec![0; 4], &patch), Err(PatchError::Truncated));
+    }
+
+    #[test]
+    fn bps_all_commands() {
+        let source = [1, 2, 3, 4, 5, 6, 7, 8];
+        let target = [1, 2, 0xAA, 0xBB, 7, 8, 7, 8, 7];
+
+        let mut commands = vec![];
+        // SourceRead 2 bytes.
+        bps_varint(((2 - 1) << 2) | 0, &mut commands);
+        // TargetRead 2 bytes.
+        bps_varint(((2 - 1) << 2) | 1, &mut commands);
+        commands.extend_from_slice(&[0xAA, 0xBB]);
+        // SourceCopy 2 bytes from offset 6.
+        bps_varint(((2 - 1) << 2) | 2, &mut commands);
+        bps_varint(6 << 1, &mut commands);
+        // TargetCopy 3 bytes from offset 4 (overlapping with the output).
+        bps_varint(((3 - 1) << 2) | 3, &mut commands);
+        bps_varint(4 << 1, &mut commands);
+
+        let patch = bps_patch(&source, &target, &commands);
+        assert_eq!(apply(source.to_vec(), &patch).unwrap(), target.to_vec());
+    }
+
+    #[test]
+    fn bps_wrong_source() {
+        let source = [1, 2, 3];
+        let mut commands = vec![];
+        bps_varint(((3 - 1) << 2) | 0, &mut commands);
+        let patch = bps_patch(&source, &source, &commands);
+
+        assert!(matches!(
+            apply(vec![1, 2, 4], &patch),
+            Err(PatchError::ChecksumMismatch { what: "source", .. })
+        ));
+    }
+
+    #[test]
+    fn bps_overlong_varint() {
+        let source = [1, 2, 3];
+        let mut patch = BPS_MAGIC.to_vec();
+        bps_varint(source.len(), &mut patch);
+        // Target size.
+        patch.extend_from_slice(&[0x7F; 10]);
+        patch.extend_from_slice(&crc32(&source).to_le_bytes());
+        patch.extend_from_slice(&0u32.to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+
+        assert!(matches!(
+            apply(source.to_vec(), &patch),
+            Err(PatchError::InvalidCommand(_))
+        ));
+    }
+
+    #[test]
+    fn bps_writes_past_the_target() {
+        let source = [1, 2, 3];
+        let mut commands = vec![];
+        // TargetCopy repeating the output far past the target size.
+        bps_varint(((0x10000 - 1) << 2) | 3, &mut commands);
+        bps_varint(0, &mut commands);
+        let patch = bps_patch(&source, &source, &commands);
+
+        assert!(matches!(
+            apply(source.to_vec(), &patch),
+            Err(PatchError::InvalidCommand(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert_eq!(apply(vec![], b"NOPE"), Err(PatchError::UnknownFormat));
+    }
+}
@@ -0,0 +1,175 @@
+// Minimal PNG encoder, enough to export graphics without extra dependencies.
+// https://www.w3.org/TR/png/
+
+use std::io::Write;
+
+use crate::patch::crc32;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// Largest stored (uncompressed) deflate block.
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Writes a palette image with 2 bits per pixel.
+///
+/// `indices` has one palette index (0-3) per pixel, row by row.
+pub fn write_indexed(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    palette: &[[u8; 3]],
+    indices: &[u8],
+) -> std::io::Result<()> {
+    assert!(palette.len() <= 4);
+    assert_eq!(indices.len(), width * height);
+
+    out.write_all(&SIGNATURE)?;
+    // Bit depth 2, color type 3 (palette).
+    write_header(out, width, height, 2, 3)?;
+    write_chunk(out, b"PLTE", palette.as_flattened())?;
+
+    let mut scanlines = vec![];
+    for row in indices.chunks(width) {
+        // Filter type None.
+        scanlines.push(0);
+        for pixels in row.chunks(4) {
+            let mut byte = 0;
+            for (idx, &pixel) in pixels.iter().enumerate() {
+                byte |= (pixel & 0b11) << (6 - idx * 2);
+            }
+            scanlines.push(byte);
+        }
+    }
+    write_chunk(out, b"IDAT", &zlib_stored(&scanlines))?;
+
+    write_chunk(out, b"IEND", &[])
+}
+
+/// Writes a truecolor image with 8 bits per channel.
+///
+/// `pixels` has 3 bytes (RGB) per pixel, row by row.
+pub fn write_rgb(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+) -> std::io::Result<()> {
+    assert_eq!(pixels.len(), width * height * 3);
+
+    out.write_all(&SIGNATURE)?;
+    // Bit depth 8, color type 2 (RGB).
+    write_header(out, width, height, 8, 2)?;
+
+    let mut scanlines = Vec::with_capacity(pixels.len() + height);
+    for row in pixels.chunks(width * 3) {
+        // Filter type None.
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+    write_chunk(out, b"IDAT", &zlib_stored(&scanlines))?;
+
+    write_chunk(out, b"IEND", &[])
+}
+
+fn write_header(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+) -> std::io::Result<()> {
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Deflate, adaptive filtering, no interlace.
+    header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header)
+}
+
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut crc_data = kind.to_vec();
+    crc_data.extend_from_slice(data);
+    out.write_all(&crc_data)?;
+    out.write_all(&crc32(&crc_data).to_be_bytes())
+}
+
+/// Zlib stream of uncompressed deflate blocks. Images are small, so compression isn't worth it.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate, 32K window, no preset dictionary, fastest compression.
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        out.push(is_final as u8);
+        out.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MOD;
+        b = (b + a) % MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn indexed_image() {
+        let mut png = vec![];
+        let palette = [[0xFF; 3], [0xAA; 3], [0x55; 3], [0; 3]];
+        write_indexed(&mut png, 5, 2, &palette, &[0, 1, 2, 3, 1, 3, 3, 3, 3, 3]).unwrap();
+
+        assert_eq!(png[..8], SIGNATURE);
+        // IHDR
+        assert_eq!(png[8..16], [0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(png[16..29], [0, 0, 0, 5, 0, 0, 0, 2, 2, 3, 0, 0, 0]);
+        assert_eq!(png[29..33], crc32(&png[12..29]).to_be_bytes());
+        // PLTE
+        assert_eq!(png[33..41], [0, 0, 0, 12, b'P', b'L', b'T', b'E']);
+
+        // IDAT: zlib header, one final stored block, two scanlines of 2 bytes.
+        let idat = &png[57..];
+        assert_eq!(idat[..4], [0, 0, 0, 17]);
+        assert_eq!(idat[4..8], *b"IDAT");
+        let scanlines = [0, 0b00_01_10_11, 0b01_00_00_00, 0, 0xFF, 0b11_00_00_00];
+        assert_eq!(idat[8..15], [0x78, 0x01, 1, 6, 0, !6, 0xFF]);
+        assert_eq!(idat[15..21], scanlines);
+        assert_eq!(idat[21..25], adler32(&scanlines).to_be_bytes());
+
+        assert_eq!(
+            png[png.len() - 12..png.len() - 4],
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D']
+        );
+    }
+
+    #[test]
+    fn rgb_image() {
+        let mut png = vec![];
+        write_rgb(&mut png, 2, 1, &[1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(png[16..29], [0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+        let idat = &png[33..];
+        assert_eq!(idat[..8], [0, 0, 0, 18, b'I', b'D', b'A', b'T']);
+        assert_eq!(idat[15..22], [0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn adler32_reference() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+}
@@ -0,0 +1,349 @@
+// Game Boy Printer on the serial port, saving every print as a PNG strip.
+// https://gbdev.io/pandocs/Gameboy_Printer.html
+//
+// A packet is: magic 88 33, command, compression flag, data length (LE), data, checksum (LE, sum
+// of the bytes from the command to the data), then 2 bytes answered with 81 (alive) and the
+// status. Printed data is 20 tiles per row, in tile order, 16 rows of pixels per DATA packet.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{cpu::SerialDevice, png, rip::SHADES};
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const ALIVE: u8 = 0x81;
+
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM_ERROR: u8 = 1 << 0;
+const STATUS_PRINTING: u8 = 1 << 1;
+const STATUS_IMAGE_FULL: u8 = 1 << 2;
+const STATUS_UNPROCESSED: u8 = 1 << 3;
+
+const WIDTH_TILES: usize = 20;
+const TILE_SIZE: usize = 16;
+/// The printer RAM holds 9 DATA packets, a whole Game Boy Camera picture.
+const IMAGE_CAPACITY: usize = 9 * 2 * WIDTH_TILES * TILE_SIZE;
+/// Games wait for the printing flag to clear, a real print takes seconds of STATUS packets.
+const PRINTING_POLLS: u8 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLo,
+    LengthHi,
+    Data,
+    ChecksumLo,
+    ChecksumHi,
+    Alive,
+    Status,
+}
+
+pub struct Printer {
+    /// Where prints are saved.
+    dir: PathBuf,
+    stage: Stage,
+
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    /// Sum of the received bytes, compared with the checksum of the packet.
+    sum: u16,
+    checksum: u16,
+
+    /// Decompressed tile data waiting to be printed.
+    image: Vec<u8>,
+    status: u8,
+    /// STATUS packets left before the current print is done.
+    printing_polls: u8,
+    /// Saved prints and errors, see [`SerialDevice::take_warnings`].
+    messages: Vec<String>,
+}
+
+impl Printer {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            stage: Stage::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: vec![],
+            sum: 0,
+            checksum: 0,
+            image: vec![],
+            status: 0,
+            printing_polls: 0,
+            messages: vec![],
+        }
+    }
+
+    /// Runs the command of a complete packet.
+    fn execute(&mut self) {
+        if self.sum != self.checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            INIT => {
+                self.image.clear();
+                self.status = 0;
+                self.printing_polls = 0;
+            }
+            DATA => {
+                let data = if self.compressed {
+                    decompress(&self.data)
+                } else {
+                    std::mem::take(&mut self.data)
+                };
+                let free = IMAGE_CAPACITY - self.image.len();
+                self.image.extend(data.into_iter().take(free));
+            }
+            PRINT if self.data.len() >= 4 => {
+                // Margins and exposure don't matter for an image.
+                let (sheets, palette) = (self.data[0], self.data[2]);
+                if sheets > 0 && !self.image.is_empty() {
+                    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                    let path = self.dir.join(format!("print-{}.png", time.as_millis()));
+                    let message = match save_print(&self.image, palette, &path) {
+                        Ok(()) => format!("Printed {}", path.display()),
+                        Err(err) => format!("Can't save {}: {}", path.display(), err),
+                    };
+                    self.messages.push(message);
+                    self.status |= STATUS_PRINTING;
+                    self.printing_polls = PRINTING_POLLS;
+                }
+                self.image.clear();
+            }
+            STATUS if self.printing_polls > 0 => {
+                self.printing_polls -= 1;
+                if self.printing_polls == 0 {
+                    self.status &= !STATUS_PRINTING;
+                }
+            }
+            _ => {}
+        }
+
+        self.status &= !(STATUS_IMAGE_FULL | STATUS_UNPROCESSED);
+        if !self.image.is_empty() {
+            self.status |= STATUS_UNPROCESSED;
+        }
+        if self.image.len() >= IMAGE_CAPACITY {
+            self.status |= STATUS_IMAGE_FULL;
+        }
+    }
+}
+
+impl SerialDevice for Printer {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let mut reply = 0;
+        self.stage = match self.stage {
+            Stage::Magic(idx) if byte == MAGIC[idx] => {
+                if idx + 1 < MAGIC.len() {
+                    Stage::Magic(idx + 1)
+                } else {
+                    Stage::Command
+                }
+            }
+            Stage::Magic(_) => Stage::Magic((byte == MAGIC[0]) as usize),
+            Stage::Command => {
+                self.command = byte;
+                self.sum = byte as u16;
+                Stage::Compression
+            }
+            Stage::Compression => {
+                self.compressed = byte & 1 != 0;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                Stage::LengthLo
+            }
+            Stage::LengthLo => {
+                self.length = byte as u16;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                Stage::LengthHi
+            }
+            Stage::LengthHi => {
+                self.length |= (byte as u16) << 8;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                self.data.clear();
+                if self.length == 0 {
+                    Stage::ChecksumLo
+                } else {
+                    Stage::Data
+                }
+            }
+            Stage::Data => {
+                self.data.push(byte);
+                self.sum = self.sum.wrapping_add(byte as u16);
+                if self.data.len() == self.length as usize {
+                    Stage::ChecksumLo
+                } else {
+                    Stage::Data
+                }
+            }
+            Stage::ChecksumLo => {
+                self.checksum = byte as u16;
+                Stage::ChecksumHi
+            }
+            Stage::ChecksumHi => {
+                self.checksum |= (byte as u16) << 8;
+                self.execute();
+                Stage::Alive
+            }
+            Stage::Alive => {
+                reply = ALIVE;
+                Stage::Status
+            }
+            Stage::Status => {
+                reply = self.status;
+                Stage::Magic(0)
+            }
+        };
+        reply
+    }
+
+    /// The printer never drives the clock.
+    fn poll_external(&mut self, _reply: u8) -> Option<u8> {
+        None
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
+}
+
+/// Expands runs: a control byte with bit 7 set repeats the next byte (control & 0x7F) + 2 times,
+/// otherwise (control + 1) bytes follow as is.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut idx = 0;
+    while idx < data.len() {
+        let control = data[idx];
+        idx += 1;
+        if control & 0x80 != 0 {
+            if let Some(&byte) = data.get(idx) {
+                out.extend(std::iter::repeat_n(byte, (control & 0x7F) as usize + 2));
+            }
+            idx += 1;
+        } else {
+            let end = (idx + control as usize + 1).min(data.len());
+            out.extend_from_slice(&data[idx..end]);
+            idx = end;
+        }
+    }
+    out
+}
+
+/// Writes the tile data as a 160 pixels wide image, with color IDs mapped to shades by `palette`
+/// like BGP.
+fn save_print(image: &[u8], palette: u8, path: &Path) -> std::io::Result<()> {
+    let tile_rows = image.len() / (WIDTH_TILES * TILE_SIZE);
+    let (width, height) = (WIDTH_TILES * 8, tile_rows * 8);
+    let mut indices = vec![0; width * height];
+    for (tile_idx, tile) in image
+        .chunks_exact(TILE_SIZE)
+        .take(tile_rows * WIDTH_TILES)
+        .enumerate()
+    {
+        let (tile_x, tile_y) = (tile_idx % WIDTH_TILES * 8, tile_idx / WIDTH_TILES * 8);
+        for (row, planes) in tile.chunks_exact(2).enumerate() {
+            for col in 0..8 {
+                let bit = 7 - col;
+                let color = (((planes[1] >> bit) & 1) << 1) | ((planes[0] >> bit) & 1);
+                indices[(tile_y + row) * width + tile_x + col] = (palette >> (color * 2)) & 0b11;
+            }
+        }
+    }
+
+    std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    png::write_indexed(&mut out, width, height, &SHADES, &indices)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Sends a whole packet.
+    ///
+    /// # Returns
+    ///
+    /// The 2 bytes answered after the checksum.
+    fn send(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> [u8; 2] {
+        let mut packet = vec![command, compressed as u8];
+        packet.extend((data.len() as u16).to_le_bytes());
+        packet.extend_from_slice(data);
+        let sum = packet
+            .iter()
+            .fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+        packet.extend(sum.to_le_bytes());
+
+        for byte in MAGIC.into_iter().chain(packet) {
+            assert_eq!(printer.exchange(byte), 0);
+        }
+        [printer.exchange(0), printer.exchange(0)]
+    }
+
+    #[test]
+    fn decompress_runs() {
+        assert_eq!(
+            decompress(&[0x81, 0xAA, 0x01, 0x10, 0x20, 0x80, 0x55]),
+            [0xAA, 0xAA, 0xAA, 0x10, 0x20, 0x55, 0x55]
+        );
+    }
+
+    #[test]
+    fn prints_data_packets() {
+        let dir = std::env::temp_dir().join(format!("gbemu-printer-{}", std::process::id()));
+        let mut printer = Printer::new(dir.clone());
+
+        assert_eq!(send(&mut printer, INIT, false, &[]), [ALIVE, 0]);
+        // A band of black: 640 bytes of 0xFF as runs of 129 and 124 bytes.
+        let mut band = [0xFF, 0xFF].repeat(4);
+        band.extend([0x80 | (124 - 2), 0xFF]);
+        assert_eq!(
+            send(&mut printer, DATA, true, &band),
+            [ALIVE, STATUS_UNPROCESSED]
+        );
+        assert_eq!(printer.image.len(), 2 * WIDTH_TILES * TILE_SIZE);
+        send(&mut printer, DATA, false, &[]);
+
+        // Bad checksum.
+        for byte in MAGIC.into_iter().chain([STATUS, 0, 0, 0, 0xFF, 0xFF]) {
+            printer.exchange(byte);
+        }
+        assert_eq!(printer.exchange(0), ALIVE);
+        assert_eq!(
+            printer.exchange(0),
+            STATUS_CHECKSUM_ERROR | STATUS_UNPROCESSED
+        );
+
+        assert_eq!(
+            send(&mut printer, PRINT, false, &[1, 0x13, 0xE4, 0x40]),
+            [ALIVE, STATUS_PRINTING]
+        );
+        for _ in 1..PRINTING_POLLS {
+            assert_eq!(
+                send(&mut printer, STATUS, false, &[]),
+                [ALIVE, STATUS_PRINTING]
+            );
+        }
+        assert_eq!(send(&mut printer, STATUS, false, &[]), [ALIVE, 0]);
+
+        let prints: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(prints.len(), 1);
+        let messages = printer.take_warnings();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("Printed"), "{}", messages[0]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
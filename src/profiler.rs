@@ -0,0 +1,152 @@
+// Timing of the emulator's own hot paths. Scopes are compiled in only with the `profiler`
+// feature, without it every frame profile is empty.
+
+use std::{cell::Cell, time::Duration};
+
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// Decoding and executing instructions, without the hardware stepped after them.
+    CpuDispatch,
+    PpuDrawLine,
+    ApuCycle,
+    /// Converting the PPU buffer to RGB frames.
+    FrameConversion,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::CpuDispatch,
+        Scope::PpuDrawLine,
+        Scope::ApuCycle,
+        Scope::FrameConversion,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::CpuDispatch => "cpu",
+            Scope::PpuDrawLine => "ppu",
+            Scope::ApuCycle => "apu",
+            Scope::FrameConversion => "frame",
+        }
+    }
+}
+
+thread_local! {
+    /// Time spent in each scope since the last [`take_frame`].
+    static ELAPSED: Cell<[Duration; 4]> = const { Cell::new([Duration::ZERO; 4]) };
+}
+
+/// Adds the time until it's dropped to its scope. Created by [`crate::profile_scope`].
+pub struct ScopeGuard {
+    scope: Scope,
+    start: std::time::Instant,
+}
+
+impl ScopeGuard {
+    pub fn new(scope: Scope) -> Self {
+        Self {
+            scope,
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        ELAPSED.with(|cell| {
+            let mut all = cell.get();
+            all[self.scope as usize] += elapsed;
+            cell.set(all);
+        });
+    }
+}
+
+pub fn enabled() -> bool {
+    cfg!(feature = "profiler")
+}
+
+/// Time spent in each scope during one frame of the emulation thread.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameProfile {
+    /// Indexed by [`Scope`].
+    pub elapsed: [Duration; 4],
+}
+
+impl FrameProfile {
+    pub fn get(&self, scope: Scope) -> Duration {
+        self.elapsed[scope as usize]
+    }
+
+    /// Draws a stacked bar of the scopes in the bottom 3 rows. The full width is the time budget
+    /// of one frame, so the bar shows how close the emulator is to running slower than real time.
+    pub fn draw_overlay(&self, buff: &mut [u32; SCREEN_HEIGHT * SCREEN_WIDTH]) {
+        const SCOPE_COLORS: [u32; 4] = [0x30FF30, 0xFF30FF, 0x30FFFF, 0xFFFFFF];
+        const BAR_HEIGHT: usize = 3;
+
+        let budget = Duration::from_millis(crate::MILLIS_PER_FRAME).as_nanos();
+        let mut col = 0;
+        for scope in Scope::ALL {
+            let width = (self.get(scope).as_nanos() * SCREEN_WIDTH as u128 / budget) as usize;
+            for row in buff
+                .chunks_exact_mut(SCREEN_WIDTH)
+                .skip(SCREEN_HEIGHT - BAR_HEIGHT)
+            {
+                row[col.min(SCREEN_WIDTH)..(col + width).min(SCREEN_WIDTH)]
+                    .fill(SCOPE_COLORS[scope as usize]);
+            }
+            col += width;
+        }
+    }
+}
+
+impl std::fmt::Display for FrameProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, scope) in Scope::ALL.iter().enumerate() {
+            if idx != 0 {
+                write!(f, " ")?;
+            }
+            write!(
+                f,
+                "{}={:.3}ms",
+                scope.name(),
+                self.get(*scope).as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the time spent in each scope on this thread since the last call.
+pub fn take_frame() -> FrameProfile {
+    FrameProfile {
+        elapsed: ELAPSED.with(|cell| cell.replace([Duration::ZERO; 4])),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scopes_are_aggregated_per_frame() {
+        take_frame();
+        {
+            let _guard = ScopeGuard::new(Scope::ApuCycle);
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        drop(ScopeGuard::new(Scope::ApuCycle));
+
+        let profile = take_frame();
+        assert!(profile.get(Scope::ApuCycle) >= Duration::from_millis(2));
+        assert_eq!(profile.get(Scope::CpuDispatch), Duration::ZERO);
+        assert_eq!(take_frame(), FrameProfile::default());
+
+        let mut buff = [0; SCREEN_HEIGHT * SCREEN_WIDTH];
+        profile.draw_overlay(&mut buff);
+        assert_eq!(buff[(SCREEN_HEIGHT - 1) * SCREEN_WIDTH], 0x30FFFF);
+        assert_eq!(buff[(SCREEN_HEIGHT - 4) * SCREEN_WIDTH], 0);
+    }
+}
@@ -0,0 +1,191 @@
+// Rewind: a ring buffer of save states captured every few frames.
+//
+// Only the newest state is kept whole. Older ones are stored as the difference to the state after
+// them, which is mostly unchanged bytes between two close frames: XOR with the newer state and
+// encode the runs of zeros. Popping the newest state decodes the next one, and the oldest delta
+// can be dropped to stay within the memory budget without touching the others.
+
+use std::collections::VecDeque;
+
+use crate::cpu::CPU;
+
+pub struct Rewind {
+    interval: u32,
+    budget: usize,
+    frames: u32,
+    newest: Option<Vec<u8>>,
+    /// Oldest first, each one decoded with the state after it.
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+}
+
+impl Rewind {
+    /// Captures a state every `interval` frames and keeps at most `budget` bytes of them.
+    pub fn new(interval: u32, budget: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            budget,
+            frames: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+        }
+    }
+
+    /// Call after every emulated frame.
+    pub fn frame_done(&mut self, cpu: &mut CPU) {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            self.push(cpu.save_state());
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.newest.replace(state) {
+            let delta = encode_delta(&previous, self.newest.as_ref().unwrap());
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        while self.memory_used() > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.delta_bytes -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Takes the newest state, the one before it becomes the newest.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            self.delta_bytes -= delta.len();
+            self.newest = Some(decode_delta(&delta, &newest));
+        }
+        self.frames = 0;
+        Some(newest)
+    }
+
+    /// Number of states that can be rewound to.
+    pub fn len(&self) -> usize {
+        self.newest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.delta_bytes
+    }
+}
+
+/// Encodes `old` as runs of `[skip, len, len bytes]` XORed with `new`, skipping equal bytes.
+/// Lengths are LEB128 and the first value is the length of `old`.
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    write_varint(&mut out, old.len());
+    let diff = |i: usize| old[i] ^ new.get(i).copied().unwrap_or(0);
+    let mut pos = 0;
+    while pos < old.len() {
+        let start = (pos..old.len())
+            .find(|&i| diff(i) != 0)
+            .unwrap_or(old.len());
+        if start == old.len() {
+            break;
+        }
+        // A run ends at a few equal bytes, a single one is cheaper to copy than to skip.
+        let mut end = start;
+        while end < old.len() && (end..(end + 4).min(old.len())).any(|i| diff(i) != 0) {
+            end += 1;
+        }
+        write_varint(&mut out, start - pos);
+        write_varint(&mut out, end - start);
+        out.extend((start..end).map(diff));
+        pos = end;
+    }
+    out
+}
+
+fn decode_delta(delta: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut input = delta.iter().copied();
+    let len = read_varint(&mut input);
+    let mut old: Vec<u8> = (0..len).map(|i| new.get(i).copied().unwrap_or(0)).collect();
+    let mut pos = 0;
+    while pos < len {
+        let skip = read_varint(&mut input);
+        if skip == usize::MAX {
+            break;
+        }
+        pos += skip;
+        for _ in 0..read_varint(&mut input) {
+            old[pos] ^= input.next().unwrap();
+            pos += 1;
+        }
+    }
+    old
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// `usize::MAX` at the end of the input.
+fn read_varint(input: &mut impl Iterator<Item = u8>) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let Some(byte) = input.next() else {
+            return usize::MAX;
+        };
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delta_round_trip() {
+        let old: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let mut new = old.clone();
+        new[3] = 0;
+        new[500..520].fill(0xFF);
+        new.truncate(990);
+        let delta = encode_delta(&old, &new);
+        assert!(delta.len() < 60, "{}", delta.len());
+        assert_eq!(decode_delta(&delta, &new), old);
+        new.extend([1; 20]);
+        assert_eq!(decode_delta(&encode_delta(&old, &new), &new), old);
+    }
+
+    #[test]
+    fn pops_newest_first_within_budget() {
+        let state = |n: u8| {
+            let mut state = vec![0; 500];
+            state[n as usize] = n;
+            state
+        };
+        let mut rewind = Rewind::new(1, 540);
+        for n in 1..=10 {
+            rewind.push(state(n));
+        }
+        assert!(rewind.memory_used() <= 540);
+        assert!(rewind.len() > 2 && rewind.len() < 10);
+        let len = rewind.len();
+        for n in (11 - len as u8..=10).rev() {
+            assert_eq!(rewind.pop(), Some(state(n)));
+        }
+        assert!(rewind.is_empty());
+        assert_eq!(rewind.pop(), None);
+    }
+}
@@ -0,0 +1,116 @@
+// Exports graphics as indexed PNGs for asset ripping: the screen with the 4 DMG shades and tiles
+// with their raw color IDs, so the art can be recolored without guessing palettes.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{memory_bus::VIDEO_RAM_SIZE, png, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// 2 bytes per row of 8 pixels.
+const TILE_SIZE: usize = 16;
+/// Tiles in the 3 tile data blocks at $8000-$97FF.
+const TILE_COUNT: usize = 384;
+
+/// Shades of the DMG LCD, from color ID 0 (white) to 3 (black).
+pub const SHADES: [[u8; 3]; 4] = [[0xFF; 3], [0xAA; 3], [0x55; 3], [0x00; 3]];
+
+/// Writes the screen (0x00RRGGBB pixels, row by row, colored by `palette`) as an image indexed by
+/// DMG shades.
+pub fn export_screen(pixels: &[u32], palette: &[u32; 4], path: &Path) -> std::io::Result<()> {
+    assert_eq!(pixels.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+
+    let indices: Vec<u8> = pixels
+        .iter()
+        .map(|pixel| palette.iter().position(|color| color == pixel).unwrap_or(0) as u8)
+        .collect();
+
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    png::write_indexed(&mut out, SCREEN_WIDTH, SCREEN_HEIGHT, &SHADES, &indices)
+}
+
+/// Saves every tile with new pixel data seen in VRAM.
+pub struct TileRipper {
+    dir: PathBuf,
+    seen: HashSet<[u8; TILE_SIZE]>,
+}
+
+impl TileRipper {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Writes tiles not seen before as `tile-CRC32.png`, so tiles of different sessions with the
+    /// same pixels share the file.
+    ///
+    /// # Returns
+    ///
+    /// Number of new tiles.
+    pub fn scan(&mut self, vram: &[u8; VIDEO_RAM_SIZE]) -> std::io::Result<usize> {
+        let mut new_tiles = 0;
+        for tile in vram.chunks_exact(TILE_SIZE).take(TILE_COUNT) {
+            let tile: [u8; TILE_SIZE] = tile.try_into().unwrap();
+            if !self.seen.insert(tile) {
+                continue;
+            }
+
+            let path = self
+                .dir
+                .join(format!("tile-{:08X}.png", crate::patch::crc32(&tile)));
+            let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+            png::write_indexed(&mut out, 8, 8, &SHADES, &tile_color_ids(&tile))?;
+            new_tiles += 1;
+        }
+        Ok(new_tiles)
+    }
+}
+
+/// https://gbdev.io/pandocs/Tile_Data.html
+fn tile_color_ids(tile: &[u8; TILE_SIZE]) -> Vec<u8> {
+    tile.chunks_exact(2)
+        .flat_map(|row| {
+            let (lo, hi) = (row[0], row[1]);
+            (0..8)
+                .rev()
+                .map(move |bit| (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tile_color_ids_from_planes() {
+        let mut tile = [0; TILE_SIZE];
+        tile[0..2].copy_from_slice(&[0b1010_0000, 0b1100_0000]);
+        let ids = tile_color_ids(&tile);
+        assert_eq!(ids[..4], [3, 2, 1, 0]);
+        assert!(ids[4..].iter().all(|&id| id == 0));
+    }
+
+    #[test]
+    fn rips_unique_tiles_once() {
+        let dir = std::env::temp_dir().join(format!("gbemu-rip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut vram = [0; VIDEO_RAM_SIZE];
+        vram[TILE_SIZE] = 0xFF;
+        // Tile maps are not tile data.
+        vram[TILE_SIZE * TILE_COUNT] = 0xAA;
+
+        let mut ripper = TileRipper::new(dir.clone());
+        assert_eq!(ripper.scan(&vram).unwrap(), 2);
+        assert_eq!(ripper.scan(&vram).unwrap(), 0);
+        vram[TILE_SIZE * 2] = 0x0F;
+        assert_eq!(ripper.scan(&vram).unwrap(), 1);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
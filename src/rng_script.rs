@@ -0,0 +1,133 @@
+// Scripted values for DIV and LY reads.
+//
+// Many games seed their RNG from DIV or LY, so replaying the same inputs may still diverge. When a
+// script is installed, each read of these registers returns the next scripted value instead of the
+// real one (the internal timers keep running normally). Once a sequence is exhausted, reads fall
+// back to the real register.
+
+use std::cell::Cell;
+
+#[derive(Clone, Debug, Default)]
+pub struct RngScript {
+    div: Vec<u8>,
+    ly: Vec<u8>,
+    // Reads happen through `&MemoryBus`.
+    div_pos: Cell<usize>,
+    ly_pos: Cell<usize>,
+}
+
+impl RngScript {
+    pub fn new(div: Vec<u8>, ly: Vec<u8>) -> Self {
+        Self {
+            div,
+            ly,
+            ..Default::default()
+        }
+    }
+
+    /// Parses lines like `DIV 0x12 0x34 56` or `LY 0 144`. Empty lines and lines starting with `#`
+    /// are ignored.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut script = Self::default();
+
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+            let values = match tokens.next().map(str::to_ascii_uppercase).as_deref() {
+                Some("DIV") => &mut script.div,
+                Some("LY") => &mut script.ly,
+                Some(other) => {
+                    return Err(format!("line {}: unknown register {other}", line_idx + 1))
+                }
+                None => unreachable!("line is not empty"),
+            };
+
+            for token in tokens {
+                let parsed = match token.strip_prefix("0x") {
+                    Some(hex) => u8::from_str_radix(hex, 16),
+                    None => token.parse(),
+                };
+                let val = parsed.map_err(|err| format!("line {}: {token}: {err}", line_idx + 1))?;
+                values.push(val);
+            }
+        }
+
+        Ok(script)
+    }
+
+    /// The script in the format of [`RngScript::parse`], without the read positions.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (name, values) in [("DIV", &self.div), ("LY", &self.ly)] {
+            if !values.is_empty() {
+                text += name;
+                for val in values {
+                    text += &format!(" 0x{val:02X}");
+                }
+                text += "\n";
+            }
+        }
+        text
+    }
+
+    pub fn next_div(&self) -> Option<u8> {
+        next(&self.div, &self.div_pos)
+    }
+
+    pub fn next_ly(&self) -> Option<u8> {
+        next(&self.ly, &self.ly_pos)
+    }
+
+    /// Start both sequences from the beginning.
+    pub fn rewind(&self) {
+        self.div_pos.set(0);
+        self.ly_pos.set(0);
+    }
+}
+
+/// Scripts are equal when their sequences are, wherever they are read.
+impl PartialEq for RngScript {
+    fn eq(&self, other: &Self) -> bool {
+        self.div == other.div && self.ly == other.ly
+    }
+}
+
+impl Eq for RngScript {}
+
+fn next(values: &[u8], pos: &Cell<usize>) -> Option<u8> {
+    let val = values.get(pos.get()).copied()?;
+    pos.set(pos.get() + 1);
+    Some(val)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_consume() {
+        let script = RngScript::parse("# comment\nDIV 0x10 2\n\nly 144\n").unwrap();
+
+        assert_eq!(script.next_div(), Some(0x10));
+        assert_eq!(script.next_ly(), Some(144));
+        assert_eq!(script.next_div(), Some(2));
+        assert_eq!(script.next_div(), None);
+        assert_eq!(script.next_ly(), None);
+
+        script.rewind();
+        assert_eq!(script.next_div(), Some(0x10));
+
+        assert_eq!(script.to_text(), "DIV 0x10 0x02\nLY 0x90\n");
+        assert_eq!(RngScript::parse(&script.to_text()).unwrap(), script);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(RngScript::parse("TIMA 1").is_err());
+        assert!(RngScript::parse("DIV 256").is_err());
+    }
+}
@@ -0,0 +1,256 @@
+// Save states: the whole emulator state as a versioned binary blob.
+//
+// Every component lists its fields once in `SaveState::visit_state`. The same code writes them
+// when saving and overwrites them when loading, so both directions can't get out of sync.
+
+use crate::error::GbError;
+
+/// Marks the start of every save state.
+const MAGIC: &[u8; 4] = b"GBST";
+/// Changes whenever the layout changes, states of other versions are rejected.
+pub const VERSION: u16 = 9;
+
+pub(crate) trait SaveState {
+    fn visit_state(&mut self, state: &mut StateVisitor);
+}
+
+/// Writes visited values to a blob, or overwrites them with values read from one.
+pub(crate) enum StateVisitor<'a> {
+    Save(Vec<u8>),
+    Load {
+        data: &'a [u8],
+        pos: usize,
+        error: Option<String>,
+    },
+}
+
+impl<'a> StateVisitor<'a> {
+    pub fn saving() -> Self {
+        let mut state = Self::Save(MAGIC.to_vec());
+        let mut version = VERSION;
+        state.u16(&mut version);
+        state
+    }
+
+    pub fn loading(data: &'a [u8]) -> Result<Self, GbError> {
+        if data.get(..MAGIC.len()) != Some(MAGIC) {
+            return Err(GbError::InvalidState("not a save state".to_string()));
+        }
+
+        let mut state = Self::Load {
+            data,
+            pos: MAGIC.len(),
+            error: None,
+        };
+        let mut version = 0;
+        state.u16(&mut version);
+        state.check(version == VERSION, || {
+            format!("version {version} is not supported (expected {VERSION})")
+        });
+        state.finish_header()?;
+        Ok(state)
+    }
+
+    pub fn is_loading(&self) -> bool {
+        matches!(self, Self::Load { .. })
+    }
+
+    /// Fails loading with `reason` unless `ok`.
+    pub fn check(&mut self, ok: bool, reason: impl FnOnce() -> String) {
+        if let Self::Load { error, .. } = self {
+            if !ok && error.is_none() {
+                *error = Some(reason());
+            }
+        }
+    }
+
+    fn finish_header(&mut self) -> Result<(), GbError> {
+        match self {
+            Self::Load {
+                error: Some(err), ..
+            } => Err(GbError::InvalidState(err.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// # Returns
+    ///
+    /// The saved blob.
+    pub fn finish_save(self) -> Vec<u8> {
+        match self {
+            Self::Save(buf) => buf,
+            Self::Load { .. } => unreachable!("finished saving while loading"),
+        }
+    }
+
+    /// Fails if a value couldn't be loaded or the blob is longer than the state.
+    pub fn finish_load(self) -> Result<(), GbError> {
+        match self {
+            Self::Load {
+                data,
+                pos,
+                error: None,
+            } if pos == data.len() => Ok(()),
+            Self::Load {
+                error: Some(err), ..
+            } => Err(GbError::InvalidState(err)),
+            Self::Load { data, pos, .. } => Err(GbError::InvalidState(format!(
+                "{} unexpected bytes at the end",
+                data.len() - pos
+            ))),
+            Self::Save(_) => unreachable!("finished loading while saving"),
+        }
+    }
+
+    pub fn visit(&mut self, val: &mut (impl SaveState + ?Sized)) {
+        val.visit_state(self);
+    }
+
+    pub fn bytes(&mut self, val: &mut [u8]) {
+        match self {
+            Self::Save(buf) => buf.extend_from_slice(val),
+            Self::Load { data, pos, error } => {
+                if error.is_some() {
+                    return;
+                }
+                match data.get(*pos..*pos + val.len()) {
+                    Some(bytes) => {
+                        val.copy_from_slice(bytes);
+                        *pos += val.len();
+                    }
+                    None => *error = Some("the state is truncated".to_string()),
+                }
+            }
+        }
+    }
+
+    pub fn u8(&mut self, val: &mut u8) {
+        self.bytes(std::slice::from_mut(val));
+    }
+
+    pub fn bool(&mut self, val: &mut bool) {
+        let mut byte = *val as u8;
+        self.u8(&mut byte);
+        self.check(byte <= 1, || format!("{byte} is not a boolean"));
+        *val = byte == 1;
+    }
+
+    pub fn u16(&mut self, val: &mut u16) {
+        let mut bytes = val.to_le_bytes();
+        self.bytes(&mut bytes);
+        *val = u16::from_le_bytes(bytes);
+    }
+
+    pub fn u32(&mut self, val: &mut u32) {
+        let mut bytes = val.to_le_bytes();
+        self.bytes(&mut bytes);
+        *val = u32::from_le_bytes(bytes);
+    }
+
+    pub fn u64(&mut self, val: &mut u64) {
+        let mut bytes = val.to_le_bytes();
+        self.bytes(&mut bytes);
+        *val = u64::from_le_bytes(bytes);
+    }
+
+    /// Saved as u64, so states don't depend on the host.
+    pub fn usize(&mut self, val: &mut usize) {
+        let mut wide = *val as u64;
+        self.u64(&mut wide);
+        *val = wide as usize;
+    }
+
+    pub fn f32s(&mut self, vals: &mut [f32]) {
+        for val in vals {
+            let mut bits = val.to_bits();
+            self.u32(&mut bits);
+            *val = f32::from_bits(bits);
+        }
+    }
+
+    /// A value saved as its register byte, e.g. a palette or an enum.
+    pub fn as_u8<T: Copy>(
+        &mut self,
+        val: &mut T,
+        to_u8: impl Fn(T) -> u8,
+        from_u8: impl Fn(u8) -> Option<T>,
+    ) {
+        let mut byte = to_u8(*val);
+        self.u8(&mut byte);
+        match from_u8(byte) {
+            Some(new) => *val = new,
+            None => self.check(false, || format!("invalid value {byte}")),
+        }
+    }
+
+    pub fn option<T: SaveState + Default>(&mut self, val: &mut Option<T>) {
+        let mut some = val.is_some();
+        self.bool(&mut some);
+        if !some {
+            *val = None;
+            return;
+        }
+        self.visit(val.get_or_insert_with(T::default));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Default, Debug, PartialEq)]
+    struct Fields {
+        flag: bool,
+        word: u16,
+        samples: [f32; 2],
+    }
+
+    impl SaveState for Fields {
+        fn visit_state(&mut self, state: &mut StateVisitor) {
+            state.bool(&mut self.flag);
+            state.u16(&mut self.word);
+            state.f32s(&mut self.samples);
+        }
+    }
+
+    #[test]
+    fn roundtrip_and_errors() {
+        let mut saved = Fields {
+            flag: true,
+            word: 0x1234,
+            samples: [0.5, -1.0],
+        };
+        let mut state = StateVisitor::saving();
+        state.visit(&mut saved);
+        let blob = state.finish_save();
+
+        let mut loaded = Fields::default();
+        let mut state = StateVisitor::loading(&blob).unwrap();
+        state.visit(&mut loaded);
+        assert!(state.finish_load().is_ok());
+        assert_eq!(loaded, saved);
+
+        let load = |blob: &[u8]| {
+            let mut state = StateVisitor::loading(blob)?;
+            state.visit(&mut Fields::default());
+            state.finish_load()
+        };
+        let message = |blob: &[u8]| load(blob).unwrap_err().to_string();
+        assert_eq!(message(b"PNG"), "invalid save state: not a save state");
+        assert_eq!(
+            message(b"GBST\x00\x00"),
+            format!("invalid save state: version 0 is not supported (expected {VERSION})")
+        );
+        assert_eq!(
+            message(&blob[..blob.len() - 1]),
+            "invalid save state: the state is truncated"
+        );
+        assert_eq!(
+            message(&[&blob[..], &[0]].concat()),
+            "invalid save state: 1 unexpected bytes at the end"
+        );
+        let mut bad_bool = blob.clone();
+        bad_bool[6] = 2;
+        assert_eq!(message(&bad_bool), "invalid save state: 2 is not a boolean");
+    }
+}
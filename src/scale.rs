@@ -0,0 +1,65 @@
+// Integer scaling of a frame to a window of any size. The frame is centered with black borders
+// (letterboxing) so pixels stay square and equally sized.
+
+/// Largest factor `width`x`height` can be multiplied by to fit in `dst_width`x`dst_height`, at
+/// least 1.
+pub fn integer_factor(width: usize, height: usize, dst_width: usize, dst_height: usize) -> usize {
+    (dst_width / width).min(dst_height / height).max(1)
+}
+
+/// Draws `src` of `width`x`height` pixels to `dst`, resized to `dst_width`x`dst_height`. A window
+/// smaller than the frame crops it.
+pub fn letterbox(
+    src: &[u32],
+    width: usize,
+    height: usize,
+    dst: &mut Vec<u32>,
+    dst_width: usize,
+    dst_height: usize,
+) {
+    dst.clear();
+    dst.resize(dst_width * dst_height, 0);
+
+    let factor = integer_factor(width, height, dst_width, dst_height);
+    let scaled_width = (width * factor).min(dst_width);
+    let scaled_height = (height * factor).min(dst_height);
+    let left = (dst_width - scaled_width) / 2;
+    let top = (dst_height - scaled_height) / 2;
+    for y in 0..scaled_height {
+        let src_row = &src[y / factor * width..][..width];
+        let dst_row = &mut dst[(top + y) * dst_width + left..][..scaled_width];
+        for (x, pixel) in dst_row.iter_mut().enumerate() {
+            *pixel = src_row[x / factor];
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn factor_fits_both_sides() {
+        assert_eq!(integer_factor(160, 144, 640, 576), 4);
+        assert_eq!(integer_factor(160, 144, 1920, 1080), 7);
+        assert_eq!(integer_factor(160, 144, 100, 100), 1);
+    }
+
+    #[test]
+    fn frame_is_centered() {
+        let src = [1, 2, 3, 4];
+        let mut dst = vec![];
+        letterbox(&src, 2, 2, &mut dst, 6, 5);
+        #[rustfmt::skip]
+        assert_eq!(dst, [
+            0, 1, 1, 2, 2, 0,
+            0, 1, 1, 2, 2, 0,
+            0, 3, 3, 4, 4, 0,
+            0, 3, 3, 4, 4, 0,
+            0, 0, 0, 0, 0, 0,
+        ]);
+
+        letterbox(&src, 2, 2, &mut dst, 1, 1);
+        assert_eq!(dst, [1]);
+    }
+}
@@ -0,0 +1,225 @@
+// Built-in sanity checks that need no external ROMs, run by `gbemu --selftest`.
+
+use crate::{
+    audio_player::VoidAudioPlayer, cpu::CPU, gpu::GPU, memory_bus::MemoryBus, model::Model,
+    sound::Sound, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+pub struct Check {
+    pub name: &'static str,
+    run: fn() -> Result<(), String>,
+}
+
+pub const CHECKS: [Check; 5] = [
+    Check {
+        name: "alu",
+        run: alu_vectors,
+    },
+    Check {
+        name: "timer",
+        run: timer_overflow,
+    },
+    Check {
+        name: "ppu",
+        run: ppu_test_pattern,
+    },
+    Check {
+        name: "apu",
+        run: apu_register_masks,
+    },
+    Check {
+        name: "demo",
+        run: crate::demo::smoke_test,
+    },
+];
+
+impl Check {
+    pub fn run(&self) -> Result<(), String> {
+        (self.run)()
+    }
+}
+
+/// Runs every check and prints the results.
+///
+/// # Returns
+///
+/// Whether all checks passed.
+pub fn run_and_print() -> bool {
+    let mut failed = 0;
+    for check in &CHECKS {
+        match check.run() {
+            Ok(()) => println!("{:<6} ok", check.name),
+            Err(err) => {
+                println!("{:<6} FAILED: {err}", check.name);
+                failed += 1;
+            }
+        }
+    }
+    println!("{} passed, {failed} failed", CHECKS.len() - failed);
+    failed == 0
+}
+
+/// Instructions that leave a result in A, with the expected A and F.
+const ALU_VECTORS: [(&str, &[u8], u8, u8); 11] = [
+    // LD A,d8; ADD A,d8
+    ("ADD carry", &[0x3E, 0x3A, 0xC6, 0xC6], 0x00, 0xB0),
+    ("ADD half carry", &[0x3E, 0x0F, 0xC6, 0x01], 0x10, 0x20),
+    // SCF; LD A,d8; ADC A,d8
+    ("ADC", &[0x37, 0x3E, 0xE1, 0xCE, 0x0F], 0xF1, 0x20),
+    // LD A,d8; SUB A,d8
+    ("SUB zero", &[0x3E, 0x3E, 0xD6, 0x3E], 0x00, 0xC0),
+    ("SUB half borrow", &[0x3E, 0x3E, 0xD6, 0x0F], 0x2F, 0x60),
+    ("SUB borrow", &[0x3E, 0x3E, 0xD6, 0x40], 0xFE, 0x50),
+    // LD A,d8; AND/XOR/OR/CP d8
+    ("AND", &[0x3E, 0x5A, 0xE6, 0x3F], 0x1A, 0x20),
+    ("XOR", &[0x3E, 0xFF, 0xEE, 0xFF], 0x00, 0x80),
+    ("OR", &[0x3E, 0x00, 0xF6, 0x00], 0x00, 0x80),
+    ("CP", &[0x3E, 0x3C, 0xFE, 0x2F], 0x3C, 0x60),
+    // LD A,d8; ADD A,d8; DAA
+    ("DAA", &[0x3E, 0x45, 0xC6, 0x38, 0x27], 0x83, 0x00),
+];
+
+fn alu_vectors() -> Result<(), String> {
+    const RESULTS: u16 = 0xC000;
+
+    let mut program = vec![];
+    for (idx, (_, code, _, _)) in ALU_VECTORS.iter().enumerate() {
+        let [lo, hi] = (RESULTS + idx as u16 * 2).to_le_bytes();
+        program.extend_from_slice(code);
+        // LD (a16),A; PUSH AF; POP BC; LD A,C; LD (a16),A
+        program.extend_from_slice(&[0xEA, lo, hi, 0xF5, 0xC1, 0x79, 0xEA, lo + 1, hi]);
+    }
+    // JR -2
+    program.extend_from_slice(&[0x18, 0xFE]);
+
+    let mut rom = vec![0; 0x8000];
+    // JP $0150, after the cartridge header.
+    rom[0x100..][..3].copy_from_slice(&[0xC3, 0x50, 0x01]);
+    rom[0x150..][..program.len()].copy_from_slice(&program);
+    let mut cpu = CPU::new_without_sound(rom);
+    let _ = cpu.run(10_000);
+
+    for (idx, (name, _, a, f)) in ALU_VECTORS.iter().enumerate() {
+        let addr = RESULTS + idx as u16 * 2;
+        let actual = (cpu.debug_read(addr), cpu.debug_read(addr + 1));
+        if actual != (*a, *f) {
+            return Err(format!(
+                "{name}: expected A={a:02X} F={f:02X}, got A={:02X} F={:02X}",
+                actual.0, actual.1
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn timer_overflow() -> Result<(), String> {
+    let mut bus = MemoryBus::with_model(
+        vec![0; 0x8000],
+        Box::new(VoidAudioPlayer::new()),
+        Model::Dmg,
+    );
+    bus.write_byte(0xFF0F, 0x00);
+    // TMA, TIMA, then start at 262144 Hz (every 16 cycles).
+    bus.write_byte(0xFF06, 0xF0);
+    bus.write_byte(0xFF05, 0xFE);
+    bus.write_byte(0xFF07, 0x05);
+
+    let timer_requested = |bus: &MemoryBus| bus.read_byte(0xFF0F) & 0b100 != 0;
+    bus.step(16);
+    if bus.read_byte(0xFF05) != 0xFF || timer_requested(&bus) {
+        return Err(format!(
+            "TIMA {:02X} before overflow",
+            bus.read_byte(0xFF05)
+        ));
+    }
+    for _ in 0..4 {
+        bus.step(4);
+    }
+    // TIMA reads 0 for one M-cycle before TMA is loaded.
+    if bus.read_byte(0xFF05) != 0 || timer_requested(&bus) {
+        return Err(format!(
+            "TIMA {:02X} right after overflow, expected 00",
+            bus.read_byte(0xFF05)
+        ));
+    }
+    bus.step(4);
+    if !timer_requested(&bus) {
+        return Err("no interrupt on overflow".to_string());
+    }
+    match bus.read_byte(0xFF05) {
+        0xF0 => Ok(()),
+        tima => Err(format!("TIMA {tima:02X} after overflow, expected TMA F0")),
+    }
+}
+
+fn ppu_test_pattern() -> Result<(), String> {
+    let mut gpu = GPU::new();
+    // Tile 0 has black even rows and white odd rows. Every map entry is tile 0.
+    for row in gpu.vram[..16].chunks_exact_mut(4) {
+        row.copy_from_slice(&[0xFF, 0xFF, 0x00, 0x00]);
+    }
+    gpu.bg_colors = crate::gpu::BackgroundColors::from(0b11_10_01_00);
+    // LCD on, $8000 tile data, $9800 map, BG on.
+    gpu.set_lcd_control(0b1001_0001);
+
+    let frame = gpu.frame_count();
+    while gpu.frame_count() == frame {
+        gpu.step(4);
+    }
+
+    let mut pixels = [0; SCREEN_HEIGHT * SCREEN_WIDTH];
+    gpu.to_rgb32(&mut pixels);
+    for (idx, &pixel) in pixels.iter().enumerate() {
+        let (x, y) = (idx % SCREEN_WIDTH, idx / SCREEN_WIDTH);
+        let expected = if y % 2 == 0 { 0x000000 } else { 0xFFFFFF };
+        if pixel != expected {
+            return Err(format!(
+                "pixel ({x}, {y}) is {pixel:06X}, expected {expected:06X}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Bits that always read as 1 after writing 0, from NR10 to NR52.
+/// https://gbdev.gg8.se/wiki/articles/Gameboy_sound_hardware#Register_Reading
+const APU_READ_MASKS: [u8; 23] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+];
+
+fn apu_register_masks() -> Result<(), String> {
+    let mut sound = Sound::with_model(Box::new(VoidAudioPlayer::new()), Model::Dmg);
+    sound.write_byte(0xFF26, 0x80);
+
+    for (addr, mask) in (0xFF10..).zip(APU_READ_MASKS) {
+        if addr != 0xFF26 {
+            sound.write_byte(addr, 0x00);
+        }
+        // NR52 reads as powered on with all channels off.
+        let expected = if addr == 0xFF26 { mask | 0x80 } else { mask };
+        let actual = sound.read_byte(addr);
+        if actual != expected {
+            return Err(format!(
+                "{:04X} reads {actual:02X}, expected {expected:02X}",
+                addr
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn all_checks_pass() {
+        for check in &CHECKS {
+            assert_eq!(check.run(), Ok(()), "{}", check.name);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+// https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+
+use crate::{
+    bit,
+    savestate::{SaveState, StateVisitor},
+};
+
+/// With the internal clock, one bit is shifted at 8192 Hz.
+const CYCLES_PER_BIT: u64 = crate::CPU_FREQ / 8192;
+
+/// The other end of the link cable, e.g. another Game Boy (see [`crate::link_cable`]). Bytes are
+/// exchanged whole instead of bit by bit.
+pub trait SerialDevice: Send {
+    /// This Game Boy drives the clock and sent `byte`.
+    ///
+    /// # Returns
+    ///
+    /// The byte of the other side, 0xFF if nothing answered.
+    fn exchange(&mut self, byte: u8) -> u8;
+
+    /// This Game Boy waits for the other side to drive the clock, with `reply` in SB. Called
+    /// regularly while the transfer is requested.
+    ///
+    /// # Returns
+    ///
+    /// The byte of the other side once it drove a transfer, which received `reply`.
+    fn poll_external(&mut self, reply: u8) -> Option<u8>;
+
+    /// Messages for the user since the last call, e.g. where a print was saved.
+    fn take_warnings(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+#[derive(Default)]
+pub struct Serial {
+    /// SB: bits are shifted out from the top and received bits are shifted in from the bottom.
+    data: u8,
+    /// SC bit 7: a transfer is requested or in progress.
+    transfer_enable: bool,
+    /// SC bit 0: this Game Boy drives the clock.
+    internal_clock: bool,
+
+    /// Bits shifted in the current transfer.
+    shifted_bits: u8,
+    cycles: u64,
+
+    /// Without a device, the cable is unplugged.
+    pub device: Option<Box<dyn SerialDevice>>,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read_byte(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF01 => self.data,
+            // Unused bits read as 1.
+            0xFF02 => 0b0111_1110 | ((self.transfer_enable as u8) << 7) | self.internal_clock as u8,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write_byte(&mut self, addr: u16, val: u8) {
+        match addr {
+            0xFF01 => self.data = val,
+            0xFF02 => {
+                self.transfer_enable = bit!(val, 7);
+                self.internal_clock = bit!(val, 0);
+                self.shifted_bits = 0;
+                self.cycles = 0;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Cycles until the transfer completes, if it's running, or until the device is polled for a
+    /// transfer driven by the other side.
+    pub fn cycles_until_interrupt(&self) -> Option<u64> {
+        if !self.transfer_enable {
+            return None;
+        }
+        if !self.internal_clock {
+            return self
+                .device
+                .as_ref()
+                .map(|_| CYCLES_PER_BIT.saturating_sub(self.cycles));
+        }
+        Some(CYCLES_PER_BIT - self.cycles + (7 - self.shifted_bits as u64) * CYCLES_PER_BIT)
+    }
+
+    /// # Returns
+    ///
+    /// Whether the transfer completed and the serial interrupt is requested.
+    pub fn step(&mut self, cycles: u64) -> bool {
+        if !self.transfer_enable {
+            return false;
+        }
+
+        self.cycles += cycles;
+        if !self.internal_clock {
+            return self.step_external();
+        }
+
+        while self.cycles >= CYCLES_PER_BIT {
+            self.cycles -= CYCLES_PER_BIT;
+            self.shifted_bits += 1;
+
+            // Without a cable, the input line is pulled high.
+            if self.device.is_none() {
+                self.data = (self.data << 1) | 1;
+            }
+
+            if self.shifted_bits == 8 {
+                if let Some(device) = &mut self.device {
+                    self.data = device.exchange(self.data);
+                }
+                self.finish_transfer();
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Without a remote Game Boy nobody drives the external clock, so the transfer never ends.
+    fn step_external(&mut self) -> bool {
+        // Polled once per bit time.
+        if self.cycles < CYCLES_PER_BIT {
+            return false;
+        }
+        self.cycles = 0;
+
+        let Some(received) = self
+            .device
+            .as_mut()
+            .and_then(|device| device.poll_external(self.data))
+        else {
+            return false;
+        };
+        self.data = received;
+        self.finish_transfer();
+        true
+    }
+
+    fn finish_transfer(&mut self) {
+        self.transfer_enable = false;
+        self.shifted_bits = 0;
+        self.cycles = 0;
+    }
+}
+
+impl SaveState for Serial {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.u8(&mut self.data);
+        state.bool(&mut self.transfer_enable);
+        state.bool(&mut self.internal_clock);
+        state.u8(&mut self.shifted_bits);
+        state.u64(&mut self.cycles);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn internal_clock_transfer_completes() {
+        let mut serial = Serial::new();
+        serial.write_byte(0xFF01, 0x42);
+        serial.write_byte(0xFF02, 0x81);
+        assert_eq!(serial.read_byte(0xFF02), 0xFF);
+
+        assert!(!serial.step(CYCLES_PER_BIT * 8 - 4));
+        assert_eq!(serial.read_byte(0xFF01), 0xFF >> 1);
+        assert!(serial.step(4));
+        assert_eq!(serial.read_byte(0xFF01), 0xFF);
+        assert_eq!(serial.read_byte(0xFF02), 0x7F);
+        assert!(!serial.step(CYCLES_PER_BIT * 8));
+    }
+
+    #[test]
+    fn external_clock_transfer_waits() {
+        let mut serial = Serial::new();
+        serial.write_byte(0xFF01, 0x42);
+        serial.write_byte(0xFF02, 0x80);
+
+        assert!(!serial.step(CYCLES_PER_BIT * 100));
+        assert_eq!(serial.read_byte(0xFF01), 0x42);
+        assert_eq!(serial.read_byte(0xFF02), 0xFE);
+    }
+
+    /// Answers with the complement and drives a transfer with 0x99 after `delay` polls.
+    struct Complement {
+        delay: u8,
+    }
+
+    impl SerialDevice for Complement {
+        fn exchange(&mut self, byte: u8) -> u8 {
+            !byte
+        }
+
+        fn poll_external(&mut self, reply: u8) -> Option<u8> {
+            assert_eq!(reply, 0x42);
+            self.delay = self.delay.checked_sub(1)?;
+            (self.delay == 0).then_some(0x99)
+        }
+    }
+
+    #[test]
+    fn transfers_with_a_device() {
+        let mut serial = Serial::new();
+        serial.device = Some(Box::new(Complement { delay: 3 }));
+
+        serial.write_byte(0xFF01, 0x42);
+        serial.write_byte(0xFF02, 0x81);
+        assert!(!serial.step(CYCLES_PER_BIT * 8 - 4));
+        assert!(serial.step(4));
+        assert_eq!(serial.read_byte(0xFF01), !0x42);
+
+        serial.write_byte(0xFF01, 0x42);
+        serial.write_byte(0xFF02, 0x80);
+        // Polled once per bit time.
+        assert!(!serial.step(CYCLES_PER_BIT - 4));
+        assert!(!serial.step(CYCLES_PER_BIT * 2));
+        assert!(!serial.step(CYCLES_PER_BIT));
+        assert!(serial.step(CYCLES_PER_BIT));
+        assert_eq!(serial.read_byte(0xFF01), 0x99);
+        assert_eq!(serial.read_byte(0xFF02), 0x7E);
+    }
+}
@@ -0,0 +1,368 @@
+// Frontend settings read from a file of `key = value` lines, reloaded when the file changes.
+//
+// palette = E0F8D0 88C070 346856 081820   # Colors of the 4 shades, from white to black, or
+//                                         # gray, green or contrast.
+// obj0_palette = FFFFFF FF8484 943A3A 000000
+//                                         # Instead of `palette` for one layer, also bg_palette,
+//                                         # window_palette and obj1_palette.
+// volume = 0.8                            # 0.0 - 1.0
+// dc_blocker = false
+// bind.quick_save = ctrl+s                # Key chord of a command, see `crate::commands`.
+// joypad.a = z, k                         # Keys of a joypad button, or `none`.
+// turbo.a = a                             # Keys pressing a button repeatedly while held.
+// turbo_rate = 15                         # Turbo presses per second.
+// gamepad.a = east, north                 # Controller buttons of a joypad button, or `none`.
+
+use std::{path::PathBuf, time::SystemTime};
+
+use crate::{
+    commands::{self, Bindings, Chord, Keymap, PadMap},
+    cpu::{JoypadKey, Layer},
+    input_macro::KEY_NAMES,
+};
+
+const LAYER_PALETTE_KEYS: [(&str, Layer); 4] = [
+    ("bg_palette", Layer::Background),
+    ("window_palette", Layer::Window),
+    ("obj0_palette", Layer::Obj0),
+    ("obj1_palette", Layer::Obj1),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub palette: [u32; 4],
+    /// Palettes of single layers, indexed by [`Layer`]. `palette` is used for the rest.
+    pub layer_palettes: [Option<[u32; 4]>; 4],
+    pub volume: f32,
+    pub dc_blocker: bool,
+    pub bindings: Bindings,
+    pub keymap: Keymap,
+    pub pad_map: PadMap,
+    /// Turbo keys of the joypad buttons, see [`crate::input`].
+    pub turbo: Keymap,
+    pub turbo_rate: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            palette: crate::palette::GRAY,
+            layer_palettes: [None; 4],
+            volume: 1.0,
+            dc_blocker: true,
+            bindings: commands::default_bindings(),
+            keymap: Keymap::default(),
+            pad_map: PadMap::default(),
+            turbo: Keymap::unbound(),
+            turbo_rate: crate::input::DEFAULT_TURBO_RATE,
+        }
+    }
+}
+
+impl Settings {
+    /// Values from `text`, the rest from `base`.
+    pub fn parse(text: &str, base: Settings) -> Result<Self, String> {
+        let mut settings = base;
+
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let err = |msg: String| format!("line {}: {msg}", line_idx + 1);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| err("expected `key = value`".to_string()))?;
+            let value = value.trim();
+            let parse_palette = || crate::palette::parse(value).map_err(err);
+            match key.trim() {
+                "palette" => settings.palette = parse_palette()?,
+                "volume" => {
+                    settings.volume = value.parse().map_err(|e| err(format!("{value}: {e}")))?;
+                    if !(0.0..=1.0).contains(&settings.volume) {
+                        return Err(err(format!("volume {value} is not in 0.0-1.0")));
+                    }
+                }
+                "dc_blocker" => {
+                    settings.dc_blocker =
+                        value.parse().map_err(|e| err(format!("{value}: {e}")))?;
+                }
+                other if other.starts_with("bind.") => {
+                    let name = &other["bind.".len()..];
+                    let command = commands::find_command(name)
+                        .ok_or_else(|| err(format!("unknown command {name}")))?;
+                    settings.bindings[command as usize] = match value {
+                        "none" => None,
+                        _ => Some(Chord::parse(value).map_err(err)?),
+                    };
+                }
+                other if other.starts_with("joypad.") => {
+                    let name = &other["joypad.".len()..];
+                    let (button, keys) =
+                        parse_button_inputs(name, value, commands::parse_key).map_err(err)?;
+                    if keys.contains(&minifb::Key::Escape) {
+                        return Err(err("escape quits the emulator".to_string()));
+                    }
+                    settings.keymap.set(button, &keys).map_err(err)?;
+                }
+                other if other.starts_with("turbo.") => {
+                    let name = &other["turbo.".len()..];
+                    let (button, keys) =
+                        parse_button_inputs(name, value, commands::parse_key).map_err(err)?;
+                    if keys.contains(&minifb::Key::Escape) {
+                        return Err(err("escape quits the emulator".to_string()));
+                    }
+                    settings.turbo.set(button, &keys).map_err(err)?;
+                }
+                "turbo_rate" => {
+                    settings.turbo_rate =
+                        value.parse().map_err(|e| err(format!("{value}: {e}")))?;
+                    if !(1..=30).contains(&settings.turbo_rate) {
+                        return Err(err(format!("turbo_rate {value} is not in 1-30")));
+                    }
+                }
+                other if other.starts_with("gamepad.") => {
+                    let name = &other["gamepad.".len()..];
+                    let (button, inputs) =
+                        parse_button_inputs(name, value, commands::parse_pad_button)
+                            .map_err(err)?;
+                    settings.pad_map.set(button, &inputs).map_err(err)?;
+                }
+                other => {
+                    let Some(&(_, layer)) =
+                        LAYER_PALETTE_KEYS.iter().find(|(key, _)| *key == other)
+                    else {
+                        return Err(err(format!("unknown setting {other}")));
+                    };
+                    settings.layer_palettes[layer as usize] = Some(parse_palette()?);
+                }
+            }
+        }
+
+        commands::check_conflicts(&settings.bindings, &settings.keymap)?;
+        commands::check_conflicts(&settings.bindings, &settings.turbo)?;
+        if let Some(key) = KEY_NAMES
+            .iter()
+            .flat_map(|&(_, button)| settings.turbo.keys(button))
+            .find(|&key| settings.keymap.contains(key))
+        {
+            return Err(format!(
+                "{} is both a joypad and a turbo key",
+                Chord::new(key)
+            ));
+        }
+        Ok(settings)
+    }
+
+    /// Recolors a screen colored by `palette` (see [`crate::CPU::set_palette`]) by the palette
+    /// of the layer of every pixel (see [`crate::gpu::GPU::to_layers`]).
+    pub fn apply_palette(&self, buff: &mut [u32], layers: &[Layer]) {
+        if self.layer_palettes.iter().all(Option::is_none) {
+            return;
+        }
+        for (pixel, layer) in buff.iter_mut().zip(layers) {
+            let Some(palette) = self.layer_palettes[*layer as usize] else {
+                continue;
+            };
+            if let Some(shade) = self.palette.iter().position(|color| color == pixel) {
+                *pixel = palette[shade];
+            }
+        }
+    }
+}
+
+/// Parses a joypad button `name` and its comma separated inputs, or `none`.
+fn parse_button_inputs<T>(
+    name: &str,
+    value: &str,
+    parse: fn(&str) -> Result<T, String>,
+) -> Result<(JoypadKey, Vec<T>), String> {
+    let &(_, button) = KEY_NAMES
+        .iter()
+        .find(|(button, _)| *button == name)
+        .ok_or_else(|| format!("unknown button {name}"))?;
+    let inputs = match value {
+        "none" => vec![],
+        _ => value
+            .split(',')
+            .map(|input| parse(input.trim()))
+            .collect::<Result<_, _>>()?,
+    };
+    Ok((button, inputs))
+}
+
+/// Reports new settings when the modification time of the file changes.
+pub struct SettingsWatcher {
+    path: PathBuf,
+    base: Settings,
+    modified: Option<SystemTime>,
+}
+
+impl SettingsWatcher {
+    pub fn new(path: PathBuf, base: Settings) -> Self {
+        Self {
+            path,
+            base,
+            modified: None,
+        }
+    }
+
+    /// # Returns
+    ///
+    /// `None` if the file didn't change since the last call.
+    pub fn poll(&mut self) -> Option<Result<Settings, String>> {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|meta| meta.modified())
+            .ok();
+        if modified.is_some() && modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        Some(
+            std::fs::read_to_string(&self.path)
+                .map_err(|err| format!("{}: {err}", self.path.display()))
+                .and_then(|text| Settings::parse(&text, self.base)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::Command;
+
+    #[test]
+    fn parse_settings() {
+        let settings = Settings::parse(
+            "# Green\npalette = E0F8D0 88C070 346856 081820\n\nvolume = 0.5 # Half\n",
+            Settings::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                palette: [0xE0F8D0, 0x88C070, 0x346856, 0x081820],
+                layer_palettes: [None; 4],
+                volume: 0.5,
+                dc_blocker: true,
+                bindings: commands::default_bindings(),
+                keymap: Keymap::default(),
+                pad_map: PadMap::default(),
+                turbo: Keymap::unbound(),
+                turbo_rate: crate::input::DEFAULT_TURBO_RATE,
+            }
+        );
+
+        let named = Settings::parse("palette = green", Settings::default()).unwrap();
+        assert_eq!(named.palette, settings.palette);
+    }
+
+    #[test]
+    fn layer_palettes() {
+        let settings = Settings::parse(
+            "obj0_palette = FFFFFF FF8484 943A3A 000000\nwindow_palette = 0 1 2 3",
+            Settings::default(),
+        )
+        .unwrap();
+        assert_eq!(settings.layer_palettes[Layer::Obj1 as usize], None);
+
+        let mut screen = [0x555555; 4];
+        let layers = [Layer::Background, Layer::Window, Layer::Obj0, Layer::Obj1];
+        settings.apply_palette(&mut screen, &layers);
+        assert_eq!(screen, [0x555555, 2, 0x943A3A, 0x555555]);
+
+        // Shades are found in the palette of the screen.
+        let settings = Settings::parse(
+            "palette = green
+bg_palette = gray",
+            settings,
+        )
+        .unwrap();
+        let mut screen = [0x346856, 0x346856];
+        settings.apply_palette(&mut screen, &[Layer::Background, Layer::Obj1]);
+        assert_eq!(screen, [0x555555, 0x346856]);
+    }
+
+    #[test]
+    fn key_bindings() {
+        let settings = Settings::parse(
+            "bind.quick_save = ctrl+s\nbind.dump_memory = none",
+            Settings::default(),
+        )
+        .unwrap();
+        let bound = |command: Command| settings.bindings[command as usize];
+        assert_eq!(bound(Command::QuickSave), Chord::parse("ctrl+s").ok());
+        assert_eq!(bound(Command::DumpMemory), None);
+        assert_eq!(bound(Command::Pause), Some(Chord::new(minifb::Key::P)));
+    }
+
+    #[test]
+    fn joypad_keys() {
+        use crate::commands::PadButton;
+        use minifb::Key;
+
+        let settings = Settings::parse(
+            "joypad.a = z, K\njoypad.select = none\nbind.pause = space\ngamepad.b = west, l1",
+            Settings::default(),
+        )
+        .unwrap();
+        let keys = |button| settings.keymap.keys(button).collect::<Vec<_>>();
+        assert_eq!(keys(JoypadKey::A), [Key::Z, Key::K]);
+        assert_eq!(keys(JoypadKey::Select), []);
+        assert_eq!(keys(JoypadKey::Start), [Key::Enter]);
+        let pad = |button| settings.pad_map.keys(button).collect::<Vec<_>>();
+        assert_eq!(pad(JoypadKey::B), [PadButton::West, PadButton::LeftTrigger]);
+        assert_eq!(pad(JoypadKey::A), [PadButton::East]);
+    }
+
+    #[test]
+    fn turbo_keys() {
+        use minifb::Key;
+
+        let settings = Settings::parse(
+            "turbo.a = a
+turbo.b = c
+turbo_rate = 20",
+            Settings::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            settings.turbo.buttons(Key::A).collect::<Vec<_>>(),
+            [JoypadKey::A]
+        );
+        assert_eq!(settings.turbo.keys(JoypadKey::Start).count(), 0);
+        assert_eq!(settings.turbo_rate, 20);
+    }
+
+    #[test]
+    fn invalid_settings() {
+        for (text, err) in [
+            ("volume 1", "line 1: expected `key = value`"),
+            ("\nvolume = 2", "line 2: volume 2 is not in 0.0-1.0"),
+            ("palette = FFFFFF", "line 1: palette needs 4 colors"),
+            ("bg_palette = 1 2 3", "line 1: palette needs 4 colors"),
+            ("speed = 2", "line 1: unknown setting speed"),
+            ("bind.fly = a", "line 1: unknown command fly"),
+            ("joypad.c = a", "line 1: unknown button c"),
+            ("joypad.a = z,", "line 1: unknown key "),
+            ("joypad.b = escape", "line 1: escape quits the emulator"),
+            ("gamepad.a = mode", "line 1: unknown controller button mode"),
+            ("joypad.a = p", "p of pause is reserved for the joypad"),
+            ("turbo.a = r", "r of soft_reset is reserved for the joypad"),
+            ("turbo.b = x", "x is both a joypad and a turbo key"),
+            ("turbo_rate = 0", "line 1: turbo_rate 0 is not in 1-30"),
+            ("bind.pause = ctrl+", "line 1: unknown key "),
+            (
+                "bind.quick_load = f5",
+                "f5 is bound to both quick_save and quick_load",
+            ),
+        ] {
+            assert_eq!(
+                Settings::parse(text, Settings::default()),
+                Err(err.to_string())
+            );
+        }
+    }
+}
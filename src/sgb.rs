@@ -0,0 +1,628 @@
+// Super Game Boy functions: games send command packets through the joypad port to color the
+// screen with 4 palettes over 8x8 cells, read more controllers and draw a border around the
+// screen. Only games with the SGB flag in their header can use them.
+// https://gbdev.io/pandocs/SGB_Functions.html
+
+use crate::{
+    gpu::{tile_data_offset, tile_map_offset, Coordinate, GPU},
+    savestate::{SaveState, StateVisitor},
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+/// Size of the picture with the border.
+pub const SGB_WIDTH: usize = 256;
+pub const SGB_HEIGHT: usize = 224;
+/// Top left corner of the game screen in the border.
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+
+/// https://gbdev.io/pandocs/The_Cartridge_Header.html#0146--sgb-flag
+const SGB_FLAG_ADDR: usize = 0x146;
+
+/// The screen is colored by 8x8 cells, each with one of the 4 palettes.
+const CELLS_X: usize = SCREEN_WIDTH / 8;
+const CELLS_Y: usize = SCREEN_HEIGHT / 8;
+const CELLS: usize = CELLS_X * CELLS_Y;
+
+/// Palettes sent with PAL_TRN, picked with PAL_SET.
+const SYSTEM_PALETTES: usize = 512;
+/// Attribute files sent with ATTR_TRN, 2 bits per cell.
+const ATTR_FILES: usize = 45;
+const ATTR_FILE_SIZE: usize = CELLS / 4;
+
+/// 4 bits per pixel tiles of the border, sent in halves with CHR_TRN.
+const BORDER_TILES: usize = 256;
+const BORDER_TILE_SIZE: usize = 32;
+/// Tile map of the border, 32x28 tiles visible out of 32x32.
+const BORDER_MAP_SIZE: usize = 32 * 32;
+const BORDER_PALETTES: usize = 4;
+
+/// Data sent by VRAM transfers: the tiles shown in the first 256 cells of the screen.
+const TRANSFER_SIZE: usize = 0x1000;
+
+const PACKET_SIZE: usize = 16;
+/// Commands are up to 7 packets long.
+const MAX_PACKETS: usize = 7;
+
+/// DMG colors in RGB555, used until a game sends its palettes.
+const GRAYS: [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+
+/// What is shown instead of the game during transfers, set by MASK_EN.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Mask {
+    #[default]
+    None,
+    /// The last frame before the mask.
+    Freeze,
+    Black,
+    /// Color 0 of the palettes.
+    Color0,
+}
+
+pub struct Sgb {
+    /// The game has the SGB flag, other games can't send packets.
+    active: bool,
+    /// P14 and P15 last written to the joypad register.
+    lines: u8,
+    /// Bits of the packet being received since a reset pulse.
+    packet_bits: Option<usize>,
+    packet: [u8; PACKET_SIZE],
+    /// Packets of the command being received.
+    command: Vec<u8>,
+
+    /// Controllers read in turn: 1, 2 or 4. Only the first one has keys.
+    players: u8,
+    player: u8,
+
+    /// RGB555 colors, color 0 is shared by all palettes.
+    palettes: [[u16; 4]; 4],
+    system_palettes: Vec<[u16; 4]>,
+    /// Palette of each cell.
+    attributes: [u8; CELLS],
+    attr_files: Vec<u8>,
+    mask: Mask,
+    /// Shades of the screen when it was frozen.
+    frozen: Vec<u8>,
+
+    border_tiles: Vec<u8>,
+    border_map: Vec<u16>,
+    border_palettes: [[u16; 16]; BORDER_PALETTES],
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Self {
+            active: false,
+            lines: 0x30,
+            packet_bits: None,
+            packet: [0; PACKET_SIZE],
+            command: vec![],
+            players: 1,
+            player: 0,
+            palettes: [GRAYS; 4],
+            system_palettes: vec![GRAYS; SYSTEM_PALETTES],
+            attributes: [0; CELLS],
+            attr_files: vec![0; ATTR_FILES * ATTR_FILE_SIZE],
+            mask: Mask::None,
+            frozen: vec![0; CELLS * 64],
+            border_tiles: vec![0; BORDER_TILES * BORDER_TILE_SIZE],
+            border_map: vec![0; BORDER_MAP_SIZE],
+            border_palettes: [[0; 16]; BORDER_PALETTES],
+        }
+    }
+}
+
+impl Sgb {
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            active: rom.get(SGB_FLAG_ADDR) == Some(&0x03),
+            ..Self::default()
+        }
+    }
+
+    /// The game can use SGB functions, so its screen is colored by [`Sgb::to_rgb32`].
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Receives packet bits from a write to the joypad register.
+    /// https://gbdev.io/pandocs/SGB_Command_Packet.html
+    pub fn write_joypad(&mut self, val: u8, gpu: &GPU) {
+        let lines = val & 0x30;
+        let old = std::mem::replace(&mut self.lines, lines);
+        if !self.active {
+            return;
+        }
+
+        match lines {
+            // Reset pulse: both lines low.
+            0x00 => {
+                self.packet_bits = Some(0);
+                self.packet = [0; PACKET_SIZE];
+            }
+            // A bit is a pulse on P15 for 1 and on P14 for 0, with both lines high between them.
+            0x10 | 0x20 if old == 0x30 => {
+                let Some(bits) = self.packet_bits else {
+                    return;
+                };
+                let bit = lines == 0x10;
+                if bits < PACKET_SIZE * 8 {
+                    self.packet[bits / 8] |= (bit as u8) << (bits % 8);
+                    self.packet_bits = Some(bits + 1);
+                } else {
+                    // Stop bit, always 0.
+                    self.packet_bits = None;
+                    if !bit {
+                        self.receive_packet(gpu);
+                    }
+                }
+            }
+            // Both lines high after reading a controller selects the next one.
+            0x30 if self.packet_bits.is_none() && old & 0x20 == 0 && self.players > 1 => {
+                self.player = (self.player + 1) % self.players;
+            }
+            _ => (),
+        }
+    }
+
+    /// The joypad register as seen by the game, with the ID of the controller when no keys are
+    /// selected.
+    pub fn read_joypad(&self, val: u8) -> u8 {
+        if self.players == 1 {
+            val
+        } else if self.lines == 0x30 {
+            (val & 0xF0) | (0x0F - self.player)
+        } else if self.player != 0 {
+            val | 0x0F
+        } else {
+            val
+        }
+    }
+
+    fn receive_packet(&mut self, gpu: &GPU) {
+        if self.command.is_empty() && self.packet[0] & 7 == 0 {
+            // Commands are at least 1 packet long.
+            return;
+        }
+        self.command.extend_from_slice(&self.packet);
+        let packets = (self.command[0] & 7) as usize;
+        if self.command.len() >= packets.min(MAX_PACKETS) * PACKET_SIZE {
+            let command = std::mem::take(&mut self.command);
+            self.run_command(&command, gpu);
+        }
+    }
+
+    fn run_command(&mut self, data: &[u8], gpu: &GPU) {
+        let color = |idx: usize| u16::from_le_bytes([data[1 + idx * 2], data[2 + idx * 2]]);
+        match data[0] >> 3 {
+            // PAL01, PAL23, PAL03, PAL12.
+            cmd @ 0x00..=0x03 => {
+                let (first, second) = [(0, 1), (2, 3), (0, 3), (1, 2)][cmd as usize];
+                for palette in &mut self.palettes {
+                    palette[0] = color(0);
+                }
+                for idx in 1..4 {
+                    self.palettes[first][idx] = color(idx);
+                    self.palettes[second][idx] = color(idx + 3);
+                }
+            }
+            0x04 => self.attr_blk(data),
+            0x05 => self.attr_lin(data),
+            0x06 => self.attr_div(data),
+            0x07 => self.attr_chr(data),
+            // PAL_SET.
+            0x0A => {
+                for (idx, palette) in self.palettes.iter_mut().enumerate() {
+                    let system = color(idx) as usize % SYSTEM_PALETTES;
+                    *palette = self.system_palettes[system];
+                }
+                let shared = self.palettes[0][0];
+                for palette in &mut self.palettes {
+                    palette[0] = shared;
+                }
+                self.set_attr_file(data[9]);
+            }
+            // PAL_TRN.
+            0x0B => {
+                let data = vram_transfer(gpu);
+                for (palette, colors) in self.system_palettes.iter_mut().zip(data.chunks(8)) {
+                    for (color, bytes) in palette.iter_mut().zip(colors.chunks(2)) {
+                        *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+                    }
+                }
+            }
+            // MLT_REQ.
+            0x11 => {
+                self.players = [1, 2, 1, 4][data[1] as usize & 3];
+                self.player = 0;
+            }
+            // CHR_TRN.
+            0x13 => {
+                let half = (data[1] & 1) as usize * TRANSFER_SIZE;
+                self.border_tiles[half..half + TRANSFER_SIZE].copy_from_slice(&vram_transfer(gpu));
+            }
+            // PCT_TRN.
+            0x14 => {
+                let data = vram_transfer(gpu);
+                let (map, palettes) = data.split_at(BORDER_MAP_SIZE * 2);
+                for (entry, bytes) in self.border_map.iter_mut().zip(map.chunks(2)) {
+                    *entry = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+                let colors = self.border_palettes.iter_mut().flatten();
+                for (color, bytes) in colors.zip(palettes.chunks(2)) {
+                    *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+            }
+            // ATTR_TRN.
+            0x15 => {
+                let data = vram_transfer(gpu);
+                self.attr_files
+                    .copy_from_slice(&data[..ATTR_FILES * ATTR_FILE_SIZE]);
+            }
+            // ATTR_SET.
+            0x16 => self.set_attr_file(data[1] | 0x80),
+            // MASK_EN.
+            0x17 => {
+                self.mask = match data[1] & 3 {
+                    0 => Mask::None,
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    _ => Mask::Color0,
+                };
+                if self.mask == Mask::Freeze {
+                    self.frozen = shades(gpu).collect();
+                }
+            }
+            // Sound, data transfers to the SNES and other commands aren't emulated.
+            _ => (),
+        }
+    }
+
+    /// Applies an attribute file when bit 7 is set, bit 6 cancels the mask.
+    fn set_attr_file(&mut self, val: u8) {
+        if val & 0x80 != 0 {
+            let file = (val & 0x3F) as usize % ATTR_FILES;
+            let data = &self.attr_files[file * ATTR_FILE_SIZE..][..ATTR_FILE_SIZE];
+            for (cell, attribute) in self.attributes.iter_mut().enumerate() {
+                *attribute = (data[cell / 4] >> (6 - cell % 4 * 2)) & 3;
+            }
+        }
+        if val & 0x40 != 0 {
+            self.mask = Mask::None;
+        }
+    }
+
+    /// Colors rectangles of cells: their insides, their edges and what's outside of them.
+    fn attr_blk(&mut self, data: &[u8]) {
+        let sets = (data[1] as usize).min((data.len() - 2) / 6);
+        for set in data[2..].chunks(6).take(sets) {
+            let control = set[0] & 7;
+            let palette = |shift: u8| (set[1] >> shift) & 3;
+            let (inside, outside) = (palette(0), palette(4));
+            // Changing only the inside or the outside changes the edge too.
+            let edge = match control {
+                1 => Some(inside),
+                4 => Some(outside),
+                _ => (control & 2 != 0).then(|| palette(2)),
+            };
+            let (x1, y1, x2, y2) = (
+                set[2] as usize,
+                set[3] as usize,
+                set[4] as usize,
+                set[5] as usize,
+            );
+            for y in 0..CELLS_Y {
+                for x in 0..CELLS_X {
+                    let in_rect = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+                    let on_edge = in_rect && (x == x1 || x == x2 || y == y1 || y == y2);
+                    let new = if on_edge {
+                        edge
+                    } else if in_rect {
+                        (control & 1 != 0).then_some(inside)
+                    } else {
+                        (control & 4 != 0).then_some(outside)
+                    };
+                    if let Some(new) = new {
+                        self.attributes[y * CELLS_X + x] = new;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Colors whole rows or columns of cells.
+    fn attr_lin(&mut self, data: &[u8]) {
+        let lines = (data[1] as usize).min(data.len() - 2);
+        for &line in &data[2..2 + lines] {
+            let idx = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 3;
+            if line & 0x80 != 0 {
+                if idx < CELLS_Y {
+                    self.attributes[idx * CELLS_X..][..CELLS_X].fill(palette);
+                }
+            } else if idx < CELLS_X {
+                for row in self.attributes.chunks_exact_mut(CELLS_X) {
+                    row[idx] = palette;
+                }
+            }
+        }
+    }
+
+    /// Colors the two sides of a horizontal or vertical line of cells, and the line.
+    fn attr_div(&mut self, data: &[u8]) {
+        let palette = |shift: u8| (data[1] >> shift) & 3;
+        let (after, before, on_line) = (palette(0), palette(2), palette(4));
+        let line = data[2] as usize;
+        for y in 0..CELLS_Y {
+            for x in 0..CELLS_X {
+                let pos = if data[1] & 0x40 != 0 { y } else { x };
+                self.attributes[y * CELLS_X + x] = match pos.cmp(&line) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on_line,
+                    std::cmp::Ordering::Greater => after,
+                };
+            }
+        }
+    }
+
+    /// Colors cells one by one from a starting cell, left to right or top to bottom.
+    fn attr_chr(&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[1] as usize % CELLS_X, data[2] as usize % CELLS_Y);
+        let count = u16::from_le_bytes([data[3], data[4]]) as usize;
+        let vertical = data[5] & 1 != 0;
+        let palettes = data[6..]
+            .iter()
+            .flat_map(|byte| [6, 4, 2, 0].map(|shift| (byte >> shift) & 3));
+        for palette in palettes.take(count.min(CELLS)) {
+            self.attributes[y * CELLS_X + x] = palette;
+            if vertical {
+                y += 1;
+                if y == CELLS_Y {
+                    (x, y) = ((x + 1) % CELLS_X, 0);
+                }
+            } else {
+                x += 1;
+                if x == CELLS_X {
+                    (x, y) = (0, (y + 1) % CELLS_Y);
+                }
+            }
+        }
+    }
+
+    /// Colors the screen of `gpu` with the palettes of its cells.
+    pub fn to_rgb32(&self, gpu: &GPU, buff: &mut [u32; SCREEN_HEIGHT * SCREEN_WIDTH]) {
+        let backdrop = rgb32(self.palettes[0][0]);
+        match self.mask {
+            Mask::None => {
+                for (idx, shade) in shades(gpu).enumerate() {
+                    buff[idx] = self.color(idx, shade);
+                }
+            }
+            Mask::Freeze => {
+                for (idx, &shade) in self.frozen.iter().enumerate() {
+                    buff[idx] = self.color(idx, shade);
+                }
+            }
+            Mask::Black => buff.fill(0),
+            Mask::Color0 => buff.fill(backdrop),
+        }
+    }
+
+    fn color(&self, pixel: usize, shade: u8) -> u32 {
+        let (x, y) = (pixel % SCREEN_WIDTH, pixel / SCREEN_WIDTH);
+        let palette = self.attributes[y / 8 * CELLS_X + x / 8];
+        rgb32(self.palettes[palette as usize][shade as usize])
+    }
+
+    /// Draws `screen` inside the border. Transparent border pixels show color 0 like the
+    /// background of the game.
+    pub fn draw_border(&self, screen: &[u32; SCREEN_HEIGHT * SCREEN_WIDTH], buff: &mut [u32]) {
+        let backdrop = rgb32(self.palettes[0][0]);
+        for y in 0..SGB_HEIGHT {
+            for x in 0..SGB_WIDTH {
+                let entry = self.border_map[y / 8 * 32 + x / 8];
+                let tile = &self.border_tiles[(entry & 0xFF) as usize * BORDER_TILE_SIZE..];
+                let col = if entry & 0x4000 != 0 {
+                    x % 8
+                } else {
+                    7 - x % 8
+                };
+                let row = if entry & 0x8000 != 0 {
+                    7 - y % 8
+                } else {
+                    y % 8
+                };
+                let color = [
+                    tile[row * 2],
+                    tile[row * 2 + 1],
+                    tile[16 + row * 2],
+                    tile[17 + row * 2],
+                ]
+                .iter()
+                .enumerate()
+                .fold(0, |color, (plane, bits)| {
+                    color | ((bits >> col) & 1) << plane
+                });
+                let palette = ((entry >> 10) & 3) as usize;
+                buff[y * SGB_WIDTH + x] = match color {
+                    0 => backdrop,
+                    _ => rgb32(self.border_palettes[palette][color as usize]),
+                };
+            }
+        }
+        for (row, line) in screen.chunks_exact(SCREEN_WIDTH).enumerate() {
+            let start = (SCREEN_Y + row) * SGB_WIDTH + SCREEN_X;
+            buff[start..start + SCREEN_WIDTH].copy_from_slice(line);
+        }
+    }
+}
+
+/// Shades 0-3 of the screen, row by row.
+fn shades(gpu: &GPU) -> impl Iterator<Item = u8> + '_ {
+    (0..SCREEN_HEIGHT)
+        .flat_map(move |y| (0..SCREEN_WIDTH).map(move |x| 3 - gpu.buffer[x][y][0] / 0x55))
+}
+
+/// Data of a VRAM transfer: the BG tiles of the first 256 cells of the screen, 20 per row.
+/// https://gbdev.io/pandocs/SGB_VRAM_Transfer.html
+fn vram_transfer(gpu: &GPU) -> Vec<u8> {
+    let map_area = if gpu.lcd_control.bg_tile_map_area {
+        0x9C00
+    } else {
+        0x9800
+    };
+    let unsigned_addressing = gpu.lcd_control.bg_and_window_tile_data_area;
+    let mut data = Vec::with_capacity(TRANSFER_SIZE);
+    for cell in 0..TRANSFER_SIZE / 16 {
+        let tile = Coordinate::new((cell % CELLS_X * 8) as u8, (cell / CELLS_X * 8) as u8);
+        let tile_idx = gpu.vram[tile_map_offset(map_area, tile)];
+        let offset = tile_data_offset(unsigned_addressing, tile_idx);
+        data.extend_from_slice(&gpu.vram[offset..offset + 16]);
+    }
+    data
+}
+
+/// RGB555 to the 0RGB of frontend buffers.
+fn rgb32(color: u16) -> u32 {
+    let channel = |shift: u16| {
+        let val = ((color >> shift) & 0x1F) as u32;
+        (val << 3) | (val >> 2)
+    };
+    (channel(0) << 16) | (channel(5) << 8) | channel(10)
+}
+
+impl SaveState for Sgb {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.bool(&mut self.active);
+        state.u8(&mut self.lines);
+        let mut bits = self.packet_bits.map_or(u8::MAX, |bits| bits as u8);
+        state.u8(&mut bits);
+        self.packet_bits = (bits != u8::MAX).then_some(bits as usize);
+        state.bytes(&mut self.packet);
+        let mut len = self.command.len() as u8;
+        state.u8(&mut len);
+        self.command.resize(len as usize, 0);
+        state.bytes(&mut self.command);
+        state.u8(&mut self.players);
+        state.u8(&mut self.player);
+        for color in self.palettes.iter_mut().flatten() {
+            state.u16(color);
+        }
+        for color in self.system_palettes.iter_mut().flatten() {
+            state.u16(color);
+        }
+        state.bytes(&mut self.attributes);
+        state.bytes(&mut self.attr_files);
+        state.as_u8(
+            &mut self.mask,
+            |mask| mask as u8,
+            |val| {
+                [Mask::None, Mask::Freeze, Mask::Black, Mask::Color0]
+                    .get(val as usize)
+                    .copied()
+            },
+        );
+        state.bytes(&mut self.frozen);
+        state.bytes(&mut self.border_tiles);
+        for entry in &mut self.border_map {
+            state.u16(entry);
+        }
+        for color in self.border_palettes.iter_mut().flatten() {
+            state.u16(color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sgb() -> Sgb {
+        let mut rom = vec![0; 0x150];
+        rom[SGB_FLAG_ADDR] = 0x03;
+        Sgb::new(&rom)
+    }
+
+    fn send(sgb: &mut Sgb, gpu: &GPU, command: &[u8]) {
+        for packet in command.chunks(PACKET_SIZE) {
+            let mut packet = packet.to_vec();
+            packet.resize(PACKET_SIZE, 0);
+            sgb.write_joypad(0x00, gpu);
+            sgb.write_joypad(0x30, gpu);
+            let bits = packet
+                .iter()
+                .flat_map(|byte| (0..8).map(move |bit| byte >> bit & 1));
+            for bit in bits.chain([0]) {
+                sgb.write_joypad(if bit == 1 { 0x10 } else { 0x20 }, gpu);
+                sgb.write_joypad(0x30, gpu);
+            }
+        }
+    }
+
+    #[test]
+    fn palettes_and_attributes() {
+        let gpu = GPU::new();
+        let mut sgb = sgb();
+        // PAL01: shared color 0, then colors 1-3 of palettes 0 and 1.
+        send(
+            &mut sgb,
+            &gpu,
+            &[0x01, 0x1F, 0x00, 1, 0, 2, 0, 3, 0, 0xE0, 0x03, 5, 0, 6, 0],
+        );
+        assert_eq!(sgb.palettes[0], [0x001F, 1, 2, 3]);
+        assert_eq!(sgb.palettes[1], [0x001F, 0x03E0, 5, 6]);
+        assert_eq!(sgb.palettes[3][0], 0x001F);
+
+        // ATTR_BLK: inside of cells 1,1 to 3,2 with palette 1, and its border.
+        send(&mut sgb, &gpu, &[0x21, 1, 0x01, 0x01, 1, 1, 3, 2]);
+        assert_eq!(sgb.attributes[0], 0);
+        assert_eq!(sgb.attributes[CELLS_X + 1], 1);
+        assert_eq!(sgb.attributes[2 * CELLS_X + 3], 1);
+        assert_eq!(sgb.attributes[2 * CELLS_X + 4], 0);
+
+        // ATTR_LIN: row 5 with palette 2.
+        send(&mut sgb, &gpu, &[0x29, 1, 0x80 | 0x40 | 5]);
+        assert!(sgb.attributes[5 * CELLS_X..][..CELLS_X]
+            .iter()
+            .all(|&pal| pal == 2));
+
+        let mut buff = [0; SCREEN_HEIGHT * SCREEN_WIDTH];
+        sgb.to_rgb32(&gpu, &mut buff);
+        // Blank screens are shade 3 in the buffer until drawn.
+        assert_eq!(buff[0], rgb32(3));
+        assert_eq!(buff[8 * SCREEN_WIDTH + 8], rgb32(6));
+
+        // MASK_EN black.
+        send(&mut sgb, &gpu, &[0xB9, 2]);
+        sgb.to_rgb32(&gpu, &mut buff);
+        assert!(buff.iter().all(|&color| color == 0));
+    }
+
+    #[test]
+    fn multiplayer_ids() {
+        let gpu = GPU::new();
+        let mut sgb = sgb();
+        // MLT_REQ with 2 players.
+        send(&mut sgb, &gpu, &[0x89, 1]);
+        assert_eq!(sgb.read_joypad(0xFF) & 0x0F, 0x0F);
+
+        // Reading the keys and deselecting them moves to the next controller.
+        sgb.write_joypad(0x20, &gpu);
+        sgb.write_joypad(0x10, &gpu);
+        assert_eq!(sgb.read_joypad(0xEE) & 0x0F, 0x0E);
+        sgb.write_joypad(0x30, &gpu);
+        assert_eq!(sgb.read_joypad(0xFF) & 0x0F, 0x0E);
+        sgb.write_joypad(0x10, &gpu);
+        sgb.write_joypad(0x30, &gpu);
+        assert_eq!(sgb.read_joypad(0xFF) & 0x0F, 0x0F);
+    }
+
+    #[test]
+    fn games_without_the_flag_are_ignored() {
+        let gpu = GPU::new();
+        let mut sgb = Sgb::new(&[0; 0x150]);
+        send(&mut sgb, &gpu, &[0x89, 1]);
+        assert_eq!(sgb.players, 1);
+        assert!(!sgb.is_active());
+    }
+}
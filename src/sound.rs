@@ -1,6 +1,11 @@
 // Fix tests   : 10
 // Passed tests: 1, 2, 3, 4, 5, 6, 7, 8, 9, 11, 12
-use crate::{audio_player::AudioPlayer, bit};
+use crate::{
+    audio_player::AudioPlayer,
+    bit,
+    model::Model,
+    savestate::{SaveState, StateVisitor},
+};
 
 // Namings: https://gbdev.gg8.se/wiki/articles/Gameboy_sound_hardware
 
@@ -12,6 +17,8 @@ const CPU_CYCLES_PER_FRAME_SEQ: u64 = crate::CPU_FREQ / 512;
 const AUDIO_SAMPLE_FREQ: u64 = crate::CPU_FREQ / crate::SAMPLE_RATE;
 
 pub struct Sound {
+    /// DMG and CGB differ in length counters during power off and in wave RAM access.
+    model: Model,
     enabled: bool,
     // > A channel is turned off when any of the following occurs:
     //    * The channel’s length timer is enabled in NRx4 and expires, or
@@ -39,6 +46,13 @@ pub struct Sound {
     left_buf: [f32; crate::AUDIO_BUF_LEN],
     right_buf: [f32; crate::AUDIO_BUF_LEN],
     buf_filled: usize,
+    /// The player couldn't consume when the current buffer was started: its samples aren't mixed
+    /// and it's dropped, but channels keep running.
+    skip_buffer: bool,
+    /// Copy of the last buffer sent to the player, for debug views.
+    last_played: crate::AudioBuff,
+    /// Debug switches to mute CH1-CH4 in the output, the channels keep running.
+    channels_enabled: [bool; 4],
 
     player: Box<dyn AudioPlayer>,
 }
@@ -73,6 +87,8 @@ struct WaveChannel {
     // 10	50% volume (shift samples read from Wave RAM right once)
     // 11	25% volume (shift samples read from Wave RAM right twice)
     output_lvl: u8,
+
+    model: Model,
 }
 
 // CH4
@@ -124,12 +140,13 @@ struct Period {
 }
 
 impl Sound {
-    pub fn new(player: Box<dyn AudioPlayer>) -> Self {
+    pub fn with_model(player: Box<dyn AudioPlayer>, model: Model) -> Self {
         Self {
+            model,
             enabled: false,
             channel1: SquareChannel::new(true),
             channel2: SquareChannel::new(false),
-            channel3: WaveChannel::new(),
+            channel3: WaveChannel::new(model),
             channel4: NoiseChannel::new(),
             panning: 0,
             left_volume: 7,
@@ -144,11 +161,38 @@ impl Sound {
             left_buf: [0.0; crate::AUDIO_BUF_LEN],
             right_buf: [0.0; crate::AUDIO_BUF_LEN],
             buf_filled: 0,
+            skip_buffer: false,
+            last_played: ([0.0; crate::AUDIO_BUF_LEN], [0.0; crate::AUDIO_BUF_LEN]),
+            channels_enabled: [true; 4],
 
             player,
         }
     }
 
+    pub fn reset(&mut self) {
+        let player = std::mem::replace(
+            &mut self.player,
+            Box::new(crate::audio_player::VoidAudioPlayer::new()),
+        );
+        let channels_enabled = self.channels_enabled;
+        *self = Self::with_model(player, self.model);
+        self.channels_enabled = channels_enabled;
+    }
+
+    /// Whether CH1-CH4 (`idx` 0-3) is heard, see [`Self::set_channel_enabled`].
+    pub fn channel_enabled(&self, idx: usize) -> bool {
+        self.channels_enabled[idx]
+    }
+
+    /// Mutes or unmutes CH1-CH4 (`idx` 0-3) in the output, without changing the emulated APU.
+    pub fn set_channel_enabled(&mut self, idx: usize, enabled: bool) {
+        self.channels_enabled[idx] = enabled;
+    }
+
+    pub fn last_played(&self) -> &crate::AudioBuff {
+        &self.last_played
+    }
+
     pub fn read_byte(&self, addr: u16) -> u8 {
         match addr {
             0xFF10..=0xFF14 => self.channel1.read_byte(addr),
@@ -179,7 +223,9 @@ impl Sound {
         if !self.enabled {
             // Turning the APU off, however, does not affect Wave RAM, which can always be
             // read/written, nor the DIV-APU counter.
+            // > On DMG, length counters are unaffected by power and can still be written while off.
             match addr {
+                _ if self.model == Model::Cgb => (),
                 0xFF11 => self
                     .channel1
                     .write_byte(addr, val & 0b111111, self.frame_seq),
@@ -221,6 +267,14 @@ impl Sound {
 
                 if !self.enabled && new_enabled {
                     self.frame_seq = 0;
+
+                    // > On CGB, length counters are reset when powered up.
+                    if self.model == Model::Cgb {
+                        self.channel1.length.reset();
+                        self.channel2.length.reset();
+                        self.channel3.length.reset();
+                        self.channel4.length.reset();
+                    }
                 }
 
                 self.enabled = new_enabled;
@@ -230,7 +284,24 @@ impl Sound {
         }
     }
 
+    /// Cycles until the next frame sequencer step, audio sample or noise LFSR shift. Only one of
+    /// each is done per [`Sound::cycle`].
+    pub fn cycles_until_event(&self) -> Option<u64> {
+        if !self.enabled {
+            return None;
+        }
+        [
+            CPU_CYCLES_PER_FRAME_SEQ.saturating_sub(self.frame_seq_clock),
+            AUDIO_SAMPLE_FREQ.saturating_sub(self.audio_buffer_clock),
+            self.channel4.period.saturating_sub(self.channel4.cycles),
+        ]
+        .into_iter()
+        .min()
+    }
+
     pub fn cycle(&mut self, cpu_ticks: u64) {
+        crate::profile_scope!(crate::profiler::Scope::ApuCycle);
+
         if !self.enabled {
             return;
         }
@@ -246,7 +317,14 @@ impl Sound {
         self.audio_buffer_clock += cpu_ticks;
         if self.audio_buffer_clock >= AUDIO_SAMPLE_FREQ {
             self.audio_buffer_clock -= AUDIO_SAMPLE_FREQ;
-            self.enqueue_sample();
+            if self.buf_filled == 0 {
+                self.skip_buffer = !self.player.can_consume();
+            }
+            if self.skip_buffer {
+                self.buf_filled += 1;
+            } else {
+                self.enqueue_sample();
+            }
         }
 
         if self.buf_filled == self.left_buf.len() {
@@ -257,7 +335,13 @@ impl Sound {
     fn play(&mut self) {
         assert_eq!(self.buf_filled, self.left_buf.len());
 
-        self.player.play((self.left_buf, self.right_buf));
+        if self.skip_buffer {
+            self.buf_filled = 0;
+            return;
+        }
+
+        self.last_played = (self.left_buf, self.right_buf);
+        self.player.play(self.last_played);
 
         self.left_buf.fill(0.0);
         self.right_buf.fill(0.0);
@@ -297,37 +381,42 @@ impl Sound {
         // volume of 8 (no volume reduction).
         // 0.25 to split volume between 4 channels.
         // 1 / 15 because of envelope volume.
-        let left_vol = self.left_volume as f32 / 7.0 * 0.25 * 1.0 / 15.0;
-        let right_vol = self.right_volume as f32 / 7.0 * 0.25 * 1.0 / 15.0;
+        let left_vol = (self.left_volume + 1) as f32 / 8.0 * 0.25 * 1.0 / 15.0;
+        let right_vol = (self.right_volume + 1) as f32 / 8.0 * 0.25 * 1.0 / 15.0;
 
         self.left_buf[self.buf_filled] = 0.0;
         self.right_buf[self.buf_filled] = 0.0;
 
-        if self.panning & 0b00010000 != 0 {
+        // Muted channels are panned nowhere.
+        let panning = (0..4)
+            .filter(|&idx| !self.channels_enabled[idx])
+            .fold(self.panning, |panning, idx| panning & !(0b0001_0001 << idx));
+
+        if panning & 0b00010000 != 0 {
             self.left_buf[self.buf_filled] += left_vol * self.channel1.sample();
         }
-        if self.panning & 0b00000001 != 0 {
+        if panning & 0b00000001 != 0 {
             self.right_buf[self.buf_filled] += right_vol * self.channel1.sample();
         }
 
-        if self.panning & 0b00100000 != 0 {
+        if panning & 0b00100000 != 0 {
             self.left_buf[self.buf_filled] += left_vol * self.channel2.sample();
         }
-        if self.panning & 0b00000010 != 0 {
+        if panning & 0b00000010 != 0 {
             self.right_buf[self.buf_filled] += right_vol * self.channel2.sample();
         }
 
-        if self.panning & 0b01000000 != 0 {
+        if panning & 0b01000000 != 0 {
             self.left_buf[self.buf_filled] += left_vol * self.channel3.sample();
         }
-        if self.panning & 0b00000100 != 0 {
+        if panning & 0b00000100 != 0 {
             self.right_buf[self.buf_filled] += right_vol * self.channel3.sample();
         }
 
-        if self.panning & 0b10000000 != 0 {
+        if panning & 0b10000000 != 0 {
             self.left_buf[self.buf_filled] += left_vol * self.channel4.sample();
         }
-        if self.panning & 0b00001000 != 0 {
+        if panning & 0b00001000 != 0 {
             self.right_buf[self.buf_filled] += right_vol * self.channel4.sample();
         }
 
@@ -474,7 +563,7 @@ impl WaveChannel {
     // https://github.com/LIJI32/SameSuite/blob/master/apu/channel_3/channel_3_delay.asm
     const WAVE_CHANNEL_TRIGGER_DELAY: u16 = 5;
 
-    pub fn new() -> Self {
+    pub fn new(model: Model) -> Self {
         Self {
             enabled: false,
             dac: false,
@@ -486,6 +575,8 @@ impl WaveChannel {
             wave_idx: 1,
             waves: [0; 16],
             output_lvl: 0,
+
+            model,
         }
     }
 
@@ -499,7 +590,7 @@ impl WaveChannel {
             0xFF30..=0xFF3F => {
                 if !self.enabled {
                     self.waves[addr as usize - 0xFF30]
-                } else if self.period.reloaded {
+                } else if self.can_access_playing_wave() {
                     self.waves[self.wave_idx as usize >> 1]
                 } else {
                     0xFF
@@ -531,7 +622,7 @@ impl WaveChannel {
             0xFF30..=0xFF3F => {
                 if !self.enabled {
                     self.waves[addr as usize - 0xFF30] = val;
-                } else if self.period.reloaded {
+                } else if self.can_access_playing_wave() {
                     self.waves[self.wave_idx as usize >> 1] = val;
                 }
             }
@@ -575,8 +666,19 @@ impl WaveChannel {
         }
     }
 
+    /// While CH3 is playing, wave RAM accesses go to the byte being played. DMG allows it only
+    /// within a few clocks of the channel reading that byte, CGB allows it at any time.
+    fn can_access_playing_wave(&self) -> bool {
+        match self.model {
+            Model::Dmg | Model::Mgb | Model::Sgb => self.period.reloaded,
+            Model::Cgb => true,
+        }
+    }
+
     fn trigger(&mut self, freq_seq: u8) {
-        if self.enabled && self.period.timer == 1 {
+        // > Triggering the wave channel on the DMG while it reads a sample byte will alter the
+        // first four bytes of wave RAM.
+        if self.model != Model::Cgb && self.enabled && self.period.timer == 1 {
             self.corrupt_wave_ram();
         }
 
@@ -896,6 +998,10 @@ impl LengthTimer {
         self.timer == 0
     }
 
+    pub fn reset(&mut self) {
+        *self = Self::new(self.max_len);
+    }
+
     pub fn set_current(&mut self, initial_len: u8) {
         self.timer = self.max_len - initial_len as u16;
     }
@@ -979,3 +1085,310 @@ impl Period {
         (2048 - self.period) * self.multiplier
     }
 }
+
+impl SaveState for Sound {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.bool(&mut self.enabled);
+        state.visit(&mut self.channel1);
+        state.visit(&mut self.channel2);
+        state.visit(&mut self.channel3);
+        state.visit(&mut self.channel4);
+        state.u8(&mut self.panning);
+        state.u8(&mut self.left_volume);
+        state.u8(&mut self.right_volume);
+        state.bool(&mut self.vin_l_enable);
+        state.bool(&mut self.vin_r_enable);
+        state.u64(&mut self.frame_seq_clock);
+        state.u8(&mut self.frame_seq);
+
+        // The buffer being filled, so the player gets it complete.
+        state.u64(&mut self.audio_buffer_clock);
+        state.f32s(&mut self.left_buf);
+        state.f32s(&mut self.right_buf);
+        state.usize(&mut self.buf_filled);
+        state.check(self.buf_filled < crate::AUDIO_BUF_LEN, || {
+            format!("audio buffer position {} is out of range", self.buf_filled)
+        });
+    }
+}
+
+impl SaveState for SquareChannel {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.bool(&mut self.enabled);
+        if let Some(sweep) = &mut self.sweep {
+            state.visit(sweep);
+        }
+        state.visit(&mut self.length);
+        state.visit(&mut self.envelope);
+        state.u8(&mut self.duty_idx);
+        state.usize(&mut self.duty_iter);
+        state.visit(&mut self.period);
+        state.bool(&mut self.dac);
+    }
+}
+
+impl SaveState for WaveChannel {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.bool(&mut self.enabled);
+        state.bool(&mut self.dac);
+        state.visit(&mut self.period);
+        state.visit(&mut self.length);
+        state.u8(&mut self.wave_idx);
+        state.bytes(&mut self.waves);
+        state.u8(&mut self.output_lvl);
+    }
+}
+
+impl SaveState for NoiseChannel {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.bool(&mut self.enabled);
+        state.bool(&mut self.dac);
+        state.visit(&mut self.length);
+        state.visit(&mut self.envelope);
+        state.u8(&mut self.ff22);
+        state.u16(&mut self.lfsr);
+        state.u64(&mut self.cycles);
+        state.u64(&mut self.period);
+    }
+}
+
+impl SaveState for Sweep {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.bool(&mut self.enabled);
+        state.u8(&mut self.period);
+        state.u8(&mut self.timer);
+        state.bool(&mut self.negate);
+        state.bool(&mut self.negate_done);
+        state.u8(&mut self.shift);
+        state.u16(&mut self.shadow_freq);
+        state.bool(&mut self.disable_channel);
+    }
+}
+
+impl SaveState for Envelope {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.u8(&mut self.timer);
+        state.u8(&mut self.volume);
+        state.u8(&mut self.init_volume);
+        state.bool(&mut self.dir_up);
+        state.u8(&mut self.init_timer);
+    }
+}
+
+impl SaveState for LengthTimer {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.bool(&mut self.enabled);
+        state.u16(&mut self.max_len);
+        state.u16(&mut self.timer);
+    }
+}
+
+impl SaveState for Period {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.u16(&mut self.period);
+        state.u16(&mut self.timer);
+        state.u16(&mut self.multiplier);
+        state.bool(&mut self.reloaded);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn powered_on(model: Model) -> Sound {
+        let mut sound =
+            Sound::with_model(Box::new(crate::audio_player::VoidAudioPlayer::new()), model);
+        sound.write_byte(0xFF26, 0x80);
+        sound
+    }
+
+    fn play_wave(sound: &mut Sound) {
+        for (idx, addr) in (0xFF30..=0xFF3F).enumerate() {
+            sound.write_byte(addr, idx as u8 * 0x11);
+        }
+        sound.write_byte(0xFF1A, 0x80);
+        sound.write_byte(0xFF1E, 0x80);
+        assert!(sound.channel3.enabled);
+    }
+
+    #[test]
+    fn dmg_wave_ram_is_locked_while_playing() {
+        let mut sound = powered_on(Model::Dmg);
+        play_wave(&mut sound);
+
+        assert_eq!(sound.read_byte(0xFF35), 0xFF);
+        sound.write_byte(0xFF35, 0xAB);
+        assert_eq!(sound.channel3.waves[0], 0x00);
+    }
+
+    #[test]
+    fn cgb_wave_ram_accesses_current_byte_while_playing() {
+        let mut sound = powered_on(Model::Cgb);
+        play_wave(&mut sound);
+
+        assert_eq!(sound.read_byte(0xFF35), 0x00);
+        sound.write_byte(0xFF35, 0xAB);
+        assert_eq!(sound.channel3.waves[0], 0xAB);
+    }
+
+    #[test]
+    fn length_writes_while_powered_off() {
+        for model in [Model::Dmg, Model::Cgb] {
+            let mut sound = powered_on(model);
+            sound.write_byte(0xFF26, 0x00);
+            let timer = sound.channel1.length.timer;
+
+            sound.write_byte(0xFF11, 0x3F);
+            let expected = if model == Model::Dmg { 1 } else { timer };
+            assert_eq!(sound.channel1.length.timer, expected, "{model:?}");
+        }
+    }
+
+    #[test]
+    fn cgb_resets_length_on_power_up() {
+        for model in [Model::Dmg, Model::Cgb] {
+            let mut sound = powered_on(model);
+            sound.write_byte(0xFF26, 0x00);
+            sound.channel2.length.timer = 10;
+
+            sound.write_byte(0xFF26, 0x80);
+            let expected = if model == Model::Dmg { 10 } else { 0 };
+            assert_eq!(sound.channel2.length.timer, expected, "{model:?}");
+        }
+    }
+
+    #[test]
+    fn skips_mixing_while_player_cant_consume() {
+        struct Busy(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+        impl AudioPlayer for Busy {
+            fn play(&mut self, _buff: crate::AudioBuff) {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }
+
+            fn can_consume(&self) -> bool {
+                false
+            }
+        }
+
+        let played = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut sound = Sound::with_model(Box::new(Busy(played.clone())), Model::Dmg);
+        sound.write_byte(0xFF26, 0x80);
+        sound.write_byte(0xFF12, 0xF0);
+        sound.write_byte(0xFF11, 0x3F);
+        sound.write_byte(0xFF14, 0xC0);
+        assert!(sound.channel1.enabled);
+
+        for _ in 0..AUDIO_SAMPLE_FREQ * crate::AUDIO_BUF_LEN as u64 * 2 {
+            sound.cycle(1);
+        }
+        assert_eq!(played.load(std::sync::atomic::Ordering::Relaxed), 0);
+        assert!(
+            !sound.channel1.enabled,
+            "length timer stopped while skipping"
+        );
+    }
+
+    /// Channels 1-4 output 15, 8, 4 and 2, so every panning combination mixes to a unique sum.
+    fn mixer_fixture() -> Sound {
+        let mut sound = powered_on(Model::Dmg);
+        for (channel, volume) in [(&mut sound.channel1, 15), (&mut sound.channel2, 8)] {
+            channel.enabled = true;
+            channel.duty_idx = 3;
+            channel.duty_iter = 1;
+            channel.envelope.volume = volume;
+        }
+        sound.channel3.enabled = true;
+        sound.channel3.output_lvl = 1;
+        sound.channel3.wave_idx = 0;
+        sound.channel3.waves[0] = 0x40;
+        sound.channel4.enabled = true;
+        sound.channel4.lfsr = 1;
+        sound.channel4.envelope.volume = 2;
+        sound
+    }
+
+    fn mix(sound: &mut Sound, nr50: u8, nr51: u8) -> (f32, f32) {
+        sound.write_byte(0xFF24, nr50);
+        sound.write_byte(0xFF25, nr51);
+        sound.buf_filled = 0;
+        sound.enqueue_sample();
+        (sound.left_buf[0], sound.right_buf[0])
+    }
+
+    #[test]
+    fn mixer_golden_samples() {
+        let mut sound = mixer_fixture();
+        // Full volume on both sides: (15 + 8 + 4 + 2) / 15 * 0.25.
+        for (nr50, nr51, expected) in [
+            (0x77, 0xFF, (29.0 / 60.0, 29.0 / 60.0)),
+            (0x73, 0x21, (8.0 / 60.0, 1.0 / 8.0)),
+            // Volume 0 is the quietest, not silence.
+            (0x07, 0xF0, (29.0 / 480.0, 0.0)),
+            (0x77, 0x84, (2.0 / 60.0, 4.0 / 60.0)),
+        ] {
+            let (left, right) = mix(&mut sound, nr50, nr51);
+            assert!(
+                (left - expected.0).abs() < 1e-6 && (right - expected.1).abs() < 1e-6,
+                "NR50={nr50:02X} NR51={nr51:02X}: ({left}, {right}) != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn muted_channels_arent_mixed() {
+        let mut sound = mixer_fixture();
+        sound.set_channel_enabled(0, false);
+        sound.set_channel_enabled(2, false);
+        // CH2 and CH4 left.
+        assert_eq!(mix(&mut sound, 0x77, 0xFF), (10.0 / 60.0, 10.0 / 60.0));
+
+        sound.reset();
+        assert!(!sound.channel_enabled(0) && sound.channel_enabled(1));
+    }
+
+    #[test]
+    fn mixer_every_volume_and_panning() {
+        const SAMPLES: [f32; 4] = [15.0, 8.0, 4.0, 2.0];
+
+        let mut sound = mixer_fixture();
+        for nr50 in (0..=0x77).filter(|nr50| nr50 & 0x88 == 0) {
+            for nr51 in 0..=0xFF {
+                let side = |volume: u8, pan_bits: u8| {
+                    let sum: f32 = (0..4)
+                        .filter(|channel| pan_bits & (1 << channel) != 0)
+                        .map(|channel| SAMPLES[channel])
+                        .sum();
+                    sum * (volume as f32 + 1.0) / 8.0 * 0.25 / 15.0
+                };
+                let expected = (side(nr50 >> 4, nr51 >> 4), side(nr50 & 7, nr51 & 0xF));
+
+                let (left, right) = mix(&mut sound, nr50, nr51);
+                assert!(
+                    (left - expected.0).abs() < 1e-6 && (right - expected.1).abs() < 1e-6,
+                    "NR50={nr50:02X} NR51={nr51:02X}: ({left}, {right}) != {expected:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn only_dmg_corrupts_wave_ram_on_retrigger() {
+        for model in [Model::Dmg, Model::Cgb] {
+            let mut channel = WaveChannel::new(model);
+            channel.waves = std::array::from_fn(|idx| idx as u8);
+            channel.enabled = true;
+            channel.dac = true;
+            channel.wave_idx = 8;
+            channel.period.timer = 1;
+
+            channel.trigger(0);
+            let expected = if model == Model::Dmg {
+                [4, 5, 6, 7]
+            } else {
+                [0, 1, 2, 3]
+            };
+            assert_eq!(channel.waves[..4], expected, "{model:?}");
+        }
+    }
+}
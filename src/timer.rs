@@ -0,0 +1,215 @@
+// DIV and TIMA, driven by the internal 16-bit counter whose upper byte is DIV.
+//
+// TIMA increments on falling edges of one counter bit (selected by TAC) ANDed with the enable bit,
+// so resetting DIV or changing TAC can increment it too. After an overflow TIMA reads 0 for one
+// M-cycle before TMA is loaded and the interrupt requested.
+// https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
+
+use crate::savestate::{SaveState, StateVisitor};
+
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+enum Reload {
+    #[default]
+    None,
+    /// TIMA overflowed and reads 0, TMA is loaded at the next M-cycle. Writing TIMA cancels it.
+    Pending,
+    /// TMA was just loaded: writes to TIMA are ignored and writes to TMA go to TIMA too.
+    Done,
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct Timer {
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    reload: Reload,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn div(&self) -> u8 {
+        (self.counter >> 8) as u8
+    }
+
+    pub fn tima(&self) -> u8 {
+        self.tima
+    }
+
+    pub fn tma(&self) -> u8 {
+        self.tma
+    }
+
+    pub fn tac(&self) -> u8 {
+        0b1111_1000 | self.tac
+    }
+
+    pub fn reset_div(&mut self) {
+        self.update_input(|timer| timer.counter = 0);
+    }
+
+    pub fn write_tima(&mut self, val: u8) {
+        match self.reload {
+            Reload::None => self.tima = val,
+            Reload::Pending => {
+                self.tima = val;
+                self.reload = Reload::None;
+            }
+            Reload::Done => (),
+        }
+    }
+
+    pub fn write_tma(&mut self, val: u8) {
+        self.tma = val;
+        if self.reload == Reload::Done {
+            self.tima = val;
+        }
+    }
+
+    pub fn write_tac(&mut self, val: u8) {
+        self.update_input(|timer| timer.tac = val & 0b111);
+    }
+
+    /// Advances by `cycles` T-cycles, in whole M-cycles.
+    ///
+    /// # Returns
+    ///
+    /// Whether the timer interrupt is requested.
+    pub fn step(&mut self, cycles: u64) -> bool {
+        let mut interrupt = false;
+        for _ in 0..cycles.div_ceil(4) {
+            match self.reload {
+                Reload::None => (),
+                Reload::Pending => {
+                    self.tima = self.tma;
+                    self.reload = Reload::Done;
+                    interrupt = true;
+                }
+                Reload::Done => self.reload = Reload::None,
+            }
+            self.update_input(|timer| timer.counter = timer.counter.wrapping_add(4));
+        }
+        interrupt
+    }
+
+    /// T-cycles until the next timer interrupt, when the timer is enabled.
+    pub fn cycles_until_interrupt(&self) -> Option<u64> {
+        if self.reload == Reload::Pending {
+            return Some(4);
+        }
+        if self.tac & 0b100 == 0 {
+            return None;
+        }
+        let period = 2 << self.input_bit();
+        let first_edge = period - (self.counter as u64 % period);
+        let increments = 0x100 - self.tima as u64;
+        // The reload happens one M-cycle after the overflow.
+        Some(first_edge + (increments - 1) * period + 4)
+    }
+
+    /// Bit of the counter that clocks TIMA.
+    fn input_bit(&self) -> u32 {
+        [9, 3, 5, 7][(self.tac & 0b11) as usize]
+    }
+
+    fn input(&self) -> bool {
+        self.tac & 0b100 != 0 && self.counter & (1 << self.input_bit()) != 0
+    }
+
+    /// Applies `update` and increments TIMA on a falling edge of its input.
+    fn update_input(&mut self, update: impl FnOnce(&mut Self)) {
+        let old = self.input();
+        update(self);
+        if old && !self.input() {
+            let (tima, overflow) = self.tima.overflowing_add(1);
+            self.tima = tima;
+            if overflow {
+                self.reload = Reload::Pending;
+            }
+        }
+    }
+}
+
+impl SaveState for Timer {
+    fn visit_state(&mut self, state: &mut StateVisitor) {
+        state.u16(&mut self.counter);
+        state.u8(&mut self.tima);
+        state.u8(&mut self.tma);
+        state.u8(&mut self.tac);
+        state.as_u8(
+            &mut self.reload,
+            |reload| reload as u8,
+            |val| {
+                [Reload::None, Reload::Pending, Reload::Done]
+                    .get(val as usize)
+                    .copied()
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// TAC of the fastest rate, 16 T-cycles per increment.
+    const FAST: u8 = 0b101;
+
+    #[test]
+    fn overflow_reloads_one_m_cycle_later() {
+        let mut timer = Timer::new();
+        timer.write_tac(FAST);
+        timer.write_tma(0x42);
+        timer.write_tima(0xFE);
+        assert_eq!(timer.cycles_until_interrupt(), Some(36));
+
+        assert!(!timer.step(32));
+        assert_eq!(timer.tima(), 0);
+        // Writes during the reload are ignored, TMA writes go through.
+        assert!(timer.step(4));
+        assert_eq!(timer.tima(), 0x42);
+        timer.write_tima(0x10);
+        timer.write_tma(0x43);
+        assert_eq!(timer.tima(), 0x43);
+
+        // Writing TIMA right after the overflow cancels the reload and the interrupt.
+        timer.step(4);
+        timer.write_tima(0xFF);
+        assert!(!timer.step(8));
+        assert_eq!(timer.tima(), 0);
+        timer.write_tima(0x20);
+        assert!(!timer.step(4));
+        assert_eq!(timer.tima(), 0x20);
+    }
+
+    #[test]
+    fn div_and_tac_writes_can_increment_tima() {
+        let mut timer = Timer::new();
+        timer.write_tac(FAST);
+        // Bit 3 of the counter is set: resetting DIV is a falling edge.
+        timer.step(8);
+        assert_eq!(timer.tima(), 0);
+        timer.reset_div();
+        assert_eq!((timer.tima(), timer.div()), (1, 0));
+
+        // Disabling the timer while the input is high too.
+        timer.step(8);
+        timer.write_tac(0b001);
+        assert_eq!(timer.tima(), 2);
+        timer.reset_div();
+        assert_eq!(timer.tima(), 2);
+    }
+
+    #[test]
+    fn div_counts_every_256_cycles() {
+        let mut timer = Timer::new();
+        timer.step(255 * 4);
+        assert_eq!(timer.div(), 3);
+        timer.step(4);
+        assert_eq!(timer.div(), 4);
+        assert_eq!(timer.cycles_until_interrupt(), None);
+    }
+}
@@ -0,0 +1,59 @@
+// Browser frontend: `requestAnimationFrame` runs a frame and puts its pixels into a canvas
+// `ImageData`. There is no sound and no saving yet.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    audio_player::VoidAudioPlayer,
+    cpu::{JoypadKey, CPU},
+};
+
+/// Keys by the `u8` given to [`WebEmulator::key_down`] and [`WebEmulator::key_up`].
+const KEYS: [JoypadKey; 8] = [
+    JoypadKey::Right,
+    JoypadKey::Left,
+    JoypadKey::Up,
+    JoypadKey::Down,
+    JoypadKey::A,
+    JoypadKey::B,
+    JoypadKey::Select,
+    JoypadKey::Start,
+];
+
+#[wasm_bindgen]
+pub struct WebEmulator {
+    cpu: CPU,
+}
+
+#[wasm_bindgen]
+impl WebEmulator {
+    /// Throws if the ROM can't be emulated.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<WebEmulator, JsError> {
+        let cpu = CPU::try_new(rom.to_vec(), Box::new(VoidAudioPlayer::new()))
+            .map_err(|err| JsError::new(&err.to_string()))?;
+        Ok(Self { cpu })
+    }
+
+    pub fn run_frame(&mut self) {
+        self.cpu.run_frame();
+    }
+
+    /// `key`: 0 right, 1 left, 2 up, 3 down, 4 A, 5 B, 6 select, 7 start. Others are ignored.
+    pub fn key_down(&mut self, key: u8) {
+        if let Some(&key) = KEYS.get(key as usize) {
+            self.cpu.key_down(key);
+        }
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        if let Some(&key) = KEYS.get(key as usize) {
+            self.cpu.key_up(key);
+        }
+    }
+
+    /// 160×144 RGBA pixels of the last frame, row by row.
+    pub fn frame_rgba(&mut self) -> Vec<u8> {
+        self.cpu.screen().to_rgba8()
+    }
+}
@@ -0,0 +1,115 @@
+// Detects games stuck in a tight loop, so headless runs of crashed games don't emulate forever.
+
+use std::ops::ControlFlow;
+
+use crate::cpu::StepInfo;
+
+/// Loops spanning more bytes than this are not considered tight, e.g. a game waiting for a button
+/// in its main loop.
+const MAX_LOOP_SIZE: u16 = 16;
+
+pub struct Watchdog {
+    max_cycles: u64,
+
+    /// Address range of the loop the CPU is executing.
+    loop_start: u16,
+    loop_end: u16,
+    /// T-cycles spent in the loop without IO writes or interrupts.
+    cycles: u64,
+    io_writes: u64,
+}
+
+impl Watchdog {
+    /// Trips after the CPU spins in a tight loop without IO writes or interrupts for `frames`.
+    pub fn new(frames: u64) -> Self {
+        Self {
+            max_cycles: frames * crate::TICKS_PER_FRAME,
+            loop_start: 0,
+            loop_end: 0,
+            cycles: 0,
+            io_writes: 0,
+        }
+    }
+
+    /// Meant to be called from [`crate::cpu::CPU::on_instruction`].
+    ///
+    /// # Returns
+    ///
+    /// `ControlFlow::Break` when emulation is stuck.
+    pub fn observe(&mut self, step: &StepInfo) -> ControlFlow<()> {
+        let loop_start = self.loop_start.min(step.pc);
+        let loop_end = self.loop_end.max(step.pc);
+
+        if step.interrupt
+            || step.io_writes != self.io_writes
+            || loop_end - loop_start > MAX_LOOP_SIZE
+        {
+            self.loop_start = step.pc;
+            self.loop_end = step.pc;
+            self.cycles = 0;
+            self.io_writes = step.io_writes;
+            return ControlFlow::Continue(());
+        }
+
+        self.loop_start = loop_start;
+        self.loop_end = loop_end;
+        self.cycles += step.cycles;
+
+        if self.cycles >= self.max_cycles {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    /// Address range of the current loop, inclusive.
+    pub fn loop_range(&self) -> (u16, u16) {
+        (self.loop_start, self.loop_end)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn step(pc: u16, io_writes: u64) -> StepInfo {
+        StepInfo {
+            pc,
+            cycles: crate::TICKS_PER_FRAME / 2,
+            interrupt: false,
+            io_writes,
+        }
+    }
+
+    #[test]
+    fn trips_on_tight_loop() {
+        let mut watchdog = Watchdog::new(4);
+        let pc = |idx: u16| 0x150 + idx % 2 * 2;
+
+        for idx in 0..7 {
+            assert!(watchdog.observe(&step(pc(idx), 0)).is_continue());
+        }
+        // The loop wrote to an IO register.
+        assert!(watchdog.observe(&step(pc(7), 1)).is_continue());
+        for idx in 0..7 {
+            assert!(watchdog.observe(&step(pc(idx), 1)).is_continue());
+        }
+        assert!(watchdog.observe(&step(pc(7), 1)).is_break());
+        assert_eq!(watchdog.loop_range(), (0x150, 0x152));
+    }
+
+    #[test]
+    fn ignores_wide_loops_and_interrupts() {
+        let mut watchdog = Watchdog::new(1);
+        for _ in 0..4 {
+            assert!(watchdog.observe(&step(0x150, 0)).is_continue());
+            assert!(watchdog.observe(&step(0x400, 0)).is_continue());
+        }
+        for _ in 0..4 {
+            let mut info = step(0x150, 0);
+            assert!(watchdog.observe(&info).is_continue());
+            info.interrupt = true;
+            assert!(watchdog.observe(&info).is_continue());
+        }
+    }
+}
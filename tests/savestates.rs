@@ -0,0 +1,182 @@
+// Save states must capture the whole emulator: an instance restored from a state mid-game has to
+// run exactly like the original, frame by frame. The games are synthetic ROMs built here, so the
+// tests need no downloads.
+
+use gbemu::{
+    cpu::{JoypadKey, CPU},
+    input_macro::InputMacro,
+};
+
+/// Cartridge types the game is built for: MBC1, MBC3 and MBC5, all with RAM and a battery.
+const CARTRIDGE_TYPES: [u8; 3] = [0x03, 0x13, 0x1B];
+
+/// Presses and releases of A, B and Right, replayed on both instances.
+const INPUTS: &str = "
+    0 a down
+    5 right down
+    9 a up
+    20 b down
+    21 right up
+    33 b up
+    40 a down
+    41 a up
+";
+
+/// A game that keeps every part of the hardware busy:
+/// - the VBlank interrupt scrolls the background, reads the buttons, switches ROM banks, moves a
+///   sprite by the bank and the buttons and writes the buttons to cartridge RAM;
+/// - the timer interrupt (every 4096 cycles) changes the tile data of the background and the
+///   sprite;
+/// - the square channel with sweep and envelope and the noise channel play;
+/// - the CPU halts between interrupts.
+fn synthetic_game(cartridge_type: u8) -> Vec<u8> {
+    let mut rom = vec![0; 0x10000];
+    rom[0x147] = cartridge_type;
+    // 64 KiB ROM (4 banks), 8 KiB RAM.
+    rom[0x148] = 0x01;
+    rom[0x149] = 0x02;
+    // Every bank starts with a different marker.
+    for bank in 1..4 {
+        rom[bank * 0x4000] = bank as u8 * 0x11;
+    }
+
+    // VBlank: JP $0200. Timer: JP $0280. Entry point: JP $0150.
+    rom[0x40..][..3].copy_from_slice(&[0xC3, 0x00, 0x02]);
+    rom[0x50..][..3].copy_from_slice(&[0xC3, 0x80, 0x02]);
+    rom[0x100..][..3].copy_from_slice(&[0xC3, 0x50, 0x01]);
+
+    #[rustfmt::skip]
+    let main = [
+        0xF3,                   // DI
+        0x31, 0xFE, 0xFF,       // LD SP,$FFFE
+        0x3E, 0x0A,             // LD A,$0A
+        0xEA, 0x00, 0x00,       // LD ($0000),A    ; Enable cartridge RAM.
+        0x3E, 0x80, 0xE0, 0x26, // LDH (NR52),$80
+        0x3E, 0x77, 0xE0, 0x24, // LDH (NR50),$77
+        0x3E, 0xFF, 0xE0, 0x25, // LDH (NR51),$FF
+        0x3E, 0x15, 0xE0, 0x10, // LDH (NR10),$15  ; Sweep up.
+        0x3E, 0x80, 0xE0, 0x11, // LDH (NR11),$80
+        0x3E, 0xF3, 0xE0, 0x12, // LDH (NR12),$F3  ; Envelope down.
+        0x3E, 0x00, 0xE0, 0x13, // LDH (NR13),$00
+        0x3E, 0x87, 0xE0, 0x14, // LDH (NR14),$87  ; Trigger.
+        0x3E, 0xF1, 0xE0, 0x21, // LDH (NR42),$F1
+        0x3E, 0x52, 0xE0, 0x22, // LDH (NR43),$52
+        0x3E, 0x80, 0xE0, 0x23, // LDH (NR44),$80  ; Trigger.
+        0x3E, 0x05, 0xE0, 0x07, // LDH (TAC),$05   ; 262144 Hz.
+        0x3E, 0x05, 0xE0, 0xFF, // LDH (IE),$05    ; VBlank and timer.
+        0x3E, 0x93, 0xE0, 0x40, // LDH (LCDC),$93  ; Objects on.
+        0x3E, 0xE4, 0xE0, 0x48, // LDH (OBP0),$E4
+        0xFB,                   // EI
+        0x76,                   // loop: HALT
+        0x00,                   // NOP
+        0x18, 0xFC,             // JR loop
+    ];
+    rom[0x150..][..main.len()].copy_from_slice(&main);
+
+    #[rustfmt::skip]
+    let vblank = [
+        0xF5, 0xC5, 0xE5,       // PUSH AF; PUSH BC; PUSH HL
+        0xF0, 0x43, 0x3C,       // LDH A,(SCX); INC A
+        0xE0, 0x43,             // LDH (SCX),A
+        0x3E, 0x10, 0xE0, 0x00, // LDH (P1),$10    ; Buttons.
+        0xF0, 0x00, 0xF0, 0x00, // LDH A,(P1); LDH A,(P1)
+        0x2F, 0xE6, 0x0F,       // CPL; AND $0F
+        0x47,                   // LD B,A
+        0xF0, 0x80, 0x3C,       // LDH A,($80); INC A  ; Frame counter.
+        0xE0, 0x80,             // LDH ($80),A
+        0xE6, 0x03, 0xF6, 0x01, // AND 3; OR 1
+        0xEA, 0x00, 0x20,       // LD ($2000),A    ; ROM bank 1-3.
+        0xFA, 0x00, 0x40,       // LD A,($4000)    ; Its marker.
+        0x80,                   // ADD A,B
+        0x21, 0x00, 0xFE,       // LD HL,$FE00     ; Object 0.
+        0x36, 0x20, 0x2C,       // LD (HL),$20; INC L
+        0x77, 0x2C,             // LD (HL),A; INC L
+        0x36, 0x01,             // LD (HL),1       ; Tile 1.
+        0xF0, 0x80, 0x6F,       // LDH A,($80); LD L,A
+        0x26, 0xA0, 0x70,       // LD H,$A0; LD (HL),B
+        0xE1, 0xC1, 0xF1,       // POP HL; POP BC; POP AF
+        0xD9,                   // RETI
+    ];
+    rom[0x200..][..vblank.len()].copy_from_slice(&vblank);
+
+    #[rustfmt::skip]
+    let timer = [
+        0xF5, 0xE5,             // PUSH AF; PUSH HL
+        0xF0, 0x81, 0x3C,       // LDH A,($81); INC A
+        0xE0, 0x81,             // LDH ($81),A
+        0xE6, 0x1F, 0x6F,       // AND $1F; LD L,A  ; Tiles 0 and 1.
+        0x26, 0x80, 0x34,       // LD H,$80; INC (HL)
+        0xE1, 0xF1,             // POP HL; POP AF
+        0xD9,                   // RETI
+    ];
+    rom[0x280..][..timer.len()].copy_from_slice(&timer);
+
+    rom
+}
+
+fn frame_hash(pixels: &[u32]) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::hash::DefaultHasher::new();
+    pixels.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn run_frames(cpu: &mut CPU, frames: usize) -> Vec<u64> {
+    (0..frames)
+        .map(|_| frame_hash(&cpu.next_frame().unwrap().pixels))
+        .collect()
+}
+
+#[test]
+fn reloaded_state_runs_like_the_original() {
+    let inputs = InputMacro::parse(INPUTS).unwrap();
+
+    for cartridge_type in CARTRIDGE_TYPES {
+        let rom = synthetic_game(cartridge_type);
+        let mut original = CPU::new_without_sound(rom.clone());
+        // Scheduled inputs aren't part of the state, so the macro finishes first.
+        original.play_macro(&inputs);
+        run_frames(&mut original, 60);
+        // Mid-game, with a button held.
+        original.key_down(JoypadKey::Select);
+        let _ = original.run(1000);
+        let state = original.save_state();
+
+        let mut reloaded = CPU::new_without_sound(rom);
+        reloaded.load_state(&state).unwrap();
+        assert_eq!(reloaded.save_state(), state);
+
+        for cpu in [&mut original, &mut reloaded] {
+            cpu.key_up(JoypadKey::Select);
+            cpu.play_macro(&inputs);
+        }
+        let expected = run_frames(&mut original, 60);
+        assert_eq!(
+            run_frames(&mut reloaded, 60),
+            expected,
+            "cartridge type 0x{cartridge_type:02X}"
+        );
+        assert!(expected.windows(2).any(|pair| pair[0] != pair[1]));
+        // Everything else, e.g. APU, timers and cartridge RAM, too.
+        assert_eq!(reloaded.save_state(), original.save_state());
+    }
+}
+
+#[test]
+fn loading_a_state_rewinds_the_same_instance() {
+    let rom = synthetic_game(CARTRIDGE_TYPES[0]);
+    let inputs = InputMacro::parse(INPUTS).unwrap();
+    let mut cpu = CPU::new_without_sound(rom);
+    run_frames(&mut cpu, 10);
+
+    let state = cpu.save_state();
+    cpu.play_macro(&inputs);
+    let expected = run_frames(&mut cpu, 90);
+    let end = cpu.save_state();
+
+    cpu.load_state(&state).unwrap();
+    cpu.play_macro(&inputs);
+    assert_eq!(run_frames(&mut cpu, 90), expected);
+    assert_eq!(cpu.save_state(), end);
+}
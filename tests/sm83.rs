@@ -0,0 +1,366 @@
+// Single-instruction tests of every opcode: the CPU starts from the registers and RAM of a case,
+// executes one instruction on a flat 64 KiB bus and must end with the final registers and RAM.
+// They need the `debug` feature: `cargo test --features debug --test sm83`.
+//
+// To run them you need to download and unpack https://github.com/SingleStepTests/sm83 to
+// `tests/sm83/`. Every file in `v1/` holds the cases of one opcode, e.g. `cb 11.json`:
+// [{"name": "cb 11 0000",
+//   "initial": {"pc": 256, "sp": 65534, "a": 1, ..., "ime": 0, "ram": [[256, 203], ...]},
+//   "final": {...},
+//   "cycles": [[256, 203, "r-m"], ...]}, ...]
+//
+// STOP (`10.json`) isn't emulated. Cases starting with an interrupt pending in IE and IF are
+// skipped: the interrupt registers live in the flat bus here, but the CPU checks its own, so HALT
+// would halt instead of running into the HALT bug.
+#![cfg(feature = "debug")]
+
+use gbemu::cpu::{Device, CPU};
+
+const SM83_TESTS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/sm83/v1/");
+
+/// RAM over the whole address space, so the built-in hardware never sees the accesses.
+struct FlatBus(Box<[u8; 0x10000]>);
+
+impl Device for FlatBus {
+    fn maps(&self, _addr: u16) -> bool {
+        true
+    }
+
+    fn read_byte(&self, addr: u16) -> u8 {
+        self.0[addr as usize]
+    }
+
+    fn write_byte(&mut self, addr: u16, val: u8) {
+        self.0[addr as usize] = val;
+    }
+}
+
+/// Just enough JSON for the test files.
+#[derive(Debug)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Json {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            pos: 0,
+        };
+        parser.value()
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(name, _)| name == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn number(&self) -> u64 {
+        match self {
+            Json::Number(val) => *val,
+            Json::Bool(val) => *val as u64,
+            _ => panic!("expected a number, found {self:?}"),
+        }
+    }
+
+    fn array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => panic!("expected an array, found {self:?}"),
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips whitespace and `byte` if it's next.
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.bytes.get(self.pos) == Some(&byte);
+        self.pos += found as usize;
+        found
+    }
+
+    fn value(&mut self) -> Json {
+        self.skip_whitespace();
+        let rest = &self.bytes[self.pos..];
+        for (word, value) in [
+            ("null", Json::Null),
+            ("true", Json::Bool(true)),
+            ("false", Json::Bool(false)),
+        ] {
+            if rest.starts_with(word.as_bytes()) {
+                self.pos += word.len();
+                return value;
+            }
+        }
+
+        match rest[0] {
+            b'"' => Json::String(self.string()),
+            b'[' => {
+                self.pos += 1;
+                let mut items = vec![];
+                while !self.eat(b']') {
+                    items.push(self.value());
+                    self.eat(b',');
+                }
+                Json::Array(items)
+            }
+            b'{' => {
+                self.pos += 1;
+                let mut fields = vec![];
+                while !self.eat(b'}') {
+                    self.skip_whitespace();
+                    let name = self.string();
+                    assert!(self.eat(b':'), "expected `:` at byte {}", self.pos);
+                    fields.push((name, self.value()));
+                    self.eat(b',');
+                }
+                Json::Object(fields)
+            }
+            _ => {
+                let len = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+                assert!(
+                    len > 0,
+                    "unexpected byte {:?} at {}",
+                    rest[0] as char,
+                    self.pos
+                );
+                self.pos += len;
+                Json::Number(std::str::from_utf8(&rest[..len]).unwrap().parse().unwrap())
+            }
+        }
+    }
+
+    /// The test files have no escapes in strings.
+    fn string(&mut self) -> String {
+        assert_eq!(
+            self.bytes[self.pos], b'"',
+            "expected a string at byte {}",
+            self.pos
+        );
+        let len = self.bytes[self.pos + 1..]
+            .iter()
+            .position(|&b| b == b'"')
+            .unwrap();
+        let string = std::str::from_utf8(&self.bytes[self.pos + 1..][..len]).unwrap();
+        self.pos += len + 2;
+        string.to_string()
+    }
+}
+
+const REGISTERS: [&str; 8] = ["a", "b", "c", "d", "e", "f", "h", "l"];
+
+fn register(cpu: &CPU, name: &str) -> u64 {
+    let regs = cpu.registers();
+    match name {
+        "a" => regs.a as u64,
+        "b" => regs.b as u64,
+        "c" => regs.c as u64,
+        "d" => regs.d as u64,
+        "e" => regs.e as u64,
+        "f" => u8::from(regs.f) as u64,
+        "h" => regs.h as u64,
+        "l" => regs.l as u64,
+        "pc" => cpu.pc() as u64,
+        "sp" => cpu.sp() as u64,
+        _ => unreachable!(),
+    }
+}
+
+/// Runs one case.
+///
+/// # Returns
+///
+/// The differences from the final state.
+fn run_case(case: &Json) -> Result<(), String> {
+    let field = |state: &str, name: &str| case.get(state).and_then(|s| s.get(name));
+
+    let mut cpu = CPU::new_without_sound(vec![0; 0x8000]);
+    cpu.attach_device(Box::new(FlatBus(Box::new([0; 0x10000]))));
+    let initial = |name| field("initial", name).unwrap().number();
+    let regs = cpu.registers_mut();
+    regs.a = initial("a") as u8;
+    regs.b = initial("b") as u8;
+    regs.c = initial("c") as u8;
+    regs.d = initial("d") as u8;
+    regs.e = initial("e") as u8;
+    regs.f = (initial("f") as u8).into();
+    regs.h = initial("h") as u8;
+    regs.l = initial("l") as u8;
+    cpu.set_pc(initial("pc") as u16);
+    cpu.set_sp(initial("sp") as u16);
+    cpu.set_ime(field("initial", "ime").is_some_and(|ime| ime.number() != 0));
+    for cell in field("initial", "ram").unwrap().array() {
+        let cell = cell.array();
+        cpu.poke(cell[0].number() as u16, cell[1].number() as u8);
+    }
+    let opcode = cpu.peek(cpu.pc());
+
+    let cycles = cpu.cycle();
+
+    let mut diffs = vec![];
+    for name in REGISTERS.into_iter().chain(["pc", "sp"]) {
+        let expected = field("final", name).unwrap().number();
+        let actual = register(&cpu, name);
+        if actual != expected {
+            diffs.push(format!("{name} is {actual:#X}, expected {expected:#X}"));
+        }
+    }
+    // DI and EI change IME one instruction late.
+    if let Some(ime) = field("final", "ime").filter(|_| !matches!(opcode, 0xF3 | 0xFB)) {
+        if cpu.ime() != (ime.number() != 0) {
+            diffs.push(format!("IME is {}, expected {}", cpu.ime(), ime.number()));
+        }
+    }
+    for cell in field("final", "ram").unwrap().array() {
+        let cell = cell.array();
+        let (addr, expected) = (cell[0].number() as u16, cell[1].number() as u8);
+        let actual = cpu.peek(addr);
+        if actual != expected {
+            diffs.push(format!(
+                "({addr:#06X}) is {actual:#04X}, expected {expected:#04X}"
+            ));
+        }
+    }
+    if let Some(expected) = case.get("cycles").map(|c| c.array().len() as u64 * 4) {
+        if cycles != expected {
+            diffs.push(format!("took {cycles} cycles, expected {expected}"));
+        }
+    }
+
+    if diffs.is_empty() {
+        Ok(())
+    } else {
+        Err(diffs.join(", "))
+    }
+}
+
+/// Whether IE and IF in the initial RAM of the case have an interrupt pending.
+fn pending_interrupt(case: &Json) -> bool {
+    let ram = case
+        .get("initial")
+        .and_then(|s| s.get("ram"))
+        .unwrap()
+        .array();
+    let read = |addr| {
+        ram.iter()
+            .map(Json::array)
+            .find(|cell| cell[0].number() == addr)
+            .map_or(0, |cell| cell[1].number())
+    };
+    read(0xFFFF) & read(0xFF0F) & 0x1F != 0
+}
+
+/// Runs the cases of one opcode file, except those with a pending interrupt.
+///
+/// # Returns
+///
+/// Number of cases, and the first failure.
+fn run_file(text: &str) -> (usize, Option<String>) {
+    let json = Json::parse(text);
+    let cases = json.array();
+    let failure = cases
+        .iter()
+        .filter(|case| !pending_interrupt(case))
+        .find_map(|case| {
+            let name = match case.get("name") {
+                Some(Json::String(name)) => name.as_str(),
+                _ => "?",
+            };
+            run_case(case).err().map(|diffs| format!("{name}: {diffs}"))
+        });
+    (cases.len(), failure)
+}
+
+#[test]
+fn every_opcode() {
+    let mut paths: Vec<_> = std::fs::read_dir(SM83_TESTS_PATH)
+        .unwrap_or_else(|err| panic!("Can't read {SM83_TESTS_PATH}: {err}"))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        // STOP panics as unimplemented.
+        .filter(|path| path.file_stem().is_some_and(|stem| stem != "10"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "No tests in {SM83_TESTS_PATH}");
+
+    let mut failures = vec![];
+    for path in &paths {
+        let text = std::fs::read_to_string(path).unwrap();
+        let (cases, failure) = run_file(&text);
+        assert!(cases > 0, "No cases in {}", path.display());
+        if let Some(failure) = failure {
+            failures.push(failure);
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} opcodes failed:\n{}",
+        failures.len(),
+        paths.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn parse_json() {
+    let json = Json::parse(r#" {"name": "00 0000", "ram": [[1, 2], []], "ok": true, "x": null} "#);
+    assert!(matches!(json.get("name"), Some(Json::String(name)) if name == "00 0000"));
+    let ram = json.get("ram").unwrap().array();
+    assert_eq!(ram[0].array()[1].number(), 2);
+    assert!(ram[1].array().is_empty());
+    assert_eq!(json.get("ok").unwrap().number(), 1);
+    assert!(matches!(json.get("x"), Some(Json::Null)));
+}
+
+#[test]
+fn flat_bus_case() {
+    // INC A at 0x0100, with the flags of the result.
+    let case = Json::parse(
+        r#"{"name": "3c 0000",
+        "initial": {"pc": 256, "sp": 0, "a": 15, "b": 0, "c": 0, "d": 0, "e": 0, "f": 16,
+            "h": 0, "l": 0, "ime": 0, "ram": [[256, 60], [65535, 1]]},
+        "final": {"pc": 257, "sp": 0, "a": 16, "b": 0, "c": 0, "d": 0, "e": 0, "f": 48,
+            "h": 0, "l": 0, "ime": 0, "ram": [[256, 60], [65535, 1]]},
+        "cycles": [[256, 60, "r-m"]]}"#,
+    );
+    assert_eq!(run_case(&case), Ok(()));
+    assert!(!pending_interrupt(&case));
+
+    // LD (HL),A writes anywhere, even to ROM addresses.
+    let case = Json::parse(
+        r#"{"name": "77 0000",
+        "initial": {"pc": 49152, "sp": 0, "a": 66, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0,
+            "h": 18, "l": 52, "ram": [[49152, 119]]},
+        "final": {"pc": 49153, "sp": 0, "a": 66, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0,
+            "h": 18, "l": 52, "ram": [[49152, 119], [4660, 66]]},
+        "cycles": [[49152, 119, "r-m"], [4660, 66, "-wm"]]}"#,
+    );
+    assert_eq!(run_case(&case), Ok(()));
+
+    // HALT with IME off and a pending interrupt is skipped.
+    let case = Json::parse(r#"{"initial": {"ram": [[256, 118], [65295, 4], [65535, 5]]}}"#);
+    assert!(pending_interrupt(&case));
+}
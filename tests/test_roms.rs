@@ -1,71 +1,172 @@
-use std::path::PathBuf;
+use std::{ops::ControlFlow, path::PathBuf};
 
 // To run integration tests you need to download and unpack
 // https://github.com/c-sp/game-boy-test-roms/ to this directory.
 
 use gbemu::{
+    audio_player::VoidAudioPlayer,
     cpu::{
-        instruction::{Instruction, JumpTest, LoadByteSource, LoadByteTarget, LoadType},
+        instruction::{Instruction, LoadByteSource, LoadByteTarget, LoadType},
         CPU,
     },
+    model::Model,
     SCREEN_HEIGHT, SCREEN_WIDTH,
 };
 
 const TEST_ROM_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/game-boy-test-roms/");
 
-fn test_rom_screen(rom_path: PathBuf, img_expected: PathBuf, timeout: u64) {
-    let img = image::open(&img_expected).unwrap().to_rgb8();
-    let rom = gbemu::read_rom(&rom_path).unwrap();
+/// Emulates until the screen matches `expected` (at most `tolerance` pixels differ) or `timeout`
+/// cycles pass.
+///
+/// Every frame is hashed first, so frames identical to the previously checked one and exact
+/// matches don't need pixel-by-pixel comparison.
+fn run_until_screen_matches(
+    cpu: &mut CPU,
+    expected: &image::RgbImage,
+    tolerance: usize,
+    timeout: u64,
+) -> bool {
+    let expected = expected.as_raw().clone();
+    let expected_hash = frame_hash(&expected);
+    let mut last_hash = None;
 
-    let mut cpu = CPU::new_without_sound(rom);
-    let mut cycles = 0;
+    cpu.on_frame(Box::new(move |frame| {
+        let actual = frame.to_rgb8();
+        let hash = frame_hash(&actual);
 
-    while cycles < timeout {
-        let prev_pc = cpu.pc();
+        if last_hash == Some(hash) {
+            return ControlFlow::Continue(());
+        }
+        last_hash = Some(hash);
 
-        cycles += cpu.cycle();
+        if hash == expected_hash || different_pixels(&actual, &expected) <= tolerance {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }));
+
+    cpu.run(timeout).is_break()
+}
+
+fn frame_hash(rgb: &[u8]) -> u64 {
+    use std::hash::{Hash, Hasher};
 
-        if prev_pc == cpu.pc() {
-            match cpu.get_current_instruction() {
-                Instruction::JR(JumpTest::Always) | Instruction::JP(JumpTest::Always) => break,
-                _ => {}
-            }
+    let mut hasher = std::hash::DefaultHasher::new();
+    rgb.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn different_pixels(lhs: &[u8], rhs: &[u8]) -> usize {
+    if lhs.len() != rhs.len() {
+        return usize::MAX;
+    }
+    lhs.chunks(3)
+        .zip(rhs.chunks(3))
+        .filter(|(l, r)| l != r)
+        .count()
+}
+
+/// Per-test folder `target/test-artifacts/<test_name>/` for images of failed tests.
+///
+/// The folder is cleaned when the test starts. Set `GBEMU_KEEP_ARTIFACTS=1` to keep old artifacts
+/// and to save them for passing tests too.
+struct Artifacts {
+    dir: PathBuf,
+    keep: bool,
+}
+
+impl Artifacts {
+    fn new(test_name: &str) -> Self {
+        let target = std::env::var_os("CARGO_TARGET_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/target")));
+        let dir = target
+            .join("test-artifacts")
+            .join(test_name.replace("::", "/"));
+        let keep = std::env::var_os("GBEMU_KEEP_ARTIFACTS").is_some_and(|val| val != "0");
+
+        if !keep && dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
         }
+
+        Self { dir, keep }
+    }
+
+    fn save_rgb8(&self, name: &str, rgb: &[u8]) {
+        std::fs::create_dir_all(&self.dir).unwrap();
+        let path = self.dir.join(name);
+        image::save_buffer(
+            &path,
+            rgb,
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+            image::ColorType::Rgb8,
+        )
+        .unwrap();
     }
 
+    /// Saves the expected and actual screens and their difference: differing pixels are red,
+    /// the rest is the dimmed expected image.
+    fn save_screens(&self, expected: &[u8], actual: &[u8]) {
+        let diff: Vec<u8> = expected
+            .chunks(3)
+            .zip(actual.chunks(3))
+            .flat_map(|(exp, act)| {
+                if exp == act {
+                    [exp[0] / 4, exp[1] / 4, exp[2] / 4]
+                } else {
+                    [0xFF, 0, 0]
+                }
+            })
+            .collect();
+
+        self.save_rgb8("expected.png", expected);
+        self.save_rgb8("actual.png", actual);
+        self.save_rgb8("diff.png", &diff);
+        eprintln!("Screens saved to {}", self.dir.display());
+    }
+}
+
+fn test_rom_screen(test_name: &str, rom_path: PathBuf, img_expected: PathBuf, timeout: u64) {
+    let artifacts = Artifacts::new(test_name);
+    let img = image::open(&img_expected).unwrap().to_rgb8();
+    let rom = gbemu::read_rom(&rom_path).unwrap();
+
+    let mut cpu = CPU::new_without_sound(rom);
+
+    let passed = run_until_screen_matches(&mut cpu, &img, 0, timeout);
+
     let mut actual = [0u8; SCREEN_HEIGHT * SCREEN_WIDTH * 3];
     cpu.gpu().to_rgb8(&mut actual);
 
-    let mut save_img = img_expected;
-    assert!(save_img.set_extension("actual.png"));
-
-    image::save_buffer(
-        &save_img,
-        &actual,
-        SCREEN_WIDTH as u32,
-        SCREEN_HEIGHT as u32,
-        image::ColorType::Rgb8,
-    )
-    .unwrap();
+    if !passed || artifacts.keep {
+        artifacts.save_screens(img.as_raw(), &actual);
+    }
 
-    assert_eq!(actual, img.as_raw().as_slice());
+    assert!(passed, "screen doesn't match {}", img_expected.display());
 }
 
 macro_rules! test_by_screen {
     ($($test_name:ident($rom_path:expr, $img_expected:expr, $timeout:literal),)*) => {
         $(#[test]
         fn $test_name() {
-            crate::test_rom_screen($rom_path, $img_expected, $timeout);
+            crate::test_rom_screen(
+                concat!(module_path!(), "::", stringify!($test_name)),
+                $rom_path,
+                $img_expected,
+                $timeout,
+            );
         })*
     };
 }
 
 // Writes the Fibonacci numbers 3/5/8/13/21/34 to the registers B/C/D/E/H/L.
 // Executes an `LD B, B` opcode.
-fn test_rom_fibonacci(rom_path: PathBuf, timeout: u64) {
+fn test_rom_fibonacci(model: Model, rom_path: PathBuf, timeout: u64) {
     let rom = gbemu::read_rom(&rom_path).unwrap();
 
-    let mut cpu = CPU::new_without_sound(rom);
+    let mut cpu = CPU::with_model(rom, Box::new(VoidAudioPlayer::new()), model);
     let mut cycles = 0;
 
     while cycles < timeout {
@@ -90,7 +191,17 @@ macro_rules! test_by_fibonacci {
     ($($test_name:ident($rom_path:expr, $timeout:literal),)*) => {
         $(#[test]
         fn $test_name() {
-            crate::test_rom_fibonacci($rom_path, $timeout);
+            crate::test_rom_fibonacci(gbemu::model::Model::Dmg, $rom_path, $timeout);
+        })*
+    };
+}
+
+/// Model-specific ROMs: every test runs on the given model.
+macro_rules! test_by_fibonacci_on {
+    ($($test_name:ident($model:ident, $rom_path:expr, $timeout:literal),)*) => {
+        $(#[test]
+        fn $test_name() {
+            crate::test_rom_fibonacci(gbemu::model::Model::$model, $rom_path, $timeout);
         })*
     };
 }
@@ -166,6 +277,11 @@ mod mooneye_test_suite {
     }
 
     mod acceptance {
+        test_by_fibonacci_on!(
+            boot_regs_dmg_abc(Dmg, path!("acceptance/boot_regs-dmgABC.gb"), 800_000),
+            boot_regs_mgb(Mgb, path!("acceptance/boot_regs-mgb.gb"), 800_000),
+        );
+
         mod bits {
             test_by_fibonacci!(
                 mem_oam(path!("acceptance/bits/mem_oam.gb"), 800_000),